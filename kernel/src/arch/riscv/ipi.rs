@@ -0,0 +1,106 @@
+//! 核间中断(IPI)
+//!
+//! 通过 SBI 的 send_ipi 向其他核发送软件中断。每个核有一个消息队列，发送方先把消息放进目标核的队列，
+//! 再触发中断；目标核在 trap 或空闲时取出队列中的所有消息处理。
+//!
+//! 内核态下不打开中断，所以正在内核中执行的核要等回到用户态或者回到 run_tasks 的循环时才会处理消息。
+//! 因此等待其他核回应时，发送方不能持有对方可能在等的锁
+
+use super::get_cpu_id;
+use crate::constants::{CPU_ID_LIMIT, FIRST_CPU_ID};
+use alloc::{collections::VecDeque, sync::Arc, vec::Vec};
+use core::sync::atomic::{AtomicUsize, Ordering};
+use lock::Mutex;
+use riscv::{asm::sfence_vma_all, register::sie};
+
+/// 发给其他核的消息
+pub enum IpiMessage {
+    /// 只是让对方核进入 trap。例如对方正在运行的线程收到了信号，需要尽快处理
+    Kick,
+    /// 清空对方核的 TLB。处理完成后将计数减一，发送方等计数变为 0 后才返回
+    TlbFlush(Arc<AtomicUsize>),
+}
+
+lazy_static::lazy_static! {
+    /// 每个核待处理的消息
+    static ref IPI_QUEUES: Vec<Mutex<VecDeque<IpiMessage>>> = {
+        let mut queues = Vec::new();
+        for _ in 0..CPU_ID_LIMIT {
+            queues.push(Mutex::new(VecDeque::new()));
+        }
+        queues
+    };
+}
+
+/// 每个核当前页表的根节点物理地址，用于判断 TLB 中是否可能缓存了某个页表的映射
+static CURRENT_ROOTS: [AtomicUsize; CPU_ID_LIMIT] = {
+    const ZERO: AtomicUsize = AtomicUsize::new(0);
+    [ZERO; CPU_ID_LIMIT]
+};
+
+/// 打开当前核的软件中断。每个核都需要调用
+pub fn init() {
+    unsafe {
+        sie::set_ssoft();
+    }
+}
+
+/// 向核 cpu_id 发送一条消息
+pub fn send_ipi(cpu_id: usize, msg: IpiMessage) {
+    IPI_QUEUES[cpu_id].lock().push_back(msg);
+    sbi_rt::send_ipi(1 << cpu_id, 0);
+}
+
+/// 处理当前核收到的所有消息
+pub fn handle_ipi() {
+    // 先清除 sip 中的 SSIP 位再取消息，这样处理过程中新到的消息会再次触发中断，不会遗漏
+    unsafe {
+        core::arch::asm!("csrc sip, {}", in(reg) 1 << 1);
+    }
+    let cpu_id = get_cpu_id();
+    loop {
+        // 取出消息后立即释放队列的锁，发送方可能正在等待
+        let msg = IPI_QUEUES[cpu_id].lock().pop_front();
+        match msg {
+            Some(IpiMessage::Kick) => {}
+            Some(IpiMessage::TlbFlush(pending)) => {
+                unsafe { sfence_vma_all() };
+                pending.fetch_sub(1, Ordering::Release);
+            }
+            None => break,
+        }
+    }
+}
+
+/// 记录当前核切换到了根节点为 root_paddr 的页表
+pub fn set_current_root(root_paddr: usize) {
+    CURRENT_ROOTS[get_cpu_id()].store(root_paddr, Ordering::SeqCst);
+}
+
+/// 获取正在使用根节点为 root_paddr 的页表的核(不含当前核)的掩码
+pub fn cpus_using_root(root_paddr: usize) -> usize {
+    let cpu_id = get_cpu_id();
+    (FIRST_CPU_ID..CPU_ID_LIMIT)
+        .filter(|&id| id != cpu_id && CURRENT_ROOTS[id].load(Ordering::SeqCst) == root_paddr)
+        .fold(0, |mask, id| mask | (1 << id))
+}
+
+/// 页表 root_paddr 中的映射被删除或修改权限后，清空当前核以及所有正在使用它的核的 TLB，等它们都完成后才返回
+pub fn tlb_shootdown(root_paddr: usize) {
+    unsafe { sfence_vma_all() };
+    let mask = cpus_using_root(root_paddr);
+    if mask == 0 {
+        return;
+    }
+    let pending = Arc::new(AtomicUsize::new(mask.count_ones() as usize));
+    for cpu_id in FIRST_CPU_ID..CPU_ID_LIMIT {
+        if mask & (1 << cpu_id) != 0 {
+            send_ipi(cpu_id, IpiMessage::TlbFlush(pending.clone()));
+        }
+    }
+    // 对方也可能正在等当前核处理它发来的消息，所以等待时也要处理自己的队列
+    while pending.load(Ordering::Acquire) != 0 {
+        handle_ipi();
+        core::hint::spin_loop();
+    }
+}
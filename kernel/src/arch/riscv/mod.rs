@@ -1,3 +1,4 @@
+pub mod ipi;
 pub mod stdin;
 pub mod stdout;
 
@@ -51,6 +51,8 @@ pub const FIRST_CPU_ID: usize = if cfg!(feature = "sifive") { 1 } else { 0 };
 pub const CPU_ID_LIMIT: usize = FIRST_CPU_ID + 4;
 /// 最后一个 CPU 的编号
 pub const LAST_CPU_ID: usize = CPU_ID_LIMIT - 1;
+/// 所有 cpu 组成的掩码，第 i 位表示编号为 i 的 cpu。任务默认可以在其中任意一个 cpu 上运行
+pub const ALL_CPU_MASK: usize = (1 << CPU_ID_LIMIT) - (1 << FIRST_CPU_ID);
 /// 是否单核运行。单核运行时，则其他核只启动，不运行用户程序
 pub const IS_SINGLE_CORE: bool = true;
 /// 是否在启动后暂停。如果为 true，则所有核都只启动，不进入用户程序
@@ -88,12 +90,34 @@ pub const PHYS_MEMORY_END: usize = 0x8800_0000;
 /// 一般来说，这个程序会通过 fork / exec 启动终端和其他程序
 pub const ORIGIN_USER_PROC_NAME: &str = "start";
 
+/// 线程名(comm)的最大长度，包括结尾的 \0
+pub const TASK_COMM_LEN: usize = 16;
+
+/// 初始进程(以及测例)的环境变量。其他进程的环境变量由 execve 的调用者给出
+pub const ORIGIN_USER_PROC_ENVS: &[&str] = &[
+    "SHLVL=1",
+    "HOME=/",
+    "PATH=/usr/sbin:/usr/bin:/sbin:/bin",
+    "PWD=/",
+    "LD_LIBRARY_PATH=/lib:/usr/lib",
+    "GCC_EXEC_PREFIX=/riscv64-linux-musl-native/bin/../lib/gcc/",
+    "COLLECT_GCC=./riscv64-linux-musl-native/bin/riscv64-linux-musl-gcc",
+    "COLLECT_LTO_WRAPPER=/riscv64-linux-musl-native/bin/../libexec/gcc/riscv64-linux-musl/11.2.1/lto-wrapper",
+    "COLLECT_GCC_OPTIONS='-march=rv64gc' '-mabi=lp64d' '-march=rv64imafdc' '-dumpdir' 'a.'",
+    "COMPILER_PATH=/riscv64-linux-musl-native/bin/../libexec/gcc/riscv64-linux-musl/11.2.1/:/riscv64-linux-musl-native/bin/../libexec/gcc/:/riscv64-linux-musl-native/bin/../lib/gcc/riscv64-linux-musl/11.2.1/../../../../riscv64-linux-musl/bin/",
+    "LIBRARY_PATH=/riscv64-linux-musl-native/bin/../lib/gcc/riscv64-linux-musl/11.2.1/:/riscv64-linux-musl-native/bin/../lib/gcc/:/riscv64-linux-musl-native/bin/../lib/gcc/riscv64-linux-musl/11.2.1/../../../../riscv64-linux-musl/lib/:/riscv64-linux-musl-native/bin/../lib/:/riscv64-linux-musl-native/bin/../usr/lib/",
+];
+
 /// 最小的 tid(进程号) 是 0，最大的 pid 是 TID_LIMIT-1
 pub const TID_LIMIT: usize = 4096;
 /// 预设的文件描述符数量限制
 pub const FD_LIMIT_ORIGIN: usize = 256;
 /// sys_pipe创建的管道的大小，单位为字节
 pub const PIPE_SIZE_LIMIT: usize = 0x40_000; // 64 KB
+/// F_SETPIPE_SZ 允许设置的最大管道大小
+pub const PIPE_SIZE_MAX: usize = 0x100_000; // 1 MB
+/// 不超过这个长度的管道写入是原子的，不会和其他写入交错
+pub const PIPE_BUF: usize = 0x1000; // 4 KB
 /// socket 使用的 buffer 大小
 pub const SOCKET_BUFFER_SIZE_LIMIT: usize = 0x200000; // 2 MB
 
@@ -135,10 +159,19 @@ pub const TMP_SIZE_LIMIT: usize = 0x8_000; // 1 MB
 /// 如果 elf 的 phdr 指示 base 是 0(如 libc-test 的 libc.so)，则需要找一个非0的位置放置
 pub const ELF_BASE_RELOCATE: usize = 0x400_0000;
 
+/// 脚本文件的 #! 行最多可以嵌套的层数，即解释器本身也是脚本时最多再找几次解释器。与 Linux 相同
+pub const MAX_INTERPRETER_DEPTH: usize = 4;
+/// 脚本文件第一行(#! 行)的最大长度，超出的部分会被忽略
+pub const SHEBANG_LINE_MAX_LEN: usize = 256;
+
 /// signal 中用到的 bitset 长度。
 pub const SIGSET_SIZE_IN_BYTE: usize = 8;
 /// 所有可能的信号数。有多少可能的信号，内核就要为其保存多少个 SigAction
 pub const SIGSET_SIZE_IN_BIT: usize = SIGSET_SIZE_IN_BYTE * 8; // =64
+/// 每个线程(以及每个进程共享的)信号队列中最多排队的实时信号数，超出时 sigqueue 返回 EAGAIN
+pub const SIGQUEUE_MAX: usize = 64;
+/// sigaltstack 设置的备用信号栈的最小大小
+pub const MINSIGSTKSZ: usize = 2048;
 /// SIGINFO 要求把一些信息存在用户栈上，从用户栈开辟一块空间来保存它们
 pub const USER_STACK_RED_ZONE: usize = 0x200; // 512 B
 /// 一个在 Sv39 页表里不合法的地址。
@@ -149,6 +182,15 @@ pub const USER_STACK_RED_ZONE: usize = 0x200; // 512 B
 ///
 /// 另外，把触发signal的用户pc写成ra是行不通的，因为 signal 语义上要求信号处理结束时恢复之前的一切寄存器状态
 pub const SIGNAL_RETURN_TRAP: usize = 0xffff_0000_8080_0000;
+/// 进程被信号终止并生成 core dump 时，在进程工作目录下创建的文件名
+pub const CORE_DUMP_FILE_NAME: &str = "core";
+/// 每个被跟踪的任务最多保存的系统调用记录数，超出时丢弃最早的记录
+pub const SYSCALL_TRACE_BUFFER_LEN: usize = 256;
+/// 跟踪记录中，用户字符串或缓冲区最多显示的字节数
+pub const SYSCALL_TRACE_STR_LEN: usize = 32;
+/// 启动时就选中跟踪系统调用的进程，格式与 `/proc/sys/kernel/syscall_trace` 相同。
+/// 之后写这个文件会替换掉它们
+pub const SYSCALL_TRACE_TARGETS: &[&str] = &[];
 
 /// sys_sendfile64 中使用 buffer 的大小
 pub const SENDFILE_BUFFER_SIZE: usize = 0x2000;
@@ -60,6 +60,10 @@ pub enum OSError {
     Loader_CanNotParseInterpreter,
     Loader_PhdrNotFound,
     Loader_Skipped,
+    // 文件既不是 ELF 也不是以 #! 开头的脚本
+    Loader_NotExecutable,
+    // 脚本的解释器嵌套层数过多
+    Loader_TooManyInterpreters,
 
     Task_NoTrapHandler,
     // 申请 physical memory 中的物理页面失败
@@ -3,10 +3,10 @@
 
 //#![deny(missing_docs)]
 
-use super::{get_link_count, FsFile};
+use super::{get_file_perm, get_link_count, FsFile};
 use crate::constants::FS_IMG_SIZE;
 use alloc::{string::String, sync::Arc, vec::Vec};
-use base_file::{normal_file_mode, File, Kstat, OpenFlags, StMode};
+use base_file::{File, Kstat, OpenFlags, StMode};
 use fatfs::{Read, Seek, SeekFrom, Write};
 use lock::Mutex;
 use timer::TimeSpec;
@@ -105,7 +105,11 @@ impl File for FatFile {
         if !self.writable {
             return None;
         }
+        let append = self.inner.lock().flags.contains(OpenFlags::APPEND);
         let mut file = self.file.lock();
+        if append {
+            file.seek(SeekFrom::End(0)).ok()?;
+        }
         let len = buf.len();
         let mut pos = 0;
         while pos < len {
@@ -168,14 +172,15 @@ impl File for FatFile {
         let len = file.seek(SeekFrom::End(0)).unwrap() as usize;
         file.seek(SeekFrom::Start(pre_pos)).unwrap();
         let nlink = get_link_count(String::from(&self.dir[..]), self.name.as_str());
+        let perm = get_file_perm(self.dir.as_str(), self.name.as_str());
         unsafe {
             (*stat).st_dev = 1;
             (*stat).st_ino = 1;
             (*stat).st_nlink = nlink as u32;
-            (*stat).st_mode = normal_file_mode(StMode::S_IFREG).bits();
+            (*stat).st_mode = StMode::S_IFREG.bits() | perm.mode;
             (*stat).st_size = len as u64;
-            (*stat).st_uid = 0;
-            (*stat).st_gid = 0;
+            (*stat).st_uid = perm.uid;
+            (*stat).st_gid = perm.gid;
             (*stat).st_atime_sec = inner.atime.tv_sec as isize;
             (*stat).st_atime_nsec = inner.atime.tv_nsec as isize;
             (*stat).st_mtime_sec = inner.mtime.tv_sec as isize;
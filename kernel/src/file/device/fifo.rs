@@ -0,0 +1,200 @@
+//! 命名管道(FIFO)
+//!
+//! FAT 文件系统不能表示特殊文件，所以由 mknodat 创建的 FIFO 另外用一张表记录。
+//! 只有 open 会在表中对应的管道上新建一端，这样不同进程通过同一路径打开的是同一个管道。
+//! stat、utimensat、exec 等只通过路径访问 FIFO 的操作不能打开它，
+//! 否则会被等待另一端的进程视为打开了一端
+
+use super::{
+    check_dir_exists, check_file_exists, get_file_perm, map_path_and_file, remove_file,
+    set_file_perm, FileDisc,
+};
+use crate::file::{fifo_stat, Fifo, Pipe};
+use alloc::{collections::BTreeMap, string::String, sync::Arc, vec::Vec};
+use base_file::{Kstat, OpenFlags, StMode};
+use lock::Mutex;
+use syscall::ErrorNo;
+use timer::TimeSpec;
+
+/// 实际文件(而不是用户文件)到 FIFO 的映射。硬链接得到的多个路径对应同一个 FifoNode
+static FIFO_MAP: Mutex<BTreeMap<FileDisc, Arc<FifoNode>>> = Mutex::new(BTreeMap::new());
+
+/// 表中记录的一个 FIFO
+struct FifoNode {
+    /// 管道本身
+    fifo: Fifo,
+    /// 访问时间和修改时间
+    times: Mutex<(TimeSpec, TimeSpec)>,
+}
+
+/// 在 dir_name 目录下创建 FIFO，返回是否成功。路径会先经过链接转换
+///
+/// 同名的文件或目录已存在时失败
+pub fn make_fifo(dir_name: &str, file_path: &str) -> bool {
+    if check_file_exists(dir_name, file_path) || check_dir_exists(&[dir_name, file_path].concat()) {
+        return false;
+    }
+    map_path_and_file(dir_name, file_path).map_or(false, |(path, file)| {
+        // 所在的目录必须存在
+        if !check_dir_exists(path.as_str()) {
+            return false;
+        }
+        let node = FifoNode {
+            fifo: Fifo::new(),
+            times: Mutex::new((TimeSpec::now(), TimeSpec::now())),
+        };
+        FIFO_MAP
+            .lock()
+            .insert(FileDisc::new(&path, &file), Arc::new(node));
+        true
+    })
+}
+
+/// 获取 FIFO 在表中的记录。输入的是实际路径和文件名
+fn get_fifo_node(path: &str, file: &str) -> Option<Arc<FifoNode>> {
+    FIFO_MAP
+        .lock()
+        .get(&FileDisc::new(&String::from(path), &String::from(file)))
+        .cloned()
+}
+
+/// 路径是否是 FIFO。输入的是实际路径和文件名
+pub fn is_fifo(path: &str, file: &str) -> bool {
+    get_fifo_node(path, file).is_some()
+}
+
+/// 打开 FIFO 的一端。路径不是 FIFO 时返回 None。路径会先经过链接转换
+///
+/// 只有 sys_open 可以调用它。返回后调用者还需要在不持有锁的情况下调用 Pipe::wait_for_peer 等待另一端
+pub fn open_fifo(dir_name: &str, file_path: &str, flags: OpenFlags) -> Option<Pipe> {
+    let (path, file) = map_path_and_file(dir_name, file_path)?;
+    get_fifo_node(&path, &file).map(|node| node.fifo.open(flags))
+}
+
+/// 删除 FIFO，返回它是否存在。输入的是实际路径和文件名
+///
+/// 已经打开的端不受影响，仍然可以继续读写
+pub fn remove_fifo(path: &str, file: &str) -> bool {
+    FIFO_MAP
+        .lock()
+        .remove(&FileDisc::new(&String::from(path), &String::from(file)))
+        .is_some()
+}
+
+/// 移动或重命名 FIFO。路径会先经过链接转换
+///
+/// 原路径不是 FIFO 时返回 None，由调用者继续在文件系统中查找。
+/// 目标位置已有文件时，replace 为 true 则替换它，否则返回 EEXIST；目标位置是目录时返回 EISDIR
+pub fn move_fifo(
+    old_dir: &str,
+    old_file: &str,
+    new_dir: &str,
+    new_file: &str,
+    replace: bool,
+) -> Option<Result<(), ErrorNo>> {
+    let (old_path, old_file) = map_path_and_file(old_dir, old_file)?;
+    let old_key = FileDisc::new(&old_path, &old_file);
+    if !FIFO_MAP.lock().contains_key(&old_key) {
+        return None;
+    }
+    let (new_path, new_file) = match map_path_and_file(new_dir, new_file) {
+        Some(new) => new,
+        None => return Some(Err(ErrorNo::EINVAL)),
+    };
+    if !check_dir_exists(new_path.as_str()) {
+        return Some(Err(ErrorNo::ENOENT));
+    }
+    let new_key = FileDisc::new(&new_path, &new_file);
+    if old_key == new_key {
+        return Some(Ok(()));
+    }
+    if check_dir_exists(&[new_path.as_str(), new_file.as_str()].concat()) {
+        return Some(Err(ErrorNo::EISDIR));
+    }
+    if check_file_exists(&new_path, &new_file) {
+        if !replace {
+            return Some(Err(ErrorNo::EEXIST));
+        }
+        // 目标是 FIFO 时，下面插入时会直接替换它
+        if !is_fifo(&new_path, &new_file) {
+            remove_file(&new_path, &new_file);
+        }
+    }
+    let mut map = FIFO_MAP.lock();
+    let node = map.remove(&old_key).unwrap();
+    map.insert(new_key, node);
+    Some(Ok(()))
+}
+
+/// 为 FIFO 添加硬链接，两个路径对应同一个管道。路径会先经过链接转换
+///
+/// 原路径不是 FIFO 时返回 None，由调用者按普通文件处理；目标位置已有文件时返回 Some(false)
+pub fn link_fifo(old_dir: &str, old_file: &str, new_dir: &str, new_file: &str) -> Option<bool> {
+    let (old_path, old_file) = map_path_and_file(old_dir, old_file)?;
+    let node = get_fifo_node(&old_path, &old_file)?;
+    if check_file_exists(new_dir, new_file) || check_dir_exists(&[new_dir, new_file].concat()) {
+        return Some(false);
+    }
+    let (new_path, new_file) = match map_path_and_file(new_dir, new_file) {
+        Some(new) if check_dir_exists(new.0.as_str()) => new,
+        _ => return Some(false),
+    };
+    // 权限表按路径记录，新的路径也使用相同的权限
+    set_file_perm(&new_path, &new_file, get_file_perm(&old_path, &old_file));
+    FIFO_MAP
+        .lock()
+        .insert(FileDisc::new(&new_path, &new_file), node);
+    Some(true)
+}
+
+/// 获取目录下所有 FIFO 的文件名，用于 getdents64。路径会先经过链接转换
+pub fn fifo_names_in_dir(dir_name: &str) -> Vec<String> {
+    map_path_and_file(dir_name, "").map_or(Vec::new(), |(path, _)| {
+        FIFO_MAP
+            .lock()
+            .keys()
+            .filter(|disc| disc.path == path)
+            .map(|disc| disc.file.clone())
+            .collect()
+    })
+}
+
+/// 修改 FIFO 的访问时间和修改时间。路径不是 FIFO 时返回 false。路径会先经过链接转换
+///
+/// 时间的格式见 `TimeSpec::set_as_utime`
+pub fn set_fifo_times(dir_name: &str, file_path: &str, atime: &TimeSpec, mtime: &TimeSpec) -> bool {
+    map_path_and_file(dir_name, file_path)
+        .and_then(|(path, file)| get_fifo_node(&path, &file))
+        .map_or(false, |node| {
+            let mut times = node.times.lock();
+            times.0.set_as_utime(atime);
+            times.1.set_as_utime(mtime);
+            true
+        })
+}
+
+/// 如果路径是 FIFO，则获取它的信息并写入 stat，返回 true；否则返回 false。路径会先经过链接转换
+///
+/// 通过路径获取信息时不能打开 FIFO，否则会被等待另一端的进程视为打开了一端
+pub fn get_fifo_stat(dir_name: &str, file_path: &str, stat: *mut Kstat) -> bool {
+    let (path, file) = match map_path_and_file(dir_name, file_path) {
+        Some(mapped) => mapped,
+        None => return false,
+    };
+    if let Some(node) = get_fifo_node(&path, &file) {
+        let perm = get_file_perm(&path, &file);
+        let (atime, mtime) = *node.times.lock();
+        fifo_stat(stat);
+        unsafe {
+            (*stat).st_mode = StMode::S_IFIFO.bits() | perm.mode;
+            (*stat).st_uid = perm.uid;
+            (*stat).st_gid = perm.gid;
+            (*stat).st_atime_sec = atime.tv_sec as _;
+            (*stat).st_atime_nsec = atime.tv_nsec as _;
+            (*stat).st_mtime_sec = mtime.tv_sec as _;
+            (*stat).st_mtime_nsec = mtime.tv_nsec as _;
+        }
+        return true;
+    }
+    false
+}
@@ -6,12 +6,14 @@
 mod fat_dir;
 mod fat_file;
 mod fd_dir;
+mod fifo;
 mod link;
+mod perm;
 mod stat;
 mod test;
 
 use super::{
-    check_virt_dir_exists, check_virt_file_exists, get_virt_dir_if_possible,
+    can_access_virt_file, check_virt_dir_exists, check_virt_file_exists, get_virt_dir_if_possible,
     get_virt_file_if_possible, try_make_virt_dir, try_remove_virt_file,
 };
 use crate::{
@@ -35,11 +37,15 @@ use base_file::{File, OpenFlags};
 pub use fat_dir::FatDir;
 pub use fat_file::FatFile;
 pub use fd_dir::FdDir;
+pub use fifo::{fifo_names_in_dir, get_fifo_stat, link_fifo, make_fifo, open_fifo, set_fifo_times};
+use fifo::{is_fifo, move_fifo, remove_fifo};
 pub use link::FileDisc;
 pub use link::{
     get_link_count, mount_fat_fs, read_link, try_add_link, try_add_rev_link, try_remove_link,
     umount_fat_fs,
 };
+pub use perm::{get_dir_perm, get_file_perm, set_file_perm, FilePerm};
+use perm::{move_file_perm, remove_file_perm};
 pub use stat::get_fs_stat as origin_fs_stat;
 use syscall::ErrorNo;
 pub use test::{
@@ -252,6 +258,14 @@ fn inner_open_dir(root: FsDir, dir_name: &str) -> Option<FsDir> {
     }
 }
 
+/// 检查当前进程能否打开 vfs 中的文件，如 `/proc/<pid>/syscalls`。路径会先经过链接转换
+///
+/// 这些文件不在权限表中，需要由 sys_openat 单独检查
+pub fn can_open_virt_file(dir_name: &str, file_path: &str) -> bool {
+    map_path_and_file(dir_name, file_path)
+        .map_or(true, |(dir, file)| can_access_virt_file(&dir, &file))
+}
+
 /// 在 dir_name 目录下，打开 name 文件。
 /// 如果不包含 OpenFlags::DIR，可能出现如下情况：
 ///
@@ -294,6 +308,11 @@ pub fn open_file(dir_name: &str, file_path: &str, flags: OpenFlags) -> Option<Ar
     if find_in_vfs.is_some() {
         return find_in_vfs;
     }
+    // FIFO 只能由 sys_open 通过 open_fifo 打开，这里打开会被等待另一端的进程视为打开了一端。
+    // 也不能在 FAT 中创建同名的文件
+    if is_fifo(&real_dir, &file_name) {
+        return None;
+    }
     let file_name = if file_name == "." {
         &""
     } else {
@@ -390,6 +409,9 @@ pub fn check_file_exists(dir_name: &str, file_path: &str) -> bool {
             if let Some(exist) = check_virt_file_exists(&real_dir, &file_name) {
                 return exist;
             }
+            if is_fifo(&real_dir, &file_name) {
+                return true;
+            }
             inner_open_dir(root, real_dir.as_str())
                 .map(|dir| {
                     for entry in dir.iter() {
@@ -414,8 +436,14 @@ fn remove_file(path: &str, name: &str) {
     if let Some(_) = try_remove_virt_file(&path.into(), &name.into()) {
         return;
     }
+    // FIFO 不在 FAT 中，只需要从表中删除
+    if remove_fifo(path, name) {
+        remove_file_perm(path, name);
+        return;
+    }
     let dir = inner_open_dir(root, path).unwrap();
     dir.remove(name).unwrap();
+    remove_file_perm(path, name);
     /*
     dir.remove(name).unwrap_or_else(|_| {
         println!("path [{}] name [{}]", path, name);
@@ -462,16 +490,23 @@ pub fn rename_or_move(
     new_file: &str,
     replace: bool,
 ) -> Result<(), ErrorNo> {
-    if let Some(old_dir) = inner_open_dir(MEMORY_FS.root_dir(), old_dir) {
-        if let Some(new_dir) = inner_open_dir(MEMORY_FS.root_dir(), new_dir) {
-            return match old_dir.rename(old_file, &new_dir, new_file) {
+    // FIFO 不在 FAT 中，只需要修改表中的记录
+    if let Some(result) = move_fifo(old_dir, old_file, new_dir, new_file, replace) {
+        if result.is_ok() {
+            move_file_perm(old_dir, old_file, new_dir, new_file);
+        }
+        return result;
+    }
+    if let Some(old_fs_dir) = inner_open_dir(MEMORY_FS.root_dir(), old_dir) {
+        if let Some(new_fs_dir) = inner_open_dir(MEMORY_FS.root_dir(), new_dir) {
+            let result = match old_fs_dir.rename(old_file, &new_fs_dir, new_file) {
                 Ok(_) => Ok(()),
                 // 如果文件已存在，检查
                 Err(Error::AlreadyExists) => {
                     if replace {
-                        new_dir.remove(new_file).unwrap();
-                        old_dir
-                            .rename(old_file, &new_dir, new_file)
+                        new_fs_dir.remove(new_file).unwrap();
+                        old_fs_dir
+                            .rename(old_file, &new_fs_dir, new_file)
                             .map_err(|_| ErrorNo::EINVAL)
                     } else {
                         Err(ErrorNo::EEXIST)
@@ -481,6 +516,10 @@ pub fn rename_or_move(
                 // 其他错误返回 rename 失败
                 _ => Err(ErrorNo::EINVAL),
             };
+            if result.is_ok() {
+                move_file_perm(old_dir, old_file, new_dir, new_file);
+            }
+            return result;
         }
     }
     Err(ErrorNo::EINVAL)
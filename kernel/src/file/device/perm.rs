@@ -0,0 +1,94 @@
+//! 文件的权限位和所有者
+//!
+//! FAT 文件系统本身不保存这些信息，所以另外用一张表记录被 chmod 修改过或由 open 新建的文件。
+//! 不在表中的文件默认属于 root，且所有用户都可以读写和执行
+
+use super::{map_path_and_file, FileDisc};
+use alloc::{collections::BTreeMap, string::String};
+use base_file::normal_file_perm;
+use lock::Mutex;
+
+/// 实际文件(而不是用户文件)到权限信息的映射
+static FILE_PERM_MAP: Mutex<BTreeMap<FileDisc, FilePerm>> = Mutex::new(BTreeMap::new());
+
+/// 文件的权限信息
+#[derive(Clone, Copy)]
+pub struct FilePerm {
+    /// 权限位，即 st_mode 中除文件类型以外的部分，包括 S_ISUID / S_ISGID 等
+    pub mode: u32,
+    /// 所有者的用户 id
+    pub uid: u32,
+    /// 所在用户组 id
+    pub gid: u32,
+}
+
+impl FilePerm {
+    /// 没有记录过的文件的默认权限
+    pub fn default_perm() -> Self {
+        Self {
+            mode: normal_file_perm().bits(),
+            uid: 0,
+            gid: 0,
+        }
+    }
+}
+
+/// 获取文件的权限信息。路径会先经过链接转换
+///
+/// 不检查文件是否存在，找不到记录时返回默认权限
+pub fn get_file_perm(dir_name: &str, file_path: &str) -> FilePerm {
+    map_path_and_file(dir_name, file_path)
+        .and_then(|(path, file)| {
+            FILE_PERM_MAP
+                .lock()
+                .get(&FileDisc::new(&path, &file))
+                .copied()
+        })
+        .unwrap_or_else(FilePerm::default_perm)
+}
+
+/// 获取目录 dir 的权限信息。
+///
+/// 目录的权限记录在它的上一级目录下，根目录没有记录，使用默认权限
+pub fn get_dir_perm(dir: &str) -> FilePerm {
+    let dir_name = dir.trim_end_matches('/');
+    match dir_name.rfind('/') {
+        Some(pos) => get_file_perm(&dir_name[..=pos], &dir_name[pos + 1..]),
+        None => FilePerm::default_perm(),
+    }
+}
+
+/// 设置文件的权限信息。路径会先经过链接转换
+///
+/// 不检查文件是否存在，由调用者保证
+pub fn set_file_perm(dir_name: &str, file_path: &str, perm: FilePerm) {
+    if let Some((path, file)) = map_path_and_file(dir_name, file_path) {
+        FILE_PERM_MAP
+            .lock()
+            .insert(FileDisc::new(&path, &file), perm);
+    }
+}
+
+/// 文件被移动或重命名后，把它的权限信息移到新的位置。路径会先经过链接转换
+///
+/// 新位置原有的记录会被覆盖；如果原文件没有记录，则新位置的记录也被删除，即恢复为默认权限
+pub fn move_file_perm(old_dir: &str, old_file: &str, new_dir: &str, new_file: &str) {
+    if let (Some((old_path, old_file)), Some((new_path, new_file))) = (
+        map_path_and_file(old_dir, old_file),
+        map_path_and_file(new_dir, new_file),
+    ) {
+        let mut map = FILE_PERM_MAP.lock();
+        let new_key = FileDisc::new(&new_path, &new_file);
+        match map.remove(&FileDisc::new(&old_path, &old_file)) {
+            Some(perm) => map.insert(new_key, perm),
+            None => map.remove(&new_key),
+        };
+    }
+}
+
+/// 文件被删除时，删除它的权限信息。输入的是实际路径和文件名
+pub fn remove_file_perm(path: &str, file: &str) {
+    FILE_PERM_MAP
+        .lock()
+        .remove(&FileDisc::new(&String::from(path), &String::from(file)));
+}
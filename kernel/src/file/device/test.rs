@@ -42,9 +42,7 @@ pub fn load_next_testcase() -> Option<Arc<TaskControlBlock>> {
             //let argv = argv.drain_filter(|s| s != "").collect();
             let argv = split_argv(user_command.as_bytes());
             TEST_STATUS.lock().load(&user_command.into());
-            Some(Arc::new(
-                TaskControlBlock::from_app_name(ROOT_DIR, NO_PARENT, argv).unwrap(),
-            ))
+            Some(TaskControlBlock::from_app_name(ROOT_DIR, NO_PARENT, argv).unwrap())
         },
     )
 }
@@ -4,7 +4,9 @@ mod backend;
 mod device;
 mod fd_manager;
 mod fs_stat;
+mod pidfd;
 mod pipe;
+mod signalfd;
 pub mod socket;
 mod stdio;
 mod vfs;
@@ -13,19 +15,29 @@ pub use fatfs::SeekFrom;
 
 pub use device::{
     add_sys_info,
+    can_open_virt_file,
     check_dir_exists,
     check_file_exists,
+    fifo_names_in_dir,
     fs_init,
     get_dir_entry_iter,
+    get_dir_perm,
+    get_fifo_stat,
+    get_file_perm,
+    link_fifo,
     list_files_at_root,
     //load_testcases,
     load_next_testcase,
+    make_fifo,
     mkdir,
     mount_fat_fs,
+    open_fifo,
     open_file,
     origin_fs_stat,
     read_link,
     rename_or_move,
+    set_fifo_times,
+    set_file_perm,
     show_testcase_result,
     try_add_link,
     try_remove_link,
@@ -33,12 +45,14 @@ pub use device::{
 };
 
 pub use backend::{BackEndFile, SyncPolicy};
-pub use device::{FatFile, FileDisc};
+pub use device::{FatFile, FileDisc, FilePerm};
 pub use fd_manager::FdManager;
 pub use fs_stat::FsStat;
-pub use pipe::{Pipe, RingBuffer};
+pub use pidfd::PidFd;
+pub use pipe::{fifo_stat, Fifo, Pipe, RingBuffer};
+pub use signalfd::SignalFd;
 pub use socket::Socket;
 pub use vfs::{
-    check_virt_dir_exists, check_virt_file_exists, get_virt_dir_if_possible,
+    can_access_virt_file, check_virt_dir_exists, check_virt_file_exists, get_virt_dir_if_possible,
     get_virt_file_if_possible, try_make_virt_dir, try_remove_virt_file, BufferFile,
 };
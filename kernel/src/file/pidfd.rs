@@ -0,0 +1,67 @@
+//! pidfd，即指向一个进程的文件描述符
+//!
+//! 只通过 Weak 指针引用进程的 TCB，所以持有 pidfd 不会阻止进程被回收
+
+use alloc::sync::{Arc, Weak};
+use base_file::{File, OpenFlags};
+use lock::Mutex;
+
+use crate::task::TaskControlBlock;
+
+/// 指向一个进程的文件
+pub struct PidFd {
+    /// 对应的进程，即线程组的组长线程
+    task: Weak<TaskControlBlock>,
+    /// 文件状态。新建的 pidfd 总是带有 CLOEXEC
+    flags: Mutex<OpenFlags>,
+}
+
+impl PidFd {
+    /// 新建一个指向 task 所在进程的 pidfd。nonblock 指定是否带有 O_NONBLOCK
+    pub fn new(task: &Arc<TaskControlBlock>, nonblock: bool) -> Self {
+        let mut flags = OpenFlags::RDWR | OpenFlags::CLOEXEC;
+        flags.set(OpenFlags::NON_BLOCK, nonblock);
+        Self {
+            task: Arc::downgrade(task),
+            flags: Mutex::new(flags),
+        }
+    }
+    /// 获取对应进程的 TCB。如果进程已经被回收，则返回 None
+    pub fn get_task(&self) -> Option<Arc<TaskControlBlock>> {
+        self.task.upgrade()
+    }
+}
+
+impl File for PidFd {
+    /// pidfd 不能直接读
+    fn read(&self, _buf: &mut [u8]) -> Option<usize> {
+        None
+    }
+    /// pidfd 不能直接写
+    fn write(&self, _buf: &[u8]) -> Option<usize> {
+        None
+    }
+    /// 进程的所有线程都已退出时可读，poll / epoll 用它等待进程退出
+    fn ready_to_read(&self) -> bool {
+        self.task
+            .upgrade()
+            .map_or(true, |task| task.thread_group.lock().is_empty())
+    }
+    /// pidfd 不能写
+    fn ready_to_write(&self) -> bool {
+        false
+    }
+    /// 进程已经被回收
+    fn is_hang_up(&self) -> bool {
+        self.task.upgrade().is_none()
+    }
+    /// 获取文件状态信息
+    fn get_status(&self) -> OpenFlags {
+        *self.flags.lock()
+    }
+    /// 设置状态信息的 CLOEXEC 位
+    fn set_close_on_exec(&self, is_set: bool) -> bool {
+        self.flags.lock().set(OpenFlags::CLOEXEC, is_set);
+        true
+    }
+}
@@ -3,12 +3,25 @@
 //! 相当于两个文件，其中一个只读，一个只可写，但指向同一片内存。
 //! Pipe 的读写可能会触发进程切换。
 //! 目前的实现中，Pipe会请求并获取页帧，不占用内核堆/栈
+//!
+//! 读写的语义与 Linux 相同：
+//! - 没有数据时，读会阻塞直到有数据或者所有写端都关闭(此时返回 0)
+//! - 所有读端都关闭后，写会向当前线程发送 SIGPIPE 并返回 EPIPE
+//! - 不超过 PIPE_BUF 的写入是原子的；更长的写入会阻塞直到全部写完
+//! - 带有 O_NONBLOCK 时，需要阻塞的读写返回 EAGAIN
+//!
+//! 命名管道(FIFO)也使用同一套实现，每次通过路径打开时在同一个管道上新建一端
 
 use super::BufferFile;
-use crate::{constants::PIPE_SIZE_LIMIT, task::suspend_current_task};
-use alloc::sync::Arc;
-use base_file::{File, OpenFlags};
+use crate::{
+    constants::{PAGE_SIZE, PIPE_BUF, PIPE_SIZE_LIMIT, PIPE_SIZE_MAX},
+    signal::{send_signal, SignalNo},
+    task::{get_current_task, signal_pending, suspend_current_task},
+};
+use alloc::{collections::VecDeque, sync::Arc, vec};
+use base_file::{normal_file_mode, File, Kstat, OpenFlags, StMode};
 use lock::Mutex;
+use syscall::ErrorNo;
 
 /// 管道内部的 buffer，是个循环队列
 pub struct RingBuffer {
@@ -83,11 +96,35 @@ impl RingBuffer {
         }
         max_len
     }
+    /// 丢弃队头的 len 字节数据
+    pub fn discard(&mut self, len: usize) {
+        let len = len.min(self.len);
+        self.len -= len;
+        self.head = (self.head + len) % self.size_limit;
+    }
+    /// 修改队列的容量，保留其中的数据。如果数据比新的容量更长，则不修改，返回 false
+    pub fn resize(&mut self, size_limit: usize) -> bool {
+        if self.len > size_limit {
+            return false;
+        }
+        let mut data = vec![0u8; self.len];
+        self.read(&mut data);
+        *self = Self::new(size_limit);
+        self.write(&data);
+        true
+    }
     /// 获取循环队列目前的存的数据长度
-    #[allow(dead_code)]
     pub fn get_len(&self) -> usize {
         self.len
     }
+    /// 获取循环队列的容量
+    pub fn get_size_limit(&self) -> usize {
+        self.size_limit
+    }
+    /// 获取循环队列还能写入的长度
+    pub fn get_free_len(&self) -> usize {
+        self.size_limit - self.len
+    }
     /// 是否 buffer 已满
     pub fn is_full(&self) -> bool {
         self.len == self.size_limit
@@ -98,118 +135,384 @@ impl RingBuffer {
     }
 }
 
-/// 管道本体，每次创建两份，一个是读端，一个是写端
-pub struct Pipe {
-    /// 标记是否是读的一端
-    is_read: bool,
+/// 管道两端共享的数据
+struct PipeBuffer {
     /// 管道内保存的数据
+    buf: RingBuffer,
+    /// 读端的数量。同一个 Pipe 被 dup 或 fork 复制时只算一个
+    readers: usize,
+    /// 写端的数量
+    writers: usize,
+    /// 读端被打开过的次数。打开 FIFO 时据此判断在等待期间是否有另一端打开过
+    read_opens: usize,
+    /// 写端被打开过的次数
+    write_opens: usize,
+    /// buf 中每个包的长度。非包模式下写入的数据会并入最后一个包
+    packets: VecDeque<usize>,
+}
+
+impl PipeBuffer {
+    /// 新建一个空的管道
+    fn new() -> Self {
+        Self {
+            buf: RingBuffer::new(PIPE_SIZE_LIMIT),
+            readers: 0,
+            writers: 0,
+            read_opens: 0,
+            write_opens: 0,
+            packets: VecDeque::new(),
+        }
+    }
+    /// 读数据。如果队头是一个包，则最多读出这个包，包中放不进 buf 的部分被丢弃
+    fn read(&mut self, buf: &mut [u8]) -> usize {
+        match self.packets.pop_front() {
+            Some(packet_len) => {
+                let max_len = packet_len.min(buf.len());
+                let read_len = self.buf.read(&mut buf[..max_len]);
+                self.buf.discard(packet_len - read_len);
+                read_len
+            }
+            None => self.buf.read(buf),
+        }
+    }
+    /// 写入尽可能多的数据。packet_mode 表示写端是否处于包模式，此时每 PIPE_BUF 字节作为一个包
+    fn write(&mut self, buf: &[u8], packet_mode: bool) -> usize {
+        if !packet_mode {
+            let write_len = self.buf.write(buf);
+            if let Some(last) = self.packets.back_mut() {
+                *last += write_len;
+            }
+            return write_len;
+        }
+        // 第一次以包模式写入时，之前写入的数据作为一个包
+        if self.packets.is_empty() && !self.buf.is_empty() {
+            self.packets.push_back(self.buf.get_len());
+        }
+        let mut write_len = 0;
+        for packet in buf.chunks(PIPE_BUF) {
+            let packet_len = self.buf.write(packet);
+            if packet_len == 0 {
+                break;
+            }
+            self.packets.push_back(packet_len);
+            write_len += packet_len;
+        }
+        write_len
+    }
+}
+
+/// 管道本体，每次创建两份，一个是读端，一个是写端。
+/// 以 O_RDWR 打开的 FIFO 同时是读端和写端
+pub struct Pipe {
+    /// 是否可读，即是否是读的一端
+    readable: bool,
+    /// 是否可写，即是否是写的一端
+    writable: bool,
+    /// 打开这一端时，另一端被打开过的次数
+    peer_opens: usize,
+    /// 两端共享的数据
     /// 只有所有持有管道的 Arc 被 Drop 时，才会释放其中的 PipeBuffer 的空间
-    data: Arc<Mutex<RingBuffer>>,
+    data: Arc<Mutex<PipeBuffer>>,
+    /// 这一端的文件状态。写端带有 O_DIRECT 时以包为单位写入
+    flags: Mutex<OpenFlags>,
 }
 
 impl Pipe {
-    /// 新建一个管道，返回两端
-    pub fn new_pipe() -> (Self, Self) {
-        let buf = Arc::new(Mutex::new(RingBuffer::new(PIPE_SIZE_LIMIT)));
+    /// 新建一个管道，返回两端。flags 中可以包含 O_CLOEXEC / O_NONBLOCK / O_DIRECT
+    pub fn new_pipe(flags: OpenFlags) -> (Self, Self) {
+        let data = Arc::new(Mutex::new(PipeBuffer::new()));
         (
-            Self {
-                is_read: true,
-                data: buf.clone(),
-            },
-            Self {
-                is_read: false,
-                data: buf,
-            },
+            Self::new_end(&data, flags),
+            Self::new_end(&data, flags | OpenFlags::WRONLY),
         )
     }
-}
-
-impl File for Pipe {
-    /// 读管道中数据
-    fn read(&self, buf: &mut [u8]) -> Option<usize> {
-        if self.is_read {
-            let mut read_len = 0;
-            // 先读一次，如果一次完成就不用切换进程了
-            read_len += self.data.lock().read(&mut buf[read_len..]);
-            info!("read pipe len {}, require {}", read_len, buf.len());
-            //let tid = crate::task::get_current_task().unwrap().get_tid_num();
-            //if buf.len() != 4 { println!("tid {} read pipe len {}, require {}", tid, read_len, buf.len()); }
-            // if buf.len() != read_len { println!("tid {} read {} got {}", tid, buf.len(), read_len); }
-
-            // 如果读够了或者写端的 fd 已经被释放了，则退出
-            // 注意这里 self.data 的引用一定是自己持有一个，写端持有一个
-            // 就算 fd 被复制，也只是复制 Pipe 外包着的 Arc，内部 self.data 的 Arc 不会复制
-            let mut cnt = 0;
-            while read_len < buf.len() && Arc::strong_count(&self.data) == 2 {
-                cnt += 1;
-                if cnt > 2 {
-                    break;
-                }
-                suspend_current_task();
-                read_len += self.data.lock().read(&mut buf[read_len..]);
-            }
-            //if buf.len() != read_len { println!("tid {} read {} final got {}", tid, buf.len(), read_len); }
-            //else { println!("tid {} read {} final got {}", tid, buf.len(), read_len); }
-            Some(read_len)
+    /// 在管道 data 上新建一端，读写权限由 flags 决定
+    fn new_end(data: &Arc<Mutex<PipeBuffer>>, flags: OpenFlags) -> Self {
+        let (readable, writable) = (flags.readable(), flags.writable());
+        let mut inner = data.lock();
+        let peer_opens = if readable {
+            inner.write_opens
         } else {
-            None
+            inner.read_opens
+        };
+        if readable {
+            inner.readers += 1;
+            inner.read_opens += 1;
+        }
+        if writable {
+            inner.writers += 1;
+            inner.write_opens += 1;
+        }
+        drop(inner);
+        Self {
+            readable,
+            writable,
+            peer_opens,
+            data: data.clone(),
+            flags: Mutex::new(flags),
         }
     }
-    /// 写入管道
-    fn write(&self, buf: &[u8]) -> Option<usize> {
-        if self.is_read {
-            None
+    /// 打开 FIFO 时，等待另一端也被打开。
+    ///
+    /// 同时可读写的一端不需要等待。只读的一端带有 O_NONBLOCK 时也不等待；
+    /// 只写的一端带有 O_NONBLOCK 时，如果没有读端则返回 ENXIO。
+    /// 阻塞等待时被信号打断返回 ERESTARTSYS
+    pub fn wait_for_peer(&self) -> Result<(), ErrorNo> {
+        if self.readable && self.writable {
+            return Ok(());
+        }
+        loop {
+            let data = self.data.lock();
+            let peer_opened = if self.readable {
+                data.writers > 0 || data.write_opens != self.peer_opens
+            } else {
+                data.readers > 0 || data.read_opens != self.peer_opens
+            };
+            drop(data);
+            if peer_opened {
+                return Ok(());
+            }
+            if self.is_nonblock() {
+                return if self.readable {
+                    Ok(())
+                } else {
+                    Err(ErrorNo::ENXIO)
+                };
+            }
+            if signal_pending() {
+                return Err(ErrorNo::ERESTARTSYS);
+            }
+            suspend_current_task();
+        }
+    }
+    /// 是否带有 O_NONBLOCK
+    fn is_nonblock(&self) -> bool {
+        self.flags.lock().contains(OpenFlags::NON_BLOCK)
+    }
+    /// 获取管道的大小，即 F_GETPIPE_SZ
+    pub fn get_size(&self) -> usize {
+        self.data.lock().buf.get_size_limit()
+    }
+    /// 修改管道的大小，即 F_SETPIPE_SZ。size 会被向上取整到 2 的幂个页，返回实际的大小。
+    ///
+    /// 和 Linux 一样，参数只取低 32 位，超过 2^31 时返回 EINVAL；
+    /// 非 root 用户设置超过 PIPE_SIZE_MAX 的大小时返回 EPERM；管道中已有的数据放不下时返回 EBUSY
+    pub fn set_size(&self, size: usize) -> Result<usize, ErrorNo> {
+        let size = size as u32 as usize;
+        if size > 1 << 31 {
+            return Err(ErrorNo::EINVAL);
+        }
+        let size = size.max(PAGE_SIZE).next_power_of_two();
+        if size > PIPE_SIZE_MAX
+            && !get_current_task()
+                .unwrap()
+                .credentials
+                .lock()
+                .is_privileged()
+        {
+            return Err(ErrorNo::EPERM);
+        }
+        if self.data.lock().buf.resize(size) {
+            Ok(size)
         } else {
-            let mut write_len = 0;
-            // 同上，如果一次完成就不用切换进程了
-            write_len += self.data.lock().write(&buf[write_len..]);
-            /*
-            unsafe {
-                static mut TIMES: usize = 0;
-                TIMES += 1;
-                if TIMES % 1000 == 0 {
-                    println!("write pipe {} times", TIMES);
-                } else if write_len > 4 {
-                    println!("write end {} times", TIMES);
-                }
-            }
-            */
-            info!("write pipe len {}", write_len);
+            Err(ErrorNo::EBUSY)
+        }
+    }
+}
 
-            //let tid = crate::task::get_current_task().unwrap().get_tid_num();
-            //if buf.len() != 4 { println!("tid {} write pipe len {}, require {}", tid, write_len, buf.len()); }
-            //if buf.len() != write_len { println!("tid {} write {} got {}", tid, buf.len(), write_len); }
-            //else { println!("tid {} write {} got {}", tid, buf.len(), write_len); }
+impl Drop for Pipe {
+    fn drop(&mut self) {
+        let mut data = self.data.lock();
+        if self.readable {
+            data.readers -= 1;
+        }
+        if self.writable {
+            data.writers -= 1;
+        }
+        // FIFO 的两端都关闭后，其中剩余的数据被丢弃
+        if data.readers == 0 && data.writers == 0 {
+            data.buf = RingBuffer::new(PIPE_SIZE_LIMIT);
+            data.packets.clear();
+        }
+    }
+}
 
-            // 同上，参见 read 函数
-            let mut cnt = 0;
-            while write_len < buf.len() && Arc::strong_count(&self.data) == 2 {
-                cnt += 1;
-                if cnt > 2 {
-                    break;
-                }
-                suspend_current_task();
-                write_len += self.data.lock().write(&buf[write_len..]);
-            }
-            //if buf.len() != write_len { println!("tid {} write {} final got {}", tid, buf.len(), write_len); }
-            //else { println!("tid {} write {} final got {}", tid, buf.len(), write_len); }
-            Some(write_len)
-        }
-    }
-    /// 已准备好读。对于 pipe 来说，这意味着读端的buffer内有值
+impl File for Pipe {
+    /// 读管道中数据。出错时的错误码见 try_read
+    fn read(&self, buf: &mut [u8]) -> Option<usize> {
+        self.try_read(buf).ok()
+    }
+    /// 写入管道。出错时的错误码见 try_write
+    fn write(&self, buf: &[u8]) -> Option<usize> {
+        self.try_write(buf).ok()
+    }
+    /// 读出当前已有的数据。
+    ///
+    /// 管道为空时，如果所有写端都已关闭则返回 0，带有 O_NONBLOCK 时返回 EAGAIN，
+    /// 否则阻塞直到有数据，被信号打断时返回 ERESTARTSYS
+    fn try_read(&self, buf: &mut [u8]) -> Result<usize, ErrorNo> {
+        if !self.readable {
+            return Err(ErrorNo::EBADF);
+        }
+        if buf.is_empty() {
+            return Ok(0);
+        }
+        loop {
+            let mut data = self.data.lock();
+            if !data.buf.is_empty() {
+                let read_len = data.read(buf);
+                info!("read pipe len {}, require {}", read_len, buf.len());
+                return Ok(read_len);
+            }
+            if data.writers == 0 {
+                return Ok(0);
+            }
+            drop(data);
+            if self.is_nonblock() {
+                return Err(ErrorNo::EAGAIN);
+            }
+            if signal_pending() {
+                return Err(ErrorNo::ERESTARTSYS);
+            }
+            suspend_current_task();
+        }
+    }
+    /// 写入 buf 中的全部数据。
+    ///
+    /// 不超过 PIPE_BUF 的写入只会在管道有足够的空间时一次写入。
+    /// 所有读端都已关闭时，向当前线程发送 SIGPIPE 并返回 EPIPE。
+    /// 需要阻塞时，如果带有 O_NONBLOCK 则返回已写入的长度，一点都没写入时返回 EAGAIN；
+    /// 否则阻塞直到全部写完，被信号打断时返回已写入的长度，一点都没写入时返回 ERESTARTSYS
+    fn try_write(&self, buf: &[u8]) -> Result<usize, ErrorNo> {
+        if !self.writable {
+            return Err(ErrorNo::EBADF);
+        }
+        if buf.is_empty() {
+            return Ok(0);
+        }
+        let atomic = buf.len() <= PIPE_BUF;
+        let packet_mode = self.flags.lock().contains(OpenFlags::DIRECT);
+        let mut write_len = 0;
+        loop {
+            let mut data = self.data.lock();
+            if data.readers == 0 {
+                drop(data);
+                let tid = get_current_task().unwrap().get_tid_num();
+                send_signal(tid, SignalNo::SIGPIPE as usize);
+                return if write_len > 0 {
+                    Ok(write_len)
+                } else {
+                    Err(ErrorNo::EPIPE)
+                };
+            }
+            if !atomic || data.buf.get_free_len() >= buf.len() {
+                write_len += data.write(&buf[write_len..], packet_mode);
+            }
+            drop(data);
+            info!("write pipe len {}, require {}", write_len, buf.len());
+            if write_len == buf.len() {
+                return Ok(write_len);
+            }
+            if self.is_nonblock() {
+                return if write_len > 0 {
+                    Ok(write_len)
+                } else {
+                    Err(ErrorNo::EAGAIN)
+                };
+            }
+            if signal_pending() {
+                return if write_len > 0 {
+                    Ok(write_len)
+                } else {
+                    Err(ErrorNo::ERESTARTSYS)
+                };
+            }
+            suspend_current_task();
+        }
+    }
+    /// 已准备好读。对于 pipe 来说，这意味着读端的buffer内有值，或者写端都已关闭
     fn ready_to_read(&self) -> bool {
-        self.is_read && !self.data.lock().is_empty()
+        if !self.readable {
+            return false;
+        }
+        let data = self.data.lock();
+        !data.buf.is_empty() || data.writers == 0
     }
-    /// 已准备好写。对于 pipe 来说，这意味着写端的buffer未满
+    /// 已准备好写。对于 pipe 来说，这意味着写端的buffer未满，或者读端都已关闭
     fn ready_to_write(&self) -> bool {
-        !self.is_read && !self.data.lock().is_full()
+        if !self.writable {
+            return false;
+        }
+        let data = self.data.lock();
+        !data.buf.is_full() || data.readers == 0
     }
     /// 是否已经终止。对于 pipe 来说，这意味着另一端已关闭
     fn is_hang_up(&self) -> bool {
-        if self.is_read {
-            self.data.lock().is_empty() && Arc::strong_count(&self.data) < 2
+        let data = self.data.lock();
+        if self.readable {
+            data.buf.is_empty() && data.writers == 0
         } else {
-            Arc::strong_count(&self.data) < 2
+            data.readers == 0
         }
     }
+    /// 获取文件信息。管道没有对应的文件，所以只填写类型和权限
+    fn get_stat(&self, stat: *mut Kstat) -> bool {
+        fifo_stat(stat);
+        true
+    }
+    /// 获取文件状态信息
+    fn get_status(&self) -> OpenFlags {
+        *self.flags.lock()
+    }
+    /// 设置文件状态信息，只能修改 O_NONBLOCK 和 O_DIRECT。只影响这一端
+    fn set_status(&self, flags: OpenFlags) -> bool {
+        let mut old_flags = self.flags.lock();
+        old_flags.set(OpenFlags::NON_BLOCK, flags.contains(OpenFlags::NON_BLOCK));
+        old_flags.set(OpenFlags::DIRECT, flags.contains(OpenFlags::DIRECT));
+        true
+    }
+    /// 设置状态信息的 CLOEXEC 位
+    fn set_close_on_exec(&self, is_set: bool) -> bool {
+        self.flags.lock().set(OpenFlags::CLOEXEC, is_set);
+        true
+    }
+}
+
+/// 填写管道或 FIFO 的文件信息，除类型为 S_IFIFO 外其他项都为 0
+pub fn fifo_stat(stat: *mut Kstat) {
+    unsafe {
+        (*stat).st_dev = 0;
+        (*stat).st_ino = 0;
+        (*stat).st_nlink = 1;
+        (*stat).st_mode = normal_file_mode(StMode::S_IFIFO).bits();
+        (*stat).st_size = 0;
+        (*stat).st_uid = 0;
+        (*stat).st_gid = 0;
+        (*stat).st_atime_sec = 0;
+        (*stat).st_atime_nsec = 0;
+        (*stat).st_mtime_sec = 0;
+        (*stat).st_mtime_nsec = 0;
+        (*stat).st_ctime_sec = 0;
+        (*stat).st_ctime_nsec = 0;
+    }
+}
+
+/// 命名管道(FIFO)。它本身不是文件，每次通过路径打开时在 data 上新建一端
+pub struct Fifo {
+    /// 所有打开的端共享的数据
+    data: Arc<Mutex<PipeBuffer>>,
+}
+
+impl Fifo {
+    /// 新建一个 FIFO
+    pub fn new() -> Self {
+        Self {
+            data: Arc::new(Mutex::new(PipeBuffer::new())),
+        }
+    }
+    /// 打开 FIFO 的一端，读写权限由 flags 决定。
+    /// 返回后调用者还需要在不持有锁的情况下调用 Pipe::wait_for_peer 等待另一端
+    pub fn open(&self, flags: OpenFlags) -> Pipe {
+        Pipe::new_end(&self.data, flags)
+    }
 }
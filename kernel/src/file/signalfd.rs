@@ -0,0 +1,209 @@
+//! signalfd，即通过读文件的方式同步地接收信号
+//!
+//! 文件本身只记录要接收的信号集合。读的时候从当前线程(以及所在进程)的待处理信号中取出信号，
+//! 所以在不同线程中读同一个 signalfd，得到的是各自收到的信号
+
+use base_file::{File, OpenFlags};
+use bitset::Bitset;
+use core::mem::size_of;
+use lock::Mutex;
+use syscall::ErrorNo;
+
+use crate::{
+    signal::{SigInfo, SignalNo},
+    task::{get_current_task, signal_pending, suspend_current_task},
+};
+
+/// 读 signalfd 时返回的信息，内存布局与 Linux 的 struct signalfd_siginfo 相同，共 128 Byte
+#[repr(C)]
+#[derive(Clone, Copy)]
+pub struct SignalFdSigInfo {
+    pub ssi_signo: u32,
+    pub ssi_errno: i32,
+    pub ssi_code: i32,
+    pub ssi_pid: u32,
+    pub ssi_uid: u32,
+    pub ssi_fd: i32,
+    pub ssi_tid: u32,
+    pub ssi_band: u32,
+    pub ssi_overrun: u32,
+    pub ssi_trapno: u32,
+    pub ssi_status: i32,
+    pub ssi_int: i32,
+    pub ssi_ptr: u64,
+    pub ssi_utime: u64,
+    pub ssi_stime: u64,
+    pub ssi_addr: u64,
+    pub ssi_addr_lsb: u16,
+    _pad2: u16,
+    pub ssi_syscall: i32,
+    pub ssi_call_addr: u64,
+    pub ssi_arch: u32,
+    _pad: [u8; 28],
+}
+
+impl From<&SigInfo> for SignalFdSigInfo {
+    /// 根据信号的来源，取出 siginfo_t 的 union 中有效的字段
+    fn from(info: &SigInfo) -> Self {
+        let mut ssi = Self {
+            ssi_signo: info.si_signo as u32,
+            ssi_errno: info.si_errno,
+            ssi_code: info.si_code,
+            ssi_pid: 0,
+            ssi_uid: 0,
+            ssi_fd: 0,
+            ssi_tid: 0,
+            ssi_band: 0,
+            ssi_overrun: 0,
+            ssi_trapno: 0,
+            ssi_status: 0,
+            ssi_int: 0,
+            ssi_ptr: 0,
+            ssi_utime: 0,
+            ssi_stime: 0,
+            ssi_addr: 0,
+            ssi_addr_lsb: 0,
+            _pad2: 0,
+            ssi_syscall: 0,
+            ssi_call_addr: 0,
+            ssi_arch: 0,
+            _pad: [0; 28],
+        };
+        let signal = SignalNo::from(info.si_signo as usize);
+        if info.is_fault() {
+            // 由异常产生的信号
+            ssi.ssi_addr = info.addr() as u64;
+            return ssi;
+        }
+        let (pid, uid) = info.sender();
+        ssi.ssi_pid = pid;
+        ssi.ssi_uid = uid;
+        if signal == SignalNo::SIGCHLD {
+            let (utime, stime) = info.child_times();
+            ssi.ssi_status = info.value() as i32;
+            ssi.ssi_utime = utime as u64;
+            ssi.ssi_stime = stime as u64;
+        } else {
+            ssi.ssi_int = info.value() as i32;
+            ssi.ssi_ptr = info.value() as u64;
+        }
+        ssi
+    }
+}
+
+/// SIGKILL 和 SIGSTOP 不能通过 signalfd 接收，和 Linux 一样直接从 mask 中去掉
+fn remove_unblockable(mask: &mut Bitset) {
+    mask.remove_bit(SignalNo::SIGKILL as usize - 1);
+    mask.remove_bit(SignalNo::SIGSTOP as usize - 1);
+}
+
+/// 接收信号的文件
+pub struct SignalFd {
+    /// 要接收的信号
+    mask: Mutex<Bitset>,
+    /// 文件状态
+    flags: Mutex<OpenFlags>,
+}
+
+impl SignalFd {
+    /// 新建一个接收 mask 中信号的 signalfd
+    pub fn new(mut mask: Bitset, nonblock: bool, cloexec: bool) -> Self {
+        remove_unblockable(&mut mask);
+        let mut flags = OpenFlags::RDONLY;
+        flags.set(OpenFlags::NON_BLOCK, nonblock);
+        flags.set(OpenFlags::CLOEXEC, cloexec);
+        Self {
+            mask: Mutex::new(mask),
+            flags: Mutex::new(flags),
+        }
+    }
+    /// 修改要接收的信号
+    pub fn set_mask(&self, mut mask: Bitset) {
+        remove_unblockable(&mut mask);
+        *self.mask.lock() = mask;
+    }
+    /// 从当前线程取出一个要接收的信号
+    fn dequeue(&self) -> Option<SigInfo> {
+        let mask = *self.mask.lock();
+        get_current_task()
+            .unwrap()
+            .signal_receivers
+            .lock()
+            .dequeue_from(mask)
+    }
+}
+
+impl File for SignalFd {
+    /// 读出信息。出错时的错误码见 try_read
+    fn read(&self, buf: &mut [u8]) -> Option<usize> {
+        self.try_read(buf).ok()
+    }
+    /// 取出尽可能多的信号，每个信号写入一个 SignalFdSigInfo。
+    ///
+    /// buf 放不下一个 SignalFdSigInfo 时返回 EINVAL；没有信号时，如果带有 O_NONBLOCK 则返回 EAGAIN，
+    /// 否则阻塞直到收到信号，被其他信号打断时返回 ERESTARTSYS
+    fn try_read(&self, buf: &mut [u8]) -> Result<usize, ErrorNo> {
+        let record_size = size_of::<SignalFdSigInfo>();
+        if buf.len() < record_size {
+            return Err(ErrorNo::EINVAL);
+        }
+        let first = loop {
+            if let Some(info) = self.dequeue() {
+                break info;
+            }
+            if self.flags.lock().contains(OpenFlags::NON_BLOCK) {
+                return Err(ErrorNo::EAGAIN);
+            }
+            if signal_pending() {
+                return Err(ErrorNo::ERESTARTSYS);
+            }
+            suspend_current_task();
+        };
+        let mut info = Some(first);
+        let mut read_len = 0;
+        while let Some(siginfo) = info {
+            let ssi = SignalFdSigInfo::from(&siginfo);
+            unsafe {
+                (buf.as_mut_ptr().add(read_len) as *mut SignalFdSigInfo).write_unaligned(ssi);
+            }
+            read_len += record_size;
+            info = if read_len + record_size <= buf.len() {
+                self.dequeue()
+            } else {
+                None
+            };
+        }
+        Ok(read_len)
+    }
+    /// signalfd 不能写
+    fn write(&self, _buf: &[u8]) -> Option<usize> {
+        None
+    }
+    /// 当前线程有要接收的信号时可读
+    fn ready_to_read(&self) -> bool {
+        let mask = *self.mask.lock();
+        get_current_task().map_or(false, |task| {
+            task.signal_receivers.lock().all_pending().0 & mask.0 != 0
+        })
+    }
+    /// signalfd 不能写
+    fn ready_to_write(&self) -> bool {
+        false
+    }
+    /// 获取文件状态信息
+    fn get_status(&self) -> OpenFlags {
+        *self.flags.lock()
+    }
+    /// 设置文件状态信息，只能修改 O_NONBLOCK
+    fn set_status(&self, flags: OpenFlags) -> bool {
+        self.flags
+            .lock()
+            .set(OpenFlags::NON_BLOCK, flags.contains(OpenFlags::NON_BLOCK));
+        true
+    }
+    /// 设置状态信息的 CLOEXEC 位
+    fn set_close_on_exec(&self, is_set: bool) -> bool {
+        self.flags.lock().set(OpenFlags::CLOEXEC, is_set);
+        true
+    }
+}
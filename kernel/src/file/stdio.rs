@@ -6,8 +6,9 @@
 //#![deny(missing_docs)]
 
 use crate::arch::stdin::getchar;
-use crate::task::suspend_current_task;
+use crate::task::{signal_pending, suspend_current_task};
 use base_file::{normal_file_mode, File, Kstat, StMode};
+use syscall::ErrorNo;
 
 /// 标准输入流
 pub struct Stdin;
@@ -17,22 +18,30 @@ pub struct Stdout;
 pub struct Stderr;
 
 impl File for Stdin {
-    /// 目前 Stdin 只支持读一个字符
+    /// 目前 Stdin 只支持读一个字符。出错时的错误码见 try_read
     fn read(&self, buf: &mut [u8]) -> Option<usize> {
+        self.try_read(buf).ok()
+    }
+    /// 读一个字符。等待输入时被信号打断则返回 ERESTARTSYS
+    fn try_read(&self, buf: &mut [u8]) -> Result<usize, ErrorNo> {
         if buf.len() == 0 {
-            return Some(0);
+            return Ok(0);
         }
         buf[0] = loop {
             // 目前调用 sys_read 会导致当前进程阻塞在用户输入上
             let c = getchar();
             if c == 0 || c == 255 {
+                // 等待输入时被信号打断
+                if signal_pending() {
+                    return Err(ErrorNo::ERESTARTSYS);
+                }
                 suspend_current_task(); //yield cpu
                 continue;
             } else {
                 break c;
             }
         };
-        Some(1)
+        Ok(1)
     }
     /// Stdin 不可写
     fn write(&self, _buf: &[u8]) -> Option<usize> {
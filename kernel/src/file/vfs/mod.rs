@@ -3,6 +3,7 @@
 
 mod null;
 mod temp;
+mod trace_targets;
 mod virt_dir;
 mod virt_file;
 mod zero;
@@ -14,12 +15,17 @@ use lock::Mutex;
 // 所以方便起见就不用 HashMap 了
 use alloc::collections::BTreeMap;
 use base_file::{File, OpenFlags};
+use fatfs::SeekFrom;
 use null::NullFile;
+use trace_targets::TraceTargetsFile;
 use virt_dir::VirtDir;
 use virt_file::{VirtFile, VirtFileInner};
 pub type BufferFile = VirtFileInner;
 use zero::ZeroFile;
 
+use crate::syscall::syscall_trace_report;
+use crate::task::{get_all_tasks, get_current_task};
+
 lazy_static::lazy_static! {
     /// 属于虚拟文件系统的目录
     static ref VFS_DIRS: Mutex<BTreeMap<String, Arc<VirtDir>>> = Mutex::new({
@@ -48,6 +54,9 @@ pub fn get_virt_file_if_possible(
     file: &String,
     flags: OpenFlags,
 ) -> Option<Arc<dyn File>> {
+    if let Some(proc_file) = get_proc_file_if_possible(dir, file) {
+        return Some(proc_file);
+    }
     match VFS_DIRS
         .lock()
         .get(dir.strip_prefix("./")?.strip_suffix("/")?)
@@ -86,6 +95,9 @@ pub fn try_make_virt_dir(dir: &VirtDir, new_dir_name: &String) -> bool {
 /// 检查是否存在对应文件。Some表示路径存在，true/false表示文件是否存在
 pub fn check_virt_file_exists(dir: &String, file_name: &String) -> Option<bool> {
     // 这里套了 option 是为了方便用问号
+    if get_proc_file_if_possible(dir, file_name).is_some() {
+        return Some(true);
+    }
     Some(
         VFS_DIRS
             .lock()
@@ -119,3 +131,58 @@ pub fn check_virt_dir_exists(dir: &String) -> Option<bool> {
             .is_some(),
     )
 }
+
+/// 如果是 `/proc/<pid>/syscalls`，返回其中的 pid
+fn proc_syscalls_pid(dir: &String, file: &String) -> Option<usize> {
+    if file != "syscalls" {
+        return None;
+    }
+    dir.strip_prefix("./proc/")?
+        .strip_suffix("/")?
+        .parse::<usize>()
+        .ok()
+}
+
+/// 检查当前进程能否读取 vfs 中的文件。
+///
+/// 目前只有 `/proc/<pid>/syscalls` 需要检查：其中的记录包含对方系统调用的参数，
+/// 所以和 ptrace 一样，要求当前进程可以访问对方的内部状态
+pub fn can_access_virt_file(dir: &String, file: &String) -> bool {
+    let pid = match proc_syscalls_pid(dir, file) {
+        Some(pid) => pid,
+        None => return true,
+    };
+    let (task, target) = match (
+        get_current_task(),
+        get_all_tasks().into_iter().find(|task| task.pid == pid),
+    ) {
+        (Some(task), Some(target)) => (task, target),
+        // 内核自己读取，或者进程不存在(之后会返回 ENOENT)
+        _ => return true,
+    };
+    if Arc::ptr_eq(&task.credentials, &target.credentials) {
+        return true;
+    }
+    let credentials = task.credentials.lock().clone();
+    let target_credentials = target.credentials.lock().clone();
+    credentials.can_inspect(&target_credentials)
+}
+
+/// 查询是否是 `/proc/<pid>/syscalls`，是则生成一个包含该进程当前系统调用跟踪记录的快照文件。
+/// 进程不存在、没有被跟踪或者当前进程无权访问时返回 None。
+///
+/// `/proc/sys/kernel/syscall_trace` 则用于选择需要跟踪的进程
+fn get_proc_file_if_possible(dir: &String, file: &String) -> Option<Arc<dyn File>> {
+    if dir == "./proc/sys/kernel/" && file == "syscall_trace" {
+        return Some(Arc::new(TraceTargetsFile::new()));
+    }
+    let pid = proc_syscalls_pid(dir, file)?;
+    if !can_access_virt_file(dir, file) {
+        return None;
+    }
+    let content = syscall_trace_report(pid)?;
+    let proc_file = VirtFile::new(OpenFlags::RDONLY);
+    proc_file.write(content.as_bytes())?;
+    proc_file.seek(SeekFrom::Start(0))?;
+    Some(Arc::new(proc_file))
+}
@@ -0,0 +1,84 @@
+//! 用于 /proc/sys/kernel/syscall_trace，查看和修改内置系统调用跟踪选中的进程，参见 `syscall/trace.rs`
+//!
+//! 读到的是打开时的选择，每项之间以空格分隔；每次写入都会整体替换为写入的内容，只有 root 可以写
+
+use alloc::vec::Vec;
+use base_file::{File, Kstat, StMode};
+use lock::Mutex;
+use syscall::ErrorNo;
+
+use crate::{
+    syscall::{set_syscall_trace_targets, syscall_trace_targets},
+    task::get_current_task,
+};
+
+pub struct TraceTargetsFile {
+    /// 打开时的内容
+    content: Vec<u8>,
+    /// 读取的位置
+    pos: Mutex<usize>,
+}
+
+impl TraceTargetsFile {
+    pub fn new() -> Self {
+        let mut content = syscall_trace_targets();
+        content.push('\n');
+        Self {
+            content: content.into_bytes(),
+            pos: Mutex::new(0),
+        }
+    }
+}
+
+impl File for TraceTargetsFile {
+    /// 从打开时的内容中读取
+    fn read(&self, buf: &mut [u8]) -> Option<usize> {
+        let mut pos = self.pos.lock();
+        let len = buf.len().min(self.content.len() - *pos);
+        buf[..len].copy_from_slice(&self.content[*pos..*pos + len]);
+        *pos += len;
+        Some(len)
+    }
+    /// 写入新的选择
+    fn write(&self, buf: &[u8]) -> Option<usize> {
+        self.try_write(buf).ok()
+    }
+    /// 非 root 用户写入时返回 EPERM，内容不是 UTF-8 时返回 EINVAL
+    fn try_write(&self, buf: &[u8]) -> Result<usize, ErrorNo> {
+        if !get_current_task()
+            .unwrap()
+            .credentials
+            .lock()
+            .is_privileged()
+        {
+            return Err(ErrorNo::EPERM);
+        }
+        let content = core::str::from_utf8(buf).map_err(|_| ErrorNo::EINVAL)?;
+        set_syscall_trace_targets(content);
+        Ok(buf.len())
+    }
+    /// 文件属性
+    fn get_stat(&self, stat: *mut Kstat) -> bool {
+        unsafe {
+            (*stat).st_dev = 0;
+            (*stat).st_ino = 0;
+            (*stat).st_nlink = 1;
+            (*stat).st_mode = (StMode::S_IFREG
+                | StMode::S_IRUSR
+                | StMode::S_IWUSR
+                | StMode::S_IRGRP
+                | StMode::S_IROTH)
+                .bits();
+            (*stat).st_size = self.content.len() as u64;
+            (*stat).st_uid = 0;
+            (*stat).st_gid = 0;
+            (*stat).st_atime_sec = 0;
+            (*stat).st_atime_nsec = 0;
+            (*stat).st_mtime_sec = 0;
+            (*stat).st_mtime_nsec = 0;
+            (*stat).st_ctime_sec = 0;
+            (*stat).st_ctime_nsec = 0;
+        }
+        true
+    }
+}
@@ -7,7 +7,26 @@ pub const AT_PHNUM: u8 = 5;
 pub const AT_PAGESZ: u8 = 6;
 //pub const AT_BASE: u8 = 7;
 //pub const AT_ENTRY: u8 = 9;
+pub const AT_UID: u8 = 11;
+pub const AT_EUID: u8 = 12;
+pub const AT_GID: u8 = 13;
+pub const AT_EGID: u8 = 14;
+pub const AT_PLATFORM: u8 = 15;
+pub const AT_HWCAP: u8 = 16;
+pub const AT_CLKTCK: u8 = 17;
+pub const AT_SECURE: u8 = 23;
 pub const AT_RANDOM: u8 = 25;
+pub const AT_EXECFN: u8 = 31;
+
+/// AT_PLATFORM 指向的字符串
+pub const PLATFORM_STR: &str = "riscv64";
+/// AT_HWCAP 的值，每一位表示一个 ISA 扩展字母是否支持，这里是 rv64imafdc
+pub const HWCAP_IMAFDC: usize = (1 << (b'i' - b'a'))
+    | (1 << (b'm' - b'a'))
+    | (1 << (b'a' - b'a'))
+    | (1 << (b'f' - b'a'))
+    | (1 << (b'd' - b'a'))
+    | (1 << (b'c' - b'a'));
 
 pub const REL_GOT: u32 = 6;
 pub const REL_PLT: u32 = 7;
@@ -10,6 +10,7 @@ use core::ptr::null;
 
 use super::flags::*;
 use super::InitStack;
+use crate::utils::fill_random_bytes;
 
 /// 初始化信息
 #[derive(Debug)]
@@ -20,18 +21,25 @@ pub struct InitInfo {
     pub envs: Vec<String>,
     /// auxiliary
     pub auxv: BTreeMap<u8, usize>,
+    /// 用户调用 execve 时给出的文件名，由 AT_EXECFN 指向
+    pub execfn: String,
 }
 
 impl InitInfo {
     /// 将初始信息序列化到栈上
     /// 由栈底(高地址)向栈顶(低地址)依次推入
-    pub fn serialize(&self, stack_top: usize) -> InitStack {
+    ///
+    /// 同时返回实际推入的辅助参数(键值对，以 AT_NULL 结尾)，供生成 core dump 时使用
+    pub fn serialize(&self, stack_top: usize) -> (InitStack, Vec<usize>) {
         let mut writer = InitStack::new(stack_top);
         // 程序名
-        writer.push_str(&self.args[0]);
-        // "随机"串。想要真正做到随机需要硬件，但目前实现暂不影响程序运行
-        let random_str = &[3703830112808742751usize, 7081108068768079778usize];
-        writer.push_slice(random_str.as_slice());
+        let execfn_pos = writer.push_str(&self.execfn);
+        // 平台名
+        let platform_pos = writer.push_str(PLATFORM_STR);
+        // 随机串，供用户库初始化 stack canary 等使用
+        let mut random_bytes = [0u8; 16];
+        fill_random_bytes(&mut random_bytes);
+        writer.push_slice(random_bytes.as_slice());
         let random_pos = writer.sp;
         // 环境变量
         let envs: Vec<_> = self
@@ -47,13 +55,19 @@ impl InitInfo {
             .collect();
         // 辅助参数
         writer.push_slice(&[null::<u8>(), null::<u8>()]);
+        let mut auxv = Vec::new();
         for (&type_, &value) in self.auxv.iter() {
             //info!("auxv {} {:x}", type_ ,value);
-            match type_ {
-                AT_RANDOM => writer.push_slice(&[type_ as usize, random_pos]),
-                _ => writer.push_slice(&[type_ as usize, value]),
+            let value = match type_ {
+                AT_RANDOM => random_pos,
+                AT_EXECFN => execfn_pos,
+                AT_PLATFORM => platform_pos,
+                _ => value,
             };
+            writer.push_slice(&[type_ as usize, value]);
+            auxv.extend_from_slice(&[type_ as usize, value]);
         }
+        auxv.extend_from_slice(&[0, 0]);
         // 环境变量的指针数组
         writer.push_slice(&[null::<u8>()]);
         writer.push_slice(envs.as_slice());
@@ -62,6 +76,6 @@ impl InitInfo {
         writer.push_slice(argv.as_slice());
         // 参数个数
         writer.push_slice(&[argv.len()]);
-        writer
+        (writer, auxv)
     }
 }
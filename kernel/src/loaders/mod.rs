@@ -13,6 +13,7 @@ use alloc::{
 use base_file::OpenFlags;
 use core::convert::From;
 use lock::Mutex;
+use timer::INTERRUPT_PER_SEC;
 use xmas_elf::{
     header,
     program::{Flags, SegmentData, Type},
@@ -26,22 +27,35 @@ use crate::constants::{
     //LIBC_SO_FILE,
     //LIBC_SO_DIR,
     ELF_BASE_RELOCATE,
+    MAX_INTERPRETER_DEPTH,
     PAGE_SIZE,
     ROOT_DIR,
+    SHEBANG_LINE_MAX_LEN,
     USER_STACK_OFFSET,
     USER_STACK_SIZE,
 };
 use crate::error::{OSError, OSResult};
-use crate::file::open_file;
+use crate::file::{check_file_exists, open_file};
 use crate::memory::addr::{page_count, page_offset, VirtAddr};
 use crate::memory::{MemorySet, PTEFlags};
 use crate::memory::{PmArea, PmAreaLazy, VmArea};
+use crate::task::Credentials;
 use crate::utils::raw_ptr_to_ref_str;
 
 pub struct ElfLoader<'a> {
     elf: ElfFile<'a>,
 }
 
+/// 用户程序启动时，除执行参数外还需要放到用户栈上的信息
+pub struct UserEnv {
+    /// 环境变量
+    pub envs: Vec<String>,
+    /// 用户调用 execve 时给出的文件名，由 AT_EXECFN 指向
+    pub execfn: String,
+    /// 新程序的凭证，用于填写 AT_UID / AT_SECURE 等
+    pub credentials: Credentials,
+}
+
 impl From<&str> for OSError {
     fn from(s: &str) -> Self {
         warn!("parse ELF file failed: {}", s);
@@ -51,7 +65,7 @@ impl From<&str> for OSError {
 
 impl<'a> ElfLoader<'a> {
     pub fn new(elf_data: &'a [u8]) -> OSResult<Self> {
-        let elf = ElfFile::new(elf_data).unwrap();
+        let elf = ElfFile::new(elf_data)?;
         // 检查类型
         if elf.header.pt1.class() != header::Class::SixtyFour {
             return Err("32-bit ELF is not supported on the riscv64".into());
@@ -81,6 +95,7 @@ impl<'a> ElfLoader<'a> {
         &mut self,
         vm: &mut MemorySet,
         args: Vec<String>,
+        env: UserEnv,
     ) -> OSResult<(VirtAddr, VirtAddr)> {
         info!("creating MemorySet from ELF...");
         // 尝试获取 interpreter 段
@@ -99,9 +114,9 @@ impl<'a> ElfLoader<'a> {
             new_args.extend(args);
             info!("args {:#?}", new_args);
             return if let Some(pos) = path.rfind("/") {
-                parse_user_app(&path[..=pos], &path[pos + 1..], vm, new_args)
+                parse_user_app(&path[..=pos], &path[pos + 1..], vm, new_args, env)
             } else {
-                parse_user_app(ROOT_DIR, path, vm, new_args)
+                parse_user_app(ROOT_DIR, path, vm, new_args, env)
             };
         }
         //println!("args {:#?}", args);
@@ -263,19 +278,7 @@ impl<'a> ElfLoader<'a> {
                 }
                 new_args
             },
-            envs: {
-                vec![
-                    "SHLVL=1".into(),
-                    "PATH=/usr/sbin:/usr/bin:/sbin:/bin".into(),
-                    "PWD=/".into(),
-                    "GCC_EXEC_PREFIX=/riscv64-linux-musl-native/bin/../lib/gcc/".into(),
-                    "COLLECT_GCC=./riscv64-linux-musl-native/bin/riscv64-linux-musl-gcc".into(),
-                    "COLLECT_LTO_WRAPPER=/riscv64-linux-musl-native/bin/../libexec/gcc/riscv64-linux-musl/11.2.1/lto-wrapper".into(),
-                    "COLLECT_GCC_OPTIONS='-march=rv64gc' '-mabi=lp64d' '-march=rv64imafdc' '-dumpdir' 'a.'".into(),
-                    "COMPILER_PATH=/riscv64-linux-musl-native/bin/../libexec/gcc/riscv64-linux-musl/11.2.1/:/riscv64-linux-musl-native/bin/../libexec/gcc/:/riscv64-linux-musl-native/bin/../lib/gcc/riscv64-linux-musl/11.2.1/../../../../riscv64-linux-musl/bin/".into(),
-                    "LIBRARY_PATH=/riscv64-linux-musl-native/bin/../lib/gcc/riscv64-linux-musl/11.2.1/:/riscv64-linux-musl-native/bin/../lib/gcc/:/riscv64-linux-musl-native/bin/../lib/gcc/riscv64-linux-musl/11.2.1/../../../../riscv64-linux-musl/lib/:/riscv64-linux-musl-native/bin/../lib/:/riscv64-linux-musl-native/bin/../usr/lib/".into(),
-                ]
-            },
+            envs: env.envs,
             auxv: {
                 use alloc::collections::btree_map::BTreeMap;
                 let mut map = BTreeMap::new();
@@ -288,12 +291,28 @@ impl<'a> ElfLoader<'a> {
                 // AT_RANDOM 比较特殊，要求指向栈上的 16Byte 的随机子串。因此这里的 0 只是占位，在之后序列化时会特殊处理
                 map.insert(AT_RANDOM, 0);
                 map.insert(AT_PAGESZ, PAGE_SIZE);
+                map.insert(AT_HWCAP, HWCAP_IMAFDC);
+                map.insert(AT_CLKTCK, INTERRUPT_PER_SEC);
+                let credentials = &env.credentials;
+                map.insert(AT_UID, credentials.uid.real as usize);
+                map.insert(AT_EUID, credentials.uid.effective as usize);
+                map.insert(AT_GID, credentials.gid.real as usize);
+                map.insert(AT_EGID, credentials.gid.effective as usize);
+                // 通过 set-user-ID 等方式获得了额外权限时，用户库需要忽略 LD_LIBRARY_PATH 等环境变量
+                let secure = credentials.uid.real != credentials.uid.effective
+                    || credentials.gid.real != credentials.gid.effective;
+                map.insert(AT_SECURE, secure as usize);
+                // 和 AT_RANDOM 一样，这两项指向栈上的字符串，在序列化时处理
+                map.insert(AT_EXECFN, 0);
+                map.insert(AT_PLATFORM, 0);
                 map
             },
+            execfn: env.execfn,
         };
 
         info!("info {:#?}", info);
-        let init_stack = info.serialize(stack_top);
+        let (init_stack, auxv) = info.serialize(stack_top);
+        vm.set_saved_auxv(auxv);
         debug!("init user proc: stack len {}", init_stack.len());
         stack_pma.write(USER_STACK_SIZE - init_stack.len(), &init_stack)?;
         stack_top -= init_stack.len();
@@ -328,46 +347,117 @@ impl From<Flags> for PTEFlags {
     }
 }
 
-#[allow(unused)]
+/// ELF 文件开头的魔数
+const ELF_MAGIC: &[u8] = b"\x7fELF";
+/// 脚本文件开头的标记
+const SHEBANG_MAGIC: &[u8] = b"#!";
+
+/// 检查文件是否是可以执行的格式，即 ELF 文件或以 #! 开头的脚本。
+/// 如果是脚本，则沿着 #! 指定的解释器逐层检查，直到遇到 ELF 文件：
+/// - 程序或某一层解释器不存在时返回 Loader_AppNotFound
+/// - 不是普通文件、格式不对或者 #! 行无法解析时返回 Loader_NotExecutable
+/// - 解释器的层数超过 MAX_INTERPRETER_DEPTH 时返回 Loader_TooManyInterpreters
+///
+/// exec 会在清空原来的地址空间之后才加载新程序，所以需要先用这个函数检查，
+/// 以便出错时还能把错误返回给用户(例如 shell 会因 ENOEXEC 改用自己解释这个文件)
+pub fn check_user_app(app_dir: &str, app_name: &str) -> OSResult {
+    let mut app_name = String::from(app_name);
+    let mut depth = 0;
+    loop {
+        // 文件存在却不能打开时(如 FIFO)，说明它不是普通文件，不能执行
+        let node = open_file(app_dir, app_name.as_str(), OpenFlags::RDONLY).ok_or_else(|| {
+            if check_file_exists(app_dir, app_name.as_str()) {
+                OSError::Loader_NotExecutable
+            } else {
+                OSError::Loader_AppNotFound
+            }
+        })?;
+        let mut header = [0u8; SHEBANG_LINE_MAX_LEN];
+        let len = node.read(&mut header).unwrap_or(0);
+        let header = &header[..len];
+        if header.starts_with(ELF_MAGIC) {
+            return Ok(());
+        }
+        if !header.starts_with(SHEBANG_MAGIC) {
+            return Err(OSError::Loader_NotExecutable);
+        }
+        if depth >= MAX_INTERPRETER_DEPTH {
+            return Err(OSError::Loader_TooManyInterpreters);
+        }
+        let (interp, _) = parse_shebang_line(header).map_err(|_| OSError::Loader_NotExecutable)?;
+        app_name = String::from(interp);
+        depth += 1;
+    }
+}
+
 /// 执行用户程序并选择解释器：
-/// - 如果程序以 .sh 结尾，则使用 busybox sh 执行
-/// - 否则，将用户程序视为根据名字获取二进制串形式的用户程序
+/// - 如果程序以 #! 开头，则按第一行指定的解释器执行
+/// - 否则，将用户程序视为 ELF 文件加载
 ///
 /// 如找不到，则返回某种 OSError
 pub fn parse_user_app(
+    app_dir: &str,
+    app_name: &str,
+    vm: &mut MemorySet,
+    args: Vec<String>,
+    env: UserEnv,
+) -> OSResult<(VirtAddr, VirtAddr)> {
+    parse_user_app_with_depth(app_dir, app_name, vm, args, env, 0)
+}
+
+/// 同 parse_user_app，depth 表示当前已经经过了几层 #! 解释器。
+///
+/// 解释器的路径与用户给出的程序路径一样，相对于 app_dir(即当前目录)解析，而不是相对于脚本所在的目录
+fn parse_user_app_with_depth(
     app_dir: &str,
     app_name: &str,
     mut vm: &mut MemorySet,
     args: Vec<String>,
+    env: UserEnv,
+    depth: usize,
 ) -> OSResult<(VirtAddr, VirtAddr)> {
-    let (app_dir, app_name, args) = if app_name.ends_with(".sh") {
-        // .sh 文件统一用 busybox 解析
-        (
-            ROOT_DIR,
-            "busybox",
-            [
-                vec![
-                    String::from("busybox"),
-                    String::from("sh"),
-                    String::from(app_dir) + &args[0],
-                ],
-                Vec::from(&args[1..]),
-            ]
-            .concat(),
-        )
-    } else {
-        (app_dir, app_name, args)
-    };
-    open_file(app_dir, app_name, OpenFlags::RDONLY)
+    let data = open_file(app_dir, app_name, OpenFlags::RDONLY)
         .map(|node| unsafe { node.read_all() })
-        .map(|data| {
-            /*
-            for i in 0..20 {
-                print!("{} ", data[i]);
-            }
-            */
-            let mut loader = ElfLoader::new(data.as_slice())?;
-            loader.init_vm(&mut vm, args)
-        })
-        .unwrap_or(Err(OSError::Loader_AppNotFound))
+        .ok_or(OSError::Loader_AppNotFound)?;
+    if data.starts_with(SHEBANG_MAGIC) {
+        if depth >= MAX_INTERPRETER_DEPTH {
+            return Err(OSError::Loader_TooManyInterpreters);
+        }
+        let (interp, interp_arg) = parse_shebang_line(&data)?;
+        // 与 Linux 相同，新的参数为 [解释器, 可选的解释器参数, 脚本路径, 原来的 argv[1..]]
+        let mut new_args = vec![String::from(interp)];
+        if let Some(arg) = interp_arg {
+            new_args.push(String::from(arg));
+        }
+        new_args.push(String::from(app_name));
+        new_args.extend(args.into_iter().skip(1));
+        info!("script interpreter args {:#?}", new_args);
+        return parse_user_app_with_depth(app_dir, interp, vm, new_args, env, depth + 1);
+    }
+    let mut loader = ElfLoader::new(data.as_slice())?;
+    loader.init_vm(&mut vm, args, env)
+}
+
+/// 解析脚本的 #! 行，返回解释器路径和可选的一个参数。
+///
+/// 与 Linux 相同，解释器路径之后直到行尾的所有内容(去掉首尾空白)都被视为同一个参数
+fn parse_shebang_line(data: &[u8]) -> OSResult<(&str, Option<&str>)> {
+    let line = &data[SHEBANG_MAGIC.len()..data.len().min(SHEBANG_LINE_MAX_LEN)];
+    let line = match line.iter().position(|&c| c == b'\n') {
+        Some(end) => &line[..end],
+        None => line,
+    };
+    let line = core::str::from_utf8(line)
+        .map_err(|_| OSError::Loader_CanNotParseInterpreter)?
+        .trim();
+    if line.is_empty() {
+        return Err(OSError::Loader_CanNotParseInterpreter);
+    }
+    Ok(match line.find(|c: char| c == ' ' || c == '\t') {
+        Some(pos) => (
+            &line[..pos],
+            Some(line[pos..].trim()).filter(|arg| !arg.is_empty()),
+        ),
+        None => (line, None),
+    })
 }
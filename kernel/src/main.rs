@@ -62,6 +62,10 @@ impl task_trampoline::TaskTrampoline for TaskTrampoline {
         task::suspend_current_task()
     }
 
+    fn signal_pending(&self) -> bool {
+        task::signal_pending()
+    }
+
     fn get_file(&self, fd: usize) -> Option<Arc<dyn base_file::File>> {
         let task = task::get_current_task().unwrap();
         let fd_manager = task.fd_manager.lock();
@@ -81,14 +85,16 @@ impl task_trampoline::TaskTrampoline for TaskTrampoline {
 
     fn manually_alloc_user_str(&self, buf: *const u8, len: usize) -> Result<(), u64> {
         let task = task::get_current_task().unwrap();
-        let mut task_vm = task.vm.lock();
+        let vm = task.get_vm();
+        let mut task_vm = vm.lock();
         // TODO: 考虑是否要将错误类型返回给用户
         task_vm.manually_alloc_user_str(buf, len).map_err(|_| 1)
     }
 
     fn manually_alloc_range(&self, start_vaddr: usize, end_vaddr: usize) -> Result<(), u64> {
         let task = task::get_current_task().unwrap();
-        let mut task_vm = task.vm.lock();
+        let vm = task.get_vm();
+        let mut task_vm = vm.lock();
         // TODO: 考虑是否要将错误类型返回给用户
         task_vm
             .manually_alloc_range(start_vaddr, end_vaddr)
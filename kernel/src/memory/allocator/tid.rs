@@ -29,6 +29,16 @@ pub fn alloc_tid() -> Option<Tid> {
     Some(Tid(TID_ALLOCATOR.lock().alloc()?))
 }
 
+/// 分配指定的 tid 并打包成 Tid。如果它不在可分配的范围内或已被占用，则返回 None
+pub fn alloc_tid_at(tid: usize) -> Option<Tid> {
+    let mut allocator = TID_ALLOCATOR.lock();
+    if tid >= TID_LIMIT || !allocator.test(tid) {
+        return None;
+    }
+    allocator.remove(tid..tid + 1);
+    Some(Tid(tid))
+}
+
 /// 初始化 tid 分配
 pub fn init() {
     TID_ALLOCATOR.lock().insert(2..TID_LIMIT)
@@ -43,6 +53,10 @@ impl Tid {
     pub fn new() -> Option<Self> {
         alloc_tid()
     }
+    /// 分配指定的 tid，如这个 tid 不可用，则返回 None。用于 clone3 的 set_tid
+    pub fn new_at(tid: usize) -> Option<Self> {
+        alloc_tid_at(tid)
+    }
 }
 
 impl Drop for Tid {
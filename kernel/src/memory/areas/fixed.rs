@@ -11,7 +11,7 @@ use super::{PmArea, VmArea};
 use crate::error::{OSError, OSResult};
 use crate::memory::{
     addr::{align_down, align_up},
-    PTEFlags, PhysAddr, VirtAddr, PAGE_SIZE,
+    Frame, PTEFlags, PhysAddr, VirtAddr, PAGE_SIZE,
 };
 
 /// 直接分配的物理地址段
@@ -41,8 +41,8 @@ impl PmArea for PmAreaFixed {
 
     fn sync_frame_with_file(&mut self, _idx: usize) {}
 
-    fn release_frame(&mut self, _idx: usize) -> OSResult {
-        Ok(())
+    fn release_frame(&mut self, _idx: usize) -> OSResult<Option<Frame>> {
+        Ok(None)
     }
 
     fn read(&mut self, offset: usize, dst: &mut [u8]) -> OSResult<usize> {
@@ -71,7 +71,7 @@ impl PmArea for PmAreaLazy {
         }
     }
 
-    fn release_frame(&mut self, idx: usize) -> OSResult {
+    fn release_frame(&mut self, idx: usize) -> OSResult<Option<Frame>> {
         let frame = self.frames[idx]
             .take()
             .ok_or(OSError::PmAreaLazy_ReleaseNotAllocatedPage)?;
@@ -81,7 +81,7 @@ impl PmArea for PmAreaLazy {
                 .write_to_offset(idx * PAGE_SIZE, frame.as_slice())
                 .unwrap_or(0);
         }
-        Ok(())
+        Ok(Some(frame))
     }
     /// 复制从 offset 位置开始的一段数据到 dst
     fn read(&mut self, offset: usize, dst: &mut [u8]) -> OSResult<usize> {
@@ -101,7 +101,7 @@ impl PmArea for PmAreaLazy {
     fn shrink_left(&mut self, new_start: usize) -> OSResult {
         if new_start < self.size() {
             for idx in 0..addr_to_page_id(new_start) {
-                self.release_frame(idx).unwrap_or(());
+                self.release_frame(idx).unwrap_or(None);
             }
             // 被删除的页帧会在 drop 时自动释放
             self.frames.drain(..addr_to_page_id(new_start));
@@ -117,7 +117,7 @@ impl PmArea for PmAreaLazy {
     fn shrink_right(&mut self, new_end: usize) -> OSResult {
         if new_end < self.size() {
             for idx in addr_to_page_id(new_end)..self.frames.len() {
-                self.release_frame(idx).unwrap_or(());
+                self.release_frame(idx).unwrap_or(None);
             }
             // 被删除的页帧会在 drop 时自动释放
             self.frames.drain(addr_to_page_id(new_end)..);
@@ -131,7 +131,7 @@ impl PmArea for PmAreaLazy {
         if left_end <= right_start && right_start < self.size() {
             let new_frames = self.frames.drain(addr_to_page_id(right_start)..).collect();
             for idx in addr_to_page_id(left_end)..addr_to_page_id(right_start) {
-                self.release_frame(idx).unwrap_or(());
+                self.release_frame(idx).unwrap_or(None);
             }
             // 被删除的页帧会在 drop 时自动释放
             self.frames.drain(addr_to_page_id(left_end)..);
@@ -7,7 +7,7 @@ mod lazy;
 
 use super::{
     addr::{align_down, align_up, PhysAddr, VirtAddr},
-    PTEFlags, PageTable, PAGE_SIZE,
+    Frame, PTEFlags, PageTable, PAGE_SIZE,
 };
 use crate::{
     error::{OSError, OSResult},
@@ -35,8 +35,9 @@ pub trait PmArea: core::fmt::Debug + Send + Sync {
     fn get_frame(&mut self, idx: usize, need_alloc: bool) -> OSResult<Option<PhysAddr>>;
     /// 同步页的信息到后端文件中
     fn sync_frame_with_file(&mut self, idx: usize);
-    /// 释放 idx 地址对应的物理页
-    fn release_frame(&mut self, idx: usize) -> OSResult;
+    /// 从区间中取出 idx 地址对应的物理页，由调用者决定什么时候真正释放它。
+    /// 不属于这个区间管理的物理页(如固定的物理地址段)返回 None
+    fn release_frame(&mut self, idx: usize) -> OSResult<Option<Frame>>;
     /// 读从 offset 开头的一段数据，成功时返回读取长度
     fn read(&mut self, offset: usize, dst: &mut [u8]) -> OSResult<usize>;
     /// 把数据写到从 offset 开头的地址，成功时返回写入长度
@@ -166,19 +167,19 @@ impl VmArea {
     fn unmap_area_partial(&self, pt: &mut PageTable, start: VirtAddr, end: VirtAddr) -> OSResult {
         let mut pma = self.pma.lock();
         for vaddr in (start..end).step_by(PAGE_SIZE) {
-            let res = pma.release_frame((vaddr - self.start) / PAGE_SIZE);
-            //if vaddr == 0x3fff_f000 { println!("page {:#x?} at {:x}", res, pt.get_root_paddr()); }
-            // 如果触发 OSError::PmAreaLazy_ReleaseNotAllocatedPage，
-            // 说明这段 area 是 Lazy 分配的，且这一页还没被用到
-            // 这种情况下不需要报错，也不需要修改页表
-            if res != Err(OSError::PmAreaLazy_ReleaseNotAllocatedPage) {
-                if res.is_err() {
-                    return res;
-                }
-                pt.unmap(vaddr).map_err(|e| {
-                    error!("failed to unmap VA: {:#x?}, {:?}", vaddr, e);
-                    e
-                })?;
+            let frame = match pma.release_frame((vaddr - self.start) / PAGE_SIZE) {
+                // 说明这段 area 是 Lazy 分配的，且这一页还没被用到
+                // 这种情况下不需要报错，也不需要修改页表
+                Err(OSError::PmAreaLazy_ReleaseNotAllocatedPage) => continue,
+                Err(e) => return Err(e),
+                Ok(frame) => frame,
+            };
+            pt.unmap(vaddr).map_err(|e| {
+                error!("failed to unmap VA: {:#x?}, {:?}", vaddr, e);
+                e
+            })?;
+            if let Some(frame) = frame {
+                pt.defer_frame_release(frame);
             }
         }
         Ok(())
@@ -3,7 +3,10 @@
 //#![deny(missing_docs)]
 
 use super::{align_down, align_up, phys_to_virt, pte_idx_of_virt_addr, Frame, PhysAddr, VirtAddr};
-use crate::error::{OSError, OSResult};
+use crate::{
+    arch::ipi,
+    error::{OSError, OSResult},
+};
 use alloc::vec::Vec;
 use riscv::{
     asm::{sfence_vma, sfence_vma_all},
@@ -106,6 +109,8 @@ pub unsafe fn get_pte_at(paddr: PhysAddr, idx: usize) -> &'static mut PageTableE
 pub struct PageTable {
     root_paddr: PhysAddr,
     frames: Vec<Frame>,
+    /// 已经从页表中删除了映射，但其他核的 TLB 中可能还有其映射的页帧，见 defer_frame_release
+    released_frames: Vec<Frame>,
 }
 
 /// 页表数据结构本身操作
@@ -117,6 +122,7 @@ impl PageTable {
             Ok(PageTable {
                 root_paddr: frame.start_paddr(),
                 frames: vec![frame],
+                released_frames: Vec::new(),
             })
         } else {
             Err(OSError::PageTable_FrameAllocFailed)
@@ -127,6 +133,7 @@ impl PageTable {
         Self {
             root_paddr: paddr,
             frames: Vec::new(),
+            released_frames: Vec::new(),
         }
     }
     /// 获取页表项中的物理地址，如页表项为空则新申请一个页面
@@ -140,6 +147,18 @@ impl PageTable {
         }
         Some(pte.addr())
     }
+    /// 暂存一个刚删除了映射的页帧。
+    ///
+    /// 其他正在使用这个页表的核仍可能通过 TLB 访问它，所以要等它们都清空 TLB 之后才能释放，
+    /// 否则页帧被重新分配后，旧的映射还能读写其中的数据。
+    /// 调用者应在清空 TLB 后用 take_released_frames 取出并释放它们；没有取出的会在页表被回收时释放
+    pub fn defer_frame_release(&mut self, frame: Frame) {
+        self.released_frames.push(frame);
+    }
+    /// 取出所有暂存的页帧，见 defer_frame_release
+    pub fn take_released_frames(&mut self) -> Vec<Frame> {
+        core::mem::take(&mut self.released_frames)
+    }
     /// 获取这个页表结构的地址，注意不是根页面地址，而是这个存着 root_paddr 和 frames 的结构的地址
     pub unsafe fn self_as_usize(&self) -> usize {
         self as *const Self as usize
@@ -319,6 +338,7 @@ impl PageTable {
         let old_root = Self::current_root_paddr();
         let new_root = self.get_root_paddr();
         //println!("switch table {:#x?} -> {:#x?}", old_root, new_root);
+        ipi::set_current_root(new_root);
         if new_root != old_root {
             Self::set_current_root_paddr(new_root);
             self.flush_tlb(None);
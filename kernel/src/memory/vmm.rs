@@ -1,8 +1,8 @@
 //! 虚拟地址段映射管理
 
 use super::{
-    addr_to_page_id, cross_page, get_phys_memory_regions, page_count, page_id_to_addr,
-    virt_to_phys, PTEFlags, PageTable, PmAreaLazy, VirtAddr, VmArea,
+    addr_to_page_id, align_down, cross_page, get_phys_memory_regions, page_count, page_id_to_addr,
+    phys_to_virt, virt_to_phys, PTEFlags, PageTable, PmAreaLazy, VirtAddr, VmArea,
 };
 use crate::{
     arch,
@@ -13,7 +13,7 @@ use crate::{
     error::{OSError, OSResult},
     file::BackEndFile,
 };
-use alloc::{boxed::Box, sync::Arc};
+use alloc::{boxed::Box, sync::Arc, vec::Vec};
 use core::{
     fmt::{Debug, Formatter, Result},
     mem::size_of,
@@ -29,6 +29,8 @@ pub struct MemorySet {
     pub pt: Box<PageTable>,
     /// 是否是用户态的
     is_user: bool,
+    /// 加载用户程序时放在用户栈上的辅助参数(键值对，以 AT_NULL 结尾)，生成 core dump 时使用
+    saved_auxv: Vec<usize>,
 }
 
 impl MemorySet {
@@ -39,6 +41,7 @@ impl MemorySet {
             area_map: RangeActionMap::new(unsafe { pt.self_as_usize() }),
             pt,
             is_user: false,
+            saved_auxv: Vec::new(),
         }
     }
 
@@ -49,6 +52,7 @@ impl MemorySet {
             area_map: RangeActionMap::new(unsafe { pt.self_as_usize() }),
             pt,
             is_user: true,
+            saved_auxv: Vec::new(),
         }
     }
     /// 取消一段内存地址映射
@@ -57,6 +61,12 @@ impl MemorySet {
         self.area_map.unmap(start, end);
         true
     }
+    /// 这一段地址是否与已有的某个地址段相交
+    pub fn is_overlap_with(&self, start: VirtAddr, end: VirtAddr) -> bool {
+        self.area_map
+            .iter()
+            .any(|area| area.is_overlap_with(start, end))
+    }
     /// 修改一段内存映射的权限
     pub fn mprotect(&mut self, start: VirtAddr, end: VirtAddr, new_flags: PTEFlags) -> bool {
         //error!("mprotect start {:x} , end {:x}", start, end);
@@ -192,6 +202,25 @@ impl MemorySet {
         self.manually_alloc_range(buf as VirtAddr, buf as VirtAddr + len - 1)
     }
 
+    /// 读取用户地址空间中以 '\0' 结尾的字符串，最多读取 max_len 字节，返回不含 '\0' 的内容。
+    ///
+    /// 与 manually_alloc_user_str 不同，只检查实际读到的字节所在的页，所以字符串后面的页未映射时不会失败
+    pub fn read_user_c_str(&mut self, buf: *const u8, max_len: usize) -> OSResult<Vec<u8>> {
+        let mut bytes = Vec::new();
+        for i in 0..max_len {
+            let addr = buf as VirtAddr + i;
+            if i == 0 || addr % PAGE_SIZE == 0 {
+                self.manually_alloc_page(addr)?;
+            }
+            let c = unsafe { *(addr as *const u8) };
+            if c == 0 {
+                break;
+            }
+            bytes.push(c);
+        }
+        Ok(bytes)
+    }
+
     /// 清空用户段的地址映射
     pub fn clear_user_pages(&mut self) {
         if !self.is_user {
@@ -212,6 +241,69 @@ impl MemorySet {
             .unmap(range_action_map::LOWER_LIMIT, USER_VIRT_ADDR_LIMIT);
     }
 
+    /// 记录加载用户程序时放在用户栈上的辅助参数
+    pub fn set_saved_auxv(&mut self, auxv: Vec<usize>) {
+        self.saved_auxv = auxv;
+    }
+
+    /// 获取加载用户程序时放在用户栈上的辅助参数
+    pub fn get_saved_auxv(&self) -> &[usize] {
+        self.saved_auxv.as_slice()
+    }
+
+    /// 获取用户地址段中所有已分配物理页的区间及其权限。
+    /// 同一地址段中连续的已分配页会合并为一个区间。用于生成 core dump
+    pub fn resident_user_ranges(&self) -> Vec<(VirtAddr, VirtAddr, PTEFlags)> {
+        let mut ranges = Vec::new();
+        for area in self.area_map.iter() {
+            if !area.is_user() {
+                continue;
+            }
+            let mut pma = area.pma.lock();
+            let mut range_start = None;
+            for vaddr in (area.start..area.end).step_by(PAGE_SIZE) {
+                let resident = matches!(
+                    pma.get_frame((vaddr - area.start) / PAGE_SIZE, false),
+                    Ok(Some(_))
+                );
+                match (resident, range_start) {
+                    (true, None) => range_start = Some(vaddr),
+                    (false, Some(start)) => {
+                        ranges.push((start, vaddr, area.flags));
+                        range_start = None;
+                    }
+                    _ => {}
+                }
+            }
+            if let Some(start) = range_start {
+                ranges.push((start, area.end, area.flags));
+            }
+        }
+        ranges
+    }
+
+    /// 把用户地址 vaddr 所在页的数据复制到 dst 中。
+    /// 如果这一页不在用户地址段中，或者还没有分配物理页，则不复制并返回 false
+    pub fn read_resident_page(&self, vaddr: VirtAddr, dst: &mut [u8]) -> bool {
+        let vaddr = align_down(vaddr);
+        if let Some(area) = self.area_map.find(vaddr) {
+            if area.is_user() && area.contains(vaddr) {
+                let frame = area
+                    .pma
+                    .lock()
+                    .get_frame((vaddr - area.start) / PAGE_SIZE, false);
+                if let Ok(Some(paddr)) = frame {
+                    let src = unsafe {
+                        core::slice::from_raw_parts(phys_to_virt(paddr) as *const u8, PAGE_SIZE)
+                    };
+                    dst[..PAGE_SIZE].copy_from_slice(src);
+                    return true;
+                }
+            }
+        }
+        false
+    }
+
     // 清空 TLB
     pub fn flush_tlb(&self) {
         self.pt.flush_tlb(None);
@@ -291,6 +383,7 @@ impl MemorySet {
     /// 2. 对用户的地址段，所有虚拟地址和其中的数据相同，但对应的物理地址与 self 中的不同
     pub fn copy_as_fork(&self) -> OSResult<MemorySet> {
         let mut ms = new_memory_set_for_task()?;
+        ms.saved_auxv = self.saved_auxv.clone();
         for area in self.area_map.iter() {
             if area.is_user() {
                 ms.push(area.copy_to_new_area_with_data()?)?;
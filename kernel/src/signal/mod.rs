@@ -2,22 +2,44 @@
 //! 信号模块和 task 管理的进程/线程相关，但又相对独立；
 //! 且如果单纯作为线程的一部分，容易因为信号发送的任意性导致死锁，因此单独列出来。
 //!
-//! 目前的模型中，不采用 ipi 实时发送信号，而是由被目标线程在 trap 时处理。因此需要开启**时钟中断**来保证信号能实际送到
+//! 信号由目标线程在 trap 返回用户态前处理。如果发送信号时目标线程正在其他核上运行，
+//! 则通过 ipi 让那个核立即进入 trap；没有在运行的线程会在下次被调度时处理
 
+use alloc::{collections::VecDeque, sync::Arc};
 use bitset::Bitset;
+use lock::Mutex;
 
 mod signal_no;
 pub use signal_no::SignalNo;
 mod sig_action;
 pub use sig_action::{SigAction, SigActionDefault, SigActionFlags, SIG_DFL, SIG_IGN};
 mod sig_info;
-pub use sig_info::SigInfo;
+pub use sig_info::{
+    SigInfo, BUS_ADRALN, BUS_ADRERR, CLD_CONTINUED, CLD_DUMPED, CLD_EXITED, CLD_KILLED,
+    CLD_STOPPED, CLD_TRAPPED, ILL_ILLOPC, ILL_ILLTRP, SEGV_ACCERR, SEGV_MAPERR, SI_KERNEL,
+    SI_QUEUE, SI_TKILL, SI_USER, TRAP_BRKPT, TRAP_TRACE,
+};
 mod ucontext;
-pub use ucontext::SignalUserContext;
+pub use ucontext::{SignalStack, SignalUserContext, SS_AUTODISARM, SS_DISABLE, SS_ONSTACK};
+mod running;
+pub use running::set_running_receivers;
+use running::{kick_receivers, kick_shared_pending};
 mod tid2signals;
-use crate::constants::SIGSET_SIZE_IN_BIT;
+use crate::{
+    constants::{SIGQUEUE_MAX, SIGSET_SIZE_IN_BIT},
+    task::get_task_from_tid,
+};
 pub use tid2signals::{get_signals_from_tid, global_logoff_signals, global_register_signals};
 
+/// 同步产生的异常信号。它们由当前指令触发，不能推迟处理
+pub const FAULT_SIGNALS: [SignalNo; 5] = [
+    SignalNo::SIGSEGV,
+    SignalNo::SIGBUS,
+    SignalNo::SIGILL,
+    SignalNo::SIGFPE,
+    SignalNo::SIGTRAP,
+];
+
 /// 处理信号的结构，每个线程有一个，根据 clone 的参数有可能是共享的
 #[derive(Clone, Copy)]
 pub struct SignalHandlers {
@@ -60,6 +82,27 @@ impl SignalHandlers {
         }
         //if signum != 33 {&self.actions[signum - 1]} else {&None}
     }
+    /// 把某个信号的处理方式恢复为默认
+    pub fn reset_action(&mut self, signum: usize) {
+        self.actions[signum - 1] = None;
+    }
+    /// 所有设置了用户处理函数(即不是 SIG_DFL 或 SIG_IGN)的信号
+    pub fn handled_set(&self) -> Bitset {
+        let mut set = Bitset::new(0);
+        for (i, action) in self.actions.iter().enumerate() {
+            if action.map_or(false, |a| a.handler != SIG_DFL && a.handler != SIG_IGN) {
+                set.add_bit(i);
+            }
+        }
+        set
+    }
+    /// 子进程退出时是否直接回收，不留下僵尸进程。
+    /// 即 SIGCHLD 被设置为 SIG_IGN，或者设置了 SA_NOCLDWAIT
+    pub fn auto_reap_children(&self) -> bool {
+        self.actions[SignalNo::SIGCHLD as usize - 1].map_or(false, |action| {
+            action.handler == SIG_IGN || action.flags.contains(SigActionFlags::SA_NOCLDWAIT)
+        })
+    }
     /// 修改某个信号对应的 SigAction。
     /// 因为 signum 的范围是 \[1,64\]，所以内部要 -1
     pub fn set_action(&mut self, signum: usize, action_pos: *const SigAction) {
@@ -70,50 +113,187 @@ impl SignalHandlers {
     }
 }
 
-/// 接受信号的结构，每个线程都独有，不会共享
-#[derive(Clone, Copy)]
+/// 已收到但还未处理的信号。
+/// 标准信号(1~31)在其中最多只有一个，重复发送时会合并；实时信号则每次发送都会排队，并保留各自的附加信息
+#[derive(Clone)]
+pub struct PendingSignals {
+    /// 已收到的信号集合
+    pub set: Bitset,
+    /// 每个已收到的信号的附加信息，按收到的顺序排列
+    queue: VecDeque<SigInfo>,
+}
+
+impl PendingSignals {
+    /// 新建一个空的信号队列
+    pub fn new() -> Self {
+        Self {
+            set: Bitset::new(0),
+            queue: VecDeque::new(),
+        }
+    }
+    /// 清空队列
+    pub fn clear(&mut self) {
+        self.set.clear();
+        self.queue.clear();
+    }
+    /// 加入一个信号。如果是实时信号且队列已满，则返回 false
+    pub fn push(&mut self, info: SigInfo) -> bool {
+        let signum = info.si_signo as usize;
+        if signum < SignalNo::SIGRTMIN as usize {
+            // 标准信号已经在队列中时直接合并
+            if self.set.contain_bit(signum - 1) {
+                return true;
+            }
+        } else if self.queue.len() >= SIGQUEUE_MAX {
+            return false;
+        }
+        self.set.add_bit(signum - 1);
+        self.queue.push_back(info);
+        true
+    }
+    /// 取出一个不在 mask 中的信号。编号小的信号优先，同一编号的信号按收到的顺序取出
+    pub fn pop(&mut self, mask: Bitset) -> Option<SigInfo> {
+        let signum = self.set.find_first_one(mask)? + 1;
+        let pos = self
+            .queue
+            .iter()
+            .position(|info| info.si_signo as usize == signum)?;
+        let info = self.queue.remove(pos)?;
+        if !self
+            .queue
+            .iter()
+            .any(|info| info.si_signo as usize == signum)
+        {
+            self.set.remove_bit(signum - 1);
+        }
+        Some(info)
+    }
+    /// 队列中由异常产生的信号，见 `SigInfo::is_fault`
+    pub fn fault_set(&self) -> Bitset {
+        let mut set = Bitset::new(0);
+        for info in self.queue.iter().filter(|info| info.is_fault()) {
+            set.add_bit(info.si_signo as usize - 1);
+        }
+        set
+    }
+    /// 丢弃某个编号的所有信号
+    pub fn discard(&mut self, signum: usize) {
+        self.set.remove_bit(signum - 1);
+        self.queue.retain(|info| info.si_signo as usize != signum);
+    }
+}
+
+/// 接受信号的结构，每个线程有一个
 pub struct SignalReceivers {
-    /// 掩码，表示哪些信号是当前线程不处理的。（目前放在进程中，实现了线程之后每个线程应该各自有一个）
+    /// 掩码，表示哪些信号是当前线程不处理的
     pub mask: Bitset,
-    /// 当前已受到的信号
-    pub sig_received: Bitset,
+    /// 发给当前线程的信号
+    pub pending: PendingSignals,
+    /// 发给整个进程的信号，同一进程的所有线程共享，由其中任意一个没有屏蔽它的线程处理。
+    /// 需要在获取 SignalReceivers 的锁之后再获取它的锁
+    pub shared_pending: Arc<Mutex<PendingSignals>>,
+    /// sigsuspend 临时替换掩码时，保存的原来的掩码。
+    /// 进入信号处理函数时，它作为处理函数返回后恢复的掩码；没有进入处理函数时，在处理完信号后直接恢复
+    pub saved_mask: Option<Bitset>,
 }
 
 impl SignalReceivers {
-    /// 新建一个处理模块
-    pub fn new() -> Self {
+    /// 新建一个处理模块。shared_pending 为所在进程共享的信号队列，如为 None 则新建一个
+    pub fn new(shared_pending: Option<Arc<Mutex<PendingSignals>>>) -> Self {
         Self {
             mask: Bitset::new(0),
-            sig_received: Bitset::new(0),
+            pending: PendingSignals::new(),
+            shared_pending: shared_pending
+                .unwrap_or_else(|| Arc::new(Mutex::new(PendingSignals::new()))),
+            saved_mask: None,
         }
     }
     /// 清空模块。
     pub fn clear(&mut self) {
         self.mask = Bitset::new(0);
-        self.sig_received = Bitset::new(0);
+        self.pending.clear();
+        self.shared_pending.lock().clear();
+        self.saved_mask = None;
     }
-    /// 处理一个信号。如果有收到的信号，则返回信号编号。否则返回 None
-    pub fn get_one_signal(&mut self) -> Option<usize> {
-        self.sig_received.find_first_one(self.mask).map(|pos| {
-            self.sig_received.remove_bit(pos);
-            pos + 1
-        })
+    /// 处理一个信号。先处理发给当前线程的信号，再处理发给进程的信号。
+    /// 如果有收到的信号，则返回信号的信息。否则返回 None
+    /// 除 mask 外，blocked 中的信号也暂不取出
+    pub fn get_one_signal(&mut self, blocked: Bitset) -> Option<SigInfo> {
+        let mut mask = self.mask;
+        mask.get_union(blocked);
+        self.pending
+            .pop(mask)
+            .or_else(|| self.shared_pending.lock().pop(mask))
     }
 
-    /// 尝试添加一个 bit 作为信号。发送的信号如果在 mask 中，则仍然会发送，只是可能不触发
-    /// 因为 signum 的范围是 \[1,64\]，所以内部要 -1
-    ///
-    /// 因为没有要求判断信号是否发送成功的要求，所有这里不设返回值
-    pub fn try_add_bit(&mut self, signum: usize) {
-        //info!("try add {}, mask = {:x}", signum, self.mask.0);
-        self.sig_received.add_bit(signum - 1);
+    /// 取出一个在 set 中的信号，不论它是否被屏蔽。用于 sigtimedwait / signalfd 同步地等待信号
+    pub fn dequeue_from(&mut self, set: Bitset) -> Option<SigInfo> {
+        let mask = Bitset::new(!set.0);
+        self.pending
+            .pop(mask)
+            .or_else(|| self.shared_pending.lock().pop(mask))
+    }
+    /// 已收到的信号，包括发给进程的
+    pub fn all_pending(&self) -> Bitset {
+        let mut set = self.pending.set;
+        set.get_union(self.shared_pending.lock().set);
+        set
+    }
+    /// 已收到且没有被屏蔽的信号，包括发给进程的
+    pub fn unmasked_pending(&self) -> Bitset {
+        let mut set = self.all_pending();
+        set.get_difference(self.mask);
+        set
+    }
+
+    /// 丢弃一个已收到但还未处理的信号，包括发给进程的
+    pub fn discard_signal(&mut self, signum: usize) {
+        self.pending.discard(signum);
+        self.shared_pending.lock().discard(signum);
+    }
+
+    /// 是否已收到某个信号，包括发给进程的
+    pub fn has_signal(&self, signum: usize) -> bool {
+        self.pending.set.contain_bit(signum - 1)
+            || self.shared_pending.lock().set.contain_bit(signum - 1)
     }
 }
 
-/// 发送一个信号给进程 tid
+/// 发送一个由内核产生的信号给线程 tid
 pub fn send_signal(tid: usize, signum: usize) {
+    send_siginfo(tid, SigInfo::kernel(signum));
+}
+
+/// 信号放入队列前，处理与暂停和继续相关的信号，见 TaskControlBlock::prepare_signal。
+/// 所有发送信号的路径都需要经过这里，否则被暂停的进程收不到 SIGCONT 和 SIGKILL
+fn prepare_signal(tid: usize, signum: usize) {
+    if let Some(task) = get_task_from_tid(tid) {
+        task.prepare_signal(signum);
+    }
+}
+
+/// 发送一个带附加信息的信号给线程 tid。如果实时信号的队列已满，则返回 false
+pub fn send_siginfo(tid: usize, info: SigInfo) -> bool {
+    prepare_signal(tid, info.si_signo as usize);
     if let Some(signals) = get_signals_from_tid(tid as usize) {
         // 获取目标线程(可以是自己)的 signals 数组
-        signals.lock().try_add_bit(signum);
+        let pushed = signals.lock().pending.push(info);
+        kick_receivers(&signals);
+        pushed
+    } else {
+        true
+    }
+}
+
+/// 发送一个带附加信息的信号给线程 tid 所在的进程。如果实时信号的队列已满，则返回 false
+pub fn send_process_siginfo(tid: usize, info: SigInfo) -> bool {
+    prepare_signal(tid, info.si_signo as usize);
+    if let Some(signals) = get_signals_from_tid(tid as usize) {
+        let shared_pending = signals.lock().shared_pending.clone();
+        let pushed = shared_pending.lock().push(info);
+        kick_shared_pending(&shared_pending);
+        pushed
+    } else {
+        true
     }
 }
@@ -0,0 +1,62 @@
+//! 记录每个核上正在运行的线程的信号队列，用于在发送信号后通知目标线程所在的核
+
+use super::{PendingSignals, SignalReceivers};
+use crate::{
+    arch::{
+        get_cpu_id,
+        ipi::{send_ipi, IpiMessage},
+    },
+    constants::{CPU_ID_LIMIT, FIRST_CPU_ID},
+};
+use alloc::sync::Arc;
+use core::sync::atomic::{AtomicUsize, Ordering};
+use lock::Mutex;
+
+/// 每个核上正在运行的线程的 SignalReceivers 地址，没有运行线程时为 0
+static RUNNING_RECEIVERS: [AtomicUsize; CPU_ID_LIMIT] = {
+    const ZERO: AtomicUsize = AtomicUsize::new(0);
+    [ZERO; CPU_ID_LIMIT]
+};
+
+/// 每个核上正在运行的线程所在进程的 shared_pending 地址，没有运行线程时为 0
+static RUNNING_SHARED_PENDING: [AtomicUsize; CPU_ID_LIMIT] = {
+    const ZERO: AtomicUsize = AtomicUsize::new(0);
+    [ZERO; CPU_ID_LIMIT]
+};
+
+/// 记录当前核开始运行信号队列为 receivers 的线程。切换出线程时传入 None
+pub fn set_running_receivers(receivers: Option<&Arc<Mutex<SignalReceivers>>>) {
+    let cpu_id = get_cpu_id();
+    let (receivers_addr, shared_addr) = receivers.map_or((0, 0), |receivers| {
+        let shared = receivers.lock().shared_pending.clone();
+        (
+            Arc::as_ptr(receivers) as usize,
+            Arc::as_ptr(&shared) as usize,
+        )
+    });
+    RUNNING_RECEIVERS[cpu_id].store(receivers_addr, Ordering::SeqCst);
+    RUNNING_SHARED_PENDING[cpu_id].store(shared_addr, Ordering::SeqCst);
+}
+
+/// 通知其他核上的 running 中记录的地址为 addr 的线程
+fn kick(running: &[AtomicUsize; CPU_ID_LIMIT], addr: usize) {
+    let cpu_id = get_cpu_id();
+    for id in FIRST_CPU_ID..CPU_ID_LIMIT {
+        if id != cpu_id && running[id].load(Ordering::SeqCst) == addr {
+            send_ipi(id, IpiMessage::Kick);
+        }
+    }
+}
+
+/// 信号队列 receivers 收到信号后，如果它的线程正在其他核上运行，则让那个核立即进入 trap 处理信号
+pub fn kick_receivers(receivers: &Arc<Mutex<SignalReceivers>>) {
+    kick(&RUNNING_RECEIVERS, Arc::as_ptr(receivers) as usize);
+}
+
+/// 进程的 shared_pending 收到信号后，让所有正在运行这个进程的线程的核立即进入 trap 处理信号
+pub fn kick_shared_pending(shared_pending: &Arc<Mutex<PendingSignals>>) {
+    kick(
+        &RUNNING_SHARED_PENDING,
+        Arc::as_ptr(shared_pending) as usize,
+    );
+}
@@ -68,15 +68,32 @@ bitflags! {
 /// 没有处理函数时的默认行为。
 /// 参见 `https://venam.nixers.net/blog/unix/2016/10/21/unix-signals.html`
 pub enum SigActionDefault {
-    Terminate, // 结束进程。其实更标准的实现应该细分为 terminate / terminate(core dump) / stop
+    Terminate, // 结束进程
+    CoreDump,  // 结束进程，并生成 core dump
     Ignore,    // 忽略信号
+    Stop,      // 暂停整个进程，直到收到 SIGCONT
+    Continue,  // 让暂停的进程继续执行。实际的继续操作在发送信号时就已完成，处理时等同于忽略
 }
 
 impl SigActionDefault {
     /// 获取默认行为
     pub fn of_signal(signal: SignalNo) -> Self {
         match signal {
-            SignalNo::SIGCHLD | SignalNo::SIGURG => Self::Ignore,
+            SignalNo::SIGCHLD | SignalNo::SIGURG | SignalNo::SIGWINCH => Self::Ignore,
+            SignalNo::SIGSTOP | SignalNo::SIGTSTP | SignalNo::SIGTTIN | SignalNo::SIGTTOU => {
+                Self::Stop
+            }
+            SignalNo::SIGCONT => Self::Continue,
+            SignalNo::SIGQUIT
+            | SignalNo::SIGILL
+            | SignalNo::SIGTRAP
+            | SignalNo::SIGABRT
+            | SignalNo::SIGBUS
+            | SignalNo::SIGFPE
+            | SignalNo::SIGSEGV
+            | SignalNo::SIGXCPU
+            | SignalNo::SIGXFSZ
+            | SignalNo::SIGSYS => Self::CoreDump,
             _ => Self::Terminate,
         }
     }
@@ -1,21 +1,152 @@
 //! 触发信号时的信息。当 SigAction 指定需要信息时，需要将其返回给用户
 
-/// 错误信息
+use super::{SignalNo, FAULT_SIGNALS};
+
+/// siginfo_t 中 si_signo/si_errno/si_code 之后的 union 部分占多少个 usize。整个结构共 128 Byte
+const SIGINFO_FIELDS: usize = 14;
+/// SIGCHLD 中 si_utime/si_stime 的单位为 clock tick。时钟频率为 100Hz，即每个 clock tick 为 10000 us
+const USEC_PER_CLOCK_TICK: usize = 10000;
+
+/// 信号的附加信息，内存布局与 Linux riscv64 下的 siginfo_t 相同，共 128 Byte
 ///
-/// 详细定义见 `https://man7.org/linux/man-pages/man2/rt_sigaction.2.html`
-/// 更准确的错误信息的内容比现在实现的要多很多，但剩下的部分根据信号不同，定义也会变得非常复杂
+/// 详细定义见 `https://man7.org/linux/man-pages/man2/rt_sigaction.2.html`。
+/// 其中 si_code 之后是一个 union，根据信号来源不同，各字段的含义不同：
+/// - 用户进程发出(kill/tkill/sigqueue)：si_pid, si_uid, si_value
+/// - SIGCHLD：si_pid, si_uid, si_status, si_utime, si_stime
+/// - SIGSEGV/SIGBUS/SIGILL/SIGFPE/SIGTRAP：si_addr
+#[repr(C)]
+#[derive(Clone, Copy)]
 pub struct SigInfo {
     pub si_signo: i32,
     pub si_errno: i32,
     pub si_code: i32,
+    _pad: i32,
+    /// union 部分，按 usize 为单位存放
+    fields: [usize; SIGINFO_FIELDS],
 }
 
-impl Default for SigInfo {
-    fn default() -> Self {
+impl SigInfo {
+    /// 新建一个只有信号编号和 si_code 的信息
+    pub fn new(signum: usize, code: i32) -> Self {
         Self {
-            si_signo: 0,
+            si_signo: signum as i32,
             si_errno: 0,
-            si_code: -6, // -6 代表原因是 TKILL
+            si_code: code,
+            _pad: 0,
+            fields: [0; SIGINFO_FIELDS],
         }
     }
+    /// 由内核产生的信号
+    pub fn kernel(signum: usize) -> Self {
+        Self::new(signum, SI_KERNEL)
+    }
+    /// 由计时器到期产生的信号，如 setitimer / alarm 的 SIGALRM
+    pub fn timer(signum: usize) -> Self {
+        Self::new(signum, SI_TIMER)
+    }
+    /// 由进程 pid(用户 uid) 通过 kill/tkill 等方式发出的信号
+    pub fn user(signum: usize, code: i32, pid: usize, uid: u32) -> Self {
+        let mut info = Self::new(signum, code);
+        info.set_sender(pid, uid);
+        info
+    }
+    /// 由访存、非法指令等异常产生的信号，addr 为出错的地址
+    pub fn fault(signum: usize, code: i32, addr: usize) -> Self {
+        let mut info = Self::new(signum, code);
+        info.fields[0] = addr;
+        info
+    }
+    /// 子进程状态变化时发给父进程的 SIGCHLD。status 为退出码或导致状态变化的信号，时间以 us 为单位
+    pub fn child(
+        code: i32,
+        pid: usize,
+        uid: u32,
+        status: i32,
+        utime_us: usize,
+        stime_us: usize,
+    ) -> Self {
+        let mut info = Self::user(SignalNo::SIGCHLD as usize, code, pid, uid);
+        info.fields[1] = status as u32 as usize;
+        // si_utime / si_stime 以 clock tick 为单位
+        info.fields[2] = utime_us / USEC_PER_CLOCK_TICK;
+        info.fields[3] = stime_us / USEC_PER_CLOCK_TICK;
+        info
+    }
+    /// 是否是由当前指令触发的异常产生的信号。
+    /// 由 kill / tkill / sigqueue 或内核主动发出的同编号信号不算，它们可以像其他信号一样推迟处理
+    pub fn is_fault(&self) -> bool {
+        FAULT_SIGNALS.contains(&SignalNo::from(self.si_signo as usize))
+            && self.si_code > 0
+            && self.si_code != SI_KERNEL
+    }
+    /// 设置发送者的 si_pid 和 si_uid
+    pub fn set_sender(&mut self, pid: usize, uid: u32) {
+        self.fields[0] = (pid as u32 as usize) | ((uid as usize) << 32);
+    }
+    /// 发送者的 si_pid 和 si_uid
+    pub fn sender(&self) -> (u32, u32) {
+        (self.fields[0] as u32, (self.fields[0] >> 32) as u32)
+    }
+    /// 异常信号中出错的地址 si_addr
+    pub fn addr(&self) -> usize {
+        self.fields[0]
+    }
+    /// sigqueue 发出的 si_value，对 SIGCHLD 来说则是 si_status
+    pub fn value(&self) -> usize {
+        self.fields[1]
+    }
+    /// SIGCHLD 中子进程的 si_utime 和 si_stime，以 clock tick 为单位
+    pub fn child_times(&self) -> (usize, usize) {
+        (self.fields[2], self.fields[3])
+    }
 }
+
+// 信号来源，即 si_code 的通用取值
+/// 由 kill 发出
+pub const SI_USER: i32 = 0;
+/// 由内核发出
+pub const SI_KERNEL: i32 = 0x80;
+/// 由 sigqueue 发出
+pub const SI_QUEUE: i32 = -1;
+/// 由计时器到期发出
+pub const SI_TIMER: i32 = -2;
+/// 由 tkill / tgkill 发出
+pub const SI_TKILL: i32 = -6;
+
+// SIGILL 信号中的 si_code
+/// 非法的操作码
+pub const ILL_ILLOPC: i32 = 1;
+/// 非法的陷入
+pub const ILL_ILLTRP: i32 = 4;
+
+// SIGBUS 信号中的 si_code
+/// 地址未对齐
+pub const BUS_ADRALN: i32 = 1;
+/// 物理地址不存在
+pub const BUS_ADRERR: i32 = 2;
+
+// SIGTRAP 信号中的 si_code
+/// 断点
+pub const TRAP_BRKPT: i32 = 1;
+/// 单步执行
+pub const TRAP_TRACE: i32 = 2;
+
+// SIGSEGV 信号中的 si_code
+/// 地址没有映射
+pub const SEGV_MAPERR: i32 = 1;
+/// 地址有映射，但没有访问权限
+pub const SEGV_ACCERR: i32 = 2;
+
+// SIGCHLD 信号中的 si_code，表示子进程状态变化的原因
+/// 子进程正常退出
+pub const CLD_EXITED: i32 = 1;
+/// 子进程被信号终止
+pub const CLD_KILLED: i32 = 2;
+/// 子进程被信号终止，且生成了 core dump
+pub const CLD_DUMPED: i32 = 3;
+/// 被跟踪的子进程陷入了暂停
+pub const CLD_TRAPPED: i32 = 4;
+/// 子进程被信号暂停
+pub const CLD_STOPPED: i32 = 5;
+/// 被暂停的子进程收到 SIGCONT 恢复执行
+pub const CLD_CONTINUED: i32 = 6;
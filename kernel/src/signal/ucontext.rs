@@ -2,6 +2,11 @@
 //!
 //! 这个文件的内容修改自 zCore (`https://github.com/rcore-os/zCore/`)
 
+use crate::constants::MINSIGSTKSZ;
+use crate::trap::FpContext;
+use core::mem::size_of;
+use syscall::ErrorNo;
+
 #[repr(C)]
 #[derive(Clone, Debug)]
 pub struct SignalUserContext {
@@ -9,17 +14,20 @@ pub struct SignalUserContext {
     pub link: usize,
     pub stack: SignalStack,
     pub sig_mask: u64,
+    /// libc 中的 sigset_t 共 128 Byte，再加上把之后的 mcontext 按 16 Byte 对齐的填充
+    sig_mask_reserved: [u64; 16],
     pub context: MachineContext,
 }
 
 impl SignalUserContext {
-    pub fn init(mask: u64, pc: usize) -> Self {
+    pub fn init(mask: u64, pc: usize, stack: SignalStack, fpstate: FpContext) -> Self {
         Self {
             flags: 0,
             link: 0,
-            stack: SignalStack::default(),
+            stack,
             sig_mask: mask,
-            context: MachineContext::init_with_pc(pc),
+            sig_mask_reserved: [0; 16],
+            context: MachineContext::init(pc, fpstate),
         }
     }
     /// pthread_cancel 会用到
@@ -28,6 +36,14 @@ impl SignalUserContext {
     }
 }
 
+/// 当前正运行在备用信号栈上
+pub const SS_ONSTACK: u32 = 1;
+/// 不使用备用信号栈
+pub const SS_DISABLE: u32 = 2;
+/// 进入信号处理函数时自动停用备用信号栈，sigreturn 时恢复
+pub const SS_AUTODISARM: u32 = 1 << 31;
+
+/// 备用信号栈，即 sigaltstack 中的 stack_t。每个线程有一个
 #[repr(C)]
 #[derive(Copy, Clone, Debug)]
 pub struct SignalStack {
@@ -41,40 +57,81 @@ impl Default for SignalStack {
         // default to disabled
         SignalStack {
             sp: 0,
-            flags: 2, // 选项 DISABLE,表示不使用栈
+            flags: SS_DISABLE, // 选项 DISABLE,表示不使用栈
             size: 0,
         }
     }
 }
 
+impl SignalStack {
+    /// 检查用户通过 sigaltstack 或 ucontext 给出的栈，并转换为内核保存的形式
+    pub fn from_user(stack: &Self) -> Result<Self, ErrorNo> {
+        match stack.flags & !SS_AUTODISARM {
+            SS_DISABLE => Ok(Self::default()),
+            // SS_ONSTACK 是旧版本中的写法，效果和 0 相同
+            0 | SS_ONSTACK => {
+                if stack.size < MINSIGSTKSZ {
+                    return Err(ErrorNo::ENOMEM);
+                }
+                Ok(Self {
+                    sp: stack.sp,
+                    flags: stack.flags & SS_AUTODISARM,
+                    size: stack.size,
+                })
+            }
+            _ => Err(ErrorNo::EINVAL),
+        }
+    }
+    /// 栈是否可用
+    pub fn is_enabled(&self) -> bool {
+        self.flags & SS_DISABLE == 0
+    }
+    /// 用户栈指针 sp 是否在这个栈上。设置了 SS_AUTODISARM 时，总是视为不在栈上
+    pub fn contains(&self, sp: usize) -> bool {
+        self.is_enabled()
+            && self.flags & SS_AUTODISARM == 0
+            && sp > self.sp
+            && sp - self.sp <= self.size
+    }
+    /// 用户程序看到的栈状态，其中 flags 会根据 sp 是否在栈上设置 SS_ONSTACK
+    pub fn state(&self, sp: usize) -> Self {
+        let flags = if !self.is_enabled() {
+            SS_DISABLE
+        } else if self.contains(sp) {
+            SS_ONSTACK
+        } else {
+            0
+        };
+        Self {
+            flags: flags | (self.flags & SS_AUTODISARM),
+            ..*self
+        }
+    }
+}
+
+/// 用户上下文中的寄存器信息，布局与 Linux riscv64 下的 mcontext_t 相同
 #[repr(C)]
 #[derive(Clone, Debug)]
 pub struct MachineContext {
-    pub reserved_: [usize; 16],
-    // 目前只设置了 pc 值
+    // 通用寄存器中目前只设置了 pc 值
     pub pc: usize,
-    pub reserved: [usize; 17],
-    pub fpstate: [usize; 66],
+    /// x1~x31
+    pub reserved: [usize; 31],
+    /// 浮点寄存器。Linux 中这里是 F/D/Q 扩展状态的 union，按最大的 Q 扩展留出空间
+    pub fpstate: FpContext,
+    fpstate_reserved: [usize; MCONTEXT_FP_SIZE - size_of::<FpContext>() / size_of::<usize>()],
 }
 
-impl Default for MachineContext {
-    fn default() -> Self {
-        Self {
-            reserved_: [0; 16],
-            pc: 0,
-            reserved: [0; 17],
-            fpstate: [0; 66],
-        }
-    }
-}
+/// mcontext_t 中浮点部分占多少个 usize
+const MCONTEXT_FP_SIZE: usize = 66;
 
 impl MachineContext {
-    pub fn init_with_pc(pc: usize) -> Self {
+    pub fn init(pc: usize, fpstate: FpContext) -> Self {
         Self {
-            reserved_: [0; 16],
             pc: pc,
-            reserved: [0; 17],
-            fpstate: [0; 66],
+            reserved: [0; 31],
+            fpstate,
+            fpstate_reserved: [0; MCONTEXT_FP_SIZE - size_of::<FpContext>() / size_of::<usize>()],
         }
     }
 }
@@ -11,19 +11,41 @@ use crate::file::SyncPolicy;
 use crate::memory::PTEFlags;
 use crate::signal::SignalNo;
 use crate::task::CloneFlags;
+use timer::TimeVal;
 
 bitflags! {
-    /// 指定 sys_wait4 的选项
+    /// 指定 sys_wait4 / sys_waitid 的选项
     pub struct WaitFlags: u32 {
         /// 不挂起当前进程，直接返回
         const WNOHANG = 1 << 0;
-        /// 报告已执行结束的用户进程的状态
-        const WIMTRACED = 1 << 1;
-        /// 报告还未结束的用户进程的状态
+        /// 报告被信号暂停的子进程的状态。在 waitid 中称为 WSTOPPED
+        const WUNTRACED = 1 << 1;
+        /// 报告已执行结束的子进程的状态。wait4 默认包含这一项，waitid 则需要显式指定
+        const WEXITED = 1 << 2;
+        /// 报告被 SIGCONT 恢复执行的子进程的状态
         const WCONTINUED = 1 << 3;
+        /// 只报告状态，不回收子进程，也不清除状态。仅用于 waitid
+        const WNOWAIT = 1 << 24;
+        /// 只等待当前线程的子进程，而不是线程组中所有线程的子进程
+        const __WNOTHREAD = 1 << 29;
+        /// 等待所有子进程，无论它们退出时是否发送 SIGCHLD
+        const __WALL = 1 << 30;
+        /// 只等待退出时不发送 SIGCHLD 的子进程(即 "clone" 子进程)
+        const __WCLONE = 1 << 31;
     }
 }
 
+// sys_waitid 使用的 idtype 选项
+/// 等待任意子进程
+pub const P_ALL: i32 = 0;
+/// 等待 id 指定的子进程
+pub const P_PID: i32 = 1;
+/// 等待进程组为 id 的任意子进程
+pub const P_PGID: i32 = 2;
+
+/// sys_setgroups 最多可设置的附加用户组个数
+pub const NGROUPS_MAX: usize = 65536;
+
 bitflags! {
     /// 指定 mmap 的选项
     pub struct MMAPPROT: u32 {
@@ -228,22 +250,164 @@ pub fn resolve_clone_flags_and_signal(flag: usize) -> (CloneFlags, SignalNo) {
     )
 }
 
-/// sys_prlimit64 使用的数组
+/// sys_clone3 的参数，所有字段都是 8 字节。
+/// 详见 `https://man7.org/linux/man-pages/man2/clone3.2.html`
 #[repr(C)]
-pub struct RLimit {
-    /// 软上限
-    pub rlim_cur: u64,
-    /// 硬上限
-    pub rlim_max: u64,
+#[derive(Clone, Copy, Default)]
+pub struct CloneArgs {
+    /// clone 选项，与 sys_clone 不同，低 8 位不再用于指定信号
+    pub flags: u64,
+    /// 带有 CLONE_PIDFD 时，指向子进程的 pidfd 被写入这个地址
+    pub pidfd: u64,
+    /// 带有 CLONE_CHILD_SETTID / CLONE_CHILD_CLEARTID 时使用的子任务地址空间中的地址
+    pub child_tid: u64,
+    /// 带有 CLONE_PARENT_SETTID 时，子任务的 tid 被写入这个地址
+    pub parent_tid: u64,
+    /// 子进程退出时向父进程发送的信号
+    pub exit_signal: u64,
+    /// 子任务用户栈的最低地址
+    pub stack: u64,
+    /// 子任务用户栈的大小
+    pub stack_size: u64,
+    /// 带有 CLONE_SETTLS 时，子任务的 tp 值
+    pub tls: u64,
+    /// 指向一个数组，指定子任务在各层 pid 命名空间中的 tid
+    pub set_tid: u64,
+    /// set_tid 数组的长度
+    pub set_tid_size: u64,
+    /// 带有 CLONE_INTO_CGROUP 时使用，目前不支持
+    pub cgroup: u64,
 }
 
-// sys_prlimit64 使用的选项
-/// 用户栈大小
-pub const RLIMIT_STACK: i32 = 3;
-/// 可以打开的 fd 数
-pub const RLIMIT_NOFILE: i32 = 7;
-/// 用户地址空间的最大大小
-pub const RLIMIT_AS: i32 = 9;
+/// 最早版本的 clone_args 长度，sys_clone3 传入的 size 不能小于这个值
+pub const CLONE_ARGS_SIZE_VER0: usize = 64;
+
+/// sys_pidfd_open 的选项，使 pidfd 带有 O_NONBLOCK
+pub const PIDFD_NONBLOCK: u32 = 1 << 11;
+
+/// sys_signalfd4 的选项，使 signalfd 带有 O_NONBLOCK
+pub const SFD_NONBLOCK: u32 = 1 << 11;
+/// sys_signalfd4 的选项，使 signalfd 带有 O_CLOEXEC
+pub const SFD_CLOEXEC: u32 = 1 << 19;
+
+/// st_mode 中表示文件类型的位，用于 sys_mknodat
+pub const S_IFMT: u32 = 0o170000;
+
+// sys_prctl 的操作
+/// 设置父进程退出时向自己发送的信号
+pub const PR_SET_PDEATHSIG: i32 = 1;
+/// 获取父进程退出时向自己发送的信号
+pub const PR_GET_PDEATHSIG: i32 = 2;
+/// 设置线程名
+pub const PR_SET_NAME: i32 = 15;
+/// 获取线程名
+pub const PR_GET_NAME: i32 = 16;
+/// 设置当前进程是否为子进程回收者
+pub const PR_SET_CHILD_SUBREAPER: i32 = 36;
+/// 获取当前进程是否为子进程回收者
+pub const PR_GET_CHILD_SUBREAPER: i32 = 37;
+/// 禁止通过 exec 获得新的权限
+pub const PR_SET_NO_NEW_PRIVS: i32 = 38;
+/// 获取是否禁止通过 exec 获得新的权限
+pub const PR_GET_NO_NEW_PRIVS: i32 = 39;
+
+numeric_enum_macro::numeric_enum! {
+    #[repr(usize)]
+    #[allow(non_camel_case_types)]
+    #[derive(Debug, PartialEq, Eq)]
+    /// sys_ptrace 的操作
+    pub enum PtraceRequest {
+        /// 让父进程跟踪自己
+        TRACEME = 0,
+        /// 读取被跟踪的任务的代码段中的一个字
+        PEEKTEXT = 1,
+        /// 读取被跟踪的任务的数据段中的一个字
+        PEEKDATA = 2,
+        /// 读取 user area 中的一个字，riscv64 上不支持
+        PEEKUSER = 3,
+        /// 修改被跟踪的任务的代码段中的一个字
+        POKETEXT = 4,
+        /// 修改被跟踪的任务的数据段中的一个字
+        POKEDATA = 5,
+        /// 恢复被跟踪的任务的运行
+        CONT = 7,
+        /// 杀死被跟踪的任务
+        KILL = 8,
+        /// 让被跟踪的任务执行一条指令后暂停
+        SINGLESTEP = 9,
+        /// 读取通用寄存器
+        GETREGS = 12,
+        /// 修改通用寄存器
+        SETREGS = 13,
+        /// 开始跟踪一个任务，并让它暂停
+        ATTACH = 16,
+        /// 停止跟踪
+        DETACH = 17,
+        /// 让被跟踪的任务运行到下一次进入或退出系统调用时暂停
+        SYSCALL = 24,
+        /// 设置跟踪选项
+        SETOPTIONS = 0x4200,
+        /// 获取最近一次事件的信息
+        GETEVENTMSG = 0x4201,
+        /// 获取导致暂停的信号的信息
+        GETSIGINFO = 0x4202,
+        /// 按类型读取寄存器
+        GETREGSET = 0x4204,
+        /// 按类型修改寄存器
+        SETREGSET = 0x4205,
+        /// 开始跟踪一个任务，但不让它暂停
+        SEIZE = 0x4206,
+        /// 让通过 SEIZE 跟踪的任务暂停
+        INTERRUPT = 0x4207,
+    }
+}
+
+/// sys_ptrace 的 GETREGSET / SETREGSET 中表示通用寄存器的类型
+pub const NT_PRSTATUS: usize = 1;
+/// sys_ptrace 的 GETREGSET / SETREGSET 中表示浮点寄存器的类型
+pub const NT_PRFPREG: usize = 2;
+
+/// sys_wait4 / sys_getrusage 使用的资源统计结构，
+/// 详见 `https://man7.org/linux/man-pages/man2/getrusage.2.html`
+#[repr(C)]
+#[derive(Default)]
+pub struct RUsage {
+    /// 用户态运行时间
+    pub ru_utime: TimeVal,
+    /// 内核态运行时间
+    pub ru_stime: TimeVal,
+    /// 其余统计项(从 ru_maxrss 到 ru_nivcsw)，目前都不统计，填 0
+    pub ru_others: [isize; 14],
+}
+
+/// sys_waitid 写入用户的 siginfo_t 结构，只包含 SIGCHLD 用到的字段。
+/// 完整的 siginfo_t 长度为 128 Byte，剩余部分填 0
+#[repr(C)]
+#[derive(Default)]
+pub struct WaitIdInfo {
+    /// 信号编号，总是 SIGCHLD
+    pub si_signo: i32,
+    /// 在 Linux 中不用
+    pub si_errno: i32,
+    /// 子进程状态变化的原因，即 CLD_EXITED / CLD_KILLED 等
+    pub si_code: i32,
+    /// 对齐用
+    _pad: i32,
+    /// 子进程的 pid
+    pub si_pid: i32,
+    /// 子进程的实际用户 id
+    pub si_uid: u32,
+    /// 子进程的退出码，或导致状态变化的信号编号
+    pub si_status: i32,
+    /// 对齐用
+    _pad2: i32,
+    /// 子进程用户态运行时间，以时钟周期计
+    pub si_utime: isize,
+    /// 子进程内核态运行时间，以时钟周期计
+    pub si_stime: isize,
+    /// 填充到 128 Byte
+    _reserved: [usize; 10],
+}
 
 numeric_enum_macro::numeric_enum! {
     #[repr(usize)]
@@ -263,6 +427,10 @@ numeric_enum_macro::numeric_enum! {
         F_SETFL = 4,
         /// 复制 fd，然后设置 cloexec 信息，即 exec 成功时删除该 fd
         F_DUPFD_CLOEXEC = 1030,
+        /// 设置管道的大小
+        F_SETPIPE_SZ = 1031,
+        /// 获取管道的大小
+        F_GETPIPE_SZ = 1032,
     }
 }
 
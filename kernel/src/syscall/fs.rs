@@ -7,20 +7,24 @@
 
 use super::{
     Dirent64, Dirent64Type, Fcntl64Cmd, IoVec, RenameFlags, SysResult, UtimensatFlags, SEEK_CUR,
-    SEEK_END, SEEK_SET,
+    SEEK_END, SEEK_SET, S_IFMT,
 };
 use crate::{
     constants::{AT_FDCWD, SENDFILE_BUFFER_SIZE},
     file::{
-        check_dir_exists, check_file_exists, get_dir_entry_iter, mkdir, mount_fat_fs, open_file,
-        origin_fs_stat, read_link, rename_or_move, try_add_link, try_remove_link, umount_fat_fs,
+        can_open_virt_file, check_dir_exists, check_file_exists, fifo_names_in_dir,
+        get_dir_entry_iter, get_dir_perm, get_fifo_stat, get_file_perm, link_fifo, make_fifo,
+        mkdir, mount_fat_fs, open_fifo, open_file, origin_fs_stat, read_link, rename_or_move,
+        set_fifo_times, set_file_perm, try_add_link, try_remove_link, umount_fat_fs,
     },
-    file::{FatFile, FsStat, Pipe, SeekFrom},
-    task::{get_current_task, TaskControlBlock},
+    file::{FatFile, FilePerm, FsStat, Pipe, SeekFrom},
+    signal::{send_signal, SignalNo},
+    task::{get_current_task, AccessMode, TaskControlBlock, RLIMIT_FSIZE, RLIM_INFINITY},
     utils::raw_ptr_to_ref_str,
 };
 use alloc::{string::String, sync::Arc};
-use base_file::{Kstat, OpenFlags};
+use base_file::{File, Kstat, OpenFlags, StMode};
+use core::mem::size_of;
 use syscall::ErrorNo;
 use timer::TimeSpec;
 
@@ -28,7 +32,8 @@ use timer::TimeSpec;
 pub fn sys_getcwd(buf: *mut u8, len: usize) -> SysResult {
     let task = get_current_task().unwrap();
     let tcb_inner = task.inner.lock();
-    let mut task_vm = task.vm.lock();
+    let vm = task.get_vm();
+    let mut task_vm = vm.lock();
     if task_vm.manually_alloc_page(buf as usize).is_err() {
         return Err(ErrorNo::EFAULT); // 地址不合法
     }
@@ -63,7 +68,8 @@ pub fn sys_read(fd: usize, buf: *mut u8, len: usize) -> SysResult {
     info!("sys_read fd {fd}");
     let task = get_current_task().unwrap();
     let tcb_inner = task.inner.lock();
-    let mut task_vm = task.vm.lock();
+    let vm = task.get_vm();
+    let mut task_vm = vm.lock();
     info!("sys_read fd {} buf {:x} len {}", fd, buf as usize, len);
     if task_vm.manually_alloc_user_str(buf, len).is_err() {
         return Err(ErrorNo::EFAULT); // 地址不合法
@@ -77,10 +83,7 @@ pub fn sys_read(fd: usize, buf: *mut u8, len: usize) -> SysResult {
         // 读文件可能触发进程切换
         drop(tcb_inner);
         drop(task_vm);
-        if let Some(read_len) = file.read(slice) {
-            //println!("[kernel] read syscall size {} wanted {}", read_len, len);
-            return Ok(read_len);
-        }
+        return file.try_read(slice);
     }
     Err(ErrorNo::EINVAL)
 }
@@ -90,7 +93,8 @@ pub fn sys_write(fd: usize, buf: *const u8, len: usize) -> SysResult {
     info!("sys_write fd {fd}");
     let task = get_current_task().unwrap();
     //let tcb_inner = task.inner.lock();
-    let mut task_vm = task.vm.lock();
+    let vm = task.get_vm();
+    let mut task_vm = vm.lock();
     //println!("write pos {:x}", buf as usize);
     /*
     let buf = buf as usize;
@@ -110,13 +114,42 @@ pub fn sys_write(fd: usize, buf: *const u8, len: usize) -> SysResult {
         // 写文件也可能触发进程切换
         //drop(tcb_inner);
         drop(task_vm); //及时去锁，可能其他程序要用
-        if let Some(write_len) = file.write(slice) {
-            return Ok(write_len);
-        }
+        let len = check_file_size_limit(&task, &file, len)?;
+        return file.try_write(&slice[..len]);
     }
     Err(ErrorNo::EINVAL)
 }
 
+/// 检查向文件写入 len 字节是否会超过 RLIMIT_FSIZE，返回实际可以写入的长度。
+/// 如果当前写入的位置已经达到上限，则向当前线程发送 SIGXFSZ 并返回 EFBIG
+///
+/// 以 O_APPEND 打开的文件总是写在文件末尾，所以按文件大小而不是当前位置检查。
+/// 不能 seek 的文件(如管道、标准输出)没有文件大小的概念，不受这个限制
+fn check_file_size_limit(
+    task: &Arc<TaskControlBlock>,
+    file: &Arc<dyn File>,
+    len: usize,
+) -> Result<usize, ErrorNo> {
+    let limit = task.rlimits.lock().get_soft(RLIMIT_FSIZE);
+    if len == 0 || limit == RLIM_INFINITY {
+        return Ok(len);
+    }
+    // 对于 O_APPEND 的文件，移动到末尾不影响之后的写入位置
+    let pos = if file.get_status().contains(OpenFlags::APPEND) {
+        file.seek(SeekFrom::End(0))
+    } else {
+        file.seek(SeekFrom::Current(0))
+    };
+    match pos {
+        Some(pos) if pos as u64 >= limit => {
+            send_signal(task.get_tid_num(), SignalNo::SIGXFSZ as usize);
+            Err(ErrorNo::EFBIG)
+        }
+        Some(pos) => Ok(len.min((limit - pos as u64) as usize)),
+        None => Ok(len),
+    }
+}
+
 /// 从同一个 fd 中读取一组字符串。
 /// 目前这个 syscall 借用 sys_read 来实现
 pub fn sys_readv(fd: usize, iov: *mut IoVec, iov_cnt: usize) -> SysResult {
@@ -126,6 +159,8 @@ pub fn sys_readv(fd: usize, iov: *mut IoVec, iov_cnt: usize) -> SysResult {
         let io_vec: &IoVec = unsafe { &*iov.add(i) };
         match sys_read(fd, io_vec.base, io_vec.len) {
             Ok(len) => read_len += len,
+            // 一个字节都没读到时，需要让用户知道出错的原因，如 EAGAIN 或被信号打断
+            Err(err) if read_len == 0 => return Err(err),
             Err(_) => {
                 break;
             }
@@ -148,6 +183,8 @@ pub fn sys_writev(fd: usize, iov: *const IoVec, iov_cnt: usize) -> SysResult {
         }
         match sys_write(fd, io_vec.base, io_vec.len) {
             Ok(len) => written_len += len,
+            // 一个字节都没写入时，需要让用户知道出错的原因，如文件超过了大小限制或管道已关闭
+            Err(err) if written_len == 0 => return Err(err),
             Err(_) => {
                 break;
             }
@@ -160,7 +197,8 @@ pub fn sys_writev(fd: usize, iov: *const IoVec, iov_cnt: usize) -> SysResult {
 pub fn sys_pread(fd: usize, buf: *mut u8, count: usize, offset: usize) -> SysResult {
     let task = get_current_task().unwrap();
     let tcb_inner = task.inner.lock();
-    let mut task_vm = task.vm.lock();
+    let vm = task.get_vm();
+    let mut task_vm = vm.lock();
     info!(
         "sys_pread fd {} buf {:x} count {} offset {}",
         fd, buf as usize, count, offset
@@ -202,7 +240,8 @@ pub fn sys_readlinkat(dir_fd: i32, path: *const u8, buf: *mut u8, len: usize) ->
         pid, tid, dir_fd, tmp_path, buf, len
     );
 
-    let mut task_vm = task.vm.lock();
+    let vm = task.get_vm();
+    let mut task_vm = vm.lock();
 
     if task_vm.manually_alloc_page(path as usize).is_err()
         || task_vm.manually_alloc_user_str(buf, len).is_err()
@@ -228,26 +267,65 @@ pub fn sys_readlinkat(dir_fd: i32, path: *const u8, buf: *mut u8, len: usize) ->
     Err(ErrorNo::EINVAL)
 }
 
-pub fn sys_access(dir_fd: i32, path: *const u8, _mode: usize) -> SysResult {
+/// 检查当前进程能否按 mode 指定的方式访问文件。
+/// mode 为 0 (F_OK) 时只检查文件是否存在，否则为 R_OK / W_OK / X_OK 的组合。
+///
+/// 检查权限时使用实际用户 id 和实际用户组 id，而不是有效 id
+pub fn sys_access(dir_fd: i32, path: *const u8, mode: usize) -> SysResult {
+    let access = AccessMode::from_bits(mode as u32).ok_or(ErrorNo::EINVAL)?;
     let task = get_current_task().unwrap();
-    let mut task_vm = task.vm.lock();
+    let vm = task.get_vm();
+    let mut task_vm = vm.lock();
     if task_vm.manually_alloc_page(path as usize).is_err() {
         return Err(ErrorNo::EFAULT); // 地址不合法
     }
     if let Some((path, file)) = resolve_path_from_fd(&task, dir_fd, path) {
         info!("access : path {} file {}", path, file);
-        if check_file_exists(path.as_str(), file) {
-            Ok(0)
-        } else if check_dir_exists((path + file).as_str()) {
+        if !check_file_exists(path.as_str(), file)
+            && !check_dir_exists((path.clone() + file).as_str())
+        {
+            return Err(ErrorNo::ENOENT);
+        }
+        let perm = get_file_perm(path.as_str(), file);
+        if task.credentials.lock().can_access(&perm, access, true) {
             Ok(0)
         } else {
-            Err(ErrorNo::ENOENT)
+            Err(ErrorNo::EACCES)
         }
     } else {
         Err(ErrorNo::EINVAL)
     }
 }
 
+/// 修改文件或目录的权限位。只有文件所有者或 root 可以修改
+///
+/// 非 root 用户设置 S_ISGID 时，如果不属于文件所在用户组，则这一位会被忽略
+pub fn sys_fchmodat(dir_fd: i32, path: *const u8, mode: usize) -> SysResult {
+    let task = get_current_task().unwrap();
+    let vm = task.get_vm();
+    let mut task_vm = vm.lock();
+    if task_vm.manually_alloc_page(path as usize).is_err() {
+        return Err(ErrorNo::EFAULT);
+    }
+    drop(task_vm);
+    let (path, file) = resolve_path_from_fd(&task, dir_fd, path).ok_or(ErrorNo::EINVAL)?;
+    if !check_file_exists(path.as_str(), file) && !check_dir_exists((path.clone() + file).as_str())
+    {
+        return Err(ErrorNo::ENOENT);
+    }
+    let mut perm = get_file_perm(path.as_str(), file);
+    let credentials = task.credentials.lock();
+    if !credentials.is_privileged() && credentials.uid.effective != perm.uid {
+        return Err(ErrorNo::EPERM);
+    }
+    perm.mode = mode as u32 & 0o7777;
+    if !credentials.is_privileged() && !credentials.in_group(perm.gid) {
+        perm.mode &= !StMode::S_ISGID.bits();
+    }
+    set_file_perm(path.as_str(), file, perm);
+    Ok(0)
+}
+
 /// 获取文件状态信息
 pub fn sys_fstat(fd: usize, kstat: *mut Kstat) -> SysResult {
     let task = get_current_task().unwrap();
@@ -266,6 +344,10 @@ pub fn sys_fstatat(dir_fd: i32, path: *const u8, kstat: *mut Kstat) -> SysResult
         if file.contains("tmp/cc") {
             return Ok(0);
         }
+        // FIFO 不能通过打开来获取信息，否则会被视为打开了它的一端
+        if get_fifo_stat(path.as_str(), file, kstat) {
+            return Ok(0);
+        }
         if let Some(file) = open_file(path.as_str(), file, OpenFlags::empty()) {
             if file.get_stat(kstat) {
                 return Ok(0);
@@ -354,6 +436,12 @@ pub fn sys_linkat(
     let task = get_current_task().unwrap();
     if let Some((old_path, old_file)) = resolve_path_from_fd(&task, old_dir_fd, old_path) {
         if let Some((new_path, new_file)) = resolve_path_from_fd(&task, new_dir_fd, new_path) {
+            // FIFO 不在 FAT 中，需要在 FIFO 的表中添加新的路径
+            if let Some(linked) =
+                link_fifo(old_path.as_str(), old_file, new_path.as_str(), new_file)
+            {
+                return if linked { Ok(0) } else { Err(ErrorNo::EEXIST) };
+            }
             if try_add_link(old_path, old_file, new_path, new_file) {
                 return Ok(0);
             }
@@ -442,13 +530,64 @@ pub fn sys_mkdir(dir_fd: i32, path: *const u8, _user_mode: u32) -> SysResult {
     Err(ErrorNo::EINVAL)
 }
 
+/// 当前进程能否在 dir_name 下创建文件 file_path，即是否有所在目录的写和执行权限
+fn can_create_file(task: &TaskControlBlock, dir_name: &str, file_path: &str) -> bool {
+    let path = [dir_name, file_path].concat();
+    let path = path.trim_end_matches('/');
+    let parent = path.rfind('/').map_or("", |pos| &path[..=pos]);
+    task.credentials.lock().can_access(
+        &get_dir_perm(parent),
+        AccessMode::W_OK | AccessMode::X_OK,
+        false,
+    )
+}
+
+/// 创建特殊文件。目前只支持 FIFO 和普通文件，其他类型返回 EPERM
+///
+/// 文件已存在时返回 EEXIST，所在目录不存在时返回 ENOENT，没有所在目录的写和执行权限时返回 EACCES
+pub fn sys_mknodat(dir_fd: i32, path: *const u8, mode: u32, _dev: usize) -> SysResult {
+    let task = get_current_task().unwrap();
+    let (parent_dir, file_path) =
+        resolve_path_from_fd(&task, dir_fd, path).ok_or(ErrorNo::EINVAL)?;
+    if check_file_exists(parent_dir.as_str(), file_path)
+        || check_dir_exists(&[parent_dir.as_str(), file_path].concat())
+    {
+        return Err(ErrorNo::EEXIST);
+    }
+    if !can_create_file(&task, parent_dir.as_str(), file_path) {
+        return Err(ErrorNo::EACCES);
+    }
+    let file_type = mode & S_IFMT;
+    let created = if file_type == 0 || file_type == StMode::S_IFREG.bits() {
+        open_file(parent_dir.as_str(), file_path, OpenFlags::CREATE).is_some()
+    } else if file_type == StMode::S_IFIFO.bits() {
+        make_fifo(parent_dir.as_str(), file_path)
+    } else {
+        return Err(ErrorNo::EPERM);
+    };
+    if !created {
+        return Err(ErrorNo::ENOENT);
+    }
+    // 新建的文件属于当前进程的有效用户和用户组
+    let umask = task.fd_manager.lock().get_umask() as u32;
+    let credentials = task.credentials.lock();
+    let perm = FilePerm {
+        mode: mode & !umask & 0o7777,
+        uid: credentials.uid.effective,
+        gid: credentials.gid.effective,
+    };
+    set_file_perm(parent_dir.as_str(), file_path, perm);
+    Ok(0)
+}
+
 /// 切换当前工作路径，如果以.开头，默认是相对路径；如果以/开头，默认是绝对路径。切换成功时返回0，失败时返回-1
 ///
 /// 会先检查要切换到的路径是否存在。
 pub fn sys_chdir(path: *const u8) -> SysResult {
     let task = get_current_task().unwrap();
     let mut tcb_inner = task.inner.lock();
-    let mut task_vm = task.vm.lock();
+    let vm = task.get_vm();
+    let mut task_vm = vm.lock();
     if task_vm.manually_alloc_page(path as usize).is_err() {
         return Err(ErrorNo::EINVAL);
     }
@@ -479,7 +618,8 @@ pub fn sys_chdir(path: *const u8) -> SysResult {
 pub fn sys_open(dir_fd: i32, path: *const u8, flags: u32, user_mode: i32) -> SysResult {
     let task = get_current_task().unwrap();
     let mut task_fd_manager = task.fd_manager.lock();
-    let mut task_vm = task.vm.lock();
+    let vm = task.get_vm();
+    let mut task_vm = vm.lock();
     // 如果 fd 已满，则不再添加
     if task_fd_manager.is_full() {
         return Err(ErrorNo::EMFILE);
@@ -517,7 +657,58 @@ pub fn sys_open(dir_fd: i32, path: *const u8, flags: u32, user_mode: i32) -> Sys
         if let Some(open_flags) = OpenFlags::from_bits(flags) {
             info!("[{:#?}]", open_flags);
             //println!("opened");
-            if let Some(node) = open_file(parent_dir.as_str(), file_path.as_str(), open_flags) {
+            // vfs 中的特殊文件不在权限表中，单独检查
+            if !can_open_virt_file(parent_dir.as_str(), file_path.as_str()) {
+                return Err(ErrorNo::EACCES);
+            }
+            // 已存在的文件需要检查权限
+            let existed = check_file_exists(parent_dir.as_str(), file_path.as_str());
+            if existed {
+                let (readable, writable) = open_flags.read_write();
+                let mut access = AccessMode::empty();
+                access.set(AccessMode::R_OK, readable);
+                access.set(AccessMode::W_OK, writable);
+                let perm = get_file_perm(parent_dir.as_str(), file_path.as_str());
+                if !task.credentials.lock().can_access(&perm, access, false) {
+                    return Err(ErrorNo::EACCES);
+                }
+            }
+            // FIFO 只在这里打开，打开后还需要等待另一端
+            let node = match open_fifo(parent_dir.as_str(), file_path.as_str(), open_flags) {
+                Some(_) if open_flags.contains(OpenFlags::EXCL) => return Err(ErrorNo::EEXIST),
+                Some(_) if open_flags.contains(OpenFlags::DIR) => return Err(ErrorNo::ENOTDIR),
+                Some(fifo) => Some(Arc::new(fifo) as Arc<dyn File>),
+                // 新建文件需要有所在目录的写和执行权限
+                None if !existed
+                    && open_flags.contains(OpenFlags::CREATE)
+                    && !can_create_file(&task, parent_dir.as_str(), file_path.as_str()) =>
+                {
+                    return Err(ErrorNo::EACCES)
+                }
+                None => open_file(parent_dir.as_str(), file_path.as_str(), open_flags),
+            };
+            if let Some(node) = node {
+                if !existed && open_flags.contains(OpenFlags::CREATE) {
+                    // 新建的文件属于当前进程的有效用户和用户组
+                    let credentials = task.credentials.lock();
+                    let perm = FilePerm {
+                        mode: (user_mode & !task_fd_manager.get_umask()) as u32 & 0o7777,
+                        uid: credentials.uid.effective,
+                        gid: credentials.gid.effective,
+                    };
+                    set_file_perm(parent_dir.as_str(), file_path.as_str(), perm);
+                }
+                if let Some(fifo) = (*node).as_any().downcast_ref::<Pipe>() {
+                    // 打开 FIFO 时可能要等待另一端被打开，等待时不能持有锁
+                    drop(task_vm);
+                    drop(task_fd_manager);
+                    fifo.wait_for_peer()?;
+                    return task
+                        .fd_manager
+                        .lock()
+                        .push(node)
+                        .map_err(|_| ErrorNo::EMFILE);
+                }
                 if let Ok(fd) = task_fd_manager.push(node) {
                     //info!("return fd {}", fd);
                     //add_sys_info(parent_dir.clone() + file_path.as_str());
@@ -551,11 +742,26 @@ pub fn sys_close(fd: usize) -> SysResult {
 /// 创建管道，在 *pipe 记录读管道的 fd，在 *(pipe+1) 记录写管道的 fd。
 /// 成功时返回 0，失败则返回 -1
 ///
+/// flags 可以包含 O_CLOEXEC / O_NONBLOCK / O_DIRECT，其他值返回 EINVAL
+///
 /// 注意，因为
-pub fn sys_pipe(pipe: *mut u32) -> SysResult {
+pub fn sys_pipe2(pipe: *mut u32, flags: u32) -> SysResult {
+    let flags = OpenFlags::from_bits(flags)
+        .filter(|flags| {
+            (OpenFlags::CLOEXEC | OpenFlags::NON_BLOCK | OpenFlags::DIRECT).contains(*flags)
+        })
+        .ok_or(ErrorNo::EINVAL)?;
     let task = get_current_task().unwrap();
+    if task
+        .get_vm()
+        .lock()
+        .manually_alloc_user_str(pipe as *const u8, 2 * size_of::<u32>())
+        .is_err()
+    {
+        return Err(ErrorNo::EFAULT);
+    }
     let mut task_fd_manager = task.fd_manager.lock();
-    let (pipe_read, pipe_write) = Pipe::new_pipe();
+    let (pipe_read, pipe_write) = Pipe::new_pipe(flags);
     if let Ok(fd1) = task_fd_manager.push(Arc::new(pipe_read)) {
         if let Ok(fd2) = task_fd_manager.push(Arc::new(pipe_write)) {
             unsafe {
@@ -605,7 +811,8 @@ pub fn sys_getdents64(fd: usize, buf: *mut u8, len: usize) -> SysResult {
     }
     if let Some(dir) = get_dir_from_fd(&task, fd as i32) {
         // 获取实际目录
-        let mut task_vm = task.vm.lock();
+        let vm = task.get_vm();
+        let mut task_vm = vm.lock();
         if task_vm.manually_alloc_page(buf as usize).is_err()
             || task_vm.manually_alloc_page(buf as usize + len - 1).is_err()
         {
@@ -613,14 +820,21 @@ pub fn sys_getdents64(fd: usize, buf: *mut u8, len: usize) -> SysResult {
         }
         if let Some(dir_iter) = get_dir_entry_iter(dir.as_str()) {
             let mut offset = 0; // buf 共有 len 长，当前将 buf.add(offset) 视为一个结构 Dirent64
-            for entry in dir_iter {
+
+            // FIFO 不在 FAT 中，排在目录中其他文件的后面
+            let fifos = fifo_names_in_dir(dir.as_str())
+                .into_iter()
+                .map(|name| (name, Dirent64Type::FIFO));
+            let entries = dir_iter.map(|entry| {
                 let file = entry.unwrap();
-                let file_name = file.file_name();
                 let file_type = if file.is_dir() {
                     Dirent64Type::DIR
                 } else {
                     Dirent64Type::REG
                 };
+                (file.file_name(), file_type)
+            });
+            for (file_name, file_type) in entries.chain(fifos) {
                 // 当前的这一项如果要放到用户给的 buf 里，会有多大
                 let entry_size = Dirent64::d_name_offset() + file_name.len() + 1;
                 // 如果放进去会超过 buffer 大小，则就此退出
@@ -665,7 +879,8 @@ pub fn sys_utimensat(
     if dir_fd != AT_FDCWD && dir_fd < 0 {
         return Err(ErrorNo::EBADF); // 错误的文件描述符
     }
-    let mut task_vm = task.vm.lock();
+    let vm = task.get_vm();
+    let mut task_vm = vm.lock();
     let fd_manager = task.fd_manager.lock();
     //info!("fd {} buf {} len {}", fd, buf as usize, len);
     if dir_fd == AT_FDCWD && task_vm.manually_alloc_page(path as usize).is_err() {
@@ -692,6 +907,10 @@ pub fn sys_utimensat(
         }
     } else if let Some((parent_dir, file_path)) = resolve_path_from_fd(&task, dir_fd, path) {
         if check_file_exists(parent_dir.as_str(), file_path) {
+            // FIFO 不能通过 open_file 打开，它的时间记录在 FIFO 的表中
+            if set_fifo_times(parent_dir.as_str(), file_path, &new_atime, &new_mtime) {
+                return Ok(0);
+            }
             if let Some(file) = open_file(parent_dir.as_str(), file_path, OpenFlags::empty()) {
                 if let Some(fat_file) = file.as_any().downcast_ref::<FatFile>() {
                     let mut inner = fat_file.inner.lock();
@@ -797,6 +1016,14 @@ pub fn sys_fcntl64(fd: usize, cmd: usize, arg: usize) -> SysResult {
                     Err(ErrorNo::EMFILE)
                 }
             }
+            Ok(Fcntl64Cmd::F_GETPIPE_SZ) => match (*file).as_any().downcast_ref::<Pipe>() {
+                Some(pipe) => Ok(pipe.get_size()),
+                None => Err(ErrorNo::EBADF),
+            },
+            Ok(Fcntl64Cmd::F_SETPIPE_SZ) => match (*file).as_any().downcast_ref::<Pipe>() {
+                Some(pipe) => pipe.set_size(arg),
+                None => Err(ErrorNo::EBADF),
+            },
             _ => Err(ErrorNo::EINVAL),
         };
     }
@@ -809,7 +1036,8 @@ pub fn sys_fcntl64(fd: usize, cmd: usize, arg: usize) -> SysResult {
 pub fn sys_sendfile64(out_fd: usize, in_fd: usize, offset: *mut usize, count: usize) -> SysResult {
     //file.seek(SeekFrom::Current(0)).unwrap()
     let task = get_current_task().unwrap();
-    let mut task_vm = task.vm.lock();
+    let vm = task.get_vm();
+    let mut task_vm = vm.lock();
     let fd_manager = task.fd_manager.lock();
     info!(
         "sendfile out fd {out_fd} in fd {in_fd} offset {:x} count {count}",
@@ -833,28 +1061,39 @@ pub fn sys_sendfile64(out_fd: usize, in_fd: usize, offset: *mut usize, count: us
             // 读取最多 count 字符
             // 这里目前直接限制了最大读取长度，没有分次读取
             // todo: 使用 buffer 分次读，避免一次读取太多到内存里
+            let count = check_file_size_limit(&task, &out_file, count)?;
             let mut buf = vec![0u8; count.min(SENDFILE_BUFFER_SIZE)];
 
-            if let Some(read_len) = in_file.read(&mut buf) {
-                if let Some(write_len) = out_file.write(&buf[..read_len]) {
-                    if offset as usize != 0 {
-                        // offset 非零则要求不更新实际文件，更新这个用户给的值
-                        unsafe {
-                            *offset += write_len;
-                        }
-                        in_file
-                            .seek(SeekFrom::Start(current_pos as u64))
-                            .unwrap_or(0);
-                    } else if write_len != read_len {
-                        // 否则更新实际文件，此时如果写不完要退回去
-                        in_file
-                            .seek(SeekFrom::Current(write_len as i64 - read_len as i64))
-                            .unwrap_or(0);
-                    }
-                    return Ok(write_len);
+            // 读写管道时可能阻塞，不能持有锁
+            drop(task_vm);
+            drop(fd_manager);
+            // 读写管道时可能返回 EAGAIN、EPIPE 或被信号打断，需要原样返回给用户
+            let read_len = in_file.try_read(&mut buf)?;
+            let write_len = match out_file.try_write(&buf[..read_len]) {
+                Ok(write_len) => write_len,
+                Err(err) => {
+                    // 一个字节都没写入，读出的数据要退回去
+                    in_file
+                        .seek(SeekFrom::Start(current_pos as u64))
+                        .unwrap_or(0);
+                    return Err(err);
                 }
+            };
+            if offset as usize != 0 {
+                // offset 非零则要求不更新实际文件，更新这个用户给的值
+                unsafe {
+                    *offset += write_len;
+                }
+                in_file
+                    .seek(SeekFrom::Start(current_pos as u64))
+                    .unwrap_or(0);
+            } else if write_len != read_len {
+                // 否则更新实际文件，此时如果写不完要退回去
+                in_file
+                    .seek(SeekFrom::Current(write_len as i64 - read_len as i64))
+                    .unwrap_or(0);
             }
-            return Err(ErrorNo::EINVAL);
+            return Ok(write_len);
         }
     }
     Err(ErrorNo::EBADF)
@@ -870,7 +1109,8 @@ pub fn sys_renameat2(
     flags: RenameFlags,
 ) -> SysResult {
     let task = get_current_task().unwrap();
-    let mut task_vm = task.vm.lock();
+    let vm = task.get_vm();
+    let mut task_vm = vm.lock();
     if task_vm.manually_alloc_page(old_path as usize).is_err()
         || task_vm.manually_alloc_page(new_path as usize).is_err()
     {
@@ -900,7 +1140,8 @@ pub fn sys_ioctl(fd: usize, request: usize, argp: *mut usize) -> SysResult {
     );
     info!("ioctl unimplemented now, error checks only");
     let task = get_current_task().unwrap();
-    let mut task_vm = task.vm.lock();
+    let vm = task.get_vm();
+    let mut task_vm = vm.lock();
     let fd_manager = task.fd_manager.lock();
     if fd_manager.get_file(fd).is_err() {
         return Err(ErrorNo::EBADF);
@@ -10,6 +10,7 @@ mod flags;
 mod waiter;
 pub use waiter::{FutexWaiter, Waiter};
 mod waiting_board;
+use waiting_board::cancel_waiter_for_thread;
 pub use waiting_board::{check_thread_blocked, set_waiter_for_thread, wake_thread};
 
 use super::{sys_gettid, SysResult};
@@ -50,7 +51,8 @@ pub fn sys_futex(
             //info!("futex wait, suspend---");
             // 检查 uaddr 处的地址
             let task = get_current_task().unwrap();
-            let mut task_vm = task.vm.lock();
+            let vm = task.get_vm();
+            let mut task_vm = vm.lock();
             if task_vm.manually_alloc_page(uaddr).is_ok() {
                 let real_val = unsafe { (uaddr as *const u32).read_volatile() };
                 if real_val != val {
@@ -73,7 +75,12 @@ pub fn sys_futex(
                     drop(task_vm); // 切换任务前取消对锁的占用
                     drop(task);
                     suspend_current_task();
-                    Ok(0)
+                    // 还没有被唤醒就回到这里，说明等待被信号打断了
+                    if cancel_waiter_for_thread(tid) {
+                        Err(ErrorNo::ERESTARTSYS)
+                    } else {
+                        Ok(0)
+                    }
                 }
             } else {
                 // 若地址无效
@@ -47,3 +47,15 @@ pub fn wake_thread(tid: usize) -> bool {
         waiting_board[tid].as_mut().map(|w| w.wake()).is_some()
     }
 }
+
+/// 取消线程的等待。如果此时线程还没有被唤醒(例如等待被信号打断)，则返回 true
+pub fn cancel_waiter_for_thread(tid: usize) -> bool {
+    let mut waiting_board = WAITING_BOARD.lock();
+    if tid >= waiting_board.len() {
+        false
+    } else {
+        waiting_board[tid]
+            .take()
+            .map_or(false, |waiter| !waiter.is_woken())
+    }
+}
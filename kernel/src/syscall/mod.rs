@@ -7,7 +7,7 @@
 //! 这两种调用间比较容易混淆的区别是，比赛测例是用 C 写的，大部分数组都是 4 Byte，
 //! 而 rCore 使用 rust，usize/isize 一般是 8 Byte。
 //! 这导致一些传入地址(非字符串,字符串大家都是统一的 1Byte 类型)的大小有问题，
-//! 如 sys_pipe() 在测例环境下需要将输入作为 *mut u32 而不是 *mut usize
+//! 如 sys_pipe2() 在测例环境下需要将输入作为 *mut u32 而不是 *mut usize
 
 //#![deny(missing_docs)]
 
@@ -16,8 +16,11 @@ mod fs;
 mod futex;
 mod loops;
 mod process;
+mod ptrace;
+mod restart;
 mod socket;
 mod syscall_no;
+mod trace;
 
 use base_file::Kstat;
 use epoll::EpollEvent;
@@ -29,12 +32,20 @@ pub use loops::clear_loop_checker;
 use loops::*;
 use poll::PollFd;
 use process::*;
+use ptrace::*;
+pub use restart::{restart_interrupted_syscall, RestartBlock};
+use restart::{sys_nanosleep, sys_restart_syscall};
 use socket::*;
 use syscall_no::SyscallNo;
 use timer::{ITimerVal, TimeSpec, TimeVal, TMS};
+pub use trace::{
+    set_syscall_trace_targets, syscall_trace_report, syscall_trace_targets, SyscallTrace,
+};
+use trace::{syscall_trace_enter, syscall_trace_exit};
 
 use crate::file::FsStat;
-use crate::signal::SigAction;
+use crate::signal::{SigAction, SigInfo, SignalStack};
+use crate::task::RLimit;
 
 type SysResult = Result<usize, syscall::ErrorNo>;
 
@@ -51,6 +62,7 @@ pub fn syscall(syscall_id: usize, args: [usize; 6]) -> isize {
         return 0;
     };
     debug!("Syscall {:?}, {:x?}", syscall_id, args);
+    syscall_trace_enter(syscall_id, &args);
 
     let result = match syscall_id {
         SyscallNo::GETCWD => sys_getcwd(args[0] as *mut u8, args[1]),
@@ -88,6 +100,13 @@ pub fn syscall(syscall_id: usize, args: [usize; 6]) -> isize {
         ),
         SyscallNo::STATFS => sys_statfs(args[0] as *const u8, args[1] as *mut FsStat),
         SyscallNo::ACCESS => sys_access(args[0] as i32, args[1] as *const u8, args[2]),
+        SyscallNo::CHMOD => sys_fchmodat(args[0] as i32, args[1] as *const u8, args[2]),
+        SyscallNo::MKNODAT => sys_mknodat(
+            args[0] as i32,
+            args[1] as *const u8,
+            args[2] as u32,
+            args[3],
+        ),
         SyscallNo::MKDIR => sys_mkdir(args[0] as i32, args[1] as *const u8, args[2] as u32),
         SyscallNo::CHDIR => sys_chdir(args[0] as *const u8),
         SyscallNo::OPEN => sys_open(
@@ -97,7 +116,7 @@ pub fn syscall(syscall_id: usize, args: [usize; 6]) -> isize {
             args[3] as i32,
         ),
         SyscallNo::CLOSE => sys_close(args[0]),
-        SyscallNo::PIPE => sys_pipe(args[0] as *mut u32),
+        SyscallNo::PIPE2 => sys_pipe2(args[0] as *mut u32, args[1] as u32),
         SyscallNo::GETDENTS64 => sys_getdents64(args[0], args[1] as *mut u8, args[2]),
         SyscallNo::LSEEK => sys_lseek(args[0], args[1] as isize, args[2] as isize),
         SyscallNo::READ => sys_read(args[0], args[1] as *mut u8, args[2]),
@@ -137,7 +156,7 @@ pub fn syscall(syscall_id: usize, args: [usize; 6]) -> isize {
             UtimensatFlags::from_bits(args[3] as u32).unwrap(),
         ),
         SyscallNo::EXIT => sys_exit(args[0] as i32),
-        SyscallNo::EXIT_GROUP => sys_exit(args[0] as i32),
+        SyscallNo::EXIT_GROUP => sys_exit_group(args[0] as i32),
         SyscallNo::SET_TID_ADDRESS => sys_set_tid_address(args[0]),
         SyscallNo::FUTEX => sys_futex(
             args[0],
@@ -147,9 +166,8 @@ pub fn syscall(syscall_id: usize, args: [usize; 6]) -> isize {
             args[4],
             args[5] as u32,
         ),
-        SyscallNo::NANOSLEEP => {
-            timer::sys_nanosleep(args[0] as *const TimeSpec, args[1] as *mut TimeSpec)
-        }
+        SyscallNo::NANOSLEEP => sys_nanosleep(args[0] as *const TimeSpec, args[1] as *mut TimeSpec),
+        SyscallNo::RESTART_SYSCALL => sys_restart_syscall(),
         SyscallNo::GETITIMER => timer::sys_gettimer(args[0], args[1] as *mut ITimerVal),
         SyscallNo::SETITIMER => timer::sys_settimer(
             args[0],
@@ -160,6 +178,18 @@ pub fn syscall(syscall_id: usize, args: [usize; 6]) -> isize {
         SyscallNo::YIELD => sys_yield(),
         SyscallNo::KILL => sys_kill(args[0] as isize, args[1] as isize),
         SyscallNo::TKILL => sys_tkill(args[0] as isize, args[1] as isize),
+        SyscallNo::SIGQUEUEINFO => {
+            sys_rt_sigqueueinfo(args[0] as isize, args[1], args[2] as *const SigInfo)
+        }
+        SyscallNo::TGSIGQUEUEINFO => sys_rt_tgsigqueueinfo(
+            args[0] as isize,
+            args[1] as isize,
+            args[2],
+            args[3] as *const SigInfo,
+        ),
+        SyscallNo::SIGALTSTACK => {
+            sys_sigaltstack(args[0] as *const SignalStack, args[1] as *mut SignalStack)
+        }
         SyscallNo::SIGACTION => sys_sigaction(
             args[0],
             args[1] as *const SigAction,
@@ -171,9 +201,31 @@ pub fn syscall(syscall_id: usize, args: [usize; 6]) -> isize {
             args[2] as *mut usize,
             args[3],
         ),
+        SyscallNo::SIGSUSPEND => sys_rt_sigsuspend(args[0] as *const usize, args[1]),
+        SyscallNo::SIGPENDING => sys_rt_sigpending(args[0] as *mut usize, args[1]),
+        SyscallNo::SIGTIMEDWAIT => sys_rt_sigtimedwait(
+            args[0] as *const usize,
+            args[1] as *mut SigInfo,
+            args[2] as *const TimeSpec,
+            args[3],
+        ),
+        SyscallNo::SIGNALFD4 => sys_signalfd4(
+            args[0] as isize,
+            args[1] as *const usize,
+            args[2],
+            args[3] as u32,
+        ),
         SyscallNo::SIGRETURN => sys_sigreturn(),
         SyscallNo::TIMES => timer::sys_times(args[0] as *mut TMS),
+        SyscallNo::SETPGID => sys_setpgid(args[0] as isize, args[1] as isize),
+        SyscallNo::GETPGID => sys_getpgid(args[0]),
+        SyscallNo::GETSID => sys_getsid(args[0]),
+        SyscallNo::SETSID => sys_setsid(),
+        SyscallNo::GETGROUPS => sys_getgroups(args[0], args[1] as *mut u32),
+        SyscallNo::SETGROUPS => sys_setgroups(args[0], args[1] as *const u32),
         SyscallNo::UNAME => sys_uname(args[0] as *mut UtsName),
+        SyscallNo::GETRLIMIT => sys_getrlimit(args[0] as i32, args[1] as *mut RLimit),
+        SyscallNo::SETRLIMIT => sys_setrlimit(args[0] as i32, args[1] as *const RLimit),
         SyscallNo::GETRUSAGE => timer::sys_getrusage(args[0] as i32, args[1] as *mut TimeVal),
         SyscallNo::UMASK => sys_umask(args[0] as i32),
         SyscallNo::GET_TIME_OF_DAY => timer::sys_get_time_of_day(args[0] as *mut TimeVal),
@@ -183,6 +235,22 @@ pub fn syscall(syscall_id: usize, args: [usize; 6]) -> isize {
         SyscallNo::GETEUID => sys_geteuid(),
         SyscallNo::GETGID => sys_getgid(),
         SyscallNo::GETEGID => sys_getegid(),
+        SyscallNo::SETREGID => sys_setregid(args[0], args[1]),
+        SyscallNo::SETGID => sys_setgid(args[0]),
+        SyscallNo::SETREUID => sys_setreuid(args[0], args[1]),
+        SyscallNo::SETUID => sys_setuid(args[0]),
+        SyscallNo::SETRESUID => sys_setresuid(args[0], args[1], args[2]),
+        SyscallNo::GETRESUID => sys_getresuid(
+            args[0] as *mut u32,
+            args[1] as *mut u32,
+            args[2] as *mut u32,
+        ),
+        SyscallNo::SETRESGID => sys_setresgid(args[0], args[1], args[2]),
+        SyscallNo::GETRESGID => sys_getresgid(
+            args[0] as *mut u32,
+            args[1] as *mut u32,
+            args[2] as *mut u32,
+        ),
         SyscallNo::GETTID => sys_gettid(),
         SyscallNo::SYSINFO => sys_sysinfo(args[0] as *mut SysInfo),
         SyscallNo::SOCKET => sys_socket(args[0], args[1], args[2]),
@@ -220,6 +288,19 @@ pub fn syscall(syscall_id: usize, args: [usize; 6]) -> isize {
         SyscallNo::BRK => sys_brk(args[0]),
         SyscallNo::MUNMAP => sys_munmap(args[0], args[1]),
         SyscallNo::CLONE => sys_clone(args[0], args[1] as isize, args[2], args[3], args[4]),
+        SyscallNo::SCHED_SETAFFINITY => {
+            sys_sched_setaffinity(args[0], args[1], args[2] as *const u8)
+        }
+        SyscallNo::SCHED_GETAFFINITY => sys_sched_getaffinity(args[0], args[1], args[2] as *mut u8),
+        SyscallNo::GETCPU => sys_getcpu(args[0] as *mut u32, args[1] as *mut u32),
+        SyscallNo::PRCTL => sys_prctl(args[0] as i32, args[1], args[2], args[3], args[4]),
+        SyscallNo::PTRACE => sys_ptrace(args[0], args[1], args[2], args[3]),
+        SyscallNo::CLONE3 => sys_clone3(args[0] as *const CloneArgs, args[1]),
+        SyscallNo::PIDFD_OPEN => sys_pidfd_open(args[0] as isize, args[1] as u32),
+        SyscallNo::PIDFD_SEND_SIGNAL => {
+            sys_pidfd_send_signal(args[0], args[1], args[2] as *const SigInfo, args[3] as u32)
+        }
+        SyscallNo::PIDFD_GETFD => sys_pidfd_getfd(args[0], args[1], args[2] as u32),
         SyscallNo::MMAP => sys_mmap(
             args[0],
             args[1],
@@ -246,9 +327,17 @@ pub fn syscall(syscall_id: usize, args: [usize; 6]) -> isize {
         SyscallNo::WAIT4 => sys_wait4(
             args[0] as isize,
             args[1] as *mut i32,
-            WaitFlags::from_bits(args[2] as u32).unwrap(),
+            args[2] as u32,
+            args[3] as *mut RUsage,
         ),
-        SyscallNo::PRLIMIT64 => sys_prlimt64(
+        SyscallNo::WAITID => sys_waitid(
+            args[0] as i32,
+            args[1],
+            args[2] as *mut WaitIdInfo,
+            args[3] as u32,
+            args[4] as *mut RUsage,
+        ),
+        SyscallNo::PRLIMIT64 => sys_prlimit64(
             args[0],
             args[1] as i32,
             args[2] as *const RLimit,
@@ -263,7 +352,6 @@ pub fn syscall(syscall_id: usize, args: [usize; 6]) -> isize {
         ),
         SyscallNo::IOCTL => sys_ioctl(args[0], args[1], args[2] as *mut usize),
         //SyscallNo::MPROTECT => 0,
-        SyscallNo::SIGTIMEDWAIT => Ok(0),
         SyscallNo::MEMBARRIER => Ok(0),
         SyscallNo::FSYNC => Ok(0),
         _ => {
@@ -275,7 +363,7 @@ pub fn syscall(syscall_id: usize, args: [usize; 6]) -> isize {
             Ok(0)
         }
     };
-    match result {
+    let ret = match result {
         Ok(a0) => {
             if syscall_id != SyscallNo::GETRUSAGE && syscall_id != SyscallNo::CLOCK_GET_TIME {
                 debug!("{:?} ret -> {} = {:#x}", syscall_id, a0, a0);
@@ -286,5 +374,7 @@ pub fn syscall(syscall_id: usize, args: [usize; 6]) -> isize {
             warn!("{:?} ret -> {:?}", syscall_id, num);
             num as isize
         }
-    }
+    };
+    syscall_trace_exit(ret);
+    ret
 }
@@ -1,25 +1,42 @@
 //! 与进程相关的系统调用
 
 use super::{
-    resolve_clone_flags_and_signal, MMAPFlags, MSyncFlags, RLimit, SysResult, UtsName, WaitFlags,
-    MMAPPROT, RLIMIT_AS, RLIMIT_NOFILE, RLIMIT_STACK, SIG_BLOCK, SIG_SETMASK, SIG_UNBLOCK,
+    resolve_clone_flags_and_signal, trace::syscall_trace_exit, CloneArgs, MMAPFlags, MSyncFlags,
+    RUsage, SysResult, UtsName, WaitFlags, WaitIdInfo, CLONE_ARGS_SIZE_VER0, MMAPPROT, NGROUPS_MAX,
+    PIDFD_NONBLOCK, PR_GET_CHILD_SUBREAPER, PR_GET_NAME, PR_GET_NO_NEW_PRIVS, PR_GET_PDEATHSIG,
+    PR_SET_CHILD_SUBREAPER, PR_SET_NAME, PR_SET_NO_NEW_PRIVS, PR_SET_PDEATHSIG, P_ALL, P_PGID,
+    P_PID, SFD_CLOEXEC, SFD_NONBLOCK, SIG_BLOCK, SIG_SETMASK, SIG_UNBLOCK,
 };
 use crate::{
-    constants::{SIGSET_SIZE_IN_BYTE, USER_STACK_SIZE, USER_VIRT_ADDR_LIMIT, USE_MSYNC},
-    file::{BackEndFile, SeekFrom},
-    memory::{align_down, align_up, page_offset},
-    signal::{send_signal, SigAction, SignalNo},
+    arch::get_cpu_id,
+    constants::{
+        ALL_CPU_MASK, PAGE_SIZE, SIGSET_SIZE_IN_BIT, SIGSET_SIZE_IN_BYTE, TASK_COMM_LEN, USE_MSYNC,
+    },
+    error::OSError,
+    file::{check_file_exists, get_file_perm, BackEndFile, PidFd, SeekFrom, SignalFd},
+    loaders::check_user_app,
+    memory::{align_down, align_up, page_offset, MemorySet, Tid},
+    signal::{
+        send_siginfo, SigAction, SigInfo, SignalNo, SignalStack, CLD_CONTINUED, CLD_DUMPED,
+        CLD_EXITED, CLD_KILLED, CLD_STOPPED, CLD_TRAPPED, SI_TKILL, SI_USER,
+    },
     syscall::flags::SysInfo,
     task::{
-        exec_new_task, exit_current_task, get_current_task, push_task_to_scheduler, signal_return,
-        suspend_current_task,
+        add_task_count, de_thread, exec_new_task, exit_current_group, exit_current_task,
+        get_active_cpu_mask, get_all_tasks, get_current_task, get_task_from_tid,
+        ptrace_clone_child, ptrace_event_stop, ptrace_exec, push_task_to_scheduler, signal_pending,
+        signal_return, sub_task_count, suspend_current_task, task_count_of, AccessMode, CloneFlags,
+        IdSet, RLimit, TaskControlBlock, TaskStatus, PTRACE_EVENT_VFORK_DONE, RLIMIT_NOFILE,
+        RLIMIT_NPROC, RLIM_NLIMITS,
     },
     utils::{raw_ptr_to_string, str_ptr_array_to_vec_string},
 };
+use alloc::{string::String, sync::Arc, vec::Vec};
+use base_file::FD_LIMIT_HARD;
 use bitset::Bitset;
 use core::mem::size_of;
 use syscall::ErrorNo;
-use timer::get_time_sec;
+use timer::{get_time_f64, get_time_sec, TimeSpec, NSEC_PER_SEC, USEC_PER_INTERRUPT};
 
 /// 进程退出，并提供 exit_code 供 wait 等 syscall 拿取
 pub fn sys_exit(exit_code: i32) -> ! {
@@ -28,6 +45,12 @@ pub fn sys_exit(exit_code: i32) -> ! {
     panic!("Unreachable in sys_exit!");
 }
 
+/// 进程退出，即结束当前线程所在线程组的所有线程，并提供 exit_code 供 wait 等 syscall 拿取
+pub fn sys_exit_group(exit_code: i32) -> ! {
+    exit_current_group(exit_code);
+    panic!("Unreachable in sys_exit_group!");
+}
+
 /// 进程主动放弃时间片，立即切换到其他进程执行
 pub fn sys_yield() -> SysResult {
     suspend_current_task();
@@ -55,6 +78,88 @@ pub fn sys_gettid() -> SysResult {
     Ok(get_current_task().unwrap().get_tid_num())
 }
 
+/// 设置进程 pid 的进程组为 pgid。
+///
+/// - pid 为 0 时表示当前进程，pgid 为 0 时表示使用 pid 作为进程组 id
+/// - 目标只能是当前进程或它的子进程，否则返回 ESRCH
+/// - 目标不能是会话首进程，且需要与当前进程在同一会话中，否则返回 EPERM
+/// - 如果 pgid 不等于 pid，则同一会话中需要已经存在这个进程组，否则返回 EPERM
+/// - 目标是已经调用过 execve 的子进程时，返回 EACCES
+pub fn sys_setpgid(pid: isize, pgid: isize) -> SysResult {
+    if pid < 0 || pgid < 0 {
+        return Err(ErrorNo::EINVAL);
+    }
+    let task = get_current_task().unwrap();
+    let pid = if pid == 0 {
+        task.get_pid_num()
+    } else {
+        pid as usize
+    };
+    let pgid = if pgid == 0 { pid } else { pgid as usize };
+    let target = if pid == task.get_pid_num() {
+        get_task_from_tid(pid).ok_or(ErrorNo::ESRCH)?
+    } else {
+        task.inner
+            .lock()
+            .children
+            .iter()
+            .find(|child| child.get_pid_num() == pid)
+            .cloned()
+            .ok_or(ErrorNo::ESRCH)?
+    };
+    if target.get_pid_num() != task.get_pid_num() && target.inner.lock().did_exec {
+        return Err(ErrorNo::EACCES);
+    }
+    let sid = task.get_sid();
+    if target.get_sid() != sid || target.get_pid_num() == sid {
+        return Err(ErrorNo::EPERM);
+    }
+    if pgid != pid
+        && !get_all_tasks()
+            .iter()
+            .any(|t| t.get_pgid() == pgid && t.get_sid() == sid)
+    {
+        return Err(ErrorNo::EPERM);
+    }
+    target.set_pgid_and_sid(pgid, sid);
+    Ok(0)
+}
+
+/// 获取进程 pid 的进程组 id。pid 为 0 时表示当前进程
+pub fn sys_getpgid(pid: usize) -> SysResult {
+    if pid == 0 {
+        Ok(get_current_task().unwrap().get_pgid())
+    } else {
+        get_task_from_tid(pid)
+            .map(|task| task.get_pgid())
+            .ok_or(ErrorNo::ESRCH)
+    }
+}
+
+/// 创建一个新会话，当前进程成为新会话和新进程组的首进程，返回新的会话 id。
+///
+/// 如果当前进程已经是某个进程组的组长(即已有以当前 pid 为 id 的进程组)，则返回 EPERM
+pub fn sys_setsid() -> SysResult {
+    let task = get_current_task().unwrap();
+    let pid = task.get_pid_num();
+    if get_all_tasks().iter().any(|t| t.get_pgid() == pid) {
+        return Err(ErrorNo::EPERM);
+    }
+    task.set_pgid_and_sid(pid, pid);
+    Ok(pid)
+}
+
+/// 获取进程 pid 的会话 id。pid 为 0 时表示当前进程
+pub fn sys_getsid(pid: usize) -> SysResult {
+    if pid == 0 {
+        Ok(get_current_task().unwrap().get_sid())
+    } else {
+        get_task_from_tid(pid)
+            .map(|task| task.get_sid())
+            .ok_or(ErrorNo::ESRCH)
+    }
+}
+
 /// 修改用户堆大小，
 ///
 /// - 如输入 brk 为 0 ，则返回堆顶地址
@@ -87,33 +192,217 @@ pub fn sys_clone(
         x if x > 0 => Some(user_stack as usize),
         _ => None,
     };
+    // sys_clone 的 pidfd 和 ptid 使用同一个地址，所以两者不能同时指定
+    if clone_flags.contains(CloneFlags::CLONE_PIDFD)
+        && clone_flags.contains(CloneFlags::CLONE_PARENT_SETTID)
+    {
+        return Err(ErrorNo::EINVAL);
+    }
+    // sys_clone 的 pidfd 也写入 ptid
+    let clone_args = CloneArgs {
+        pidfd: ptid as u64,
+        child_tid: ctid as u64,
+        parent_tid: ptid as u64,
+        exit_signal: signal as u64,
+        tls: tls as u64,
+        ..Default::default()
+    };
+    clone_task(clone_flags, user_stack, &clone_args, None)
+    /*
+    if signal == SignalNo::SIGCHLD { // 子进程
+        let user_stack = if user_stack == 0 { None } else { Some(user_stack) };
+        sys_fork(user_stack)
+    } else {
+        info!("flags {:#?} user_stack {:x}, ptid {:x} tls {:x} ctid {:x}", clone_flags, user_stack, ptid, tls, ctid);
+        return -1
+    }
+    */
+}
+
+/// 创建一个子任务，参数以 clone_args 结构体的形式给出，size 为用户传入的结构体大小。如成功，返回其 tid
+///
+/// 与 sys_clone 不同，用户栈由栈底 stack 和大小 stack_size 指定，退出信号由 exit_signal 单独指定
+pub fn sys_clone3(args: *const CloneArgs, size: usize) -> SysResult {
+    if size < CLONE_ARGS_SIZE_VER0 {
+        return Err(ErrorNo::EINVAL);
+    }
+    if size > PAGE_SIZE {
+        return Err(ErrorNo::E2BIG);
+    }
+    let task = get_current_task().unwrap();
+    // 用户的结构体可能比内核的版本短，没有的字段视为 0
+    let mut clone_args = CloneArgs::default();
+    let len = size.min(size_of::<CloneArgs>());
+    let vm = task.get_vm();
+    if vm
+        .lock()
+        .manually_alloc_user_str(args as *const u8, len)
+        .is_err()
+    {
+        return Err(ErrorNo::EFAULT);
+    }
+    unsafe {
+        core::ptr::copy_nonoverlapping(
+            args as *const u8,
+            &mut clone_args as *mut CloneArgs as *mut u8,
+            len,
+        );
+    }
+    info!(
+        "clone3: flags {:x} exit_signal {} stack {:x} size {:x}",
+        clone_args.flags, clone_args.exit_signal, clone_args.stack, clone_args.stack_size
+    );
+    // 低 8 位是 sys_clone 用来指定信号的，在 clone3 中不能使用；高 32 位的选项目前都不支持
+    if clone_args.flags & 0xff != 0 || clone_args.flags >> 32 != 0 {
+        return Err(ErrorNo::EINVAL);
+    }
+    let clone_flags = CloneFlags::from_bits_truncate(clone_args.flags as u32);
+    if clone_args.exit_signal as usize > SIGSET_SIZE_IN_BIT {
+        return Err(ErrorNo::EINVAL);
+    }
+    // 栈底和栈大小必须同时指定或同时不指定。因为栈向下增长，所以实际的栈顶是 stack + stack_size
+    let user_stack = match (clone_args.stack, clone_args.stack_size) {
+        (0, 0) => None,
+        (0, _) | (_, 0) => return Err(ErrorNo::EINVAL),
+        (stack, stack_size) => Some(stack.checked_add(stack_size).ok_or(ErrorNo::EINVAL)? as usize),
+    };
+    // 没有 pid 命名空间，所以 set_tid 最多只能指定一层
+    let set_tid = match clone_args.set_tid_size {
+        0 => None,
+        1 => {
+            let set_tid = clone_args.set_tid as *const i32;
+            if vm.lock().manually_alloc_type(set_tid).is_err() {
+                return Err(ErrorNo::EFAULT);
+            }
+            match unsafe { *set_tid } {
+                tid if tid <= 0 => return Err(ErrorNo::EINVAL),
+                tid => Some(tid as usize),
+            }
+        }
+        _ => return Err(ErrorNo::EINVAL),
+    };
+    drop(vm);
+    drop(task);
+    clone_task(clone_flags, user_stack, &clone_args, set_tid)
+}
+
+/// sys_clone 和 sys_clone3 共同的部分，创建一个子任务并返回其 tid
+///
+/// - user_stack 为子任务的栈顶，如为 None，则沿用当前任务的栈
+/// - args 中的 exit_signal 为子进程退出时发送给父进程的信号，为 0 时不发送；flags 和栈相关的字段不使用
+/// - set_tid 为指定的子任务 tid，如为 None，则自动分配
+///
+/// 如果带有 CLONE_VFORK，则当前任务会阻塞，直到子进程 exec 或退出
+fn clone_task(
+    clone_flags: CloneFlags,
+    user_stack: Option<usize>,
+    args: &CloneArgs,
+    set_tid: Option<usize>,
+) -> SysResult {
+    let pidfd = args.pidfd as usize;
+    // pidfd 指向的是进程，所以不能用于创建线程
+    if clone_flags.contains(CloneFlags::CLONE_PIDFD)
+        && clone_flags.contains(CloneFlags::CLONE_THREAD)
+    {
+        return Err(ErrorNo::EINVAL);
+    }
     let old_task = get_current_task().unwrap();
+    if clone_flags.contains(CloneFlags::CLONE_PIDFD) {
+        // 提前检查，以免创建子任务之后才发现无法写入 pidfd
+        if old_task
+            .get_vm()
+            .lock()
+            .manually_alloc_type(pidfd as *const i32)
+            .is_err()
+        {
+            return Err(ErrorNo::EFAULT);
+        }
+        if old_task.fd_manager.lock().is_full() {
+            return Err(ErrorNo::EMFILE);
+        }
+    }
+    let credentials = old_task.credentials.lock().clone();
+    // 同一实际用户 id 拥有的任务数不能超过 RLIMIT_NPROC，有特权时不受限制
+    if !credentials.is_privileged() {
+        let nproc_limit = old_task.rlimits.lock().get_soft(RLIMIT_NPROC);
+        if task_count_of(credentials.uid.real) as u64 >= nproc_limit {
+            return Err(ErrorNo::EAGAIN);
+        }
+    }
+    let tid = match set_tid {
+        Some(tid) => {
+            // 指定 tid 需要特权
+            if !credentials.is_privileged() {
+                return Err(ErrorNo::EPERM);
+            }
+            Some(Tid::new_at(tid).ok_or(ErrorNo::EEXIST)?)
+        }
+        None => None,
+    };
     // 生成新任务。注意 from_clone 方法内部已经把对用户的返回值设成了0
-    // 第二个参数指定了子任务退出时是否发送 SIGCHLD
+    // 第二个参数指定了子进程退出时发送的信号
     let new_task = old_task.from_clone(
         user_stack,
-        signal == SignalNo::SIGCHLD,
+        args.exit_signal as usize,
         clone_flags,
-        tls,
-        ptid,
-        ctid,
+        args.tls as usize,
+        args.parent_tid as usize,
+        args.child_tid as usize,
+        tid,
     );
+    if clone_flags.contains(CloneFlags::CLONE_PIDFD) {
+        // 上面已经检查过 fd 没有满，所以这里一定能放入
+        let fd = old_task
+            .fd_manager
+            .lock()
+            .push(Arc::new(PidFd::new(&new_task, false)))
+            .unwrap();
+        unsafe {
+            *(pidfd as *mut i32) = fd as i32;
+        }
+    }
     // 获取新进程的 pid。必须提前在此拿到 usize 形式的 pid，因为后续 new_task 插入任务队列后就不能调用它的方法了
     let new_task_tid = new_task.get_tid_num();
+    // 当前任务被跟踪时，新任务可能也需要被跟踪，且当前任务需要向跟踪者报告这次 clone
+    let ptrace_event = ptrace_clone_child(
+        &old_task,
+        &new_task,
+        clone_flags,
+        args.exit_signal == SignalNo::SIGCHLD as u64,
+    );
     // 将新任务加入调度器
-    push_task_to_scheduler(new_task);
+    if clone_flags.contains(CloneFlags::CLONE_VFORK) {
+        push_task_to_scheduler(new_task.clone());
+        drop(old_task);
+        if let Some(event) = ptrace_event {
+            ptrace_event_stop(event, new_task_tid);
+        }
+        wait_for_vfork_child(&new_task);
+        ptrace_event_stop(PTRACE_EVENT_VFORK_DONE, new_task_tid);
+    } else {
+        push_task_to_scheduler(new_task);
+        if let Some(event) = ptrace_event {
+            ptrace_event_stop(event, new_task_tid);
+        }
+    }
     //println!("new task {new_task_tid}");
     //println!("create time {}", crate::timer::get_time());
     Ok(new_task_tid)
-    /*
-    if signal == SignalNo::SIGCHLD { // 子进程
-        let user_stack = if user_stack == 0 { None } else { Some(user_stack) };
-        sys_fork(user_stack)
-    } else {
-        info!("flags {:#?} user_stack {:x}, ptid {:x} tls {:x} ctid {:x}", clone_flags, user_stack, ptid, tls, ctid);
-        return -1
+}
+
+/// vfork 后父进程阻塞，直到子进程 exec(换成了自己的地址空间)或退出。
+/// 如果当前进程在等待时开始退出(如收到 SIGKILL)，则也不再等待
+fn wait_for_vfork_child(child: &Arc<TaskControlBlock>) {
+    let task = get_current_task().unwrap();
+    let vm = task.get_vm();
+    loop {
+        let child_done = !Arc::ptr_eq(&child.get_vm(), &vm)
+            || matches!(child.get_status(), TaskStatus::Dying | TaskStatus::Zombie);
+        if child_done || task.thread_group.lock().get_group_exit_code().is_some() {
+            return;
+        }
+        suspend_current_task();
     }
-    */
 }
 
 /// 复制当前进程
@@ -139,120 +428,469 @@ fn sys_fork(user_stack: Option<usize>) -> SysResult {
 */
 /// 将当前进程替换为指定用户程序。
 ///
-/// 环境变量留了接口但目前未实现
-pub fn sys_execve(path: *const u8, args: *const usize, mut _envs: *const usize) -> SysResult {
-    sys_exec(path, args)
+/// args 和 envs 都是以空指针结尾的字符串指针数组，envs 为空指针时视为没有环境变量
+pub fn sys_execve(path: *const u8, args: *const usize, envs: *const usize) -> SysResult {
+    sys_exec(path, args, envs)
 }
 
 /// 将当前进程替换为指定用户程序。
 ///
 /// 如果找到这个名字的用户程序，返回 argc(参数个数)；
 /// 如果没有找到这个名字的用户程序，则返回 -1
-fn sys_exec(path: *const u8, args: *const usize) -> SysResult {
+fn sys_exec(path: *const u8, args: *const usize, envs: *const usize) -> SysResult {
     // 因为这里直接用用户空间提供的虚拟地址来访问，所以一定能连续访问到字符串，不需要考虑物理地址是否连续。
-    // 把路径和参数复制到内核里。因为上面的 slice 在用户空间中，在 exec 中会被 drop 掉。
+    // 把路径、参数和环境变量复制到内核里。因为上面的 slice 在用户空间中，在 exec 中会被 drop 掉。
     let app_name = unsafe { raw_ptr_to_string(path) };
     let args = unsafe { str_ptr_array_to_vec_string(args) };
-    // 而且目前认为所有用户程序在根目录下，所以直接把路径当作文件名
-    if get_current_task().unwrap().exec(&app_name, args) {
-        exec_new_task();
-        Ok(0)
+    let envs = if envs.is_null() {
+        Vec::new()
     } else {
-        sys_exit(0);
-        //Err(ErrorNo::EINVAL)
+        unsafe { str_ptr_array_to_vec_string(envs) }
+    };
+    // 而且目前认为所有用户程序在根目录下，所以直接把路径当作文件名
+    let task = get_current_task().unwrap();
+    let dir = task.inner.lock().dir.clone();
+    let perm = get_file_perm(dir.as_str(), app_name.as_str());
+    if check_file_exists(dir.as_str(), app_name.as_str())
+        && !task
+            .credentials
+            .lock()
+            .can_access(&perm, AccessMode::X_OK, false)
+    {
+        return Err(ErrorNo::EACCES);
     }
+    // exec 失败时原来的地址空间已经被清空，所以要提前检查文件格式，包括脚本的每一层解释器
+    check_user_app(dir.as_str(), app_name.as_str()).map_err(|err| match err {
+        OSError::Loader_AppNotFound => ErrorNo::ENOENT,
+        OSError::Loader_TooManyInterpreters => ErrorNo::ELOOP,
+        _ => ErrorNo::ENOEXEC,
+    })?;
+    // 新程序只在当前线程中执行，同组的其他线程需要先退出
+    if !de_thread() {
+        return Err(ErrorNo::EAGAIN);
+    }
+    task.exec(&app_name, args, envs)?;
+    drop(task);
+    // 成功的 exec 不会回到 syscall()，所以在这里写入跟踪记录
+    syscall_trace_exit(0);
+    ptrace_exec();
+    exec_new_task();
+    Ok(0)
+}
+
+/// wait 系列系统调用等待的子进程范围
+#[derive(Clone, Copy)]
+enum WaitTarget {
+    /// 任意子进程
+    Any,
+    /// pid 为指定值的子进程
+    Pid(usize),
+    /// 进程组为指定值的子进程
+    Pgid(usize),
 }
 
-/// 等待子进程执行完成。如果它还没完成，则先切换掉
+/// 子进程的一次状态变化，由 wait 系列系统调用报告给用户
+struct WaitEvent {
+    /// 子进程的 pid
+    pid: usize,
+    /// wait4 写入用户的 status
+    status: i32,
+    /// waitid 写入 siginfo 的 si_code，即 CLD_EXITED / CLD_KILLED 等
+    code: i32,
+    /// waitid 写入 siginfo 的 si_status，即退出码或导致状态变化的信号编号
+    si_status: i32,
+    /// 子进程的用户态运行时间，以微秒计
+    utime_us: usize,
+    /// 子进程的内核态运行时间，以微秒计
+    stime_us: usize,
+}
+
+/// 等待子进程的状态变化。如果还没有子进程发生状态变化，则先切换掉
+///
+/// - pid > 0 时等待指定子进程，pid == -1 时等待任意子进程
+/// - pid == 0 时等待与当前进程同一进程组的子进程，pid < -1 时等待进程组为 -pid 的子进程
 ///
-/// 目前只支持 WNOHANG 选项
-pub fn sys_wait4(pid: isize, exit_code_ptr: *mut i32, option: WaitFlags) -> SysResult {
+/// options 中只能包含 WNOHANG / WUNTRACED / WCONTINUED / __WNOTHREAD / __WCLONE / __WALL，否则返回 EINVAL。
+/// 如果 status 不为 0，则将子进程的状态写入；如果 rusage 不为 0，则将子进程的资源统计写入
+pub fn sys_wait4(pid: isize, status: *mut i32, options: u32, rusage: *mut RUsage) -> SysResult {
+    info!("sys_wait4 {}, {:x}, {:x}", pid, status as usize, options);
+    let option = WaitFlags::from_bits(options)
+        .filter(|option| !option.intersects(WaitFlags::WEXITED | WaitFlags::WNOWAIT))
+        .ok_or(ErrorNo::EINVAL)?;
+    let target = match pid {
+        -1 => WaitTarget::Any,
+        0 => WaitTarget::Pgid(get_current_task().unwrap().get_pgid()),
+        x if x > 0 => WaitTarget::Pid(x as usize),
+        x => WaitTarget::Pgid((-x) as usize),
+    };
+    // wait4 总是报告已退出的子进程
+    let option = option | WaitFlags::WEXITED;
+    let event = match wait_child_until_found(target, option)? {
+        Some(event) => event,
+        None => return Ok(0),
+    };
+    info!("find child and return {}", event.pid);
+    let task = get_current_task().unwrap();
+    let vm = task.get_vm();
+    let mut task_vm = vm.lock();
+    if status as usize != 0 {
+        if task_vm.manually_alloc_type(status).is_err() {
+            return Err(ErrorNo::EFAULT);
+        }
+        unsafe {
+            *status = event.status;
+        }
+    }
+    write_child_rusage(&mut task_vm, rusage, &event)?;
+    Ok(event.pid)
+}
+
+/// 等待子进程的状态变化，并将其信息以 siginfo 的形式写入 infop。成功时返回 0
+///
+/// - idtype 为 P_PID 时等待 pid 为 id 的子进程；为 P_PGID 时等待进程组为 id 的子进程(id 为 0 时表示当前进程组)；为 P_ALL 时等待任意子进程
+/// - options 中至少需要包含 WEXITED / WUNTRACED(即 WSTOPPED) / WCONTINUED 之一
+/// - 如果设置了 WNOHANG 且没有子进程发生状态变化，则写入的 si_pid 为 0
+/// - 如果 rusage 不为 0，则将子进程的资源统计写入
+pub fn sys_waitid(
+    idtype: i32,
+    id: usize,
+    infop: *mut WaitIdInfo,
+    options: u32,
+    rusage: *mut RUsage,
+) -> SysResult {
     info!(
-        "sys_wait4 {}, {:x}, {:#?}",
-        pid, exit_code_ptr as usize, option
+        "sys_waitid {} {} {:x} {:x}",
+        idtype, id, infop as usize, options
     );
+    let option = WaitFlags::from_bits(options).ok_or(ErrorNo::EINVAL)?;
+    if !option.intersects(WaitFlags::WEXITED | WaitFlags::WUNTRACED | WaitFlags::WCONTINUED) {
+        return Err(ErrorNo::EINVAL);
+    }
+    let target = match idtype {
+        P_ALL => WaitTarget::Any,
+        P_PID => WaitTarget::Pid(id),
+        P_PGID if id == 0 => WaitTarget::Pgid(get_current_task().unwrap().get_pgid()),
+        P_PGID => WaitTarget::Pgid(id),
+        _ => return Err(ErrorNo::EINVAL),
+    };
+    let event = wait_child_until_found(target, option)?;
+    let task = get_current_task().unwrap();
+    let vm = task.get_vm();
+    let mut task_vm = vm.lock();
+    if infop as usize != 0 {
+        if task_vm.manually_alloc_type(infop).is_err() {
+            return Err(ErrorNo::EFAULT);
+        }
+        let mut info = WaitIdInfo::default();
+        if let Some(event) = &event {
+            info.si_signo = SignalNo::SIGCHLD as i32;
+            info.si_code = event.code;
+            info.si_pid = event.pid as i32;
+            info.si_status = event.si_status;
+            info.si_utime = (event.utime_us / USEC_PER_INTERRUPT) as isize;
+            info.si_stime = (event.stime_us / USEC_PER_INTERRUPT) as isize;
+        }
+        unsafe {
+            *infop = info;
+        }
+    }
+    if let Some(event) = &event {
+        write_child_rusage(&mut task_vm, rusage, event)?;
+    }
+    Ok(0)
+}
+
+/// 反复查找子进程和被当前进程跟踪的任务的状态变化，直到找到为止。
+/// 如果设置了 WNOHANG 且暂时没有找到，则返回 None
+fn wait_child_until_found(
+    target: WaitTarget,
+    option: WaitFlags,
+) -> Result<Option<WaitEvent>, ErrorNo> {
     loop {
-        let child_pid = waitpid(pid, exit_code_ptr);
-        // 找不到子进程，直接返回-1
-        if child_pid == -1 {
-            return Err(ErrorNo::EINVAL);
-        } else if child_pid == -2 {
-            if option.contains(WaitFlags::WNOHANG) {
-                return Ok(0);
-            } else {
-                info!("find child but suspend");
-                suspend_current_task();
-            }
-        } else {
-            info!("find child and return {}", child_pid);
-            return Ok(child_pid as usize);
+        let (found_tracee, event) = wait_tracee(target, option);
+        if event.is_some() {
+            return Ok(event);
         }
+        match wait_child(target, option) {
+            Ok(Some(event)) => return Ok(Some(event)),
+            // 没有符合条件的子进程，但有符合条件的被跟踪的任务时，也需要继续等待
+            Err(ErrorNo::ECHILD) if found_tracee => {}
+            Err(err) => return Err(err),
+            Ok(None) => {}
+        }
+        if option.contains(WaitFlags::WNOHANG) {
+            return Ok(None);
+        }
+        if signal_pending() {
+            return Err(ErrorNo::ERESTARTSYS);
+        }
+        suspend_current_task();
     }
 }
 
-/// 等待一个子进程执行完成
+/// 查找一个符合条件的子进程的状态变化
+///
+/// 1. 如果找不到符合条件的子进程，返回 ECHILD
+/// 2. 如果能找到，但它们都没有需要报告的状态变化，返回 None
+/// 3. 否则，返回这个状态变化。如果子进程已退出且没有设置 WNOWAIT，则回收它
 ///
-/// 1. 如果找不到对应 pid 的进程，或者它不是调用进程的子进程，返回 -1
-/// 2. 如果能找到，但该子进程没有运行结束，返回 -2
-/// 3. 否则，返回这个进程的 pid。
-/// 3.1 如果 exit_code_ptr != 0，则将子进程的 exit_code 写入 exit_code_ptr
-fn waitpid(pid: isize, exit_code_ptr: *mut i32) -> isize {
-    let request_pid = pid as usize;
+/// 子进程属于创建它的线程，但默认会查找线程组中所有线程的子进程。设置了 __WNOTHREAD 时只查找当前线程的
+fn wait_child(target: WaitTarget, option: WaitFlags) -> Result<Option<WaitEvent>, ErrorNo> {
     let task = get_current_task().unwrap();
+    let waiters = if option.contains(WaitFlags::__WNOTHREAD) {
+        vec![task]
+    } else {
+        let members = task.thread_group.lock().get_members();
+        members.into_iter().filter_map(get_task_from_tid).collect()
+    };
+    let mut found_child = false;
+    for waiter in waiters {
+        match wait_child_of(&waiter, target, option) {
+            Ok(Some(event)) => return Ok(Some(event)),
+            Ok(None) => found_child = true,
+            Err(_) => {}
+        }
+    }
+    if found_child {
+        Ok(None)
+    } else {
+        Err(ErrorNo::ECHILD)
+    }
+}
+
+/// 在线程 task 的子进程中查找一个符合条件的状态变化，返回值同 `wait_child`
+///
+/// 这里拿着 task 的锁，要求获取子进程的锁。其实内部用的是 try_lock：
+/// 因为如果子进程已退出，则一定可以拿到锁；反之如果拿不到锁，说明子进程一定还在运行，暂时视为没有状态变化
+fn wait_child_of(
+    task: &Arc<TaskControlBlock>,
+    target: WaitTarget,
+    option: WaitFlags,
+) -> Result<Option<WaitEvent>, ErrorNo> {
     let mut tcb_inner = task.inner.lock();
-    // 找到这个子进程并返回它在 children 数组里的下标。
-    // 如果找不到，它设为 -1; 如果找到了但没结束，它设为 -2
-    let mut flag: isize = -1;
-    let mut exit_code: i32 = -1;
-    let mut pid_found: isize = pid;
+    let mut found_child = false;
+    let mut event = None;
+    let mut reaped_idx = None;
     for (idx, child) in tcb_inner.children.iter().enumerate() {
-        //info!("waitpid child iter {:?}", child.pid);
-        // 等待任意的子进程
-        if pid == -1 {
-            flag = -2;
-            if let Some(code) = child.get_code_if_exit() {
-                exit_code = code;
-                flag = idx as isize;
-                pid_found = child.get_pid_num() as isize;
-                break;
+        // 默认只等待退出时发送 SIGCHLD 的子进程，__WCLONE 则相反，__WALL 表示两者都等待
+        if !option.contains(WaitFlags::__WALL)
+            && option.contains(WaitFlags::__WCLONE)
+                == (child.get_exit_signal() == SignalNo::SIGCHLD as usize)
+        {
+            continue;
+        }
+        let pid = child.get_pid_num();
+        let mut child_inner = match child.inner.try_lock() {
+            Some(child_inner) => child_inner,
+            None => {
+                // 拿不到锁时无法检查子进程的进程组，此时先视为符合条件
+                found_child |= match target {
+                    WaitTarget::Pid(request_pid) => pid == request_pid,
+                    _ => true,
+                };
+                continue;
             }
+        };
+        let matched = match target {
+            WaitTarget::Any => true,
+            WaitTarget::Pid(request_pid) => pid == request_pid,
+            WaitTarget::Pgid(pgid) => child_inner.pgid == pgid,
+        };
+        if !matched {
+            continue;
         }
-        // 找到这个子进程了
-        else if child.get_pid_num() == request_pid {
-            // 这里拿着当前进程的锁，要求获取子进程的锁
-            // 其实内部用的是 try_lock：
-            // 因为如果子进程已退出，则一定可以拿到锁;
-            // 反之如果拿不到锁，说明子进程一定还在运行，也就不用去拿了
-            if let Some(code) = child.get_code_if_exit() {
-                exit_code = code;
-                flag = idx as isize;
-            } else {
-                flag = -2;
+        found_child = true;
+        let (utime_us, stime_us) = child.time.lock().output_raw();
+        if child_inner.task_status == TaskStatus::Zombie {
+            // 线程组中还有其他线程在运行时，进程还没有结束
+            if !option.contains(WaitFlags::WEXITED) || !child.thread_group.lock().is_empty() {
+                continue;
+            }
+            // 进程的退出状态以线程组为准，而不是组长线程自己的
+            let thread_group = child.thread_group.lock();
+            let (exit_code, term_signal) = thread_group.exit_status();
+            let core_dumped = thread_group.core_dumped;
+            drop(thread_group);
+            event = Some(exit_event(
+                pid,
+                exit_code,
+                term_signal,
+                core_dumped,
+                utime_us,
+                stime_us,
+            ));
+            if !option.contains(WaitFlags::WNOWAIT) {
+                reaped_idx = Some(idx);
             }
             break;
         }
-    }
-    //println!("wait flag {} exit_code {} pid_found {} write_to_ptr {:x}", flag, exit_code, pid_found, exit_code_ptr as usize);
-    /*
-    if task.get_pid_num() == 2 {
-        println!("sons = {}, flag {} code {}", tcb_inner.children.len(), flag, exit_code);
-        if flag >= 0 && exit_code < 0 {
-            panic!("");
+        if option.contains(WaitFlags::WUNTRACED) && child_inner.stop_signal_to_report != 0 {
+            let signum = child_inner.stop_signal_to_report as i32;
+            event = Some(WaitEvent {
+                pid,
+                status: (signum << 8) | 0x7f,
+                code: CLD_STOPPED,
+                si_status: signum,
+                utime_us,
+                stime_us,
+            });
+            if !option.contains(WaitFlags::WNOWAIT) {
+                child_inner.stop_signal_to_report = 0;
+            }
+            break;
+        }
+        if option.contains(WaitFlags::WCONTINUED) && child_inner.continued_to_report {
+            event = Some(WaitEvent {
+                pid,
+                status: 0xffff,
+                code: CLD_CONTINUED,
+                si_status: SignalNo::SIGCONT as i32,
+                utime_us,
+                stime_us,
+            });
+            if !option.contains(WaitFlags::WNOWAIT) {
+                child_inner.continued_to_report = false;
+            }
+            break;
         }
     }
-    */
-    if flag >= 0 {
-        let _child = tcb_inner.children.remove(flag as usize);
-        if exit_code_ptr as usize != 0 {
-            unsafe {
-                //info!("write exit code {}", exit_code);
-                *exit_code_ptr = exit_code << 8;
+    if let Some(idx) = reaped_idx {
+        tcb_inner.children.remove(idx);
+    }
+    if event.is_some() {
+        Ok(event)
+    } else if found_child {
+        Ok(None)
+    } else {
+        Err(ErrorNo::ECHILD)
+    }
+}
+
+/// 查找一个符合条件的、被当前进程跟踪的任务的状态变化。返回是否有符合条件的被跟踪的任务，以及找到的状态变化
+///
+/// - 被跟踪的任务的暂停总是会被报告，不需要 WUNTRACED。此时 target 中的 pid 可以是任意线程的 tid
+/// - 被跟踪的任务如果不是当前进程的子进程，它退出时也会报告给当前进程，然后停止跟踪。它仍需要由父进程回收
+///
+/// 与 `wait_child` 相同，这里用 try_lock 获取任务的锁。拿不到锁的任务一定还在运行，暂时视为没有状态变化
+fn wait_tracee(target: WaitTarget, option: WaitFlags) -> (bool, Option<WaitEvent>) {
+    let pid = get_current_task().unwrap().get_pid_num();
+    let consume = !option.contains(WaitFlags::WNOWAIT);
+    let mut found_tracee = false;
+    for tracee in get_all_tasks() {
+        let tid = tracee.get_tid_num();
+        let mut inner = match tracee.inner.try_lock() {
+            Some(inner) => inner,
+            None => {
+                // 拿不到锁时无法检查它是否被当前进程跟踪，先视为符合条件，等下次再检查
+                found_tracee |= match target {
+                    WaitTarget::Pid(request_pid) => tid == request_pid,
+                    _ => true,
+                };
+                continue;
+            }
+        };
+        match inner.ptrace.as_ref() {
+            Some(state) if state.get_tracer_pid() == pid => {}
+            _ => continue,
+        }
+        let matched = match target {
+            WaitTarget::Any => true,
+            WaitTarget::Pid(request_pid) => tid == request_pid,
+            WaitTarget::Pgid(pgid) => inner.pgid == pgid,
+        };
+        if !matched {
+            continue;
+        }
+        found_tracee = true;
+        let (utime_us, stime_us) = tracee.time.lock().output_raw();
+        if inner.task_status == TaskStatus::Zombie {
+            // 子进程退出时由 wait_child 报告
+            if inner.ppid == pid || !option.contains(WaitFlags::WEXITED) {
+                continue;
             }
+            // 被跟踪的是单个线程，所以报告的是它自己的退出状态
+            let core_dumped = inner.term_signal != 0 && tracee.thread_group.lock().core_dumped;
+            let event = exit_event(
+                tid,
+                inner.exit_code,
+                inner.term_signal,
+                core_dumped,
+                utime_us,
+                stime_us,
+            );
+            if consume {
+                inner.ptrace = None;
+            }
+            return (true, Some(event));
+        }
+        if let Some(status) = inner
+            .ptrace
+            .as_mut()
+            .and_then(|state| state.take_stop_to_report(consume))
+        {
+            let event = WaitEvent {
+                pid: tid,
+                status: ((status << 8) | 0x7f) as i32,
+                code: CLD_TRAPPED,
+                si_status: status as i32,
+                utime_us,
+                stime_us,
+            };
+            return (true, Some(event));
         }
-        pid_found
+    }
+    (found_tracee, None)
+}
+
+/// 生成已退出的任务的退出事件。term_signal 为终止它的信号编号，正常退出时为 0
+///
+/// 其中 status 的格式为：
+/// - 正常退出时，第 8~15 位为 exit_code 的低 8 位
+/// - 被信号终止时，低 7 位为信号编号，如果生成了 core dump 则第 7 位为 1
+fn exit_event(
+    pid: usize,
+    exit_code: i32,
+    term_signal: usize,
+    core_dumped: bool,
+    utime_us: usize,
+    stime_us: usize,
+) -> WaitEvent {
+    let (code, si_status, status) = if term_signal != 0 {
+        let code = if core_dumped { CLD_DUMPED } else { CLD_KILLED };
+        let status = term_signal as i32 | if core_dumped { 0x80 } else { 0 };
+        (code, term_signal as i32, status)
     } else {
-        flag
+        (CLD_EXITED, exit_code, (exit_code & 0xff) << 8)
+    };
+    WaitEvent {
+        pid,
+        status,
+        code,
+        si_status,
+        utime_us,
+        stime_us,
+    }
+}
+
+/// 如果 rusage 不为 0，则将子进程的资源统计写入
+fn write_child_rusage(
+    task_vm: &mut MemorySet,
+    rusage: *mut RUsage,
+    event: &WaitEvent,
+) -> Result<(), ErrorNo> {
+    if rusage as usize != 0 {
+        if task_vm.manually_alloc_type(rusage).is_err() {
+            return Err(ErrorNo::EFAULT);
+        }
+        unsafe {
+            *rusage = RUsage {
+                ru_utime: event.utime_us.into(),
+                ru_stime: event.stime_us.into(),
+                ..Default::default()
+            };
+        }
     }
+    Ok(())
 }
 
 /// 映射一段内存
@@ -374,7 +1012,8 @@ pub fn sys_uname(uts: *mut UtsName) -> SysResult {
 /// 目前只支持启动时间
 pub fn sys_sysinfo(info: *mut SysInfo) -> SysResult {
     let task = get_current_task().unwrap();
-    let mut task_vm = task.vm.lock();
+    let vm = task.get_vm();
+    let mut task_vm = vm.lock();
     if task_vm.manually_alloc_type(info).is_err() {
         return Err(ErrorNo::EFAULT);
     }
@@ -384,50 +1023,546 @@ pub fn sys_sysinfo(info: *mut SysInfo) -> SysResult {
     Ok(0)
 }
 
-/// 获取用户 id。在实现多用户权限前默认为最高权限
+/// 获取实际用户 id
 pub fn sys_getuid() -> SysResult {
-    Ok(0)
+    Ok(get_current_task().unwrap().credentials.lock().uid.real as usize)
 }
 
-/// 获取有效用户 id，即相当于哪个用户的权限。在实现多用户权限前默认为最高权限
+/// 获取有效用户 id，即相当于哪个用户的权限
 pub fn sys_geteuid() -> SysResult {
-    Ok(0)
+    Ok(get_current_task().unwrap().credentials.lock().uid.effective as usize)
 }
 
-/// 获取用户组 id。在实现多用户权限前默认为最高权限
+/// 获取实际用户组 id
 pub fn sys_getgid() -> SysResult {
-    Ok(0)
+    Ok(get_current_task().unwrap().credentials.lock().gid.real as usize)
 }
 
-/// 获取有效用户组 id，即相当于哪个用户的权限。在实现多用户权限前默认为最高权限
+/// 获取有效用户组 id，即相当于哪个用户组的权限
 pub fn sys_getegid() -> SysResult {
+    Ok(get_current_task().unwrap().credentials.lock().gid.effective as usize)
+}
+
+/// 用户传入的 id 为 -1 时表示不修改，此时返回 None
+fn optional_id(id: usize) -> Option<u32> {
+    if id as u32 == u32::MAX {
+        None
+    } else {
+        Some(id as u32)
+    }
+}
+
+/// 修改当前进程的用户 id 或用户组 id。
+/// is_uid 表示修改的是用户 id 还是用户组 id，是否有特权均由修改前的有效用户 id 决定
+fn set_ids(is_uid: bool, set: impl FnOnce(&mut IdSet, bool) -> bool) -> SysResult {
+    let task = get_current_task().unwrap();
+    let mut credentials = task.credentials.lock();
+    let privileged = credentials.is_privileged();
+    let old_real_uid = credentials.uid.real;
+    let ids = if is_uid {
+        &mut credentials.uid
+    } else {
+        &mut credentials.gid
+    };
+    if set(ids, privileged) {
+        // 实际用户 id 变化时，线程组内所有还未退出的线程都转移到新的 id 下计数
+        if credentials.uid.real != old_real_uid {
+            let count = task.thread_group.lock().get_members().len();
+            sub_task_count(old_real_uid, count);
+            add_task_count(credentials.uid.real, count);
+        }
+        Ok(0)
+    } else {
+        Err(ErrorNo::EPERM)
+    }
+}
+
+/// 设置用户 id。root 会同时修改实际、有效和保存的用户 id，其他用户只能修改有效用户 id
+pub fn sys_setuid(uid: usize) -> SysResult {
+    let uid = optional_id(uid).ok_or(ErrorNo::EINVAL)?;
+    set_ids(true, |ids, privileged| ids.set(uid, privileged))
+}
+
+/// 设置用户组 id。规则同 setuid
+pub fn sys_setgid(gid: usize) -> SysResult {
+    let gid = optional_id(gid).ok_or(ErrorNo::EINVAL)?;
+    set_ids(false, |ids, privileged| ids.set(gid, privileged))
+}
+
+/// 设置实际和有效用户 id，-1 表示不修改
+pub fn sys_setreuid(ruid: usize, euid: usize) -> SysResult {
+    set_ids(true, |ids, privileged| {
+        ids.set_re(optional_id(ruid), optional_id(euid), privileged)
+    })
+}
+
+/// 设置实际和有效用户组 id，-1 表示不修改
+pub fn sys_setregid(rgid: usize, egid: usize) -> SysResult {
+    set_ids(false, |ids, privileged| {
+        ids.set_re(optional_id(rgid), optional_id(egid), privileged)
+    })
+}
+
+/// 设置实际、有效和保存的用户 id，-1 表示不修改
+pub fn sys_setresuid(ruid: usize, euid: usize, suid: usize) -> SysResult {
+    set_ids(true, |ids, privileged| {
+        ids.set_res(
+            optional_id(ruid),
+            optional_id(euid),
+            optional_id(suid),
+            privileged,
+        )
+    })
+}
+
+/// 设置实际、有效和保存的用户组 id，-1 表示不修改
+pub fn sys_setresgid(rgid: usize, egid: usize, sgid: usize) -> SysResult {
+    set_ids(false, |ids, privileged| {
+        ids.set_res(
+            optional_id(rgid),
+            optional_id(egid),
+            optional_id(sgid),
+            privileged,
+        )
+    })
+}
+
+/// 把一组 id 写入用户给出的三个地址
+fn write_ids(ids: IdSet, real: *mut u32, effective: *mut u32, saved: *mut u32) -> SysResult {
+    let task = get_current_task().unwrap();
+    let vm = task.get_vm();
+    let mut task_vm = vm.lock();
+    for (ptr, id) in [
+        (real, ids.real),
+        (effective, ids.effective),
+        (saved, ids.saved),
+    ] {
+        if task_vm.manually_alloc_type(ptr).is_err() {
+            return Err(ErrorNo::EFAULT);
+        }
+        unsafe {
+            *ptr = id;
+        }
+    }
+    Ok(0)
+}
+
+/// 获取实际、有效和保存的用户 id
+pub fn sys_getresuid(ruid: *mut u32, euid: *mut u32, suid: *mut u32) -> SysResult {
+    let ids = get_current_task().unwrap().credentials.lock().uid;
+    write_ids(ids, ruid, euid, suid)
+}
+
+/// 获取实际、有效和保存的用户组 id
+pub fn sys_getresgid(rgid: *mut u32, egid: *mut u32, sgid: *mut u32) -> SysResult {
+    let ids = get_current_task().unwrap().credentials.lock().gid;
+    write_ids(ids, rgid, egid, sgid)
+}
+
+/// 获取附加用户组，写入 list 中，返回附加用户组的个数。
+/// 如果 size 为 0，则只返回个数；如果 size 小于附加用户组的个数，则返回 EINVAL
+pub fn sys_getgroups(size: usize, list: *mut u32) -> SysResult {
+    let task = get_current_task().unwrap();
+    let groups = task.credentials.lock().groups.clone();
+    if size == 0 {
+        return Ok(groups.len());
+    }
+    if size < groups.len() {
+        return Err(ErrorNo::EINVAL);
+    }
+    let vm = task.get_vm();
+    let mut task_vm = vm.lock();
+    for (i, &gid) in groups.iter().enumerate() {
+        let ptr = unsafe { list.add(i) };
+        if task_vm.manually_alloc_type(ptr).is_err() {
+            return Err(ErrorNo::EFAULT);
+        }
+        unsafe {
+            *ptr = gid;
+        }
+    }
+    Ok(groups.len())
+}
+
+/// 设置附加用户组，只有 root 可以调用
+pub fn sys_setgroups(size: usize, list: *const u32) -> SysResult {
+    if size > NGROUPS_MAX {
+        return Err(ErrorNo::EINVAL);
+    }
+    let task = get_current_task().unwrap();
+    if !task.credentials.lock().is_privileged() {
+        return Err(ErrorNo::EPERM);
+    }
+    let mut groups = Vec::with_capacity(size);
+    let vm = task.get_vm();
+    let mut task_vm = vm.lock();
+    for i in 0..size {
+        let ptr = unsafe { list.add(i) };
+        if task_vm.manually_alloc_type(ptr).is_err() {
+            return Err(ErrorNo::EFAULT);
+        }
+        groups.push(unsafe { *ptr });
+    }
+    drop(task_vm);
+    task.credentials.lock().groups = groups;
     Ok(0)
 }
 
 /// 向 pid 指定的进程发送信号。
-/// 如果进程中有多个线程，则会发送给任意一个未阻塞的线程。
+/// 如果进程中有多个线程，则由其中任意一个没有屏蔽该信号的线程处理。
 ///
 /// pid 有如下情况
 /// 1. pid > 0，则发送给指定进程
-/// 2. pid = 0，则发送给所有同组进程
-/// 3. pid = -1，则发送给除了初始进程(pid=1)外的所有当前进程有权限的进程
-/// 4. pid < -2，则发送给组内 pid 为参数相反数的进程
+/// 2. pid = 0，则发送给当前进程所在进程组的所有进程
+/// 3. pid = -1，则发送给除了初始进程和当前进程外的所有进程
+/// 4. pid < -1，则发送给进程组 id 为 -pid 的所有进程
 ///
-/// 目前 2/3/4 未实现。对于 1，仿照 zCore 的设置，认为**当前进程自己或其直接子进程** 是"有权限"或者"同组"的进程。
+/// 如果 signal_id == 0，则不发送信号，仅检查是否存在对应进程。
+/// 找不到任何目标进程时返回 ESRCH
 pub fn sys_kill(pid: isize, signal_id: isize) -> SysResult {
     info!("kill pid {}, signal id {}", pid, signal_id);
-    if pid > 0 && signal_id > 0 {
-        //println!("kill pid {}, signal id {}", pid, signal_id);
-        send_signal(pid as usize, signal_id as usize);
-        Ok(0)
-    } else if pid == 0 {
-        Err(ErrorNo::ESRCH)
+    if signal_id < 0 || signal_id as usize > SIGSET_SIZE_IN_BIT {
+        return Err(ErrorNo::EINVAL);
+    }
+    let task = get_current_task().unwrap();
+    let targets: Vec<Arc<TaskControlBlock>> = if pid > 0 {
+        // pid 需要是进程的 pid，即组长线程的 tid。其他线程的 tid 视为不存在
+        get_task_from_tid(pid as usize)
+            .filter(|t| t.get_pid_num() == t.get_tid_num())
+            .into_iter()
+            .collect()
     } else {
-        // 如果 signal_id == 0，则仅为了检查是否存在对应进程，此时应该返回参数错误。是的，用户库是会刻意触发这个错误的
-        Err(ErrorNo::EINVAL)
+        // None 表示发送给所有进程
+        let target_pgid = match pid {
+            0 => Some(task.get_pgid()),
+            -1 => None,
+            _ => Some((-pid) as usize),
+        };
+        let current_pid = task.get_pid_num();
+        get_all_tasks()
+            .into_iter()
+            .filter(|t| t.get_pid_num() == t.get_tid_num())
+            .filter(|t| match target_pgid {
+                Some(pgid) => t.get_pgid() == pgid,
+                None => t.get_pid_num() != current_pid && t.inner.lock().parent.is_some(),
+            })
+            .collect()
+    };
+    if targets.is_empty() {
+        return Err(ErrorNo::ESRCH);
+    }
+    let targets: Vec<Arc<TaskControlBlock>> = targets
+        .into_iter()
+        .filter(|t| can_send_signal_to(&task, t, signal_id as usize))
+        .collect();
+    if targets.is_empty() {
+        return Err(ErrorNo::EPERM);
+    }
+    if signal_id > 0 {
+        let info = sender_siginfo(&task, signal_id as usize, SI_USER);
+        for target in targets {
+            target.send_siginfo_to_process(info);
+        }
+    }
+    Ok(0)
+}
+
+/// 检查 task 是否有权限向 target 发送信号 signum：
+/// 需要有特权或用户 id 匹配，而 SIGCONT 则可以发给同一会话中的任意进程
+fn can_send_signal_to(
+    task: &Arc<TaskControlBlock>,
+    target: &Arc<TaskControlBlock>,
+    signum: usize,
+) -> bool {
+    Arc::ptr_eq(&target.credentials, &task.credentials)
+        || task
+            .credentials
+            .lock()
+            .can_signal(&target.credentials.lock())
+        || (signum == SignalNo::SIGCONT as usize && target.get_sid() == task.get_sid())
+}
+
+/// 获取当前进程的 fd 对应的 pidfd 所指向的进程。
+/// 如 fd 不存在或不是 pidfd，返回 EBADF；如进程已经被回收，返回 ESRCH
+fn get_task_from_pidfd(pidfd: usize) -> Result<Arc<TaskControlBlock>, ErrorNo> {
+    let file = get_current_task()
+        .unwrap()
+        .fd_manager
+        .lock()
+        .get_file(pidfd)
+        .map_err(|_| ErrorNo::EBADF)?;
+    // 需要对 Arc 里的文件本身 downcast，否则 as_any 得到的是 Arc
+    (*file)
+        .as_any()
+        .downcast_ref::<PidFd>()
+        .ok_or(ErrorNo::EBADF)?
+        .get_task()
+        .ok_or(ErrorNo::ESRCH)
+}
+
+/// 获取一个指向进程 pid 的 pidfd。flags 只能为 0 或 PIDFD_NONBLOCK
+///
+/// pid 必须是一个进程(即线程组组长)的 pid，而不能是普通线程的 tid
+pub fn sys_pidfd_open(pid: isize, flags: u32) -> SysResult {
+    info!("pidfd_open pid {} flags {:x}", pid, flags);
+    if pid <= 0 || flags & !PIDFD_NONBLOCK != 0 {
+        return Err(ErrorNo::EINVAL);
+    }
+    let target = get_task_from_tid(pid as usize).ok_or(ErrorNo::ESRCH)?;
+    if target.get_pid_num() != target.get_tid_num() {
+        return Err(ErrorNo::EINVAL);
+    }
+    let pidfd = PidFd::new(&target, flags & PIDFD_NONBLOCK != 0);
+    get_current_task()
+        .unwrap()
+        .fd_manager
+        .lock()
+        .push(Arc::new(pidfd))
+        .map_err(|_| ErrorNo::EMFILE)
+}
+
+/// 向 pidfd 指向的进程发送信号。signal_id 为 0 时只检查进程是否存在以及是否有权限。
+///
+/// info 非空时，作为信号的附加信息，要求同 sys_rt_sigqueueinfo；否则视为由 kill 发出。flags 必须为 0
+pub fn sys_pidfd_send_signal(
+    pidfd: usize,
+    signal_id: usize,
+    info: *const SigInfo,
+    flags: u32,
+) -> SysResult {
+    info!("pidfd_send_signal pidfd {}, signal id {}", pidfd, signal_id);
+    if flags != 0 || signal_id > SIGSET_SIZE_IN_BIT {
+        return Err(ErrorNo::EINVAL);
+    }
+    let target = get_task_from_pidfd(pidfd)?;
+    // 进程已经退出但还未被回收时，也视为不存在
+    if target.thread_group.lock().is_empty() {
+        return Err(ErrorNo::ESRCH);
+    }
+    let task = get_current_task().unwrap();
+    let info = if info.is_null() {
+        sender_siginfo(&task, signal_id, SI_USER)
+    } else {
+        read_user_siginfo(&task, info, signal_id, target.get_pid_num())?
+    };
+    if !can_send_signal_to(&task, &target, signal_id) {
+        return Err(ErrorNo::EPERM);
+    }
+    if signal_id > 0 && !target.send_siginfo_to_process(info) {
+        return Err(ErrorNo::EAGAIN);
+    }
+    Ok(0)
+}
+
+/// 复制 pidfd 指向的进程的文件描述符 target_fd 到当前进程，返回新的 fd。flags 必须为 0
+///
+/// 当前进程需要有权限访问目标进程，即有特权或与它属于同一用户
+pub fn sys_pidfd_getfd(pidfd: usize, target_fd: usize, flags: u32) -> SysResult {
+    info!("pidfd_getfd pidfd {}, target fd {}", pidfd, target_fd);
+    if flags != 0 {
+        return Err(ErrorNo::EINVAL);
+    }
+    let target = get_task_from_pidfd(pidfd)?;
+    let task = get_current_task().unwrap();
+    if !Arc::ptr_eq(&target.credentials, &task.credentials)
+        && !task
+            .credentials
+            .lock()
+            .can_inspect(&target.credentials.lock())
+    {
+        return Err(ErrorNo::EPERM);
+    }
+    let file = target
+        .fd_manager
+        .lock()
+        .get_file(target_fd)
+        .map_err(|_| ErrorNo::EBADF)?;
+    // Linux 会给新的 fd 设置 CLOEXEC，但这里文件状态是和原 fd 共享的，设置它会影响目标进程，所以不修改
+    let fd = task
+        .fd_manager
+        .lock()
+        .push(file)
+        .map_err(|_| ErrorNo::EMFILE)?;
+    Ok(fd)
+}
+
+/// 对当前线程或进程进行一些设置，option 指定操作，其余参数的含义由 option 决定：
+/// - PR_SET_NAME / PR_GET_NAME：设置或获取线程名，arg2 指向最长 16 字节的字符串
+/// - PR_SET_PDEATHSIG / PR_GET_PDEATHSIG：设置父进程退出时收到的信号，或将其写入 arg2 指向的 int
+/// - PR_SET_CHILD_SUBREAPER / PR_GET_CHILD_SUBREAPER：设置当前进程是否为子进程回收者，或将其写入 arg2 指向的 int
+/// - PR_SET_NO_NEW_PRIVS / PR_GET_NO_NEW_PRIVS：禁止通过 exec 获得新的权限(arg2 必须为 1)，或返回是否已禁止
+pub fn sys_prctl(option: i32, arg2: usize, arg3: usize, arg4: usize, arg5: usize) -> SysResult {
+    info!("prctl option {} arg2 {:x}", option, arg2);
+    let task = get_current_task().unwrap();
+    match option {
+        PR_SET_NAME => {
+            // 超过 TASK_COMM_LEN - 1 字节的部分会被截断
+            let name = task
+                .get_vm()
+                .lock()
+                .read_user_c_str(arg2 as *const u8, TASK_COMM_LEN - 1)
+                .map_err(|_| ErrorNo::EFAULT)?;
+            task.inner.lock().comm = String::from_utf8_lossy(&name).into_owned();
+            Ok(0)
+        }
+        PR_GET_NAME => {
+            let vm = task.get_vm();
+            if vm
+                .lock()
+                .manually_alloc_user_str(arg2 as *const u8, TASK_COMM_LEN)
+                .is_err()
+            {
+                return Err(ErrorNo::EFAULT);
+            }
+            let mut name = [0u8; TASK_COMM_LEN];
+            let comm = task.inner.lock().comm.clone();
+            let len = comm.len().min(TASK_COMM_LEN - 1);
+            name[..len].copy_from_slice(&comm.as_bytes()[..len]);
+            unsafe {
+                core::ptr::copy_nonoverlapping(name.as_ptr(), arg2 as *mut u8, TASK_COMM_LEN);
+            }
+            Ok(0)
+        }
+        PR_SET_PDEATHSIG => {
+            if arg2 > SIGSET_SIZE_IN_BIT {
+                return Err(ErrorNo::EINVAL);
+            }
+            task.inner.lock().pdeath_signal = arg2;
+            Ok(0)
+        }
+        PR_GET_PDEATHSIG => {
+            let signum = task.inner.lock().pdeath_signal as i32;
+            write_prctl_int(&task, arg2, signum)
+        }
+        PR_SET_CHILD_SUBREAPER => {
+            task.thread_group.lock().child_subreaper = arg2 != 0;
+            Ok(0)
+        }
+        PR_GET_CHILD_SUBREAPER => {
+            let is_subreaper = task.thread_group.lock().child_subreaper as i32;
+            write_prctl_int(&task, arg2, is_subreaper)
+        }
+        PR_SET_NO_NEW_PRIVS => {
+            // 设置后不能取消
+            if arg2 != 1 || arg3 != 0 || arg4 != 0 || arg5 != 0 {
+                return Err(ErrorNo::EINVAL);
+            }
+            task.credentials.lock().no_new_privs = true;
+            Ok(0)
+        }
+        PR_GET_NO_NEW_PRIVS => {
+            if arg2 != 0 || arg3 != 0 || arg4 != 0 || arg5 != 0 {
+                return Err(ErrorNo::EINVAL);
+            }
+            Ok(task.credentials.lock().no_new_privs as usize)
+        }
+        _ => Err(ErrorNo::EINVAL),
+    }
+}
+
+/// 把 prctl 的 GET 类操作的结果写入用户地址 addr 处的 int
+fn write_prctl_int(task: &Arc<TaskControlBlock>, addr: usize, value: i32) -> SysResult {
+    let vm = task.get_vm();
+    if vm.lock().manually_alloc_type(addr as *const i32).is_err() {
+        return Err(ErrorNo::EFAULT);
+    }
+    unsafe {
+        *(addr as *mut i32) = value;
+    }
+    Ok(0)
+}
+
+/// 获取 sched_setaffinity / sched_getaffinity 操作的线程。tid 为 0 时表示当前线程
+fn get_affinity_target(tid: usize) -> Result<Arc<TaskControlBlock>, ErrorNo> {
+    if tid == 0 {
+        Ok(get_current_task().unwrap())
+    } else {
+        get_task_from_tid(tid).ok_or(ErrorNo::ESRCH)
     }
 }
 
+/// 设置线程 tid 允许运行的 cpu。mask 指向长为 cpusetsize 字节的掩码，超出内核支持的 cpu 数的部分被忽略
+///
+/// 掩码中至少需要有一个正在运行用户程序的 cpu。如果当前线程不再被允许在当前 cpu 上运行，则立即让出 cpu
+pub fn sys_sched_setaffinity(tid: usize, cpusetsize: usize, mask: *const u8) -> SysResult {
+    info!("sched_setaffinity tid {} size {}", tid, cpusetsize);
+    let task = get_current_task().unwrap();
+    let target = get_affinity_target(tid)?;
+    // 需要有特权，或者有效用户 id 等于对方的实际或有效用户 id
+    if !Arc::ptr_eq(&target.credentials, &task.credentials) {
+        let credentials = task.credentials.lock();
+        let target_credentials = target.credentials.lock();
+        if !credentials.is_privileged()
+            && credentials.uid.effective != target_credentials.uid.real
+            && credentials.uid.effective != target_credentials.uid.effective
+        {
+            return Err(ErrorNo::EPERM);
+        }
+    }
+    let len = cpusetsize.min(size_of::<usize>());
+    let mut new_mask = [0u8; size_of::<usize>()];
+    if len > 0 {
+        let vm = task.get_vm();
+        if vm.lock().manually_alloc_user_str(mask, len).is_err() {
+            return Err(ErrorNo::EFAULT);
+        }
+        unsafe {
+            core::ptr::copy_nonoverlapping(mask, new_mask.as_mut_ptr(), len);
+        }
+    }
+    let new_mask = usize::from_le_bytes(new_mask) & ALL_CPU_MASK;
+    if new_mask & get_active_cpu_mask() == 0 {
+        return Err(ErrorNo::EINVAL);
+    }
+    *target.cpu_mask.lock() = new_mask;
+    if Arc::ptr_eq(&target, &task) && !task.can_run_on(get_cpu_id()) {
+        drop(target);
+        drop(task);
+        suspend_current_task();
+    }
+    Ok(0)
+}
+
+/// 获取线程 tid 允许运行的 cpu，写入 mask 指向的长为 cpusetsize 字节的掩码中。
+/// 成功时返回写入的字节数
+pub fn sys_sched_getaffinity(tid: usize, cpusetsize: usize, mask: *mut u8) -> SysResult {
+    info!("sched_getaffinity tid {} size {}", tid, cpusetsize);
+    // 长度需要能放下所有 cpu，且是 usize 的整数倍
+    if cpusetsize < size_of::<usize>() || cpusetsize % size_of::<usize>() != 0 {
+        return Err(ErrorNo::EINVAL);
+    }
+    let target = get_affinity_target(tid)?;
+    let cpu_mask = *target.cpu_mask.lock() & get_active_cpu_mask();
+    let task = get_current_task().unwrap();
+    let vm = task.get_vm();
+    if vm.lock().manually_alloc_type(mask as *const usize).is_err() {
+        return Err(ErrorNo::EFAULT);
+    }
+    unsafe {
+        *(mask as *mut usize) = cpu_mask;
+    }
+    Ok(size_of::<usize>())
+}
+
+/// 获取当前线程所在的 cpu 编号和 NUMA 节点编号，分别写入 cpu 和 node 指向的地址(如不为 0)。
+/// 目前只有一个 NUMA 节点，所以 node 总是 0
+pub fn sys_getcpu(cpu: *mut u32, node: *mut u32) -> SysResult {
+    let task = get_current_task().unwrap();
+    let vm = task.get_vm();
+    let mut task_vm = vm.lock();
+    for (addr, value) in [(cpu, get_cpu_id() as u32), (node, 0)] {
+        if !addr.is_null() {
+            if task_vm.manually_alloc_type(addr).is_err() {
+                return Err(ErrorNo::EFAULT);
+            }
+            unsafe {
+                *addr = value;
+            }
+        }
+    }
+    Ok(0)
+}
+
 /// 向 tid 指定的线程发送信号。
 ///
 /// 在 `https://man7.org/linux/man-pages/man2/tkill.2.html` 中，建议使用 tgkill 替代，
@@ -436,13 +1571,97 @@ pub fn sys_kill(pid: isize, signal_id: isize) -> SysResult {
 pub fn sys_tkill(tid: isize, signal_id: isize) -> SysResult {
     //info!("tkill tid {}, signal id {}", tid, signal_id);
     if tid > 0 {
-        send_signal(tid as usize, signal_id as usize);
+        let task = get_current_task().unwrap();
+        send_siginfo(
+            tid as usize,
+            sender_siginfo(&task, signal_id as usize, SI_TKILL),
+        );
         Ok(0)
     } else {
         Err(ErrorNo::EINVAL)
     }
 }
 
+/// 向进程 pid 发送信号 signal_id，附加信息由用户在 uinfo 中给出。libc 的 sigqueue 即通过它实现。
+///
+/// 如果 signal_id == 0，则不发送信号，仅检查是否存在对应进程以及是否有权限。
+/// 实时信号的队列已满时返回 EAGAIN
+pub fn sys_rt_sigqueueinfo(pid: isize, signal_id: usize, uinfo: *const SigInfo) -> SysResult {
+    info!("rt_sigqueueinfo pid {}, signal id {}", pid, signal_id);
+    if signal_id > SIGSET_SIZE_IN_BIT {
+        return Err(ErrorNo::EINVAL);
+    }
+    let target = get_task_from_tid(pid as usize)
+        .filter(|target| pid > 0 && target.get_pid_num() == target.get_tid_num())
+        .ok_or(ErrorNo::ESRCH)?;
+    let task = get_current_task().unwrap();
+    let info = read_user_siginfo(&task, uinfo, signal_id, target.get_pid_num())?;
+    if !can_send_signal_to(&task, &target, signal_id) {
+        return Err(ErrorNo::EPERM);
+    }
+    if signal_id > 0 && !target.send_siginfo_to_process(info) {
+        return Err(ErrorNo::EAGAIN);
+    }
+    Ok(0)
+}
+
+/// 向进程 tgid 中的线程 tid 发送信号 signal_id，附加信息由用户在 uinfo 中给出。
+/// 其余同 sys_rt_sigqueueinfo
+pub fn sys_rt_tgsigqueueinfo(
+    tgid: isize,
+    tid: isize,
+    signal_id: usize,
+    uinfo: *const SigInfo,
+) -> SysResult {
+    info!(
+        "rt_tgsigqueueinfo tgid {}, tid {}, signal id {}",
+        tgid, tid, signal_id
+    );
+    if tgid <= 0 || tid <= 0 || signal_id > SIGSET_SIZE_IN_BIT {
+        return Err(ErrorNo::EINVAL);
+    }
+    let target = get_task_from_tid(tid as usize)
+        .filter(|target| target.get_pid_num() == tgid as usize)
+        .ok_or(ErrorNo::ESRCH)?;
+    let task = get_current_task().unwrap();
+    let info = read_user_siginfo(&task, uinfo, signal_id, target.get_pid_num())?;
+    if !can_send_signal_to(&task, &target, signal_id) {
+        return Err(ErrorNo::EPERM);
+    }
+    if signal_id > 0 {
+        if !send_siginfo(tid as usize, info) {
+            return Err(ErrorNo::EAGAIN);
+        }
+    }
+    Ok(0)
+}
+
+/// 当前进程通过 kill / tkill 等发出信号时附带的信息，记录发送者的 pid 和实际用户 id
+fn sender_siginfo(task: &Arc<TaskControlBlock>, signum: usize, code: i32) -> SigInfo {
+    let uid = task.credentials.lock().uid.real;
+    SigInfo::user(signum, code, task.get_pid_num(), uid)
+}
+
+/// 读取用户给出的信号附加信息，并把其中的 si_signo 设为 signum。
+///
+/// 发给其他进程时，不允许 si_code 为非负数或 SI_TKILL，以免冒充 kill 或者内核发出的信号
+fn read_user_siginfo(
+    task: &Arc<TaskControlBlock>,
+    uinfo: *const SigInfo,
+    signum: usize,
+    target_pid: usize,
+) -> Result<SigInfo, ErrorNo> {
+    if task.get_vm().lock().manually_alloc_type(uinfo).is_err() {
+        return Err(ErrorNo::EFAULT);
+    }
+    let mut info = unsafe { *uinfo };
+    if (info.si_code >= 0 || info.si_code == SI_TKILL) && target_pid != task.get_pid_num() {
+        return Err(ErrorNo::EPERM);
+    }
+    info.si_signo = signum as i32;
+    Ok(info)
+}
+
 /// 改变当前线程屏蔽的信号类型。
 ///
 /// 所有信号类型存放在 sigsetsize Byte 大小的一个 bitset 里(因为是riscv64，默认为 8)
@@ -471,7 +1690,8 @@ pub fn sys_sigprocmask(
     );
 
     let task = get_current_task().unwrap();
-    let mut task_vm = task.vm.lock();
+    let vm = task.get_vm();
+    let mut task_vm = vm.lock();
     let mut receiver = task.signal_receivers.lock();
 
     if old_set as usize != 0 {
@@ -501,6 +1721,131 @@ pub fn sys_sigprocmask(
     Ok(0)
 }
 
+/// 读取用户给出的信号集合，并去掉不能被屏蔽或等待的 SIGKILL 和 SIGSTOP
+fn read_user_sigset(task: &Arc<TaskControlBlock>, set: *const usize) -> Result<Bitset, ErrorNo> {
+    if task.get_vm().lock().manually_alloc_type(set).is_err() {
+        return Err(ErrorNo::EFAULT);
+    }
+    let mut set = Bitset::new(unsafe { *set });
+    set.remove_bit(SignalNo::SIGKILL as usize - 1);
+    set.remove_bit(SignalNo::SIGSTOP as usize - 1);
+    Ok(set)
+}
+
+/// 暂时把信号掩码替换为 mask，然后休眠直到收到需要处理的信号。
+///
+/// 总是返回 EINTR。如果进入了信号处理函数，则处理函数返回后才恢复原来的掩码
+pub fn sys_rt_sigsuspend(mask: *const usize, sigsetsize: usize) -> SysResult {
+    if sigsetsize != SIGSET_SIZE_IN_BYTE {
+        return Err(ErrorNo::EINVAL);
+    }
+    let task = get_current_task().unwrap();
+    let mask = read_user_sigset(&task, mask)?;
+    let mut receivers = task.signal_receivers.lock();
+    receivers.saved_mask = Some(receivers.mask);
+    receivers.mask = mask;
+    drop(receivers);
+    while !task.has_pending_signal() {
+        suspend_current_task();
+    }
+    // 没有调用处理函数时需要重启，由 handle_signals 恢复掩码后再次进入
+    Err(ErrorNo::ERESTARTNOHAND)
+}
+
+/// 获取已收到但被屏蔽的信号，写入 set 中
+pub fn sys_rt_sigpending(set: *mut usize, sigsetsize: usize) -> SysResult {
+    if sigsetsize != SIGSET_SIZE_IN_BYTE {
+        return Err(ErrorNo::EINVAL);
+    }
+    let task = get_current_task().unwrap();
+    if task.get_vm().lock().manually_alloc_type(set).is_err() {
+        return Err(ErrorNo::EFAULT);
+    }
+    let receivers = task.signal_receivers.lock();
+    unsafe {
+        *set = receivers.all_pending().0 & receivers.mask.0;
+    }
+    Ok(0)
+}
+
+/// 同步地等待 set 中的信号。收到时将它从队列中取出，把附加信息写入 info(非空时)，并返回信号编号。
+///
+/// 如果 timeout 非空，则最多等待这么长时间，超时返回 EAGAIN；被 set 之外的信号打断时返回 EINTR
+pub fn sys_rt_sigtimedwait(
+    set: *const usize,
+    info: *mut SigInfo,
+    timeout: *const TimeSpec,
+    sigsetsize: usize,
+) -> SysResult {
+    if sigsetsize != SIGSET_SIZE_IN_BYTE {
+        return Err(ErrorNo::EINVAL);
+    }
+    let task = get_current_task().unwrap();
+    let set = read_user_sigset(&task, set)?;
+    let end_time = if timeout.is_null() {
+        None
+    } else {
+        if task.get_vm().lock().manually_alloc_type(timeout).is_err() {
+            return Err(ErrorNo::EFAULT);
+        }
+        let timeout = unsafe { &*timeout };
+        // tv_nsec 为负数时，作为 usize 读出也不会小于 NSEC_PER_SEC
+        if (timeout.tv_sec as isize) < 0 || timeout.tv_nsec >= NSEC_PER_SEC {
+            return Err(ErrorNo::EINVAL);
+        }
+        Some(get_time_f64() + timeout.time_in_sec())
+    };
+    if !info.is_null() && task.get_vm().lock().manually_alloc_type(info).is_err() {
+        return Err(ErrorNo::EFAULT);
+    }
+    loop {
+        let siginfo = task.signal_receivers.lock().dequeue_from(set);
+        if let Some(siginfo) = siginfo {
+            if !info.is_null() {
+                unsafe {
+                    *info = siginfo;
+                }
+            }
+            return Ok(siginfo.si_signo as usize);
+        }
+        if end_time.map_or(false, |end_time| get_time_f64() >= end_time) {
+            return Err(ErrorNo::EAGAIN);
+        }
+        if task.has_pending_signal() {
+            return Err(ErrorNo::EINTR);
+        }
+        suspend_current_task();
+    }
+}
+
+/// 创建一个接收 mask 中信号的 signalfd，或者在 fd 不为 -1 时修改已有的 signalfd 接收的信号。
+///
+/// 成功时返回 signalfd 的文件描述符。flags 可以包含 SFD_NONBLOCK 和 SFD_CLOEXEC
+pub fn sys_signalfd4(fd: isize, mask: *const usize, sizemask: usize, flags: u32) -> SysResult {
+    info!("signalfd4 fd {} flags {:x}", fd, flags);
+    if sizemask != SIGSET_SIZE_IN_BYTE || flags & !(SFD_NONBLOCK | SFD_CLOEXEC) != 0 {
+        return Err(ErrorNo::EINVAL);
+    }
+    let task = get_current_task().unwrap();
+    let mask = read_user_sigset(&task, mask)?;
+    let mut fd_manager = task.fd_manager.lock();
+    if fd == -1 {
+        let signalfd = SignalFd::new(mask, flags & SFD_NONBLOCK != 0, flags & SFD_CLOEXEC != 0);
+        return fd_manager
+            .push(Arc::new(signalfd))
+            .map_err(|_| ErrorNo::EMFILE);
+    }
+    let file = fd_manager
+        .get_file(fd as usize)
+        .map_err(|_| ErrorNo::EBADF)?;
+    (*file)
+        .as_any()
+        .downcast_ref::<SignalFd>()
+        .ok_or(ErrorNo::EINVAL)?
+        .set_mask(mask);
+    Ok(fd as usize)
+}
+
 /// 改变当前进程的信号处理函数。
 ///
 /// 如果 action 为 0，则不设置；如果 old_action 为 0，则不存入。
@@ -513,7 +1858,8 @@ pub fn sys_sigaction(
         return Err(ErrorNo::EINVAL); // 特殊信号不能被覆盖
     }
     let task = get_current_task().unwrap();
-    let mut task_vm = task.vm.lock();
+    let vm = task.get_vm();
+    let mut task_vm = vm.lock();
     let mut handler = task.signal_handlers.lock();
 
     unsafe {
@@ -554,6 +1900,42 @@ pub fn sys_sigaction(
     Ok(0)
 }
 
+/// 设置或获取当前线程的备用信号栈。设置了 SA_ONSTACK 的信号处理函数会在这个栈上执行
+///
+/// 如果 ss 为 0，则不设置；如果 old_ss 为 0，则不存入。
+/// 正运行在备用信号栈上时不能修改它，此时返回 EPERM
+pub fn sys_sigaltstack(ss: *const SignalStack, old_ss: *mut SignalStack) -> SysResult {
+    let task = get_current_task().unwrap();
+    let vm = task.get_vm();
+    let new_stack = if ss as usize != 0 {
+        if vm.lock().manually_alloc_type(ss).is_err() {
+            return Err(ErrorNo::EFAULT);
+        }
+        Some(SignalStack::from_user(unsafe { &*ss })?)
+    } else {
+        None
+    };
+    let user_sp = unsafe { (*task.kernel_stack.get_first_context()).get_sp() };
+    let mut inner = task.inner.lock();
+    let state = inner.signal_stack.state(user_sp);
+    if let Some(stack) = new_stack {
+        if inner.signal_stack.contains(user_sp) {
+            return Err(ErrorNo::EPERM);
+        }
+        inner.signal_stack = stack;
+    }
+    drop(inner);
+    if old_ss as usize != 0 {
+        if vm.lock().manually_alloc_type(old_ss).is_err() {
+            return Err(ErrorNo::EFAULT);
+        }
+        unsafe {
+            *old_ss = state;
+        }
+    }
+    Ok(0)
+}
+
 /// 从信号处理过程中返回，即恢复信号处理前的用户程序上下文。
 ///
 /// sigreturn 没有返回值，因此也不该写入 a0。
@@ -578,58 +1960,82 @@ pub fn sys_set_tid_address(addr: usize) -> SysResult {
     sys_gettid()
 }
 
-/// 修改一些资源的限制
+/// 获取和修改进程 pid 的资源限制，pid 设为 0 时表示当前进程。
+/// 如果 old_limit 非空，则把原来的限制写入其中；如果 new_limit 非空，则改为新的限制
 ///
-/// pid 设为0时，表示应用于自己
-pub fn sys_prlimt64(
+/// - 访问其他进程需要有特权，或者对方的实际/有效/保存的 id 都等于自己的实际 id
+/// - 软上限不能超过硬上限，提高硬上限需要特权
+pub fn sys_prlimit64(
     pid: usize,
     resource: i32,
     new_limit: *const RLimit,
     old_limit: *mut RLimit,
 ) -> SysResult {
-    info!("pid {} resource {}", pid, resource);
-    if pid == 0 {
-        let task = get_current_task().unwrap();
-        let mut fd_manger = task.fd_manager.lock();
-
-        match resource {
-            RLIMIT_STACK => {
-                if old_limit as usize != 0 {
-                    unsafe {
-                        *old_limit = RLimit {
-                            rlim_cur: USER_STACK_SIZE as u64,
-                            rlim_max: USER_STACK_SIZE as u64,
-                        };
-                    }
-                }
-            }
-            RLIMIT_NOFILE => {
-                if old_limit as usize != 0 {
-                    let limit = fd_manger.get_limit();
-                    unsafe {
-                        *old_limit = RLimit {
-                            rlim_cur: limit as u64,
-                            rlim_max: limit as u64,
-                        };
-                    }
-                }
-                if new_limit as usize != 0 {
-                    let new_limit = unsafe { (*new_limit).rlim_cur };
-                    fd_manger.modify_limit(new_limit as usize);
-                }
-            }
-            RLIMIT_AS => {
-                if old_limit as usize != 0 {
-                    unsafe {
-                        *old_limit = RLimit {
-                            rlim_cur: USER_VIRT_ADDR_LIMIT as u64,
-                            rlim_max: USER_VIRT_ADDR_LIMIT as u64,
-                        };
-                    }
-                }
+    info!("prlimit64 pid {} resource {}", pid, resource);
+    if resource < 0 || resource as usize >= RLIM_NLIMITS {
+        return Err(ErrorNo::EINVAL);
+    }
+    let resource = resource as usize;
+    let task = get_current_task().unwrap();
+    let target = if pid == 0 {
+        task.clone()
+    } else {
+        get_task_from_tid(pid).ok_or(ErrorNo::ESRCH)?
+    };
+    let credentials = task.credentials.lock().clone();
+    if !Arc::ptr_eq(&target.credentials, &task.credentials)
+        && !credentials.can_inspect(&target.credentials.lock())
+    {
+        return Err(ErrorNo::EPERM);
+    }
+    let vm = task.get_vm();
+    let new_limit = if new_limit as usize != 0 {
+        if vm.lock().manually_alloc_type(new_limit).is_err() {
+            return Err(ErrorNo::EFAULT); // 地址不合法
+        }
+        Some(unsafe { *new_limit })
+    } else {
+        None
+    };
+    if old_limit as usize != 0 && vm.lock().manually_alloc_type(old_limit).is_err() {
+        return Err(ErrorNo::EFAULT); // 地址不合法
+    }
+    let mut rlimits = target.rlimits.lock();
+    let limit = rlimits.get(resource);
+    if let Some(new_limit) = new_limit {
+        if new_limit.rlim_cur > new_limit.rlim_max {
+            return Err(ErrorNo::EINVAL);
+        }
+        if new_limit.rlim_max > limit.rlim_max && !credentials.is_privileged() {
+            return Err(ErrorNo::EPERM);
+        }
+        if resource == RLIMIT_NOFILE {
+            // fd 分配器的大小是固定的，不能超过 FD_LIMIT_HARD
+            if new_limit.rlim_max > FD_LIMIT_HARD as u64 {
+                return Err(ErrorNo::EPERM);
             }
-            _ => {}
+            target
+                .fd_manager
+                .lock()
+                .modify_limit(new_limit.rlim_cur as usize);
+        }
+        rlimits.set(resource, new_limit);
+    }
+    drop(rlimits);
+    if old_limit as usize != 0 {
+        unsafe {
+            *old_limit = limit;
         }
     }
     Ok(0)
 }
+
+/// 获取当前进程的资源限制。相当于 pid 为 0 的 sys_prlimit64
+pub fn sys_getrlimit(resource: i32, limit: *mut RLimit) -> SysResult {
+    sys_prlimit64(0, resource, 0 as *const RLimit, limit)
+}
+
+/// 修改当前进程的资源限制。相当于 pid 为 0 的 sys_prlimit64
+pub fn sys_setrlimit(resource: i32, limit: *const RLimit) -> SysResult {
+    sys_prlimit64(0, resource, limit, 0 as *mut RLimit)
+}
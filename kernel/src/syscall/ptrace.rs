@@ -0,0 +1,347 @@
+//! 进程跟踪(ptrace)的系统调用，即跟踪者一侧的操作
+//!
+//! 被跟踪的任务一侧的暂停逻辑见 `task/ptrace.rs`
+
+use super::{IoVec, PtraceRequest, SysResult, NT_PRFPREG, NT_PRSTATUS};
+use crate::{
+    memory::PTEFlags,
+    signal::{send_signal, SigInfo, SignalNo},
+    task::{
+        get_current_task, get_task_from_tid, PtraceOptions, PtraceResume, PtraceState,
+        TaskControlBlock, TaskStatus,
+    },
+    trap::FpContext,
+};
+use alloc::sync::Arc;
+use core::{mem::size_of, slice};
+use syscall::ErrorNo;
+
+/// 通用寄存器的数量，依次为 pc 和 x1~x31，与 riscv64 Linux 的 `struct user_regs_struct` 相同
+const USER_REGS_COUNT: usize = 32;
+
+/// 进程跟踪。各操作的含义见 `flags.rs: PtraceRequest`，参数 addr 和 data 的含义随操作而不同：
+/// - PEEKTEXT / PEEKDATA 从被跟踪的任务的 addr 处读取一个字，写入跟踪者的 data 处
+/// - POKETEXT / POKEDATA 把 data 写入被跟踪的任务的 addr 处
+/// - CONT / SYSCALL / SINGLESTEP / DETACH 恢复被跟踪的任务，data 为要注入的信号
+/// - GETREGS / SETREGS 读写通用寄存器，data 指向 32 个 usize
+/// - GETREGSET / SETREGSET 中 addr 为寄存器类型(NT_PRSTATUS 或 NT_PRFPREG)，data 指向一个 IoVec，读写后修改其中的长度
+/// - SEIZE / SETOPTIONS 中 data 为跟踪选项
+///
+/// 除了 TRACEME / ATTACH / SEIZE 外，pid 必须是当前线程跟踪的任务；
+/// 除了 KILL / INTERRUPT 外，被跟踪的任务必须处于暂停中，否则返回 ESRCH
+pub fn sys_ptrace(request: usize, pid: usize, addr: usize, data: usize) -> SysResult {
+    let request = PtraceRequest::try_from(request).map_err(|_| ErrorNo::EIO)?;
+    info!("sys_ptrace {:?} pid {} {:x} {:x}", request, pid, addr, data);
+    let task = get_current_task().unwrap();
+    match request {
+        PtraceRequest::TRACEME => return ptrace_traceme(&task),
+        PtraceRequest::ATTACH => return ptrace_attach(&task, pid, None),
+        PtraceRequest::SEIZE => {
+            let options = PtraceOptions::from_bits(data).ok_or(ErrorNo::EIO)?;
+            return ptrace_attach(&task, pid, Some(options));
+        }
+        _ => {}
+    }
+    let need_stopped = !matches!(request, PtraceRequest::KILL | PtraceRequest::INTERRUPT);
+    let tracee = get_tracee(&task, pid, need_stopped)?;
+    match request {
+        PtraceRequest::PEEKTEXT | PtraceRequest::PEEKDATA => {
+            let mut word = [0u8; size_of::<usize>()];
+            tracee
+                .get_vm()
+                .lock()
+                .read(addr, word.len(), &mut word, PTEFlags::USER)
+                .map_err(|_| ErrorNo::EIO)?;
+            write_to_user(&task, data, &word)
+        }
+        PtraceRequest::POKETEXT | PtraceRequest::POKEDATA => {
+            let word = data.to_le_bytes();
+            tracee
+                .get_vm()
+                .lock()
+                .write(addr, word.len(), &word, PTEFlags::USER)
+                .map_err(|_| ErrorNo::EIO)?;
+            Ok(0)
+        }
+        PtraceRequest::CONT => ptrace_resume(&tracee, PtraceResume::Continue, data),
+        PtraceRequest::SYSCALL => ptrace_resume(&tracee, PtraceResume::Syscall, data),
+        PtraceRequest::SINGLESTEP => ptrace_resume(&tracee, PtraceResume::SingleStep, data),
+        PtraceRequest::DETACH => ptrace_resume(&tracee, PtraceResume::Detach, data),
+        PtraceRequest::KILL => {
+            // 暂停中的任务发现自己收到了 SIGKILL 后会自己恢复运行
+            send_signal(tracee.get_tid_num(), SignalNo::SIGKILL as usize);
+            Ok(0)
+        }
+        PtraceRequest::INTERRUPT => {
+            let interrupted = tracee
+                .inner
+                .lock()
+                .ptrace
+                .as_mut()
+                .map_or(false, |state| state.request_interrupt());
+            if interrupted {
+                Ok(0)
+            } else {
+                Err(ErrorNo::EIO)
+            }
+        }
+        PtraceRequest::GETREGS => {
+            let regs = get_regs(&tracee);
+            write_to_user(&task, data, regs_as_bytes(&regs))
+        }
+        PtraceRequest::SETREGS => {
+            let mut regs = [0usize; USER_REGS_COUNT];
+            read_from_user(&task, data, regs_as_bytes_mut(&mut regs))?;
+            set_regs(&tracee, &regs);
+            Ok(0)
+        }
+        PtraceRequest::GETREGSET | PtraceRequest::SETREGSET => {
+            if addr != NT_PRSTATUS && addr != NT_PRFPREG {
+                return Err(ErrorNo::EINVAL);
+            }
+            let iov = data as *mut IoVec;
+            let vm = task.get_vm();
+            if vm.lock().manually_alloc_type(iov).is_err() {
+                return Err(ErrorNo::EFAULT);
+            }
+            let (base, len) = unsafe { ((*iov).base as usize, (*iov).len) };
+            let get = request == PtraceRequest::GETREGSET;
+            let len = if addr == NT_PRSTATUS {
+                let mut regs = get_regs(&tracee);
+                let len = len.min(size_of::<[usize; USER_REGS_COUNT]>());
+                if get {
+                    write_to_user(&task, base, &regs_as_bytes(&regs)[..len])?;
+                } else {
+                    read_from_user(&task, base, &mut regs_as_bytes_mut(&mut regs)[..len])?;
+                    set_regs(&tracee, &regs);
+                }
+                len
+            } else {
+                // 浮点寄存器的布局与 riscv64 Linux 的 `struct __riscv_d_ext_state` 相同
+                let mut fp_context = tracee.get_saved_fp_context();
+                let len = len.min(size_of::<FpContext>());
+                if get {
+                    write_to_user(&task, base, &fp_as_bytes(&fp_context)[..len])?;
+                } else {
+                    read_from_user(&task, base, &mut fp_as_bytes_mut(&mut fp_context)[..len])?;
+                    tracee.set_saved_fp_context(&fp_context);
+                }
+                len
+            };
+            unsafe {
+                (*iov).len = len;
+            }
+            Ok(0)
+        }
+        PtraceRequest::SETOPTIONS => {
+            let options = PtraceOptions::from_bits(data).ok_or(ErrorNo::EINVAL)?;
+            if let Some(state) = tracee.inner.lock().ptrace.as_mut() {
+                state.options = options;
+            }
+            Ok(0)
+        }
+        PtraceRequest::GETEVENTMSG => {
+            let msg = tracee
+                .inner
+                .lock()
+                .ptrace
+                .as_ref()
+                .map_or(0, |state| state.event_msg);
+            write_to_user(&task, data, &msg.to_le_bytes())
+        }
+        PtraceRequest::GETSIGINFO => {
+            let info = tracee
+                .inner
+                .lock()
+                .ptrace
+                .as_ref()
+                .and_then(|state| state.last_siginfo)
+                .ok_or(ErrorNo::EINVAL)?;
+            write_to_user(&task, data, unsafe {
+                slice::from_raw_parts(&info as *const SigInfo as *const u8, size_of::<SigInfo>())
+            })
+        }
+        _ => Err(ErrorNo::EIO),
+    }
+}
+
+/// 让父进程跟踪当前线程
+fn ptrace_traceme(task: &Arc<TaskControlBlock>) -> SysResult {
+    let mut inner = task.inner.lock();
+    let parent = inner.parent.as_ref().and_then(|p| p.upgrade());
+    match parent {
+        Some(parent) if inner.ptrace.is_none() => {
+            inner.ptrace = Some(PtraceState::new(&parent, PtraceOptions::empty(), false));
+            Ok(0)
+        }
+        _ => Err(ErrorNo::EPERM),
+    }
+}
+
+/// 开始跟踪 tid 为 pid 的任务。options 为 None 时是 PTRACE_ATTACH，此时会向它发送 SIGSTOP；
+/// 否则是 PTRACE_SEIZE，不会让它暂停
+///
+/// 不能跟踪同一进程中的线程或已经被跟踪的任务，且需要有权限检查它的凭证
+fn ptrace_attach(
+    task: &Arc<TaskControlBlock>,
+    pid: usize,
+    options: Option<PtraceOptions>,
+) -> SysResult {
+    let tracee = get_task_from_tid(pid).ok_or(ErrorNo::ESRCH)?;
+    if tracee.get_pid_num() == task.get_pid_num()
+        || matches!(tracee.get_status(), TaskStatus::Dying | TaskStatus::Zombie)
+    {
+        return Err(ErrorNo::EPERM);
+    }
+    let credentials = task.credentials.lock().clone();
+    if !credentials.can_inspect(&tracee.credentials.lock()) {
+        return Err(ErrorNo::EPERM);
+    }
+    let mut inner = tracee.inner.lock();
+    if inner.ptrace.is_some() {
+        return Err(ErrorNo::EPERM);
+    }
+    inner.ptrace = Some(PtraceState::new(
+        task,
+        options.unwrap_or(PtraceOptions::empty()),
+        options.is_some(),
+    ));
+    drop(inner);
+    if options.is_none() {
+        send_signal(pid, SignalNo::SIGSTOP as usize);
+    }
+    Ok(0)
+}
+
+/// 获取当前线程跟踪的、tid 为 pid 的任务。need_stopped 为 true 时还要求它处于暂停中
+fn get_tracee(
+    task: &Arc<TaskControlBlock>,
+    pid: usize,
+    need_stopped: bool,
+) -> Result<Arc<TaskControlBlock>, ErrorNo> {
+    let tracee = get_task_from_tid(pid).ok_or(ErrorNo::ESRCH)?;
+    let inner = tracee.inner.lock();
+    let is_tracee = inner.ptrace.as_ref().map_or(false, |state| {
+        state.get_tracer_tid() == task.get_tid_num()
+            && (!need_stopped || state.get_stop_status().is_some())
+    });
+    drop(inner);
+    if is_tracee {
+        Ok(tracee)
+    } else {
+        Err(ErrorNo::ESRCH)
+    }
+}
+
+/// 以 mode 方式恢复暂停中的被跟踪的任务，signal 为要注入的信号，为 0 时不注入
+fn ptrace_resume(tracee: &Arc<TaskControlBlock>, mode: PtraceResume, signal: usize) -> SysResult {
+    if signal > 64 {
+        return Err(ErrorNo::EIO);
+    }
+    let resumed = tracee
+        .inner
+        .lock()
+        .ptrace
+        .as_mut()
+        .map_or(false, |state| state.resume(mode, signal));
+    if resumed {
+        Ok(0)
+    } else {
+        Err(ErrorNo::ESRCH)
+    }
+}
+
+/// 读取暂停中的任务的通用寄存器
+fn get_regs(tracee: &TaskControlBlock) -> [usize; USER_REGS_COUNT] {
+    // 暂停中的任务不会修改内核栈上的用户上下文
+    let trap_cx = unsafe { &*tracee.kernel_stack.get_first_context() };
+    let mut regs = [0usize; USER_REGS_COUNT];
+    regs[0] = trap_cx.sepc;
+    regs[1..].copy_from_slice(&trap_cx.x[1..]);
+    regs
+}
+
+/// 修改暂停中的任务的通用寄存器
+fn set_regs(tracee: &TaskControlBlock, regs: &[usize; USER_REGS_COUNT]) {
+    let trap_cx = unsafe { &mut *tracee.kernel_stack.get_first_context() };
+    trap_cx.sepc = regs[0];
+    trap_cx.x[1..].copy_from_slice(&regs[1..]);
+}
+
+/// 获取寄存器数组的字节表示
+fn regs_as_bytes(regs: &[usize; USER_REGS_COUNT]) -> &[u8] {
+    unsafe {
+        slice::from_raw_parts(
+            regs.as_ptr() as *const u8,
+            size_of::<[usize; USER_REGS_COUNT]>(),
+        )
+    }
+}
+
+/// 获取寄存器数组的可变字节表示
+fn regs_as_bytes_mut(regs: &mut [usize; USER_REGS_COUNT]) -> &mut [u8] {
+    unsafe {
+        slice::from_raw_parts_mut(
+            regs.as_mut_ptr() as *mut u8,
+            size_of::<[usize; USER_REGS_COUNT]>(),
+        )
+    }
+}
+
+/// 获取浮点寄存器的字节表示
+fn fp_as_bytes(fp_context: &FpContext) -> &[u8] {
+    unsafe {
+        slice::from_raw_parts(
+            fp_context as *const FpContext as *const u8,
+            size_of::<FpContext>(),
+        )
+    }
+}
+
+/// 获取浮点寄存器的可变字节表示
+fn fp_as_bytes_mut(fp_context: &mut FpContext) -> &mut [u8] {
+    unsafe {
+        slice::from_raw_parts_mut(
+            fp_context as *mut FpContext as *mut u8,
+            size_of::<FpContext>(),
+        )
+    }
+}
+
+/// 把 src 写入当前任务的用户地址 addr 处
+fn write_to_user(task: &TaskControlBlock, addr: usize, src: &[u8]) -> SysResult {
+    if src.is_empty() {
+        return Ok(0);
+    }
+    let vm = task.get_vm();
+    if vm
+        .lock()
+        .manually_alloc_user_str(addr as *const u8, src.len())
+        .is_err()
+    {
+        return Err(ErrorNo::EFAULT);
+    }
+    unsafe {
+        slice::from_raw_parts_mut(addr as *mut u8, src.len()).copy_from_slice(src);
+    }
+    Ok(0)
+}
+
+/// 从当前任务的用户地址 addr 处读取数据到 dst
+fn read_from_user(task: &TaskControlBlock, addr: usize, dst: &mut [u8]) -> SysResult {
+    if dst.is_empty() {
+        return Ok(0);
+    }
+    let vm = task.get_vm();
+    if vm
+        .lock()
+        .manually_alloc_user_str(addr as *const u8, dst.len())
+        .is_err()
+    {
+        return Err(ErrorNo::EFAULT);
+    }
+    unsafe {
+        dst.copy_from_slice(slice::from_raw_parts(addr as *const u8, dst.len()));
+    }
+    Ok(0)
+}
@@ -0,0 +1,96 @@
+//! 被信号打断的系统调用的重启
+//!
+//! 阻塞中的系统调用发现有需要处理的信号时，返回 `ERESTARTSYS` 等内核内部的错误码。
+//! 回到用户态前处理信号时，再根据是否调用了信号处理函数以及 SA_RESTART，决定返回 EINTR 还是重启它：
+//! - 重启即把 pc 退回到 ecall 指令，并恢复第一个参数，回到用户态后重新执行这个系统调用
+//! - 对于 nanosleep 这类需要记住进度的调用，返回 `ERESTART_RESTARTBLOCK` 并在任务中保存 `RestartBlock`，
+//!   重启时改为执行 restart_syscall，从保存的进度继续
+
+use syscall::ErrorNo;
+use timer::{get_time_f64, nanosleep_until, TimeSpec};
+
+use super::{SysResult, SyscallNo};
+use crate::signal::SigActionFlags;
+use crate::task::get_current_task;
+use crate::trap::TrapContext;
+
+/// ecall 指令的长度
+const ECALL_INSTRUCTION_LEN: usize = 4;
+
+/// restart_syscall 继续执行被打断的系统调用时需要的状态
+#[derive(Clone, Copy)]
+pub enum RestartBlock {
+    /// 休眠到 end_time(以秒计)为止，被打断时剩余时间写入 rem
+    Nanosleep { end_time: f64, rem: usize },
+}
+
+/// 系统调用返回后、回到用户态前，检查它是否被信号打断，如果是则修改返回值或重启它。
+///
+/// - orig_a0 为系统调用的第一个参数，重启时需要恢复它
+/// - handler_flags 为即将调用的信号处理函数的选项，没有调用处理函数时为 None
+pub fn restart_interrupted_syscall(
+    cx: &mut TrapContext,
+    orig_a0: usize,
+    handler_flags: Option<SigActionFlags>,
+) {
+    let ret = cx.get_a0() as isize;
+    let restart = if ret == ErrorNo::ERESTARTNOINTR as isize {
+        true
+    } else if ret == ErrorNo::ERESTARTSYS as isize {
+        handler_flags.map_or(true, |flags| flags.contains(SigActionFlags::SA_RESTART))
+    } else if ret == ErrorNo::ERESTARTNOHAND as isize {
+        handler_flags.is_none()
+    } else if ret == ErrorNo::ERESTART_RESTARTBLOCK as isize {
+        if handler_flags.is_none() {
+            cx.x[17] = SyscallNo::RESTART_SYSCALL as usize;
+        }
+        handler_flags.is_none()
+    } else {
+        return;
+    };
+    if restart {
+        cx.set_a0(orig_a0);
+        let sepc = cx.get_sepc();
+        cx.set_sepc(sepc - ECALL_INSTRUCTION_LEN);
+    } else {
+        cx.set_a0(ErrorNo::EINTR as isize as usize);
+    }
+}
+
+/// 当前任务休眠一段时间。被信号打断时可以通过 restart_syscall 继续休眠到原定的时间
+pub fn sys_nanosleep(req: *const TimeSpec, rem: *mut TimeSpec) -> SysResult {
+    let task = get_current_task().unwrap();
+    if task.get_vm().lock().manually_alloc_type(req).is_err() {
+        return Err(ErrorNo::EFAULT);
+    }
+    let end_time = get_time_f64() + unsafe { (*req).time_in_sec() };
+    nanosleep_with_restart(end_time, rem)
+}
+
+/// 继续执行被信号打断的系统调用。如果没有保存的状态，返回 EINTR
+pub fn sys_restart_syscall() -> SysResult {
+    let block = get_current_task()
+        .unwrap()
+        .inner
+        .lock()
+        .restart_block
+        .take();
+    match block {
+        Some(RestartBlock::Nanosleep { end_time, rem }) => {
+            nanosleep_with_restart(end_time, rem as *mut TimeSpec)
+        }
+        None => Err(ErrorNo::EINTR),
+    }
+}
+
+/// 休眠到 end_time 为止。如果被信号打断，则保存重启需要的状态
+fn nanosleep_with_restart(end_time: f64, rem: *mut TimeSpec) -> SysResult {
+    let ret = nanosleep_until(end_time, rem);
+    if let Err(ErrorNo::ERESTART_RESTARTBLOCK) = ret {
+        get_current_task().unwrap().inner.lock().restart_block = Some(RestartBlock::Nanosleep {
+            end_time,
+            rem: rem as usize,
+        });
+    }
+    ret
+}
@@ -2,7 +2,7 @@
 
 use super::SysResult;
 use crate::file::socket::*;
-use crate::task::suspend_current_task;
+use crate::task::{signal_pending, suspend_current_task};
 use crate::{file::Socket, task::get_current_task};
 use alloc::sync::Arc;
 use base_file::OpenFlags;
@@ -48,7 +48,8 @@ pub fn sys_sendto(
     _addr_len: usize,
 ) -> SysResult {
     let task = get_current_task().unwrap();
-    let mut task_vm = task.vm.lock();
+    let vm = task.get_vm();
+    let mut task_vm = vm.lock();
     let fd_manager = task.fd_manager.lock();
     if task_vm.manually_alloc_user_str(buf, len).is_err() {
         return Err(ErrorNo::EFAULT); // 地址不合法
@@ -78,7 +79,8 @@ pub fn sys_recvfrom(
     _src_len_pos: *mut u32,
 ) -> SysResult {
     let task = get_current_task().unwrap();
-    let mut task_vm = task.vm.lock();
+    let vm = task.get_vm();
+    let mut task_vm = vm.lock();
     if task_vm.manually_alloc_user_str(buf, len).is_err() {
         return Err(ErrorNo::EFAULT); // 地址不合法
     }
@@ -102,6 +104,9 @@ pub fn sys_recvfrom(
         }
 
         drop(fd_manager);
+        if signal_pending() {
+            return Err(ErrorNo::ERESTARTSYS);
+        }
         suspend_current_task(); // yield
     }
 }
@@ -110,7 +115,8 @@ pub fn sys_recvfrom(
 pub fn sys_bind(fd: usize, addr: *const u8, addr_len: usize) -> SysResult {
     info!("sys_bind: fd: {} addr: {:p} len: {}", fd, addr, addr_len);
     let task = get_current_task().unwrap();
-    let mut task_vm = task.vm.lock();
+    let vm = task.get_vm();
+    let mut task_vm = vm.lock();
     if task_vm.manually_alloc_user_str(addr, addr_len).is_err() {
         return Err(ErrorNo::EFAULT); // 地址不合法
     }
@@ -145,7 +151,8 @@ pub fn sys_listen(fd: usize, backlog: usize) -> SysResult {
 pub fn sys_connect(fd: usize, addr: *const u8, addr_len: usize) -> SysResult {
     info!("sys_connect: fd: {} addr: {:p} len: {}", fd, addr, addr_len);
     let task = get_current_task().unwrap();
-    let mut task_vm = task.vm.lock();
+    let vm = task.get_vm();
+    let mut task_vm = vm.lock();
     if task_vm.manually_alloc_user_str(addr, addr_len).is_err() {
         return Err(ErrorNo::EFAULT); // 地址不合法
     }
@@ -204,7 +211,8 @@ pub fn sys_accept4(fd: usize, addr: *mut u8, addr_len: *mut u32, flags: i32) ->
         fd, addr, addr_len
     );
     let task = get_current_task().unwrap();
-    let mut task_vm = task.vm.lock();
+    let vm = task.get_vm();
+    let mut task_vm = vm.lock();
     if task_vm.manually_alloc_page(addr as usize).is_err()
         || task_vm.manually_alloc_page(addr_len as usize).is_err()
     {
@@ -257,6 +265,9 @@ pub fn sys_accept4(fd: usize, addr: *mut u8, addr_len: *mut u32, flags: i32) ->
             return Err(ErrorNo::EBADF);
         }
         drop(fd_manager);
+        if signal_pending() {
+            return Err(ErrorNo::ERESTARTSYS);
+        }
         suspend_current_task(); // yield
     }
 }
@@ -15,6 +15,7 @@ numeric_enum_macro::numeric_enum! {
         DUP3 = 24,
         FCNTL64 = 25,
         IOCTL = 29,
+        MKNODAT = 33,
         MKDIR = 34,
         UNLINKAT = 35,
         LINKAT = 37,
@@ -26,7 +27,7 @@ numeric_enum_macro::numeric_enum! {
         CHMOD = 53,
         OPEN = 56,
         CLOSE = 57,
-        PIPE = 59,
+        PIPE2 = 59,
         GETDENTS64 = 61,
         LSEEK = 62,
         READ = 63,
@@ -37,6 +38,7 @@ numeric_enum_macro::numeric_enum! {
         SENDFILE64 = 71,
         PSELECT6 = 72,
         PPOLL = 73,
+        SIGNALFD4 = 74,
         READLINKAT = 78,
         FSTATAT = 79,
         FSTAT = 80,
@@ -45,6 +47,7 @@ numeric_enum_macro::numeric_enum! {
         UTIMENSAT = 88,
         EXIT = 93,
         EXIT_GROUP = 94,
+        WAITID = 95,
         SET_TID_ADDRESS = 96,
         FUTEX = 98,
         SET_ROBUST_LIST	= 99,
@@ -54,18 +57,43 @@ numeric_enum_macro::numeric_enum! {
         SETITIMER = 103,
         CLOCK_GET_TIME = 113,
         SYSLOG = 116,
+        PTRACE = 117,
+        SCHED_SETAFFINITY = 122,
+        SCHED_GETAFFINITY = 123,
         YIELD = 124,
+        RESTART_SYSCALL = 128,
         KILL = 129,
         TKILL = 130,
+        SIGALTSTACK = 132,
+        SIGSUSPEND = 133,
         SIGACTION = 134,
         SIGPROCMASK = 135,
+        SIGPENDING = 136,
         SIGTIMEDWAIT = 137,
+        SIGQUEUEINFO = 138,
         SIGRETURN = 139,
+        SETREGID = 143,
+        SETGID = 144,
+        SETREUID = 145,
+        SETUID = 146,
+        SETRESUID = 147,
+        GETRESUID = 148,
+        SETRESGID = 149,
+        GETRESGID = 150,
         TIMES = 153,
+        SETPGID = 154,
+        GETPGID = 155,
+        GETSID = 156,
+        SETSID = 157,
+        GETGROUPS = 158,
+        SETGROUPS = 159,
         UNAME = 160,
+        GETRLIMIT = 163,
+        SETRLIMIT = 164,
         GETRUSAGE = 165,
         UMASK = 166,
         PRCTL = 167,
+        GETCPU = 168,
         GET_TIME_OF_DAY = 169,
         GETPID = 172,
         GETPPID = 173,
@@ -97,10 +125,15 @@ numeric_enum_macro::numeric_enum! {
         MPROTECT = 226,
         MSYNC = 227,
         MADVISE = 233,
+        TGSIGQUEUEINFO = 240,
         ACCEPT4 = 242,
         WAIT4 = 260,
         PRLIMIT64 = 261,
         RENAMEAT2 = 276,
         MEMBARRIER = 283,
+        PIDFD_SEND_SIGNAL = 424,
+        PIDFD_OPEN = 434,
+        CLONE3 = 435,
+        PIDFD_GETFD = 438,
     }
 }
@@ -0,0 +1,526 @@
+//! 内置的系统调用跟踪(类似 strace)
+//!
+//! 通过写 `/proc/sys/kernel/syscall_trace` 按 pid 或程序名选中需要跟踪的进程，启动时的初始值见 `SYSCALL_TRACE_TARGETS`。
+//! 被选中的进程每次系统调用都会记录调用号、解码后的参数、返回值/错误码以及耗时，
+//! 保存在每个任务自己的环形缓冲区里，可以通过读 `/proc/<pid>/syscalls` 获取。读取它和 ptrace 一样需要有权访问对方进程
+//!
+//! 参数的解码在系统调用返回后进行，这样才能看到 read/fstat 等调用写回用户空间的内容。
+//! 例外是 execve 和 exit：前者成功后旧的地址空间已不存在，后者不会返回，所以它们在进入时解码。
+//! 成功的 execve 也不会回到 `syscall()`，它的记录由 sys_exec 在切换到新程序前写入
+
+use alloc::collections::VecDeque;
+use alloc::string::String;
+use alloc::vec::Vec;
+use base_file::Kstat;
+use core::fmt::Write;
+use core::mem::MaybeUninit;
+use timer::{get_time_us, TimeSpec};
+
+use super::SyscallNo;
+use crate::constants::{
+    PAGE_SIZE, SYSCALL_TRACE_BUFFER_LEN, SYSCALL_TRACE_STR_LEN, SYSCALL_TRACE_TARGETS,
+};
+use crate::memory::{phys_to_virt, MemorySet, PTEFlags};
+use crate::task::{get_all_tasks, get_current_task, TaskControlBlock};
+use lock::Mutex;
+
+/// execve 的 argv 最多显示多少项
+const MAX_ARGV_SHOWN: usize = 8;
+/// openat 等调用中表示当前目录的 dirfd
+const AT_FDCWD: i32 = -100;
+/// 返回值在 [-MAX_ERRNO, -1] 之间时表示错误码
+const MAX_ERRNO: usize = 4095;
+
+lazy_static::lazy_static! {
+    /// 选中需要跟踪系统调用的进程。每项可以是十进制的 pid，也可以是程序名(与 comm 比较)。为空时不跟踪任何进程
+    ///
+    /// 初始值来自 SYSCALL_TRACE_TARGETS，这样从第一个用户程序开始就可以跟踪
+    static ref TRACE_TARGETS: Mutex<Vec<String>> = Mutex::new(
+        SYSCALL_TRACE_TARGETS.iter().map(|&target| String::from(target)).collect()
+    );
+}
+
+/// 一个任务的系统调用跟踪记录
+pub struct SyscallTrace {
+    /// 最近的记录，最多 SYSCALL_TRACE_BUFFER_LEN 条
+    records: VecDeque<SyscallRecord>,
+    /// 缓冲区满时被丢弃的记录数
+    dropped: usize,
+    /// 正在进行中的系统调用，返回时写入记录
+    pending: Option<PendingSyscall>,
+}
+
+/// 一条系统调用记录
+struct SyscallRecord {
+    /// 进入系统调用时的系统时间(us)
+    start_us: usize,
+    /// 系统调用编号
+    id: SyscallNo,
+    /// 解码后的参数
+    args: String,
+    /// 返回值。None 表示调用没有返回(如 exit)
+    ret: Option<isize>,
+    /// 耗时(us)
+    duration_us: usize,
+}
+
+/// 一次正在进行中的、需要记录的系统调用
+struct PendingSyscall {
+    start_us: usize,
+    id: SyscallNo,
+    args: [usize; 6],
+    /// 在进入时就已经解码好的参数
+    decoded: Option<String>,
+}
+
+impl SyscallTrace {
+    /// 如果 pid 或程序名被选中跟踪，则新建一个跟踪记录，否则返回 None
+    pub fn new_if_selected(pid: usize, comm: &str) -> Option<Self> {
+        is_selected(&TRACE_TARGETS.lock(), pid, comm).then(|| Self {
+            records: VecDeque::new(),
+            dropped: 0,
+            pending: None,
+        })
+    }
+    /// 加入一条记录，缓冲区满时丢弃最早的一条
+    fn push(&mut self, record: SyscallRecord) {
+        if self.records.len() == SYSCALL_TRACE_BUFFER_LEN {
+            self.records.pop_front();
+            self.dropped += 1;
+        }
+        self.records.push_back(record);
+    }
+    /// 把所有记录按 strace 的格式输出到 out
+    fn dump(&self, out: &mut String) {
+        if self.dropped > 0 {
+            let _ = writeln!(out, "... {} earlier records dropped", self.dropped);
+        }
+        for record in self.records.iter() {
+            let _ = write!(
+                out,
+                "[{:6}.{:06}] {}({}) = ",
+                record.start_us / 1_000_000,
+                record.start_us % 1_000_000,
+                syscall_name(record.id),
+                record.args
+            );
+            let _ = match record.ret {
+                None => writeln!(out, "?"),
+                Some(ret) if is_error(ret) => writeln!(
+                    out,
+                    "-1 (errno {}) <{}.{:06}>",
+                    -ret,
+                    record.duration_us / 1_000_000,
+                    record.duration_us % 1_000_000
+                ),
+                Some(ret) => writeln!(
+                    out,
+                    "{} <{}.{:06}>",
+                    ret,
+                    record.duration_us / 1_000_000,
+                    record.duration_us % 1_000_000
+                ),
+            };
+        }
+    }
+}
+
+/// pid 或程序名是否在 targets 中
+fn is_selected(targets: &[String], pid: usize, comm: &str) -> bool {
+    targets.iter().any(|target| match target.parse::<usize>() {
+        Ok(target_pid) => target_pid == pid,
+        Err(_) => target == comm,
+    })
+}
+
+/// 获取当前选中跟踪的进程，每项之间以空格分隔
+pub fn syscall_trace_targets() -> String {
+    TRACE_TARGETS.lock().join(" ")
+}
+
+/// 重新设置选中跟踪的进程，content 中每项之间以空白或逗号分隔。
+///
+/// 已经存在的进程如果被选中，会立即开始跟踪；不再被选中的进程则继续跟踪，已有的记录不会丢失
+pub fn set_syscall_trace_targets(content: &str) {
+    *TRACE_TARGETS.lock() = content
+        .split(|c: char| c.is_whitespace() || c == ',')
+        .filter(|target| !target.is_empty())
+        .map(String::from)
+        .collect();
+    for task in get_all_tasks() {
+        let comm = task.inner.lock().comm.clone();
+        let mut trace = task.syscall_trace.lock();
+        if trace.is_none() {
+            *trace = SyscallTrace::new_if_selected(task.pid, &comm);
+        }
+    }
+}
+
+/// 进入系统调用时检查当前任务是否被跟踪，如果是则开始记录
+pub fn syscall_trace_enter(id: SyscallNo, args: &[usize; 6]) {
+    let task = match get_current_task() {
+        Some(task) => task,
+        None => return,
+    };
+    if task.syscall_trace.lock().is_none() {
+        return;
+    }
+    let start_us = get_time_us();
+    // execve 需要在旧的地址空间还在时解码参数
+    let decoded = (id == SyscallNo::EXECVE).then(|| decode_args(&task, id, args, None));
+    if let Some(trace) = task.syscall_trace.lock().as_mut() {
+        match id {
+            // 不会返回的调用，直接记录
+            SyscallNo::EXIT | SyscallNo::EXIT_GROUP => trace.push(SyscallRecord {
+                start_us,
+                id,
+                args: format!("{}", args[0] as i32),
+                ret: None,
+                duration_us: 0,
+            }),
+            _ => {
+                trace.pending = Some(PendingSyscall {
+                    start_us,
+                    id,
+                    args: *args,
+                    decoded,
+                })
+            }
+        }
+    };
+}
+
+/// 系统调用返回时，解码参数并把记录写入当前任务的缓冲区
+pub fn syscall_trace_exit(ret: isize) {
+    let task = get_current_task().unwrap();
+    let pending = match task
+        .syscall_trace
+        .lock()
+        .as_mut()
+        .and_then(|trace| trace.pending.take())
+    {
+        Some(pending) => pending,
+        None => return,
+    };
+    let args = match pending.decoded {
+        Some(decoded) => decoded,
+        None => decode_args(&task, pending.id, &pending.args, Some(ret)),
+    };
+    if let Some(trace) = task.syscall_trace.lock().as_mut() {
+        trace.push(SyscallRecord {
+            start_us: pending.start_us,
+            id: pending.id,
+            args,
+            ret: Some(ret),
+            duration_us: get_time_us() - pending.start_us,
+        });
+    };
+}
+
+/// 生成 `/proc/<pid>/syscalls` 的内容。进程不存在或没有被跟踪时返回 None
+///
+/// 进程中每个被跟踪的线程分别输出自己的记录
+pub fn syscall_trace_report(pid: usize) -> Option<String> {
+    let mut out = String::new();
+    let mut found = false;
+    for task in get_all_tasks().iter().filter(|task| task.pid == pid) {
+        if let Some(trace) = task.syscall_trace.lock().as_ref() {
+            let _ = writeln!(out, "# tid {}", task.get_tid_num());
+            trace.dump(&mut out);
+            found = true;
+        }
+    }
+    found.then(|| out)
+}
+
+/// 返回值是否表示错误码
+fn is_error(ret: isize) -> bool {
+    ret < 0 && ret >= -(MAX_ERRNO as isize)
+}
+
+/// 系统调用名，与 Linux 中的名字一致
+fn syscall_name(id: SyscallNo) -> String {
+    match id {
+        SyscallNo::OPEN => String::from("openat"),
+        SyscallNo::MKDIR => String::from("mkdirat"),
+        SyscallNo::FCNTL64 => String::from("fcntl"),
+        SyscallNo::NANOSLEEP => String::from("nanosleep"),
+        _ => format!("{:?}", id).to_lowercase(),
+    }
+}
+
+/// 按系统调用的类型解码参数。ret 为 None 表示调用还没有返回，此时不解码输出参数
+fn decode_args(
+    task: &TaskControlBlock,
+    id: SyscallNo,
+    args: &[usize; 6],
+    ret: Option<isize>,
+) -> String {
+    // 调用成功时，输出参数才有意义
+    let ok = ret.filter(|&ret| !is_error(ret));
+    match id {
+        SyscallNo::OPEN => format!(
+            "{}, {}, {:#x}, {:#o}",
+            dirfd(args[0]),
+            user_str(task, args[1]),
+            args[2],
+            args[3]
+        ),
+        SyscallNo::MKDIR => format!(
+            "{}, {}, {:#o}",
+            dirfd(args[0]),
+            user_str(task, args[1]),
+            args[2]
+        ),
+        SyscallNo::UNLINKAT => format!(
+            "{}, {}, {:#x}",
+            dirfd(args[0]),
+            user_str(task, args[1]),
+            args[2]
+        ),
+        SyscallNo::LINKAT | SyscallNo::RENAMEAT2 => format!(
+            "{}, {}, {}, {}, {:#x}",
+            dirfd(args[0]),
+            user_str(task, args[1]),
+            dirfd(args[2]),
+            user_str(task, args[3]),
+            args[4]
+        ),
+        SyscallNo::CHDIR => user_str(task, args[0]),
+        SyscallNo::STATFS => format!("{}, {:#x}", user_str(task, args[0]), args[1]),
+        SyscallNo::READLINKAT => format!(
+            "{}, {}, {}, {}",
+            dirfd(args[0]),
+            user_str(task, args[1]),
+            match ok {
+                Some(len) => user_buf(task, args[2], len as usize),
+                None => format!("{:#x}", args[2]),
+            },
+            args[3]
+        ),
+        SyscallNo::FSTATAT => format!(
+            "{}, {}, {}, {:#x}",
+            dirfd(args[0]),
+            user_str(task, args[1]),
+            match ok {
+                Some(_) => user_kstat(task, args[2]),
+                None => format!("{:#x}", args[2]),
+            },
+            args[3]
+        ),
+        SyscallNo::FSTAT => format!(
+            "{}, {}",
+            args[0],
+            match ok {
+                Some(_) => user_kstat(task, args[1]),
+                None => format!("{:#x}", args[1]),
+            }
+        ),
+        SyscallNo::READ => format!(
+            "{}, {}, {}",
+            args[0],
+            match ok {
+                Some(len) => user_buf(task, args[1], len as usize),
+                None => format!("{:#x}", args[1]),
+            },
+            args[2]
+        ),
+        SyscallNo::WRITE => format!(
+            "{}, {}, {}",
+            args[0],
+            user_buf(task, args[1], args[2]),
+            args[2]
+        ),
+        SyscallNo::EXECVE => format!(
+            "{}, {}, {:#x}",
+            user_str(task, args[0]),
+            user_argv(task, args[1]),
+            args[2]
+        ),
+        SyscallNo::NANOSLEEP => format!("{}, {:#x}", user_timespec(task, args[0]), args[1]),
+        SyscallNo::WAIT4 => format!(
+            "{}, {}, {:#x}, {:#x}",
+            args[0] as isize,
+            match ok {
+                Some(_) if args[1] != 0 => read_user::<i32>(task, args[1]).map_or(
+                    format!("{:#x}", args[1]),
+                    |status| format!("[{:#x}]", status)
+                ),
+                _ => format!("{:#x}", args[1]),
+            },
+            args[2],
+            args[3]
+        ),
+        SyscallNo::DUP | SyscallNo::CLOSE => format!("{}", args[0]),
+        SyscallNo::DUP3 => format!("{}, {}", args[0], args[1]),
+        SyscallNo::KILL | SyscallNo::TKILL => format!("{}, {}", args[0] as isize, args[1]),
+        _ => format!(
+            "{:#x}, {:#x}, {:#x}, {:#x}, {:#x}, {:#x}",
+            args[0], args[1], args[2], args[3], args[4], args[5]
+        ),
+    }
+}
+
+/// 解码 dirfd 参数
+fn dirfd(fd: usize) -> String {
+    if fd as i32 == AT_FDCWD {
+        String::from("AT_FDCWD")
+    } else {
+        format!("{}", fd as i32)
+    }
+}
+
+/// 查询用户地址 vaddr 在页表中的映射，返回内核可以直接访问的对应地址。
+/// 只查询页表，不会为还没有分配的页分配物理页。这一页没有映射或者用户不可读时返回 None
+fn resident_addr(vm: &MemorySet, vaddr: usize) -> Option<usize> {
+    let entry = unsafe { &*vm.pt.get_entry(vaddr)? };
+    entry
+        .flags()
+        .contains(PTEFlags::VALID | PTEFlags::READ | PTEFlags::USER)
+        .then(|| phys_to_virt(entry.addr()) + vaddr % PAGE_SIZE)
+}
+
+/// 把用户地址 addr 开始的数据复制到 dst 中，只读取已经映射的页。
+///
+/// 跟踪只是旁观者，不能因为解码参数而改变被跟踪进程的地址空间，所以不会为懒分配的页分配物理页。
+/// 其中有一页不能读取时返回 false
+fn read_user_bytes(task: &TaskControlBlock, addr: usize, dst: &mut [u8]) -> bool {
+    let vm = task.get_vm();
+    let vm = vm.lock();
+    let mut copied = 0;
+    while copied < dst.len() {
+        let pos = addr.wrapping_add(copied);
+        let src = match resident_addr(&vm, pos) {
+            Some(src) => src,
+            None => return false,
+        };
+        let len = (PAGE_SIZE - pos % PAGE_SIZE).min(dst.len() - copied);
+        dst[copied..copied + len]
+            .copy_from_slice(unsafe { core::slice::from_raw_parts(src as *const u8, len) });
+        copied += len;
+    }
+    true
+}
+
+/// 读取用户地址 addr 处一个类型为 T 的值。T 只能是任意字节都合法的结构体
+fn read_user<T>(task: &TaskControlBlock, addr: usize) -> Option<T> {
+    if addr == 0 {
+        return None;
+    }
+    let mut value = MaybeUninit::<T>::uninit();
+    let bytes = unsafe {
+        core::slice::from_raw_parts_mut(value.as_mut_ptr() as *mut u8, core::mem::size_of::<T>())
+    };
+    read_user_bytes(task, addr, bytes).then(|| unsafe { value.assume_init() })
+}
+
+/// 从用户地址读取最多 limit 字节的以 '\0' 结尾的字符串。返回读到的内容以及是否被截断
+fn read_user_cstr(task: &TaskControlBlock, addr: usize, limit: usize) -> Option<(Vec<u8>, bool)> {
+    let vm = task.get_vm();
+    let vm = vm.lock();
+    let mut bytes = Vec::new();
+    let mut pos = addr;
+    // 逐页检查，避免越过映射的边界
+    loop {
+        let src = resident_addr(&vm, pos)?;
+        let page_len = PAGE_SIZE - pos % PAGE_SIZE;
+        for &c in unsafe { core::slice::from_raw_parts(src as *const u8, page_len) } {
+            if c == 0 {
+                return Some((bytes, false));
+            }
+            if bytes.len() == limit {
+                return Some((bytes, true));
+            }
+            bytes.push(c);
+        }
+        pos += page_len;
+    }
+}
+
+/// 把字节串按 C 字符串字面量的格式输出
+fn escape(bytes: &[u8], truncated: bool) -> String {
+    let mut out = String::from("\"");
+    for &c in bytes {
+        match c {
+            b'"' => out.push_str("\\\""),
+            b'\\' => out.push_str("\\\\"),
+            b'\n' => out.push_str("\\n"),
+            b'\t' => out.push_str("\\t"),
+            0x20..=0x7e => out.push(c as char),
+            _ => {
+                let _ = write!(out, "\\x{:02x}", c);
+            }
+        }
+    }
+    out.push('"');
+    if truncated {
+        out.push_str("...");
+    }
+    out
+}
+
+/// 解码用户传入的字符串参数
+fn user_str(task: &TaskControlBlock, addr: usize) -> String {
+    if addr == 0 {
+        return String::from("NULL");
+    }
+    match read_user_cstr(task, addr, SYSCALL_TRACE_STR_LEN) {
+        Some((bytes, truncated)) => escape(&bytes, truncated),
+        None => format!("{:#x}", addr),
+    }
+}
+
+/// 解码用户缓冲区，最多显示 SYSCALL_TRACE_STR_LEN 字节
+fn user_buf(task: &TaskControlBlock, addr: usize, len: usize) -> String {
+    let shown = len.min(SYSCALL_TRACE_STR_LEN);
+    if shown == 0 {
+        return String::from("\"\"");
+    }
+    let mut bytes = vec![0u8; shown];
+    if !read_user_bytes(task, addr, &mut bytes) {
+        return format!("{:#x}", addr);
+    }
+    escape(&bytes, shown < len)
+}
+
+/// 解码 execve 的 argv 数组
+fn user_argv(task: &TaskControlBlock, addr: usize) -> String {
+    if addr == 0 {
+        return String::from("NULL");
+    }
+    let mut out = String::from("[");
+    for i in 0..=MAX_ARGV_SHOWN {
+        match read_user::<usize>(task, addr + i * core::mem::size_of::<usize>()) {
+            Some(0) => break,
+            Some(_) if i == MAX_ARGV_SHOWN => out.push_str(", ..."),
+            Some(arg) => {
+                if i > 0 {
+                    out.push_str(", ");
+                }
+                out.push_str(&user_str(task, arg));
+            }
+            None => return format!("{:#x}", addr),
+        }
+    }
+    out.push(']');
+    out
+}
+
+/// 解码 struct timespec
+fn user_timespec(task: &TaskControlBlock, addr: usize) -> String {
+    match read_user::<TimeSpec>(task, addr) {
+        Some(ts) => format!("{{tv_sec={}, tv_nsec={}}}", ts.tv_sec, ts.tv_nsec),
+        None => format!("{:#x}", addr),
+    }
+}
+
+/// 解码 struct stat 中常用的字段
+fn user_kstat(task: &TaskControlBlock, addr: usize) -> String {
+    match read_user::<Kstat>(task, addr) {
+        Some(stat) => format!(
+            "{{st_mode={:#o}, st_size={}, st_ino={}}}",
+            stat.st_mode, stat.st_size, stat.st_ino
+        ),
+        None => format!("{:#x}", addr),
+    }
+}
@@ -0,0 +1,340 @@
+//! 进程被默认行为是 core dump 的信号终止时，生成 ELF 格式的 core 文件
+//!
+//! 文件格式与 Linux 生成的 core 文件相同，可以在主机上用 riscv64 的 gdb 加载：
+//! - 一个 PT_NOTE 段，包含 NT_PRSTATUS(终止进程的信号，以及触发信号时的寄存器)、NT_AUXV(辅助参数)
+//!   和 NT_PRFPREG(浮点寄存器)
+//! - 用户地址空间中每一段连续的已分配页对应一个 PT_LOAD 段，未分配的页不写入文件
+//!
+//! core 文件生成在进程的工作目录下，需要有目录的写权限，大小受 RLIMIT_CORE 限制。
+//! 已有的 core 文件会先被删除，再重新创建
+
+use super::{AccessMode, Credentials, TaskControlBlock, RLIMIT_CORE};
+use crate::{
+    constants::{CORE_DUMP_FILE_NAME, PAGE_SIZE},
+    file::{
+        check_file_exists, get_dir_perm, get_file_perm, open_file, set_file_perm, try_remove_link,
+        FilePerm,
+    },
+    memory::{align_up, PTEFlags},
+};
+use alloc::{sync::Arc, vec::Vec};
+use base_file::{File, OpenFlags};
+use core::{mem::size_of, slice};
+use timer::TimeVal;
+
+/// ELF 类型：core 文件
+const ET_CORE: u16 = 4;
+/// ELF 机器类型：RISC-V
+const EM_RISCV: u16 = 243;
+/// 段类型：可加载的段
+const PT_LOAD: u32 = 1;
+/// 段类型：note 段
+const PT_NOTE: u32 = 4;
+/// 段权限：可执行
+const PF_X: u32 = 1;
+/// 段权限：可写
+const PF_W: u32 = 2;
+/// 段权限：可读
+const PF_R: u32 = 4;
+/// note 类型：进程状态和寄存器
+const NT_PRSTATUS: u32 = 1;
+/// note 类型：浮点寄存器
+const NT_PRFPREG: u32 = 2;
+/// note 类型：辅助参数
+const NT_AUXV: u32 = 6;
+/// note 的名字，包括结尾的 '\0'
+const NOTE_NAME: &[u8] = b"CORE\0";
+
+/// ELF 文件头
+#[repr(C)]
+struct ElfHeader {
+    e_ident: [u8; 16],
+    e_type: u16,
+    e_machine: u16,
+    e_version: u32,
+    e_entry: u64,
+    e_phoff: u64,
+    e_shoff: u64,
+    e_flags: u32,
+    e_ehsize: u16,
+    e_phentsize: u16,
+    e_phnum: u16,
+    e_shentsize: u16,
+    e_shnum: u16,
+    e_shstrndx: u16,
+}
+
+/// ELF 程序头，描述一个段
+#[repr(C)]
+struct ProgramHeader {
+    p_type: u32,
+    p_flags: u32,
+    p_offset: u64,
+    p_vaddr: u64,
+    p_paddr: u64,
+    p_filesz: u64,
+    p_memsz: u64,
+    p_align: u64,
+}
+
+/// NT_PRSTATUS 的内容，与 riscv64 Linux 的 `struct elf_prstatus` 相同
+#[repr(C)]
+#[derive(Default)]
+struct ElfPrStatus {
+    /// 信号信息，依次为 si_signo / si_code / si_errno
+    pr_info: [i32; 3],
+    /// 当前处理的信号
+    pr_cursig: u16,
+    /// 对齐用
+    _pad: u16,
+    /// 已收到但未处理的信号
+    pr_sigpend: u64,
+    /// 被屏蔽的信号
+    pr_sighold: u64,
+    pr_pid: i32,
+    pr_ppid: i32,
+    pr_pgrp: i32,
+    pr_sid: i32,
+    /// 用户态时间
+    pr_utime: TimeVal,
+    /// 内核态时间
+    pr_stime: TimeVal,
+    /// 子进程的用户态时间
+    pr_cutime: TimeVal,
+    /// 子进程的内核态时间
+    pr_cstime: TimeVal,
+    /// 通用寄存器，依次为 pc 和 x1~x31
+    pr_reg: [usize; 32],
+    /// 是否有浮点寄存器的信息
+    pr_fpvalid: i32,
+    /// 对齐用
+    _pad2: i32,
+}
+
+/// 获取结构体的字节表示
+fn as_bytes<T>(value: &T) -> &[u8] {
+    unsafe { slice::from_raw_parts(value as *const T as *const u8, size_of::<T>()) }
+}
+
+/// 4 字节对齐
+fn align4(len: usize) -> usize {
+    (len + 3) & !3
+}
+
+/// 在 buf 的末尾加入一个 note，名字为 "CORE"
+fn push_note(buf: &mut Vec<u8>, note_type: u32, desc: &[u8]) {
+    buf.extend_from_slice(&(NOTE_NAME.len() as u32).to_le_bytes());
+    buf.extend_from_slice(&(desc.len() as u32).to_le_bytes());
+    buf.extend_from_slice(&note_type.to_le_bytes());
+    buf.extend_from_slice(NOTE_NAME);
+    buf.resize(align4(buf.len()), 0);
+    buf.extend_from_slice(desc);
+    buf.resize(align4(buf.len()), 0);
+}
+
+/// 生成 PT_NOTE 段的内容
+fn build_notes(task: &Arc<TaskControlBlock>, signum: usize, auxv: &[usize]) -> Vec<u8> {
+    let mut status = ElfPrStatus::default();
+    status.pr_info[0] = signum as i32;
+    status.pr_cursig = signum as u16;
+    let receivers = task.signal_receivers.lock();
+    status.pr_sigpend = receivers.pending.set.0 as u64;
+    status.pr_sighold = receivers.mask.0 as u64;
+    drop(receivers);
+    let inner = task.inner.lock();
+    status.pr_pid = task.get_tid_num() as i32;
+    status.pr_ppid = inner.ppid as i32;
+    status.pr_pgrp = inner.pgid as i32;
+    status.pr_sid = inner.sid as i32;
+    drop(inner);
+    task.time
+        .lock()
+        .output(&mut status.pr_utime, &mut status.pr_stime);
+    // 默认处理信号时没有修改过内核栈上的用户上下文，所以它就是触发信号时的状态
+    let trap_cx = unsafe { &*task.kernel_stack.get_first_context() };
+    status.pr_reg[0] = trap_cx.sepc;
+    status.pr_reg[1..].copy_from_slice(&trap_cx.x[1..]);
+    // 浮点寄存器可能还没保存，这里会先保存下来。它的内存布局与 riscv64 Linux 的 elf_fpregset_t 相同
+    let fp_context = task.save_fp_context();
+    status.pr_fpvalid = 1;
+
+    let mut notes = Vec::new();
+    push_note(&mut notes, NT_PRSTATUS, as_bytes(&status));
+    let auxv_bytes = unsafe {
+        slice::from_raw_parts(auxv.as_ptr() as *const u8, auxv.len() * size_of::<usize>())
+    };
+    push_note(&mut notes, NT_AUXV, auxv_bytes);
+    push_note(&mut notes, NT_PRFPREG, as_bytes(&fp_context));
+    notes
+}
+
+/// 把页表项的权限转换为段的权限
+fn segment_flags(flags: PTEFlags) -> u32 {
+    let mut p_flags = 0;
+    if flags.contains(PTEFlags::READ) {
+        p_flags |= PF_R;
+    }
+    if flags.contains(PTEFlags::WRITE) {
+        p_flags |= PF_W;
+    }
+    if flags.contains(PTEFlags::EXECUTE) {
+        p_flags |= PF_X;
+    }
+    p_flags
+}
+
+/// 写 core 文件，超过 RLIMIT_CORE 的部分会被丢弃
+struct CoreWriter {
+    /// core 文件
+    file: Arc<dyn File>,
+    /// 还可以写入的字节数
+    remaining: usize,
+}
+
+impl CoreWriter {
+    /// 写入一段数据。返回是否还可以继续写入
+    fn write(&mut self, data: &[u8]) -> bool {
+        let len = data.len().min(self.remaining);
+        if len > 0 && self.file.write(&data[..len]) != Some(len) {
+            self.remaining = 0;
+            return false;
+        }
+        self.remaining -= len;
+        self.remaining > 0
+    }
+}
+
+/// 检查凭证为 credentials 的进程能否在目录 dir 下创建或改写 core 文件：
+/// 需要有目录的写和执行权限，如果 core 文件已存在，还需要有它的写权限
+fn can_write_core_file(credentials: &Credentials, dir: &str) -> bool {
+    if !credentials.can_access(
+        &get_dir_perm(dir),
+        AccessMode::W_OK | AccessMode::X_OK,
+        false,
+    ) {
+        return false;
+    }
+    !check_file_exists(dir, CORE_DUMP_FILE_NAME)
+        || credentials.can_access(
+            &get_file_perm(dir, CORE_DUMP_FILE_NAME),
+            AccessMode::W_OK,
+            false,
+        )
+}
+
+/// 为当前线程 task 所在的进程生成 core 文件，signum 是终止进程的信号。返回是否生成了 core 文件
+///
+/// 调用者需保证组内其他线程都已经退出，这样生成 core 文件时地址空间和寄存器不会再被修改。
+/// RLIMIT_CORE 小于一页，或者没有工作目录的写权限时不生成；core 文件大小超过 RLIMIT_CORE 时会被截断
+pub fn write_core_dump(task: &Arc<TaskControlBlock>, signum: usize) -> bool {
+    let limit = task.rlimits.lock().get_soft(RLIMIT_CORE);
+    if limit < PAGE_SIZE as u64 {
+        return false;
+    }
+    let dir = task.inner.lock().dir.clone();
+    let credentials = task.credentials.lock().clone();
+    if !can_write_core_file(&credentials, dir.as_str()) {
+        return false;
+    }
+    // 打开已有的文件时不会清空它，所以要先删除，以免新文件比原来的短时留下原来的内容
+    if check_file_exists(dir.as_str(), CORE_DUMP_FILE_NAME) {
+        try_remove_link(dir.clone(), CORE_DUMP_FILE_NAME);
+    }
+    let file = match open_file(
+        dir.as_str(),
+        CORE_DUMP_FILE_NAME,
+        OpenFlags::CREATE | OpenFlags::WRONLY,
+    ) {
+        Some(file) => file,
+        None => return false,
+    };
+    // 新建的 core 文件只有进程的有效用户可以读写
+    set_file_perm(
+        dir.as_str(),
+        CORE_DUMP_FILE_NAME,
+        FilePerm {
+            mode: 0o600,
+            uid: credentials.uid.effective,
+            gid: credentials.gid.effective,
+        },
+    );
+
+    // 只在读取地址空间信息时持有锁，写文件时不持有
+    let vm = task.get_vm();
+    let (ranges, auxv) = {
+        let vm = vm.lock();
+        (vm.resident_user_ranges(), vm.get_saved_auxv().to_vec())
+    };
+    let notes = build_notes(task, signum, auxv.as_slice());
+    let phnum = ranges.len() + 1;
+    let notes_offset = size_of::<ElfHeader>() + phnum * size_of::<ProgramHeader>();
+    let mut headers = Vec::new();
+    let mut e_ident = [0u8; 16];
+    // 依次为魔数、64 位、小端序、版本号
+    e_ident[..7].copy_from_slice(&[0x7f, b'E', b'L', b'F', 2, 1, 1]);
+    headers.extend_from_slice(as_bytes(&ElfHeader {
+        e_ident,
+        e_type: ET_CORE,
+        e_machine: EM_RISCV,
+        e_version: 1,
+        e_entry: 0,
+        e_phoff: size_of::<ElfHeader>() as u64,
+        e_shoff: 0,
+        e_flags: 0,
+        e_ehsize: size_of::<ElfHeader>() as u16,
+        e_phentsize: size_of::<ProgramHeader>() as u16,
+        e_phnum: phnum as u16,
+        e_shentsize: 0,
+        e_shnum: 0,
+        e_shstrndx: 0,
+    }));
+    headers.extend_from_slice(as_bytes(&ProgramHeader {
+        p_type: PT_NOTE,
+        p_flags: 0,
+        p_offset: notes_offset as u64,
+        p_vaddr: 0,
+        p_paddr: 0,
+        p_filesz: notes.len() as u64,
+        p_memsz: 0,
+        p_align: 4,
+    }));
+    // 各段的数据从 note 之后按页对齐开始存放
+    let mut offset = align_up(notes_offset + notes.len());
+    for &(start, end, flags) in ranges.iter() {
+        headers.extend_from_slice(as_bytes(&ProgramHeader {
+            p_type: PT_LOAD,
+            p_flags: segment_flags(flags),
+            p_offset: offset as u64,
+            p_vaddr: start as u64,
+            p_paddr: 0,
+            p_filesz: (end - start) as u64,
+            p_memsz: (end - start) as u64,
+            p_align: PAGE_SIZE as u64,
+        }));
+        offset += end - start;
+    }
+    headers.extend_from_slice(notes.as_slice());
+    headers.resize(align_up(headers.len()), 0);
+
+    let mut writer = CoreWriter {
+        file,
+        remaining: limit.min(usize::MAX as u64) as usize,
+    };
+    if !writer.write(headers.as_slice()) {
+        return true;
+    }
+    let mut page = vec![0u8; PAGE_SIZE];
+    for (start, end, _) in ranges {
+        for vaddr in (start..end).step_by(PAGE_SIZE) {
+            // 每次只在复制一页时持有地址空间的锁，复制出来后再写入文件。
+            // 组内其他线程都已退出，页通常不会被释放，这里只是以防万一写入全 0
+            if !vm.lock().read_resident_page(vaddr, page.as_mut_slice()) {
+                page.fill(0);
+            }
+            if !writer.write(page.as_slice()) {
+                return true;
+            }
+        }
+    }
+    true
+}
@@ -1,20 +1,25 @@
 //! 每个核当前正在运行的任务及上下文信息
 
 use super::{
-    TaskContext, TaskControlBlock, TaskStatus, __move_to_context, __switch,
-    fetch_task_from_scheduler, push_task_to_scheduler, ORIGIN_USER_PROC,
+    credentials::sub_task_count,
+    ptrace::{handle_ptrace_interrupt, ptrace_stop, release_tracees},
+    write_core_dump, TaskContext, TaskControlBlock, TaskStatus, __move_to_context, __switch,
+    fetch_task_from_scheduler, get_all_tasks, get_task_from_tid, global_logoff_task,
+    push_stopped_task_to_scheduler, push_task_to_scheduler, wake_stopped_tasks, ORIGIN_USER_PROC,
+    RLIMIT_CPU, RLIM_INFINITY,
 };
 use crate::{
-    arch::get_cpu_id,
-    constants::{CPU_ID_LIMIT, IS_TEST_ENV, NO_PARENT, USER_STACK_RED_ZONE},
+    arch::{get_cpu_id, ipi::handle_ipi},
+    constants::{CPU_ID_LIMIT, IS_TEST_ENV, NO_PARENT},
     error::{OSError, OSResult},
     file::show_testcase_result,
     memory::{enable_kernel_page_table, PTEFlags, VirtAddr},
     signal::{
-        global_logoff_signals, send_signal, SigActionDefault, SigActionFlags, SigInfo, SignalNo,
-        SignalUserContext, SIG_IGN,
+        get_signals_from_tid, global_logoff_signals, send_process_siginfo, send_signal,
+        set_running_receivers, SigActionDefault, SigActionFlags, SigInfo, SignalNo,
+        SignalUserContext, CLD_DUMPED, CLD_EXITED, CLD_KILLED, SIG_IGN, SI_USER,
     },
-    syscall::{check_thread_blocked, clear_loop_checker},
+    syscall::{check_thread_blocked, clear_loop_checker, restart_interrupted_syscall, wake_thread},
 };
 use alloc::{sync::Arc, vec::Vec};
 use core::mem::size_of;
@@ -29,6 +34,8 @@ pub struct CpuLocal {
     current: Option<Arc<TaskControlBlock>>,
     /// 无任务时的上下文，实际存的是启动时的上下文(其中的栈是 entry.S 中的 idle_stack)
     idle_task_cx: TaskContext,
+    /// 这个核的浮点寄存器最近一次恢复的是哪个线程的状态
+    fp_owner: Option<usize>,
 }
 
 impl CpuLocal {
@@ -37,6 +44,7 @@ impl CpuLocal {
         Self {
             current: None,
             idle_task_cx: TaskContext::zero_init(),
+            fp_owner: None,
         }
     }
     /// 获取无用户程序状态的内核上下文
@@ -64,17 +72,42 @@ lazy_static::lazy_static! {
     };
 }
 
+/// 正在执行用户程序的 cpu 组成的掩码，每个核进入 run_tasks 时设置自己对应的位
+static ACTIVE_CPU_MASK: Mutex<usize> = Mutex::new(0);
+
+/// 获取正在执行用户程序的 cpu 组成的掩码
+pub fn get_active_cpu_mask() -> usize {
+    *ACTIVE_CPU_MASK.lock()
+}
+
 /// 开始执行用户程序
 pub fn run_tasks() -> ! {
     let cpu_id = get_cpu_id();
+    *ACTIVE_CPU_MASK.lock() |= 1 << cpu_id;
     loop {
+        // 内核态不开中断，空闲时在这里处理其他核发来的消息
+        handle_ipi();
         if let Some(task) = fetch_task_from_scheduler() {
+            // 如果任务不允许在这个核上运行，则放回去等其他核取
+            if !task.can_run_on(cpu_id) {
+                push_task_to_scheduler(task);
+                continue;
+            }
             let tid = task.get_tid_num();
-            // 如果线程正在等待，则不进入
-            if check_thread_blocked(tid) {
+            // 如果线程正在等待，则不进入。但收到需要处理的信号时，等待会被打断
+            if check_thread_blocked(tid) && !task.has_pending_signal() {
                 push_task_to_scheduler(task);
                 continue;
             }
+            // 如果线程所在的进程被暂停，则放入暂停列表，直到收到 SIGCONT 或 SIGKILL 才重新调度
+            if task.is_stopped() {
+                task.set_status(TaskStatus::Stopped);
+                push_stopped_task_to_scheduler(task);
+                continue;
+            }
+            // 记录这个核正在运行的线程，其他核给它发信号时会通过 ipi 通知这个核。
+            // 这里需要获取信号的锁，所以放在获取 cpu_local 的锁之前
+            set_running_receivers(Some(&task.signal_receivers));
             let mut cpu_local = CPU_CONTEXTS[cpu_id].lock();
             //let mut task_inner = task.lock();
             let idle_task_cx_ptr = cpu_local.get_idle_task_cx_ptr();
@@ -85,8 +118,10 @@ pub fn run_tasks() -> ! {
             trace!("[cpu {}] now running on tid = {}", cpu_id, tid);
             //drop(task_inner);
             unsafe {
-                task.vm.lock().activate();
+                task.get_vm().lock().activate();
             }
+            // 按需恢复任务的浮点寄存器
+            task.load_fp_context(cpu_id, &mut cpu_local.fp_owner);
             // 标记内核态进入任务的时间
             task.time.lock().switch_into_task();
             cpu_local.current = Some(task);
@@ -103,6 +138,9 @@ pub fn run_tasks() -> ! {
             let mut cpu_local = CPU_CONTEXTS[cpu_id].lock();
             // 标记内核态退出任务的时间
             cpu_local.current().unwrap().time.lock().switch_out_task();
+            // 任务修改过浮点寄存器时才需要保存
+            cpu_local.current().unwrap().save_fp_context();
+            set_running_receivers(None);
             // 切换回只有内核的页表。在此之后就不能再访问该任务用户空间的内容
             enable_kernel_page_table();
             // 此时已切回空闲任务
@@ -114,6 +152,10 @@ pub fn run_tasks() -> ! {
                         // 将暂停的用户程序塞回任务队列
                         push_task_to_scheduler(task);
                     }
+                    TaskStatus::Stopped => {
+                        // 被信号暂停的任务不放回队列，直到所在进程继续执行
+                        push_stopped_task_to_scheduler(task);
+                    }
                     TaskStatus::Dying => {
                         if !IS_TEST_ENV && task.get_pid_num() == 0 {
                             // 这是初始进程，且不在测试环境
@@ -159,19 +201,44 @@ pub fn suspend_current_task() {
     }
 }
 
+/// 因收到暂停信号而暂停当前用户程序，回到 idle 状态。
+/// 在所在进程收到 SIGCONT 前，它不会再被调度
+fn stop_current_task() {
+    let cpu_id = get_cpu_id();
+    let mut cpu_local = CPU_CONTEXTS[cpu_id].lock();
+    let task = cpu_local.current().unwrap();
+    task.set_status(TaskStatus::Stopped);
+    trace!("[cpu {}] tid {} stopped", cpu_id, task.get_tid_num());
+    let current_task_cx_ptr = task.get_task_cx_ptr() as *mut TaskContext;
+    let idle_task_cx_ptr = cpu_local.get_idle_task_cx_ptr();
+    drop(task);
+    drop(cpu_local);
+    // 切换回 run_tasks() 中
+    unsafe {
+        __switch(current_task_cx_ptr, idle_task_cx_ptr);
+    }
+}
+
 /// 终止当前用户程序，回到 idle 状态
+///
+/// 如果所在的线程组正在退出，则使用整个线程组的 exit_code
 pub fn exit_current_task(exit_code: i32) {
     let cpu_id = get_cpu_id();
     let mut cpu_local = CPU_CONTEXTS[cpu_id].lock();
     let task = cpu_local.current().unwrap();
+    let thread_group = task.thread_group.lock();
+    let exit_code = thread_group.get_group_exit_code().unwrap_or(exit_code);
+    let term_signal = thread_group.get_group_term_signal();
+    drop(thread_group);
     // let task_inner = task.lock();
     task.set_status(TaskStatus::Dying);
     task.set_exit_code(exit_code);
+    task.inner.lock().term_signal = term_signal;
     // clear_child_tid 的值不为 0，则将这个用户地址处的值写为0
     let addr = task.inner.lock().clear_child_tid;
     if addr != 0 {
         // 确认这个地址在用户地址空间中。如果没有也不需要报错，因为线程马上就退出了
-        if task.vm.lock().manually_alloc_page(addr).is_ok() {
+        if task.get_vm().lock().manually_alloc_page(addr).is_ok() {
             info!("exit, clear tid {:x}", addr);
             unsafe {
                 *(addr as *mut i32) = 0;
@@ -196,6 +263,89 @@ pub fn exit_current_task(exit_code: i32) {
     }
 }
 
+/// 终止当前线程所在的整个线程组，回到 idle 状态
+///
+/// 组内其他线程会收到 SIGKILL，并在下一次 trap 时退出。
+/// 如果其他线程正阻塞在 futex 等待上，还需要唤醒它们，否则它们不会被调度，也就无法处理信号
+pub fn exit_current_group(exit_code: i32) {
+    exit_current_group_with_signal(exit_code, 0, false);
+}
+
+/// 因收到信号 signum 而终止当前线程所在的整个线程组，回到 idle 状态。
+///
+/// 父进程 wait 时会得到对应的信号编号。exit_code 仍会记录在 TCB 中，主要用于测试环境中判断测例结果
+pub fn exit_current_group_by_signal(signum: usize, exit_code: i32) {
+    exit_current_group_with_signal(exit_code, signum, false);
+}
+
+/// 因收到默认行为是 core dump 的信号 signum 而终止当前线程所在的整个线程组，回到 idle 状态。
+///
+/// 如果 RLIMIT_CORE 允许，会在进程的工作目录下生成 core 文件，父进程 wait 时可以得知这一点
+fn exit_current_group_with_core_dump(signum: usize, exit_code: i32) {
+    exit_current_group_with_signal(exit_code, signum, true);
+}
+
+/// 终止当前线程所在的整个线程组。term_signal 为终止线程组的信号编号，正常退出时为 0。
+/// core_dump 表示是否需要尝试生成 core 文件
+fn exit_current_group_with_signal(exit_code: i32, term_signal: usize, core_dump: bool) {
+    let task = get_current_task().unwrap();
+    let mut thread_group = task.thread_group.lock();
+    // 如果线程组已经在退出了，说明是被其他原因终止的，不需要再生成 core 文件
+    let is_first_to_exit = thread_group.get_group_exit_code().is_none();
+    let others = thread_group.start_group_exit(exit_code, term_signal, task.get_tid_num());
+    drop(thread_group);
+    for tid in others {
+        send_signal(tid, SignalNo::SIGKILL as usize);
+        wake_thread(tid);
+    }
+    // 开始退出的线程组不再暂停，其中被暂停的线程需要重新调度才能退出
+    wake_stopped_tasks(task.pid);
+    if core_dump && is_first_to_exit {
+        // 先等组内其他线程都退出，以免生成 core 文件时它们还在修改地址空间和寄存器
+        while task.thread_group.lock().get_members().len() > 1 {
+            suspend_current_task();
+        }
+        if write_core_dump(&task, term_signal) {
+            task.thread_group.lock().core_dumped = true;
+        }
+    }
+    drop(task);
+    exit_current_task(exit_code);
+}
+
+/// execve 前终止当前线程所在线程组中的其他线程，并等待它们退出。
+/// 如果当前线程不是组长线程，则之后由它接替组长的 tid，成为组内唯一的线程。
+///
+/// 如果线程组已经在退出(如收到了致命信号，或其他线程已经在 execve)，则返回 false，此时当前线程也会很快被终止
+pub fn de_thread() -> bool {
+    let task = get_current_task().unwrap();
+    let mut thread_group = task.thread_group.lock();
+    let others = match thread_group.start_exec(task.get_tid_num()) {
+        Some(others) => others,
+        None => return false,
+    };
+    drop(thread_group);
+    for tid in others {
+        send_signal(tid, SignalNo::SIGKILL as usize);
+        wake_thread(tid);
+    }
+    // 线程组不再暂停，其中被暂停的线程需要重新调度才能退出
+    wake_stopped_tasks(task.pid);
+    while task.thread_group.lock().get_members().len() > 1 {
+        suspend_current_task();
+    }
+    if task.get_tid_num() != task.pid {
+        // 组长线程离开线程组后还要在 handle_zombie_task 中处理完剩下的部分，最后一步是删除它的信号。
+        // 在此之前不能接替它的 tid
+        while get_signals_from_tid(task.pid).is_some() {
+            suspend_current_task();
+        }
+        task.take_over_leader(get_task_from_tid(task.pid));
+    }
+    task.thread_group.lock().finish_exec();
+    true
+}
+
 /// 通过 exec 系统调用，直接切换到新的用户进程
 pub fn exec_new_task() {
     let cpu_id = get_cpu_id();
@@ -210,7 +360,8 @@ pub fn exec_new_task() {
     }
 }
 /// 处理退出的任务：
-/// 将它的子进程全部交给初始进程 ORIGIN_USER_PROC，然后标记当前进程的状态为 Zombie。
+/// 将它的子进程全部交给最近的子进程回收者(没有则为初始进程 ORIGIN_USER_PROC)，然后标记当前进程的状态为 Zombie。
+/// 如果它是线程组中最后一个退出的线程，还需要通知父进程。
 /// 这里会需要获取当前核正在运行的用户程序、ORIGIN_USER_PROC、所有子进程的锁。
 ///
 /// 这里每修改一个子进程的 parent 指针，都要重新用 try_lock 拿子进程的锁和 ORIGIN_USER_PROC 的锁。
@@ -228,8 +379,16 @@ pub fn exec_new_task() {
 /// 所以卡在这个函数上的进程最终一定能以某种顺序依次执行完成，也就消除了死锁。
 ///
 fn handle_zombie_task(_cpu_local: &mut CpuLocal, task: Arc<TaskControlBlock>) {
+    // 子进程会被交给同组的其他线程或最近的子进程回收者。这里需要拿它们的锁，所以要在拿自己的锁之前找到它
+    let reaper = find_child_reaper(&task);
+    // 交给同组的其他线程时，子进程的父进程实际上没有变化
+    let to_sibling = reaper.pid == task.pid;
     let mut tcb_inner = task.inner.lock();
     //let task_inner = task.lock();
+    // 进程退出后可能成为孤儿进程组的，是它自己的进程组和子进程所在的进程组
+    let mut pgids_to_check = vec![tcb_inner.pgid];
+    // 设置了 pdeath_signal 的子进程，需要在父进程退出时收到信号
+    let mut pdeath_signals = Vec::new();
     for child in tcb_inner.children.iter() {
         loop {
             // 这里把获取子进程的锁放在外层，是因为如果当前进程和子进程都在这个函数里，
@@ -237,15 +396,23 @@ fn handle_zombie_task(_cpu_local: &mut CpuLocal, task: Arc<TaskControlBlock>) {
             // 因为每个进程在进这个函数时都拿着自己的锁，所以此时只有子进程先执行完成，父进程才能继续执行。
             // 为了防止父进程反复抢 start_proc 的锁又不得不释放，所以把获取子进程的锁放在外层
             if let Some(mut child_inner) = child.inner.try_lock() {
-                if tcb_inner.ppid == NO_PARENT || IS_TEST_ENV {
+                if !pgids_to_check.contains(&child_inner.pgid) {
+                    pgids_to_check.push(child_inner.pgid);
+                }
+                if child_inner.pdeath_signal != 0 {
+                    pdeath_signals.push((child.clone(), child_inner.pdeath_signal));
+                }
+                if !to_sibling && (tcb_inner.ppid == NO_PARENT || IS_TEST_ENV) {
                     child_inner.ppid = NO_PARENT;
                     break;
-                } else if let Some(mut start_proc_tcb_inner) =
-                    ORIGIN_USER_PROC.clone().inner.try_lock()
-                {
-                    child_inner.parent = Some(Arc::downgrade(&ORIGIN_USER_PROC));
-                    child_inner.ppid = 0;
-                    start_proc_tcb_inner.children.push(child.clone());
+                } else if let Some(mut reaper_inner) = reaper.inner.try_lock() {
+                    // 交给其他进程回收时，退出时改为发送 SIGCHLD
+                    if !to_sibling {
+                        child.thread_group.lock().exit_signal = SignalNo::SIGCHLD as usize;
+                    }
+                    child_inner.parent = Some(Arc::downgrade(&reaper));
+                    child_inner.ppid = reaper.get_pid_num();
+                    reaper_inner.children.push(child.clone());
                     // 拿到锁并修改完成后，退到外层循环去修改下一个子进程
                     break;
                 }
@@ -256,23 +423,156 @@ fn handle_zombie_task(_cpu_local: &mut CpuLocal, task: Arc<TaskControlBlock>) {
     tcb_inner.children.clear();
     tcb_inner.task_status = TaskStatus::Zombie;
     // 在测试环境中时，手动检查退出时的 exit_code
-    if IS_TEST_ENV && task.pid == task.tid.0 {
+    if IS_TEST_ENV && task.pid == task.get_tid_num() {
         show_testcase_result(tcb_inner.exit_code);
     }
-    //println!("tid {} is dead", task.tid.0);
+    //println!("tid {} is dead", task.get_tid_num());
     //println!("dead time {}", crate::timer::get_time());
-    // 退出时向父进程发送信号，其中选项可被 sys_clone 控制
-    if task.send_sigchld_when_exit || task.pid == task.tid.0 {
-        send_signal(tcb_inner.ppid, SignalNo::SIGCHLD as usize);
+    // 线程组中最后一个线程退出时，向父进程发送一次信号，信号由组长线程创建时的 clone 参数 exit_signal 决定。
+    // 同组线程的 ppid 都是相同的
+    // 离开线程组的同时从实际用户 id 的任务数中减去。两者需在凭证锁内一起完成，见 `syscall/process.rs: set_ids()`
+    let credentials = task.credentials.lock();
+    let mut thread_group = task.thread_group.lock();
+    let process_exited = thread_group.remove_member(task.get_tid_num(), tcb_inner.exit_code);
+    sub_task_count(credentials.uid.real, 1);
+    drop(credentials);
+    let exit_signal = thread_group.exit_signal;
+    if process_exited && exit_signal != 0 {
+        // 最后退出的线程不一定是组长，所以进程的退出状态要从线程组获取
+        let (exit_code, term_signal) = thread_group.exit_status();
+        let (code, status) = if term_signal != 0 {
+            let code = if thread_group.core_dumped {
+                CLD_DUMPED
+            } else {
+                CLD_KILLED
+            };
+            (code, term_signal as i32)
+        } else {
+            (CLD_EXITED, exit_code)
+        };
+        let mut info = task.child_siginfo(code, status);
+        info.si_signo = exit_signal as i32;
+        send_process_siginfo(tcb_inner.ppid, info);
+    }
+    drop(thread_group);
+    let sid = tcb_inner.sid;
+    // 没有被跟踪的进程退出时，如果父进程不等待子进程，则直接回收，不留下僵尸进程
+    let parent = if process_exited && tcb_inner.ptrace.is_none() {
+        tcb_inner.parent.as_ref().and_then(|p| p.upgrade())
+    } else {
+        None
+    };
+    drop(tcb_inner);
+    if let Some(parent) = parent {
+        if parent.signal_handlers.lock().auto_reap_children() {
+            parent
+                .inner
+                .lock()
+                .children
+                .retain(|child| child.pid != task.pid);
+        }
+    }
+    release_tracees(&task);
+    for (child, signum) in pdeath_signals {
+        send_signal(child.get_tid_num(), signum);
+    }
+    if process_exited {
+        handle_orphaned_process_groups(pgids_to_check, sid);
     }
     // 通知全局表将 signals 删除
-    global_logoff_signals(task.tid.0);
+    global_logoff_signals(task.get_tid_num());
+    // 非组长线程不是任何进程的子进程，不会被 wait 回收，所以退出后直接从全局表中删除。
+    // 之后就只有这里还持有它的 TCB，函数返回时即被释放
+    if task.pid != task.get_tid_num() {
+        global_logoff_task(task.get_tid_num());
+    }
     // 释放用户段占用的物理页面
     // 如果这里不释放，等僵尸进程被回收时 MemorySet 被 Drop，也可以释放这些页面
 
     // <- 之前是那么考虑的，但内存压力大的情况下好像可能不够用，还是提前gc吧
-    if Arc::strong_count(&task.vm) == 1 {
-        task.vm.lock().clear_user_pages();
+    // 除了 TCB 自己持有的引用外，这里的 vm 还会多算一次引用
+    let vm = task.get_vm();
+    if Arc::strong_count(&vm) == 2 {
+        vm.lock().clear_user_pages();
+    }
+}
+
+/// 找到 task 退出后接收它的子进程的任务：
+/// - 如果同一线程组中还有其他没有退出的线程，则交给其中一个，因为子进程属于整个进程而不只是创建它的线程。
+///   如果是因为其他线程 execve 而退出，则交给这个线程
+/// - 否则交给最近的还未退出的子进程回收者祖先。如没有，则为初始进程
+fn find_child_reaper(task: &Arc<TaskControlBlock>) -> Arc<TaskControlBlock> {
+    let thread_group = task.thread_group.lock();
+    let siblings = if let Some(exec_tid) = thread_group.get_exec_tid() {
+        vec![exec_tid]
+    } else if thread_group.get_group_exit_code().is_none() {
+        thread_group.get_members()
+    } else {
+        Vec::new()
+    };
+    drop(thread_group);
+    let sibling = siblings
+        .into_iter()
+        .filter(|&tid| tid != task.get_tid_num())
+        .filter_map(get_task_from_tid)
+        .find(|t| !matches!(t.get_status(), TaskStatus::Dying | TaskStatus::Zombie));
+    if let Some(sibling) = sibling {
+        return sibling;
+    }
+    let mut ancestor = task.inner.lock().parent.as_ref().and_then(|p| p.upgrade());
+    while let Some(current) = ancestor {
+        let thread_group = current.thread_group.lock();
+        if thread_group.child_subreaper
+            && !thread_group.is_empty()
+            && thread_group.get_group_exit_code().is_none()
+        {
+            drop(thread_group);
+            return current;
+        }
+        drop(thread_group);
+        ancestor = current
+            .inner
+            .lock()
+            .parent
+            .as_ref()
+            .and_then(|p| p.upgrade());
+    }
+    ORIGIN_USER_PROC.clone()
+}
+
+/// 检查 pgids 中的进程组是否成为了会话 sid 中的孤儿进程组，
+/// 即组内没有任何进程的父进程在同一会话的其他进程组中。
+///
+/// 如果一个进程组因此成为孤儿进程组，且组内有被暂停的进程，则向组内所有进程发送 SIGHUP 和 SIGCONT，
+/// 否则这些进程将再也没有机会被唤醒
+fn handle_orphaned_process_groups(pgids: Vec<usize>, sid: usize) {
+    // 所有还未退出的进程
+    let processes: Vec<Arc<TaskControlBlock>> = get_all_tasks()
+        .into_iter()
+        .filter(|t| t.pid == t.get_tid_num() && t.get_status() != TaskStatus::Zombie)
+        .collect();
+    for pgid in pgids {
+        let members: Vec<&Arc<TaskControlBlock>> = processes
+            .iter()
+            .filter(|t| t.get_pgid() == pgid && t.get_sid() == sid)
+            .collect();
+        let orphaned = members.iter().all(|t| {
+            let parent = t.inner.lock().parent.as_ref().and_then(|p| p.upgrade());
+            match parent {
+                Some(parent) => {
+                    parent.get_status() == TaskStatus::Zombie
+                        || parent.get_pgid() == pgid
+                        || parent.get_sid() != sid
+                }
+                None => true,
+            }
+        });
+        if orphaned && members.iter().any(|t| t.is_stopped()) {
+            for t in members {
+                t.send_signal_to_process(SignalNo::SIGHUP as usize);
+                t.send_signal_to_process(SignalNo::SIGCONT as usize);
+            }
+        }
     }
 }
 
@@ -281,7 +581,7 @@ pub fn handle_user_page_fault(vaddr: VirtAddr, access_flags: PTEFlags) -> OSResu
     let cpu_id = get_cpu_id();
     let cpu_local = CPU_CONTEXTS[cpu_id].lock();
     if let Some(task) = cpu_local.current() {
-        task.vm.lock().handle_page_fault(vaddr, access_flags)
+        task.get_vm().lock().handle_page_fault(vaddr, access_flags)
     } else {
         Err(OSError::Task_NoTrapHandler)
     }
@@ -311,84 +611,252 @@ pub fn timer_user_to_kernel() {
         .timer_user_to_kernel();
 }
 
+/// 检查当前进程使用的 CPU 时间是否超过了 RLIMIT_CPU，在用户态的时钟中断时调用：
+/// - 超过硬上限时，发送 SIGKILL
+/// - 超过软上限时，发送 SIGXCPU，之后每过一秒都会再收到一次 SIGXCPU。软上限本身不会被修改
+///
+/// 进程的 CPU 时间是组内所有还未退出的线程的用户态和内核态时间之和
+pub fn check_cpu_time_limit() {
+    let task = get_current_task().unwrap();
+    if task.rlimits.lock().get_soft(RLIMIT_CPU) == RLIM_INFINITY {
+        return;
+    }
+    let members = task.thread_group.lock().get_members();
+    let used_us: usize = members
+        .into_iter()
+        .filter_map(get_task_from_tid)
+        .map(|thread| {
+            let (utime_us, stime_us) = thread.time.lock().output_raw();
+            utime_us + stime_us
+        })
+        .sum();
+    let used_sec = (used_us / 1_000_000) as u64;
+    let mut rlimits = task.rlimits.lock();
+    let signal = if used_sec >= rlimits.get(RLIMIT_CPU).rlim_max {
+        SignalNo::SIGKILL
+    } else if used_sec >= rlimits.next_sigxcpu_sec() {
+        rlimits.delay_sigxcpu(used_sec);
+        SignalNo::SIGXCPU
+    } else {
+        return;
+    };
+    drop(rlimits);
+    task.send_signal_to_process(signal as usize);
+}
+
+/// 向当前线程发送由异常产生的信号，如 SIGSEGV/SIGILL/SIGBUS/SIGTRAP。
+///
+/// 如果这个信号被屏蔽或者被忽略，则解除屏蔽并恢复默认处理方式。
+/// 否则回到用户态后会再次执行出错的指令，反复触发同一个异常
+pub fn send_fault_signal(info: SigInfo) {
+    let task = get_current_task().unwrap();
+    let signum = info.si_signo as usize;
+    let mut receivers = task.signal_receivers.lock();
+    let mut handlers = task.signal_handlers.lock();
+    let ignored = handlers
+        .get_action_ref(signum)
+        .map_or(false, |action| action.handler == SIG_IGN);
+    if receivers.mask.contain_bit(signum - 1) || ignored {
+        receivers.mask.remove_bit(signum - 1);
+        handlers.reset_action(signum);
+    }
+    // 标准信号会合并，如果已经有一个由 kill 等发出的同编号信号，要换成这次异常的信息
+    receivers.pending.discard(signum);
+    receivers.pending.push(info);
+}
+
+/// 当前线程是否有需要处理的信号。阻塞的系统调用检查到它时，应当返回 EINTR 或重启
+pub fn signal_pending() -> bool {
+    get_current_task().unwrap().has_pending_signal()
+}
+
 /// 处理当前线程的信号
-pub fn handle_signals() {
+///
+/// 如果是从系统调用返回，则 orig_a0 为系统调用的第一个参数。
+/// 系统调用被信号打断时，根据是否调用了信号处理函数，决定返回 EINTR 还是重启它
+pub fn handle_signals(orig_a0: Option<usize>) {
+    if !deliver_signal(orig_a0) {
+        let task = get_current_task().unwrap();
+        if let Some(orig_a0) = orig_a0 {
+            let trap_cx = unsafe { &mut *task.kernel_stack.get_first_context() };
+            restart_interrupted_syscall(trap_cx, orig_a0, None);
+        }
+        // 没有进入信号处理函数时，sigsuspend 临时替换的掩码直接恢复
+        let mut receivers = task.signal_receivers.lock();
+        if let Some(mask) = receivers.saved_mask.take() {
+            receivers.mask = mask;
+        }
+    }
+}
+
+/// 取出一个信号并处理。如果进入了用户的信号处理函数，则返回 true
+fn deliver_signal(orig_a0: Option<usize>) -> bool {
     // 仅在 trap 时调用这个函数，所以保证当前线程和对应 signals 都是存在的
     let task = get_current_task().unwrap();
+    handle_ptrace_interrupt();
     // 如果其他线程正在向这里发送信号，则当前线程在此被阻塞
     let mut sig_inner = task.signal_receivers.lock();
-    let handler = task.signal_handlers.lock();
-    if let Some(signum) = sig_inner.get_one_signal() {
+    let mut handler = task.signal_handlers.lock();
+    let deferred = task.deferred_signals(&sig_inner, &handler);
+    if let Some(mut info) = sig_inner.get_one_signal(deferred) {
+        let mut signum = info.si_signo as usize;
+        // 被跟踪时，除 SIGKILL 外的信号都先交给跟踪者，由它决定实际处理哪个信号，或者丢弃这个信号
+        let traced = signum != SignalNo::SIGKILL as usize
+            && task.inner.lock().ptrace.as_mut().map_or(false, |state| {
+                state.last_siginfo = Some(info);
+                true
+            });
+        if traced {
+            drop(handler);
+            drop(sig_inner);
+            let injected = ptrace_stop(signum);
+            if injected == 0 {
+                return false;
+            }
+            if injected != signum {
+                // 跟踪者换成了其他信号
+                signum = injected;
+                info = SigInfo::new(signum, SI_USER);
+            }
+            sig_inner = task.signal_receivers.lock();
+            handler = task.signal_handlers.lock();
+        }
         let signal = SignalNo::from(signum);
         //println!("tid {} handling signal: {:#?}", task.get_tid_num(), signal);
-        // 保存成功说明当前没有在处理其他信号
-        if task.save_trap_cx_if_not_handling_signals() {
-            // 如果有，则调取处理函数
-            if let Some(action) = handler.get_action_ref(signum) {
-                //println!("flags: {:#?}", action.flags);
-                if action.handler == SIG_IGN {
-                    return;
+        // 如果有，则调取处理函数
+        if let Some(&action) = handler.get_action_ref(signum) {
+            //println!("flags: {:#?}", action.flags);
+            if action.handler == SIG_IGN {
+                return false;
+            }
+            // 正在处理其他信号时，只有异常信号可以嵌套进入自己的处理函数。
+            // 其他信号(如跟踪者注入的信号，或者由 kill 等发出的 SIGSEGV)放回队列，等 sigreturn 之后再处理
+            if task.is_handling_signals() {
+                if !info.is_fault() {
+                    sig_inner.pending.push(info);
+                    return false;
+                }
+                // 异常信号被屏蔽时，send_fault_signal 已经把它恢复为默认处理方式，不会到这里。
+                // 如果在它自己的处理函数中(设置了 SA_NODEFER)又触发了它，再进入处理函数会无限递归触发，只能直接结束
+                if task.is_handling_signal(signum) {
+                    drop(handler);
+                    drop(sig_inner);
+                    drop(task);
+                    exit_current_group_with_core_dump(signum, -1);
+                    return false;
                 }
-                // 保存后开始操作准备修改上下文，跳转到用户的信号处理函数
+            }
+            // 被打断的系统调用要在保存上下文之前决定返回 EINTR 还是重启，这样 sigreturn 后才能正确地继续执行
+            if let Some(orig_a0) = orig_a0 {
                 let trap_cx = unsafe { &mut *task.kernel_stack.get_first_context() };
-                trap_cx.set_ra(action.get_restorer());
-                //println!("restorer {}", action.get_restorer());
-                // 这里假设了用户栈没有溢出
-                info!("sp now {:x}", trap_cx.get_sp());
-                let mut sp = trap_cx.get_sp() - USER_STACK_RED_ZONE;
-                let old_pc = trap_cx.get_sepc();
-                trap_cx.set_sepc(action.handler);
-                trap_cx.set_a0(signum);
-                if action.flags.contains(SigActionFlags::SA_SIGINFO) {
-                    task.save_if_set_siginfo(true);
-                    // 如果带 SIGINFO，则需要在用户栈上放额外的信息
-                    sp = (sp - size_of::<SigInfo>()) & !0xf;
-                    info!("add siginfo at {:x}", sp);
-                    let mut info = SigInfo::default();
-                    info.si_signo = signum as i32;
-                    unsafe {
-                        *(sp as *mut SigInfo) = info;
-                    }
-                    trap_cx.set_a1(sp);
-                    sp = (sp - size_of::<SignalUserContext>()) & !0xf;
-                    unsafe {
-                        *(sp as *mut SignalUserContext) =
-                            SignalUserContext::init(sig_inner.mask.0 as u64, old_pc);
-                    }
-                    trap_cx.set_a2(sp);
-                    //let v = unsafe { *((sp + 0xb0) as *const usize) };
-                    //info!("read {} pc {}", v, old_pc);
-
-                    let tp = trap_cx.x[4];
-                    //let cancel = tp - 156;
-                    info!("val {}", unsafe { *((tp - 168) as *const u32) }); //tid
-                    info!("val {}", unsafe { *((tp - 156) as *const u32) });
-                    info!("val {}", unsafe { *((tp - 152) as *const u8) });
-                    info!("val {}", unsafe { *((tp - 151) as *const u8) });
+                restart_interrupted_syscall(trap_cx, orig_a0, Some(action.flags));
+            }
+            // 处理函数返回后恢复的掩码。如果是在 sigsuspend 中被打断的，则为调用 sigsuspend 前的掩码
+            let old_mask = sig_inner.saved_mask.take().unwrap_or(sig_inner.mask);
+            task.push_signal_frame(signum, old_mask);
+            // 设置了 SA_RESETHAND 时，进入处理函数前就把处理方式恢复为默认
+            if action.flags.contains(SigActionFlags::SA_RESETHAND) {
+                handler.reset_action(signum);
+            }
+            // 保存后开始操作准备修改上下文，跳转到用户的信号处理函数
+            let trap_cx = unsafe { &mut *task.kernel_stack.get_first_context() };
+            trap_cx.set_ra(action.get_restorer());
+            //println!("restorer {}", action.get_restorer());
+            info!("sp now {:x}", trap_cx.get_sp());
+            // 设置了 SA_ONSTACK 时，栈帧放在备用信号栈上，这样用户栈溢出时也能处理 SIGSEGV
+            let (mut sp, stack_low, uc_stack) = task.signal_frame_stack(
+                trap_cx.get_sp(),
+                action.flags.contains(SigActionFlags::SA_ONSTACK),
+            );
+            let frame_size = size_of::<SigInfo>() + size_of::<SignalUserContext>() + 0x20;
+            if task
+                .get_vm()
+                .lock()
+                .manually_alloc_range(sp - frame_size, sp - 1)
+                .is_err()
+            {
+                // 无法放下信号栈帧，只能结束进程
+                drop(handler);
+                drop(sig_inner);
+                drop(task);
+                exit_current_group_with_core_dump(SignalNo::SIGSEGV as usize, -1);
+                return false;
+            }
+            let old_pc = trap_cx.get_sepc();
+            trap_cx.set_sepc(action.handler);
+            trap_cx.set_a0(signum);
+            if action.flags.contains(SigActionFlags::SA_SIGINFO) {
+                task.save_if_set_siginfo(true);
+                // 如果带 SIGINFO，则需要在用户栈上放额外的信息
+                sp = (sp - size_of::<SigInfo>()) & !0xf;
+                info!("add siginfo at {:x}", sp);
+                unsafe {
+                    *(sp as *mut SigInfo) = info;
                 }
-                trap_cx.set_sp(sp);
-                //info!("into signal handler, sp = {:x} old_pc = {:x}", sp, old_pc);
-            } else {
-                // 否则，查找默认处理方式
-                match SigActionDefault::of_signal(signal) {
-                    SigActionDefault::Terminate => {
-                        // 这里不需要 drop(task)，因为当前函数没有用到 task_inner，在 task.save_trap... 内部用过后已经 drop 了
-                        drop(handler);
-                        drop(sig_inner);
-                        exit_current_task(0);
-                    }
-                    SigActionDefault::Ignore => {
-                        // 忽略信号时，要将已保存的上下文删除
-                        task.load_trap_cx_if_handling_signals();
+                trap_cx.set_a1(sp);
+                sp = (sp - size_of::<SignalUserContext>()) & !0xf;
+                unsafe {
+                    *(sp as *mut SignalUserContext) = SignalUserContext::init(
+                        old_mask.0 as u64,
+                        old_pc,
+                        uc_stack,
+                        task.save_fp_context(),
+                    );
+                }
+                trap_cx.set_a2(sp);
+                //let v = unsafe { *((sp + 0xb0) as *const usize) };
+                //info!("read {} pc {}", v, old_pc);
+
+                let tp = trap_cx.x[4];
+                //let cancel = tp - 156;
+                info!("val {}", unsafe { *((tp - 168) as *const u32) }); //tid
+                info!("val {}", unsafe { *((tp - 156) as *const u32) });
+                info!("val {}", unsafe { *((tp - 152) as *const u8) });
+                info!("val {}", unsafe { *((tp - 151) as *const u8) });
+            }
+            trap_cx.set_sp(sp);
+            task.set_signal_handler_sp(stack_low, sp);
+            // 处理函数执行期间屏蔽 sa_mask 中的信号。除非设置了 SA_NODEFER，否则也屏蔽这个信号本身
+            sig_inner.mask.get_union(action.mask);
+            if !action.flags.contains(SigActionFlags::SA_NODEFER) {
+                sig_inner.mask.add_bit(signum - 1);
+            }
+            // SIGKILL 和 SIGSTOP 不能被屏蔽
+            sig_inner.mask.remove_bit(SignalNo::SIGKILL as usize - 1);
+            sig_inner.mask.remove_bit(SignalNo::SIGSTOP as usize - 1);
+            //info!("into signal handler, sp = {:x} old_pc = {:x}", sp, old_pc);
+            return true;
+        } else {
+            // 否则，查找默认处理方式
+            match SigActionDefault::of_signal(signal) {
+                SigActionDefault::Terminate => {
+                    drop(handler);
+                    drop(sig_inner);
+                    drop(task);
+                    exit_current_group_by_signal(signum, 0);
+                }
+                SigActionDefault::CoreDump => {
+                    drop(handler);
+                    drop(sig_inner);
+                    drop(task);
+                    exit_current_group_with_core_dump(signum, 0);
+                }
+                SigActionDefault::Ignore | SigActionDefault::Continue => {}
+                SigActionDefault::Stop => {
+                    drop(handler);
+                    drop(sig_inner);
+                    task.stop_process(signum);
+                    // 可能在此之前进程已收到 SIGCONT，此时不需要暂停
+                    if task.is_stopped() {
+                        drop(task);
+                        stop_current_task();
                     }
                 }
             }
-        } else if signal == SignalNo::SIGSEGV || signal == SignalNo::SIGBUS {
-            //在处理信号的过程中又触发 SIGSEGV 或 SIGBUS，那么说明该直接结束了，否则会无限递归触发
-            exit_current_task(-1);
         }
     }
     //info!("signal handler finish");
+    false
 }
 
 /// 从信号处理中返回。
@@ -396,8 +864,12 @@ pub fn handle_signals() {
 pub fn signal_return() -> isize {
     // 仅在 sys_sigreturn 中调用这个函数，所以保证当前线程和对应 signals 都是存在的
     let task = get_current_task().unwrap();
-    //let signals = get_signals_from_tid(task.tid.0).unwrap();
-    if task.load_trap_cx_if_handling_signals() {
+    //let signals = get_signals_from_tid(task.get_tid_num()).unwrap();
+    if let Some(mut mask) = task.load_trap_cx_if_handling_signals() {
+        // 恢复进入处理函数前的信号掩码，其中 SIGKILL 和 SIGSTOP 不能被屏蔽
+        mask.remove_bit(SignalNo::SIGKILL as usize - 1);
+        mask.remove_bit(SignalNo::SIGSTOP as usize - 1);
+        task.signal_receivers.lock().mask = mask;
         // 上面已经 load 了，此处获取的值是原来的上下文
         let trap_cx = unsafe { &mut *task.kernel_stack.get_first_context() };
         trap_cx.get_a0() as isize
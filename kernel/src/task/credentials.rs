@@ -0,0 +1,213 @@
+//! 任务的用户和用户组凭证，用于 setuid 等 syscall 以及文件访问时的权限检查
+//!
+//! 同一线程组的所有线程共享一份凭证
+//!
+//! 另外维护每个实际用户 id 拥有的任务数，用于 clone 时检查 RLIMIT_NPROC
+
+use crate::file::FilePerm;
+use alloc::{collections::BTreeMap, vec::Vec};
+use base_file::StMode;
+use bitflags::*;
+use lock::Mutex;
+
+bitflags! {
+    /// 访问文件时需要的权限，与 access 系统调用的 mode 参数定义相同
+    pub struct AccessMode: u32 {
+        /// 读
+        const R_OK = 4;
+        /// 写
+        const W_OK = 2;
+        /// 执行
+        const X_OK = 1;
+    }
+}
+
+/// 每个实际用户 id 拥有的还未退出的任务数。
+/// 任务创建时加一，变成 Zombie 时减一，修改实际用户 id 时把整个线程组的计数转移到新的 id 上
+static TASK_COUNT: Mutex<BTreeMap<u32, usize>> = Mutex::new(BTreeMap::new());
+
+/// 获取实际用户 id 为 uid 的还未退出的任务数
+pub fn task_count_of(uid: u32) -> usize {
+    TASK_COUNT.lock().get(&uid).copied().unwrap_or(0)
+}
+
+/// 实际用户 id 为 uid 的任务增加 count 个
+pub fn add_task_count(uid: u32, count: usize) {
+    *TASK_COUNT.lock().entry(uid).or_insert(0) += count;
+}
+
+/// 实际用户 id 为 uid 的任务减少 count 个
+pub fn sub_task_count(uid: u32, count: usize) {
+    let mut task_count = TASK_COUNT.lock();
+    if let Some(n) = task_count.get_mut(&uid) {
+        *n = n.saturating_sub(count);
+        if *n == 0 {
+            task_count.remove(&uid);
+        }
+    }
+}
+
+/// 用户 id 或用户组 id 的一组值
+#[derive(Clone, Copy, Default)]
+pub struct IdSet {
+    /// 实际 id，即启动这个进程的用户
+    pub real: u32,
+    /// 有效 id，权限检查时使用
+    pub effective: u32,
+    /// 保存的 id，使有效 id 可以在降权后再恢复
+    pub saved: u32,
+}
+
+impl IdSet {
+    /// 三个 id 中是否有一个等于 id
+    fn contains(&self, id: u32) -> bool {
+        self.real == id || self.effective == id || self.saved == id
+    }
+    /// setuid / setgid。
+    /// 有特权时同时修改三个 id，否则只能把有效 id 改为实际 id 或保存的 id。返回是否修改成功
+    pub fn set(&mut self, id: u32, privileged: bool) -> bool {
+        if privileged {
+            *self = Self {
+                real: id,
+                effective: id,
+                saved: id,
+            };
+            true
+        } else if id == self.real || id == self.saved {
+            self.effective = id;
+            true
+        } else {
+            false
+        }
+    }
+    /// setreuid / setregid，None 表示不修改对应的 id。返回是否修改成功
+    ///
+    /// 没有特权时，实际 id 只能改为原来的实际 id 或有效 id，有效 id 只能改为原来三个 id 之一。
+    /// 如果修改了实际 id，或有效 id 被改为与原来的实际 id 不同的值，则保存的 id 也会被改为新的有效 id
+    pub fn set_re(&mut self, real: Option<u32>, effective: Option<u32>, privileged: bool) -> bool {
+        if !privileged {
+            if real.map_or(false, |id| id != self.real && id != self.effective)
+                || effective.map_or(false, |id| !self.contains(id))
+            {
+                return false;
+            }
+        }
+        let old_real = self.real;
+        if let Some(id) = real {
+            self.real = id;
+        }
+        if let Some(id) = effective {
+            self.effective = id;
+        }
+        if real.is_some() || effective.map_or(false, |id| id != old_real) {
+            self.saved = self.effective;
+        }
+        true
+    }
+    /// setresuid / setresgid，None 表示不修改对应的 id。返回是否修改成功
+    ///
+    /// 没有特权时，每个 id 都只能改为原来三个 id 之一
+    pub fn set_res(
+        &mut self,
+        real: Option<u32>,
+        effective: Option<u32>,
+        saved: Option<u32>,
+        privileged: bool,
+    ) -> bool {
+        if !privileged
+            && [real, effective, saved]
+                .iter()
+                .any(|id| id.map_or(false, |id| !self.contains(id)))
+        {
+            return false;
+        }
+        if let Some(id) = real {
+            self.real = id;
+        }
+        if let Some(id) = effective {
+            self.effective = id;
+        }
+        if let Some(id) = saved {
+            self.saved = id;
+        }
+        true
+    }
+}
+
+/// 任务的凭证。初始进程的凭证全为 0，即 root
+#[derive(Clone, Default)]
+pub struct Credentials {
+    /// 用户 id
+    pub uid: IdSet,
+    /// 用户组 id
+    pub gid: IdSet,
+    /// 附加用户组
+    pub groups: Vec<u32>,
+    /// 是否禁止通过 exec 获得新的权限(由 prctl 设置，设置后不能取消)。
+    /// 为 true 时 exec 会忽略文件的 set-user-ID / set-group-ID 位
+    pub no_new_privs: bool,
+}
+
+impl Credentials {
+    /// 是否有特权，即有效用户 id 是否为 root
+    pub fn is_privileged(&self) -> bool {
+        self.uid.effective == 0
+    }
+    /// 是否属于用户组 gid
+    pub fn in_group(&self, gid: u32) -> bool {
+        self.gid.effective == gid || self.groups.contains(&gid)
+    }
+    /// 检查是否可以按 access 指定的方式访问权限信息为 perm 的文件。
+    /// use_real_id 为 true 时用实际 id 而不是有效 id 检查，用于 access 系统调用
+    ///
+    /// root 可以读写任何文件，但只有文件至少有一个执行权限位时才能执行
+    pub fn can_access(&self, perm: &FilePerm, access: AccessMode, use_real_id: bool) -> bool {
+        let (uid, gid) = if use_real_id {
+            (self.uid.real, self.gid.real)
+        } else {
+            (self.uid.effective, self.gid.effective)
+        };
+        if uid == 0 {
+            let exec_bits = (StMode::S_IXUSR | StMode::S_IXGRP | StMode::S_IXOTH).bits();
+            return !access.contains(AccessMode::X_OK) || perm.mode & exec_bits != 0;
+        }
+        let granted = (if perm.uid == uid {
+            perm.mode >> 6
+        } else if perm.gid == gid || self.groups.contains(&perm.gid) {
+            perm.mode >> 3
+        } else {
+            perm.mode
+        }) & 0o7;
+        access.bits() & !granted == 0
+    }
+    /// 检查是否可以向凭证为 target 的任务发送信号：
+    /// 有特权，或者实际/有效用户 id 等于对方的实际/保存的用户 id
+    pub fn can_signal(&self, target: &Credentials) -> bool {
+        self.is_privileged()
+            || [self.uid.real, self.uid.effective]
+                .iter()
+                .any(|&id| id == target.uid.real || id == target.uid.saved)
+    }
+    /// 检查是否可以访问凭证为 target 的任务的内部状态(如复制它的文件描述符)：
+    /// 有特权，或者对方的实际/有效/保存的用户 id 和用户组 id 都分别等于自己的实际用户 id 和实际用户组 id
+    pub fn can_inspect(&self, target: &Credentials) -> bool {
+        self.is_privileged()
+            || ([target.uid.real, target.uid.effective, target.uid.saved]
+                .iter()
+                .all(|&id| id == self.uid.real)
+                && [target.gid.real, target.gid.effective, target.gid.saved]
+                    .iter()
+                    .all(|&id| id == self.gid.real))
+    }
+    /// exec 时根据文件的 set-user-ID / set-group-ID 位修改有效 id，然后把有效 id 存入保存的 id
+    pub fn apply_exec(&mut self, perm: &FilePerm) {
+        if perm.mode & StMode::S_ISUID.bits() != 0 && !self.no_new_privs {
+            self.uid.effective = perm.uid;
+        }
+        if perm.mode & StMode::S_ISGID.bits() != 0 && !self.no_new_privs {
+            self.gid.effective = perm.gid;
+        }
+        self.uid.saved = self.uid.effective;
+        self.gid.saved = self.gid.effective;
+    }
+}
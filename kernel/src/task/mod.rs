@@ -6,11 +6,17 @@
 
 mod clone_flags;
 mod context;
+mod core_dump;
 mod cpu_local;
+mod credentials;
 mod kernel_stack;
+mod ptrace;
+mod resource_limits;
 mod scheduler;
 mod switch;
 mod task;
+mod thread_group;
+mod tid2task;
 mod time_stat;
 
 use crate::constants::{ORIGIN_USER_PROC_NAME, ROOT_DIR};
@@ -19,20 +25,38 @@ use switch::{__move_to_context, __switch};
 
 pub use clone_flags::CloneFlags;
 pub use context::TaskContext;
+use core_dump::write_core_dump;
 pub use cpu_local::{
-    exec_new_task, exit_current_task, get_current_task, handle_signals, handle_user_page_fault,
-    run_tasks, signal_return, suspend_current_task, timer_kernel_to_user, timer_user_to_kernel,
+    check_cpu_time_limit, de_thread, exec_new_task, exit_current_group,
+    exit_current_group_by_signal, exit_current_task, get_active_cpu_mask, get_current_task,
+    handle_signals, handle_user_page_fault, run_tasks, send_fault_signal, signal_pending,
+    signal_return, suspend_current_task, timer_kernel_to_user, timer_user_to_kernel,
+};
+pub use credentials::{
+    add_task_count, sub_task_count, task_count_of, AccessMode, Credentials, IdSet,
 };
 pub use kernel_stack::KernelStack;
+pub use ptrace::{
+    handle_breakpoint, prepare_single_step, ptrace_clone_child, ptrace_event_stop, ptrace_exec,
+    ptrace_syscall_enter, ptrace_syscall_exit, PtraceOptions, PtraceResume, PtraceState,
+    PTRACE_EVENT_VFORK_DONE,
+};
+pub use resource_limits::{
+    RLimit, ResourceLimits, RLIMIT_AS, RLIMIT_CORE, RLIMIT_CPU, RLIMIT_FSIZE, RLIMIT_NOFILE,
+    RLIMIT_NPROC, RLIMIT_STACK, RLIM_INFINITY, RLIM_NLIMITS,
+};
 pub use scheduler::Scheduler;
 pub use scheduler::{fetch_task_from_scheduler, push_task_to_scheduler};
+use scheduler::{push_stopped_task_to_scheduler, wake_stopped_tasks};
 pub use task::{TaskControlBlock, TaskControlBlockInner, TaskStatus};
+pub use thread_group::ThreadGroup;
+pub use tid2task::{get_all_tasks, get_task_from_tid};
+use tid2task::{global_logoff_task, global_register_task};
 pub use time_stat::TimeStat;
 
 lazy_static::lazy_static! {
     /// 第一个用户程序
     /// 任务调度器启动时会自动在队列中插入它作为第一个用户程序
-    pub static ref ORIGIN_USER_PROC: Arc<TaskControlBlock> = Arc::new(
-        TaskControlBlock::from_app_name(ROOT_DIR, 0, vec![ORIGIN_USER_PROC_NAME.into()]).unwrap()
-    );
+    pub static ref ORIGIN_USER_PROC: Arc<TaskControlBlock> =
+        TaskControlBlock::from_app_name(ROOT_DIR, 0, vec![ORIGIN_USER_PROC_NAME.into()]).unwrap();
 }
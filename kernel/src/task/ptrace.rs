@@ -0,0 +1,602 @@
+//! 进程跟踪(ptrace)中被跟踪的任务一侧的状态和暂停逻辑
+//!
+//! 被跟踪的任务在以下情况下会暂停，等待跟踪者通过 wait 得知暂停，再通过 ptrace 读写它的内存和寄存器、恢复它的运行：
+//! - 收到信号时(SIGKILL 除外)，见 `cpu_local.rs: handle_signals()`
+//! - 跟踪者通过 PTRACE_SYSCALL 恢复它后，下一次进入和退出系统调用时
+//! - 跟踪者通过 PTRACE_SINGLESTEP 恢复它后，执行完一条指令时
+//! - exec 成功后，以及设置了对应选项时的 fork / vfork / clone 之后
+//! - 跟踪者通过 PTRACE_INTERRUPT 要求它暂停时
+//!
+//! 暂停的任务不会离开调度队列，而是在内核中反复检查自己是否已被恢复，与 wait 等阻塞的系统调用相同。
+//!
+//! 硬件没有提供单步执行的支持，所以单步是用断点模拟的：回到用户态前，解析当前指令所有可能的后继地址，
+//! 在这些地址上写入 c.ebreak；触发断点异常后再写回原来的指令。
+//!
+//! 断点写在共享的代码段中，同一地址空间的其他线程也可能碰到它。
+//! 这时不向它们发送 SIGTRAP，而是让它们让出 CPU 后重新执行这条指令，直到断点被写回
+
+use super::{
+    get_all_tasks, get_current_task, send_fault_signal, suspend_current_task, CloneFlags,
+    TaskControlBlock,
+};
+use crate::{
+    memory::{MemorySet, PTEFlags},
+    signal::{send_signal, SigInfo, SignalNo, CLD_TRAPPED, TRAP_BRKPT, TRAP_TRACE},
+    trap::TrapContext,
+};
+use alloc::{
+    sync::{Arc, Weak},
+    vec::Vec,
+};
+use bitflags::*;
+use lock::Mutex;
+
+// 暂停时报告的事件，位于 wait 得到的 status 的第 16~23 位
+/// fork 出了新进程
+const PTRACE_EVENT_FORK: usize = 1;
+/// vfork 出了新进程
+const PTRACE_EVENT_VFORK: usize = 2;
+/// clone 出了新任务
+const PTRACE_EVENT_CLONE: usize = 3;
+/// exec 成功
+const PTRACE_EVENT_EXEC: usize = 4;
+/// vfork 出的子进程已经 exec 或退出
+pub const PTRACE_EVENT_VFORK_DONE: usize = 5;
+/// 因 PTRACE_INTERRUPT 而暂停
+const PTRACE_EVENT_STOP: usize = 128;
+
+/// syscall 暂停时，如果设置了 PTRACE_O_TRACESYSGOOD，则报告的信号为 SIGTRAP | 0x80
+const SYSCALL_TRAP_FLAG: usize = 0x80;
+
+/// c.ebreak 指令，单步执行时作为断点写入用户代码
+const C_EBREAK: [u8; 2] = 0x9002u16.to_le_bytes();
+/// ebreak 指令的低 16 位
+const EBREAK_LOW: [u8; 2] = 0x0073u16.to_le_bytes();
+
+/// 插入和写回单步执行的断点时持有，使检查同一地址空间中其他线程的断点和修改代码不会交错
+static STEP_BREAKPOINT_LOCK: Mutex<()> = Mutex::new(());
+
+bitflags! {
+    /// PTRACE_SETOPTIONS 设置的选项。除 TRACESYSGOOD 和 EXITKILL 外，第 i 位对应编号为 i 的事件
+    pub struct PtraceOptions: usize {
+        /// syscall 暂停时报告 SIGTRAP | 0x80，以便与真正的 SIGTRAP 区分
+        const TRACESYSGOOD = 1;
+        /// fork 后暂停，并自动跟踪子进程
+        const TRACEFORK = 1 << 1;
+        /// vfork 后暂停，并自动跟踪子进程
+        const TRACEVFORK = 1 << 2;
+        /// clone 后暂停，并自动跟踪新任务
+        const TRACECLONE = 1 << 3;
+        /// exec 后报告 PTRACE_EVENT_EXEC，而不是发送 SIGTRAP
+        const TRACEEXEC = 1 << 4;
+        /// vfork 出的子进程 exec 或退出后暂停
+        const TRACEVFORKDONE = 1 << 5;
+        /// 退出前暂停。目前只接受这个选项，不会因此暂停
+        const TRACEEXIT = 1 << 6;
+        /// 跟踪者退出时，向被跟踪的任务发送 SIGKILL
+        const EXITKILL = 1 << 20;
+    }
+}
+
+/// 跟踪者恢复被跟踪的任务时指定的运行方式
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum PtraceResume {
+    /// 正常运行，直到下一次收到信号
+    Continue,
+    /// 运行到下一次进入或退出系统调用
+    Syscall,
+    /// 执行一条指令
+    SingleStep,
+    /// 停止跟踪，然后正常运行
+    Detach,
+}
+
+/// 被跟踪的任务的状态，存放在 TaskControlBlockInner 中
+pub struct PtraceState {
+    /// 跟踪者
+    tracer: Weak<TaskControlBlock>,
+    /// 跟踪者的 tid。只有这个线程可以对被跟踪的任务调用 ptrace
+    tracer_tid: usize,
+    /// 跟踪者的 pid。跟踪者所在进程的所有线程都可以通过 wait 得知被跟踪的任务的暂停
+    tracer_pid: usize,
+    /// PTRACE_SETOPTIONS 设置的选项
+    pub options: PtraceOptions,
+    /// 是否是通过 PTRACE_SEIZE 开始跟踪的。只有这种情况下可以使用 PTRACE_INTERRUPT
+    seized: bool,
+    /// 当前暂停的状态，即 wait 得到的 status 的第 8~23 位。没有暂停时为 None
+    stop_status: Option<usize>,
+    /// 当前的暂停是否还未被 wait 报告
+    stop_to_report: bool,
+    /// 跟踪者恢复运行时指定的方式
+    resume_mode: PtraceResume,
+    /// 跟踪者恢复运行时要求注入的信号，为 0 时不注入
+    resume_signal: usize,
+    /// 单步执行时插入的断点，依次为地址和原来的指令
+    step_breakpoints: Vec<(usize, [u8; 2])>,
+    /// PTRACE_GETEVENTMSG 获取的信息，如 fork 出的子进程的 pid
+    pub event_msg: usize,
+    /// 跟踪者是否通过 PTRACE_INTERRUPT 要求暂停
+    interrupt_requested: bool,
+    /// 导致最近一次暂停的信号的附加信息，即 PTRACE_GETSIGINFO 获取的内容
+    pub last_siginfo: Option<SigInfo>,
+}
+
+impl PtraceState {
+    /// 开始被 tracer 跟踪
+    pub fn new(tracer: &Arc<TaskControlBlock>, options: PtraceOptions, seized: bool) -> Self {
+        Self {
+            tracer: Arc::downgrade(tracer),
+            tracer_tid: tracer.get_tid_num(),
+            tracer_pid: tracer.get_pid_num(),
+            options,
+            seized,
+            stop_status: None,
+            stop_to_report: false,
+            resume_mode: PtraceResume::Continue,
+            resume_signal: 0,
+            step_breakpoints: Vec::new(),
+            event_msg: 0,
+            interrupt_requested: false,
+            last_siginfo: None,
+        }
+    }
+    /// 获取跟踪者的 tid
+    pub fn get_tracer_tid(&self) -> usize {
+        self.tracer_tid
+    }
+    /// 获取跟踪者的 pid
+    pub fn get_tracer_pid(&self) -> usize {
+        self.tracer_pid
+    }
+    /// 获取当前暂停的状态，没有暂停时为 None
+    pub fn get_stop_status(&self) -> Option<usize> {
+        self.stop_status
+    }
+    /// 获取还未被 wait 报告的暂停状态。consume 为 true 时，之后不再报告这次暂停
+    pub fn take_stop_to_report(&mut self, consume: bool) -> Option<usize> {
+        if !self.stop_to_report {
+            return None;
+        }
+        if consume {
+            self.stop_to_report = false;
+        }
+        self.stop_status
+    }
+    /// 以 mode 方式恢复暂停的任务，signal 为要注入的信号。如果任务没有暂停，则返回 false
+    pub fn resume(&mut self, mode: PtraceResume, signal: usize) -> bool {
+        if self.stop_status.is_none() {
+            return false;
+        }
+        self.stop_status = None;
+        self.stop_to_report = false;
+        self.resume_mode = mode;
+        self.resume_signal = signal;
+        true
+    }
+    /// 要求任务在下一次回到用户态前暂停。只有通过 PTRACE_SEIZE 跟踪的任务可以这样做，否则返回 false
+    pub fn request_interrupt(&mut self) -> bool {
+        if self.seized {
+            self.interrupt_requested = true;
+        }
+        self.seized
+    }
+}
+
+/// 当前线程进入 ptrace 暂停，直到跟踪者恢复它、跟踪者停止跟踪或者当前进程被杀死。
+/// status 是跟踪者 wait 时得到的暂停状态，即 wstatus 的第 8~23 位
+///
+/// 返回跟踪者要求注入的信号，0 表示不注入。如果当前线程没有被跟踪，则直接返回 0
+pub fn ptrace_stop(status: usize) -> usize {
+    let task = get_current_task().unwrap();
+    let mut inner = task.inner.lock();
+    let state = match inner.ptrace.as_mut() {
+        Some(state) => state,
+        None => return 0,
+    };
+    state.stop_status = Some(status);
+    state.stop_to_report = true;
+    state.interrupt_requested = false;
+    if status > 0x7f {
+        // 不是因信号暂停，si_code 为暂停状态本身。因信号暂停时由 handle_signals 设置
+        state.last_siginfo = Some(SigInfo::new(status & 0x7f, status as i32));
+    }
+    let breakpoints = core::mem::take(&mut state.step_breakpoints);
+    let tracer = state.tracer.upgrade();
+    drop(inner);
+    // 跟踪者在暂停时可能读取代码，所以先写回单步执行时插入的断点
+    remove_breakpoints(&task, breakpoints);
+    if let Some(tracer) = tracer {
+        tracer.send_siginfo_to_process(task.child_siginfo(CLD_TRAPPED, (status & 0xff) as i32));
+    }
+    let signal = loop {
+        let mut inner = task.inner.lock();
+        match inner.ptrace.as_mut() {
+            Some(state) if state.stop_status.is_some() => {}
+            Some(state) => {
+                let signal = state.resume_signal;
+                if state.resume_mode == PtraceResume::Detach {
+                    inner.ptrace = None;
+                }
+                break signal;
+            }
+            None => break 0,
+        }
+        drop(inner);
+        // 暂停时被杀死，则不再等待跟踪者
+        let killed = task.thread_group.lock().get_group_exit_code().is_some()
+            || task
+                .signal_receivers
+                .lock()
+                .has_signal(SignalNo::SIGKILL as usize);
+        if killed {
+            if let Some(state) = task.inner.lock().ptrace.as_mut() {
+                state.stop_status = None;
+                state.stop_to_report = false;
+            }
+            break 0;
+        }
+        suspend_current_task();
+    };
+    // 跟踪者可能修改了代码，需要刷新指令缓存
+    unsafe { core::arch::asm!("fence.i") };
+    signal
+}
+
+/// 如果当前线程被跟踪且设置了 event 对应的选项，则报告这个事件并暂停，msg 为 PTRACE_GETEVENTMSG 获取的信息。
+/// 跟踪者要求注入的信号会发送给当前线程
+///
+/// 返回是否暂停了
+pub fn ptrace_event_stop(event: usize, msg: usize) -> bool {
+    let task = get_current_task().unwrap();
+    let mut inner = task.inner.lock();
+    match inner.ptrace.as_mut() {
+        Some(state) if state.options.bits() & (1 << event) != 0 => state.event_msg = msg,
+        _ => return false,
+    }
+    drop(inner);
+    let signal = ptrace_stop(SignalNo::SIGTRAP as usize | (event << 8));
+    if signal != 0 {
+        send_signal(task.get_tid_num(), signal);
+    }
+    true
+}
+
+/// exec 成功后、回到用户态前调用，让跟踪者有机会在新程序的第一条指令前设置断点：
+/// 设置了 PTRACE_O_TRACEEXEC 时报告 PTRACE_EVENT_EXEC，否则相当于在回到用户态前收到了 SIGTRAP
+pub fn ptrace_exec() {
+    let task = get_current_task().unwrap();
+    let mut inner = task.inner.lock();
+    match inner.ptrace.as_mut() {
+        // 断点在旧的地址空间中，已经随着它一起被清空了
+        Some(state) => state.step_breakpoints.clear(),
+        None => return,
+    }
+    // exec 已经把任务上下文设为了直接回到新程序，而暂停时切换任务会覆盖它，所以先保存下来
+    let task_cx = inner.task_cx;
+    drop(inner);
+    let tid = task.get_tid_num();
+    if !ptrace_event_stop(PTRACE_EVENT_EXEC, tid) {
+        let signal = ptrace_stop(SignalNo::SIGTRAP as usize);
+        if signal != 0 {
+            send_signal(tid, signal);
+        }
+    }
+    task.inner.lock().task_cx = task_cx;
+}
+
+/// 跟踪者通过 PTRACE_SYSCALL 恢复当前线程时，在进入或退出系统调用时暂停。返回是否暂停了
+fn ptrace_syscall_stop() -> bool {
+    let task = get_current_task().unwrap();
+    let inner = task.inner.lock();
+    let status = match inner.ptrace.as_ref() {
+        Some(state) if state.resume_mode == PtraceResume::Syscall => {
+            if state.options.contains(PtraceOptions::TRACESYSGOOD) {
+                SignalNo::SIGTRAP as usize | SYSCALL_TRAP_FLAG
+            } else {
+                SignalNo::SIGTRAP as usize
+            }
+        }
+        _ => return false,
+    };
+    drop(inner);
+    let signal = ptrace_stop(status);
+    if signal != 0 {
+        send_signal(task.get_tid_num(), signal);
+    }
+    true
+}
+
+/// 进入系统调用前调用。如果跟踪者要求在系统调用处暂停，则暂停，此时跟踪者可以修改系统调用号和参数。
+///
+/// 返回是否需要执行这个系统调用：如果跟踪者把系统调用号改成了 -1，则跳过它，返回值为跟踪者设置的 a0
+pub fn ptrace_syscall_enter(cx: &TrapContext) -> bool {
+    !ptrace_syscall_stop() || cx.x[17] != usize::MAX
+}
+
+/// 系统调用返回后调用。如果跟踪者要求在系统调用处暂停，则暂停，此时跟踪者可以读取或修改返回值
+pub fn ptrace_syscall_exit() {
+    ptrace_syscall_stop();
+}
+
+/// 被跟踪时，如果跟踪者通过 PTRACE_INTERRUPT 要求暂停，则报告 PTRACE_EVENT_STOP 并暂停
+pub fn handle_ptrace_interrupt() {
+    let task = get_current_task().unwrap();
+    let requested = task
+        .inner
+        .lock()
+        .ptrace
+        .as_ref()
+        .map_or(false, |state| state.interrupt_requested);
+    if requested {
+        let signal = ptrace_stop(SignalNo::SIGTRAP as usize | (PTRACE_EVENT_STOP << 8));
+        if signal != 0 {
+            send_signal(task.get_tid_num(), signal);
+        }
+    }
+}
+
+/// 用户程序在 addr 处执行 ebreak 时调用。写回单步执行时插入的断点，然后向当前线程发送 SIGTRAP。
+///
+/// 如果碰到的是同一地址空间中其他线程单步执行插入的断点，则不发送信号，让出 CPU 后重新执行这条指令。
+/// 单步执行完成后恢复为普通的运行方式，这样即使 SIGTRAP 被屏蔽，也不会一直单步执行下去
+pub fn handle_breakpoint(addr: usize) {
+    let task = get_current_task().unwrap();
+    let guard = STEP_BREAKPOINT_LOCK.lock();
+    let is_own = task.inner.lock().ptrace.as_ref().map_or(false, |state| {
+        state.step_breakpoints.iter().any(|&(bp, _)| bp == addr)
+    });
+    if !is_own && (shared_breakpoint(&task, addr).is_some() || !is_ebreak_at(&task, addr)) {
+        // 其他线程的断点，或者断点在陷入内核后已经被其他线程写回了
+        drop(guard);
+        suspend_current_task();
+        return;
+    }
+    drop(guard);
+    let mut inner = task.inner.lock();
+    let breakpoints = match inner.ptrace.as_mut() {
+        Some(state) => {
+            if state.resume_mode == PtraceResume::SingleStep {
+                state.resume_mode = PtraceResume::Continue;
+            }
+            core::mem::take(&mut state.step_breakpoints)
+        }
+        None => Vec::new(),
+    };
+    drop(inner);
+    // 碰到的是单步执行插入的断点，还是用户程序自己的断点
+    let code = if is_own { TRAP_TRACE } else { TRAP_BRKPT };
+    remove_breakpoints(&task, breakpoints);
+    send_fault_signal(SigInfo::fault(SignalNo::SIGTRAP as usize, code, addr));
+}
+
+/// task 的地址空间中 addr 处的指令是否是 ebreak 或 c.ebreak
+fn is_ebreak_at(task: &TaskControlBlock, addr: usize) -> bool {
+    let mut code = [0u8; 2];
+    task.get_vm()
+        .lock()
+        .read(addr, 2, &mut code, PTEFlags::USER)
+        .is_ok()
+        && (code == C_EBREAK || code == EBREAK_LOW)
+}
+
+/// 与 task 共享地址空间的其他线程是否在 addr 处插入了单步执行的断点，如果有则返回原来的指令。
+///
+/// 调用时需持有 STEP_BREAKPOINT_LOCK，且不能持有 task 的 inner 和地址空间的锁
+fn shared_breakpoint(task: &TaskControlBlock, addr: usize) -> Option<[u8; 2]> {
+    let vm = task.get_vm();
+    get_all_tasks()
+        .into_iter()
+        .filter(|other| {
+            other.get_tid_num() != task.get_tid_num() && Arc::ptr_eq(&other.get_vm(), &vm)
+        })
+        .find_map(|other| {
+            other.inner.lock().ptrace.as_ref().and_then(|state| {
+                state
+                    .step_breakpoints
+                    .iter()
+                    .find(|&&(bp, _)| bp == addr)
+                    .map(|&(_, origin)| origin)
+            })
+        })
+}
+
+/// 回到用户态前调用。如果跟踪者要求单步执行且还没有插入断点，则在当前指令的所有后继地址上插入断点
+pub fn prepare_single_step() {
+    let task = get_current_task().unwrap();
+    match task.inner.lock().ptrace.as_ref() {
+        Some(state)
+            if state.resume_mode == PtraceResume::SingleStep
+                && state.step_breakpoints.is_empty() => {}
+        _ => return,
+    }
+    let trap_cx = unsafe { &*task.kernel_stack.get_first_context() };
+    let vm = task.get_vm();
+    let addrs = next_pcs(&vm.lock(), trap_cx);
+    let guard = STEP_BREAKPOINT_LOCK.lock();
+    let mut breakpoints = Vec::new();
+    for addr in addrs {
+        // 其他线程可能已经在这里插入了断点，这时原来的指令以它记录的为准
+        let shared = shared_breakpoint(&task, addr);
+        let vm = vm.lock();
+        let mut origin = [0u8; 2];
+        if vm.read(addr, 2, &mut origin, PTEFlags::USER).is_ok()
+            && vm.write(addr, 2, &C_EBREAK, PTEFlags::USER).is_ok()
+        {
+            breakpoints.push((addr, shared.unwrap_or(origin)));
+        }
+    }
+    drop(guard);
+    let mut inner = task.inner.lock();
+    match inner.ptrace.as_mut() {
+        Some(state) => state.step_breakpoints = breakpoints,
+        None => {
+            // 检查之后跟踪者退出了，不再单步执行
+            drop(inner);
+            remove_breakpoints(&task, breakpoints);
+        }
+    }
+    unsafe { core::arch::asm!("fence.i") };
+}
+
+/// 写回 task 的地址空间中插入的断点。调用前需已经把它们从 task 的跟踪状态中取出。
+///
+/// 同一地址空间中的其他线程在同一位置也插入了断点时，保留断点，由最后一个线程写回
+fn remove_breakpoints(task: &TaskControlBlock, breakpoints: Vec<(usize, [u8; 2])>) {
+    if breakpoints.is_empty() {
+        return;
+    }
+    let _guard = STEP_BREAKPOINT_LOCK.lock();
+    let vm = task.get_vm();
+    for (addr, origin) in breakpoints.iter().rev() {
+        if shared_breakpoint(task, *addr).is_none() {
+            vm.lock()
+                .write(*addr, 2, origin, PTEFlags::USER)
+                .unwrap_or(());
+        }
+    }
+}
+
+/// 将 value 的低 bits 位视为有符号数，扩展为 usize，以便与地址做 wrapping_add
+fn sign_extend(value: u32, bits: u32) -> usize {
+    (((value << (32 - bits)) as i32) >> (32 - bits)) as isize as usize
+}
+
+/// 解析 pc 处的指令，获取它执行完后所有可能的下一条指令的地址。无法读取指令时返回空
+fn next_pcs(vm: &MemorySet, cx: &TrapContext) -> Vec<usize> {
+    let pc = cx.sepc;
+    let reg = |idx: u32| if idx == 0 { 0 } else { cx.x[idx as usize] };
+    let mut buf = [0u8; 4];
+    if vm.read(pc, 2, &mut buf[..2], PTEFlags::USER).is_err() {
+        return Vec::new();
+    }
+    let mut targets = if buf[0] & 0b11 != 0b11 {
+        // 压缩指令
+        let inst = u16::from_le_bytes([buf[0], buf[1]]) as u32;
+        let quadrant = inst & 0b11;
+        let funct3 = (inst >> 13) & 0b111;
+        let rs1 = (inst >> 7) & 0x1f;
+        let rs2 = (inst >> 2) & 0x1f;
+        match (quadrant, funct3) {
+            // c.j
+            (1, 0b101) => {
+                let imm = (((inst >> 12) & 1) << 11)
+                    | (((inst >> 11) & 1) << 4)
+                    | (((inst >> 9) & 3) << 8)
+                    | (((inst >> 8) & 1) << 10)
+                    | (((inst >> 7) & 1) << 6)
+                    | (((inst >> 6) & 1) << 7)
+                    | (((inst >> 3) & 7) << 1)
+                    | (((inst >> 2) & 1) << 5);
+                vec![pc.wrapping_add(sign_extend(imm, 12))]
+            }
+            // c.beqz / c.bnez
+            (1, 0b110) | (1, 0b111) => {
+                let imm = (((inst >> 12) & 1) << 8)
+                    | (((inst >> 10) & 3) << 3)
+                    | (((inst >> 5) & 3) << 6)
+                    | (((inst >> 3) & 3) << 1)
+                    | (((inst >> 2) & 1) << 5);
+                vec![pc + 2, pc.wrapping_add(sign_extend(imm, 9))]
+            }
+            // c.jr / c.jalr
+            (2, 0b100) if rs2 == 0 && rs1 != 0 => vec![reg(rs1) & !1],
+            _ => vec![pc + 2],
+        }
+    } else {
+        if vm.read(pc + 2, 2, &mut buf[2..], PTEFlags::USER).is_err() {
+            return Vec::new();
+        }
+        let inst = u32::from_le_bytes(buf);
+        match inst & 0x7f {
+            // jal
+            0x6f => {
+                let imm = (((inst >> 31) & 1) << 20)
+                    | (((inst >> 21) & 0x3ff) << 1)
+                    | (((inst >> 20) & 1) << 11)
+                    | (((inst >> 12) & 0xff) << 12);
+                vec![pc.wrapping_add(sign_extend(imm, 21))]
+            }
+            // jalr
+            0x67 => vec![reg((inst >> 15) & 0x1f).wrapping_add(sign_extend(inst >> 20, 12)) & !1],
+            // 条件跳转
+            0x63 => {
+                let imm = (((inst >> 31) & 1) << 12)
+                    | (((inst >> 25) & 0x3f) << 5)
+                    | (((inst >> 8) & 0xf) << 1)
+                    | (((inst >> 7) & 1) << 11);
+                vec![pc + 4, pc.wrapping_add(sign_extend(imm, 13))]
+            }
+            _ => vec![pc + 4],
+        }
+    };
+    targets.dedup();
+    targets
+}
+
+/// clone 出新任务 child 后、将它加入调度器前调用，决定它是否也被当前任务的跟踪者跟踪。
+/// sends_sigchld 表示 child 退出时是否向父进程发送 SIGCHLD，用于区分 fork 和 clone。
+///
+/// - 当前任务设置了对应的 PTRACE_O_TRACEFORK / TRACEVFORK / TRACECLONE 选项时，返回需要报告的事件，
+///   调用者应在之后通过 `ptrace_event_stop` 报告它
+/// - 带有 CLONE_UNTRACED 时不跟踪新任务；带有 CLONE_PTRACE 时，即使没有设置选项也跟踪新任务
+///
+/// 被跟踪的新任务会先收到 SIGSTOP，从而在开始运行时就暂停
+pub fn ptrace_clone_child(
+    parent: &TaskControlBlock,
+    child: &TaskControlBlock,
+    flags: CloneFlags,
+    sends_sigchld: bool,
+) -> Option<usize> {
+    let inner = parent.inner.lock();
+    let state = inner.ptrace.as_ref()?;
+    let event = if flags.contains(CloneFlags::CLONE_VFORK) {
+        PTRACE_EVENT_VFORK
+    } else if sends_sigchld {
+        PTRACE_EVENT_FORK
+    } else {
+        PTRACE_EVENT_CLONE
+    };
+    let traced_by_option = state.options.bits() & (1 << event) != 0;
+    if flags.contains(CloneFlags::CLONE_UNTRACED)
+        || !(traced_by_option || flags.contains(CloneFlags::CLONE_PTRACE))
+    {
+        return None;
+    }
+    let tracer = state.tracer.upgrade()?;
+    let child_state = PtraceState::new(&tracer, state.options, state.seized);
+    drop(inner);
+    child.inner.lock().ptrace = Some(child_state);
+    send_signal(child.get_tid_num(), SignalNo::SIGSTOP as usize);
+    if traced_by_option {
+        Some(event)
+    } else {
+        None
+    }
+}
+
+/// 跟踪者 tracer 退出时调用，停止跟踪它跟踪的所有任务：
+/// 暂停的任务会被恢复运行；如果设置了 PTRACE_O_EXITKILL，则杀死被跟踪的任务
+pub fn release_tracees(tracer: &TaskControlBlock) {
+    let tracer_tid = tracer.get_tid_num();
+    for task in get_all_tasks() {
+        let mut inner = task.inner.lock();
+        let state = match inner.ptrace.as_mut() {
+            Some(state) if state.tracer_tid == tracer_tid => state,
+            _ => continue,
+        };
+        let exit_kill = state.options.contains(PtraceOptions::EXITKILL);
+        // 暂停的任务会自己停止跟踪，否则需要在这里写回断点
+        let breakpoints = if state.resume(PtraceResume::Detach, 0) {
+            Vec::new()
+        } else {
+            let breakpoints = core::mem::take(&mut state.step_breakpoints);
+            inner.ptrace = None;
+            breakpoints
+        };
+        drop(inner);
+        remove_breakpoints(&task, breakpoints);
+        if exit_kill {
+            task.send_signal_to_process(SignalNo::SIGKILL as usize);
+        }
+    }
+}
@@ -0,0 +1,123 @@
+//! 进程的资源限制，用于 sys_prlimit64 / sys_getrlimit / sys_setrlimit
+//!
+//! 同一线程组的所有线程共享一份资源限制，fork 时复制给子进程
+//!
+//! 目前会实际检查的限制有：
+//! - RLIMIT_CPU：在用户态的时钟中断中检查，见 `cpu_local.rs: check_cpu_time_limit()`
+//! - RLIMIT_FSIZE：在写文件的 syscall 中检查
+//! - RLIMIT_NPROC：在 clone 时检查
+//! - RLIMIT_NOFILE：修改时同步到 FdManager 中
+//! - RLIMIT_CORE：决定是否生成 core dump
+
+use crate::constants::{FD_LIMIT_ORIGIN, TID_LIMIT, USER_STACK_SIZE, USER_VIRT_ADDR_LIMIT};
+
+/// 一项资源的软上限和硬上限，也是 sys_prlimit64 使用的数组
+#[repr(C)]
+#[derive(Clone, Copy)]
+pub struct RLimit {
+    /// 软上限
+    pub rlim_cur: u64,
+    /// 硬上限
+    pub rlim_max: u64,
+}
+
+impl RLimit {
+    /// 软上限和硬上限相同的限制
+    const fn fixed(limit: u64) -> Self {
+        Self {
+            rlim_cur: limit,
+            rlim_max: limit,
+        }
+    }
+}
+
+/// 表示没有限制
+pub const RLIM_INFINITY: u64 = u64::MAX;
+
+// 资源的编号
+/// 进程可以使用的 CPU 时间，以秒为单位
+pub const RLIMIT_CPU: usize = 0;
+/// 进程可以创建的文件的最大大小
+pub const RLIMIT_FSIZE: usize = 1;
+/// 用户栈大小
+pub const RLIMIT_STACK: usize = 3;
+/// core dump 文件的最大大小，为 0 时不生成 core dump
+pub const RLIMIT_CORE: usize = 4;
+/// 同一实际用户 id 可以拥有的任务数
+pub const RLIMIT_NPROC: usize = 6;
+/// 可以打开的 fd 数
+pub const RLIMIT_NOFILE: usize = 7;
+/// 用户地址空间的最大大小
+pub const RLIMIT_AS: usize = 9;
+/// 资源的种类数
+pub const RLIM_NLIMITS: usize = 16;
+
+/// 进程的资源限制表
+pub struct ResourceLimits {
+    /// 每种资源的限制，下标为资源编号
+    limits: [RLimit; RLIM_NLIMITS],
+    /// 下一次发送 SIGXCPU 时已使用的 CPU 时间(秒)。
+    /// 为 None 时表示还没有超过 RLIMIT_CPU 的软上限，超过后每过一秒发送一次。
+    /// 单独记录而不修改软上限，用户读取到的限制始终是设置的值
+    next_sigxcpu_sec: Option<u64>,
+}
+
+impl Clone for ResourceLimits {
+    /// fork 出的子进程 CPU 时间从 0 开始计算，所以 SIGXCPU 的时间点不继承
+    fn clone(&self) -> Self {
+        Self {
+            limits: self.limits,
+            next_sigxcpu_sec: None,
+        }
+    }
+}
+
+impl Default for ResourceLimits {
+    /// 初始进程的资源限制。除了下面列出的几项，其余资源都没有限制
+    fn default() -> Self {
+        let mut limits = [RLimit::fixed(RLIM_INFINITY); RLIM_NLIMITS];
+        limits[RLIMIT_STACK] = RLimit::fixed(USER_STACK_SIZE as u64);
+        limits[RLIMIT_CORE] = RLimit {
+            rlim_cur: 0,
+            rlim_max: RLIM_INFINITY,
+        };
+        limits[RLIMIT_NPROC] = RLimit::fixed(TID_LIMIT as u64);
+        limits[RLIMIT_NOFILE] = RLimit {
+            rlim_cur: FD_LIMIT_ORIGIN as u64,
+            rlim_max: base_file::FD_LIMIT_HARD as u64,
+        };
+        limits[RLIMIT_AS] = RLimit::fixed(USER_VIRT_ADDR_LIMIT as u64);
+        Self {
+            limits,
+            next_sigxcpu_sec: None,
+        }
+    }
+}
+
+impl ResourceLimits {
+    /// 获取编号为 resource 的资源的限制。调用者需保证 resource < RLIM_NLIMITS
+    pub fn get(&self, resource: usize) -> RLimit {
+        self.limits[resource]
+    }
+    /// 获取编号为 resource 的资源的软上限
+    pub fn get_soft(&self, resource: usize) -> u64 {
+        self.limits[resource].rlim_cur
+    }
+    /// 修改编号为 resource 的资源的限制。调用者需保证 resource < RLIM_NLIMITS，
+    /// 且已经检查过新的限制是合法的
+    pub fn set(&mut self, resource: usize, limit: RLimit) {
+        self.limits[resource] = limit;
+        if resource == RLIMIT_CPU {
+            self.next_sigxcpu_sec = None;
+        }
+    }
+    /// 已使用的 CPU 时间达到多少秒时发送下一次 SIGXCPU
+    pub fn next_sigxcpu_sec(&self) -> u64 {
+        self.next_sigxcpu_sec
+            .unwrap_or(self.limits[RLIMIT_CPU].rlim_cur)
+    }
+    /// 在已使用 used_sec 秒 CPU 时间时发送了 SIGXCPU，下一次推后一秒
+    pub fn delay_sigxcpu(&mut self, used_sec: u64) {
+        self.next_sigxcpu_sec = Some(used_sec + 1);
+    }
+}
@@ -1,6 +1,6 @@
 use super::{TaskControlBlock, ORIGIN_USER_PROC};
 use crate::{arch::get_cpu_id, constants::IS_TEST_ENV, file::load_next_testcase};
-use alloc::{collections::VecDeque, sync::Arc};
+use alloc::{collections::VecDeque, sync::Arc, vec::Vec};
 use lock::Mutex;
 
 lazy_static::lazy_static! {
@@ -22,6 +22,8 @@ lazy_static::lazy_static! {
 /// 在 struct 外部会加一个 Mutex 锁
 pub struct Scheduler {
     ready_queue: VecDeque<Arc<TaskControlBlock>>,
+    /// 所在进程被信号暂停的任务。它们不在队列中，直到进程收到 SIGCONT 或 SIGKILL 才被放回
+    stopped_tasks: Vec<Arc<TaskControlBlock>>,
 }
 
 impl Scheduler {
@@ -29,6 +31,7 @@ impl Scheduler {
     pub fn new() -> Self {
         Self {
             ready_queue: VecDeque::new(),
+            stopped_tasks: Vec::new(),
         }
     }
     /// 添加一个任务到队列中
@@ -50,6 +53,32 @@ pub fn push_task_to_scheduler(task: Arc<TaskControlBlock>) {
     GLOBAL_TASK_SCHEDULER.lock().push(task)
 }
 
+/// 将所在进程被暂停的任务放入暂停列表，它不会再被取出，直到调用 `wake_stopped_tasks`。
+///
+/// 检查进程是否暂停和放入列表都在调度器的锁内完成，所以不会错过在此期间让进程继续执行的信号。
+/// 如果进程此时已经不再暂停，则直接放回队列
+pub fn push_stopped_task_to_scheduler(task: Arc<TaskControlBlock>) {
+    let mut scheduler = GLOBAL_TASK_SCHEDULER.lock();
+    if task.is_stopped() {
+        scheduler.stopped_tasks.push(task);
+    } else {
+        scheduler.push(task);
+    }
+}
+
+/// 将进程 pid 中被暂停的任务全部放回任务队列。
+/// 需要在进程不再暂停之后调用，且调用时不能持有线程组的锁
+pub fn wake_stopped_tasks(pid: usize) {
+    let mut scheduler = GLOBAL_TASK_SCHEDULER.lock();
+    let (woken, stopped): (Vec<_>, Vec<_>) = core::mem::take(&mut scheduler.stopped_tasks)
+        .into_iter()
+        .partition(|task| task.pid == pid);
+    scheduler.stopped_tasks = stopped;
+    for task in woken {
+        scheduler.push(task);
+    }
+}
+
 /// 从任务队列中拿一个任务，返回其TCB。
 /// 非阻塞，即如果没有任务可取，则直接返回 None
 pub fn fetch_task_from_scheduler() -> Option<Arc<TaskControlBlock>> {
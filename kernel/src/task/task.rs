@@ -3,22 +3,39 @@
 
 //#![deny(missing_docs)]
 
-use super::{CloneFlags, KernelStack, TaskContext, TimeStat};
+use super::{
+    add_task_count, get_task_from_tid, global_logoff_task, global_register_task,
+    wake_stopped_tasks, CloneFlags, Credentials, KernelStack, PtraceState, ResourceLimits,
+    TaskContext, ThreadGroup, TimeStat,
+};
 use crate::{
-    arch::get_cpu_id,
-    constants::{NO_PARENT, USER_STACK_OFFSET},
-    file::{check_file_exists, BackEndFile, FdManager},
-    loaders::parse_user_app,
+    arch::{get_cpu_id, ipi::tlb_shootdown},
+    constants::{
+        ALL_CPU_MASK, NO_PARENT, ORIGIN_USER_PROC_ENVS, SIGSET_SIZE_IN_BIT, TASK_COMM_LEN,
+        USER_STACK_OFFSET, USER_STACK_RED_ZONE,
+    },
+    file::{check_file_exists, get_file_perm, BackEndFile, FdManager},
+    loaders::{parse_user_app, UserEnv},
     memory::{new_memory_set_for_task, phys_to_virt, MemorySet, PTEFlags, Tid, VirtAddr},
-    signal::{global_register_signals, SignalHandlers, SignalReceivers, SignalUserContext},
-    trap::TrapContext,
+    signal::{
+        get_signals_from_tid, global_logoff_signals, global_register_signals, send_process_siginfo,
+        send_signal, SigActionDefault, SigActionFlags, SigInfo, SignalHandlers, SignalNo,
+        SignalReceivers, SignalStack, SignalUserContext, CLD_CONTINUED, CLD_STOPPED, SIG_IGN,
+        SS_AUTODISARM,
+    },
+    syscall::{RestartBlock, SyscallTrace},
+    trap::{FpContext, TrapContext},
 };
 use alloc::{
     string::String,
     sync::{Arc, Weak},
     vec::Vec,
 };
+use base_file::StMode;
+use bitset::Bitset;
 use lock::Mutex;
+use riscv::register::sstatus::FS;
+use syscall::ErrorNo;
 
 /// 任务控制块，包含一个用户程序的所有状态信息，但不包括与调度有关的信息。
 /// 默认在TCB的外层对其的访问不会冲突，所以外部没有用锁保护，内部的 mutex 仅用来提供可变性
@@ -32,29 +49,39 @@ pub struct TaskControlBlock {
     pub kernel_stack: KernelStack,
     /// 进程 id。创建任务时实际分配的是 tid 而不是 pid，所以没有对应的 Pid 结构保护
     pub pid: usize,
-    /// 线程 id
-    pub tid: Tid,
-    /// 当退出时是否向父进程发送信号 SIGCHLD。
-    /// 如果创建时带 CLONE_THREAD 选项，则不发送信号，除非它是线程组(即拥有相同pid的所有线程)中最后一个退出的线程；
-    /// 否则发送信号
-    pub send_sigchld_when_exit: bool,
+    /// 线程 id。execve 时非组长线程会接替组长的 tid，所以是可变的，应通过 get_tid_num 获取
+    tid: Mutex<Tid>,
+    /// 所在的线程组。同一线程组(即拥有相同pid)的所有线程共享这个结构
+    pub thread_group: Arc<Mutex<ThreadGroup>>,
     /// 信号量对应的一组处理函数。
     /// 因为发送信号是通过 pid/tid 查找的，因此放在 inner 中一起调用时更容易导致死锁
     pub signal_handlers: Arc<Mutex<SignalHandlers>>,
     /// 接收信号的结构。每个线程中一定是独特的，而上面的 handler 可能是共享的
     pub signal_receivers: Arc<Mutex<SignalReceivers>>,
-    /// 任务的内存段(内含页表)，同时包括用户态和内核态
-    pub vm: Arc<Mutex<MemorySet>>,
+    /// 任务的内存段(内含页表)，同时包括用户态和内核态。
+    /// exec 时会换成新的地址空间(原来的可能与其他任务共享，如 vfork 出的子进程)，所以外面多套一层锁。
+    /// 应通过 get_vm 获取
+    vm: Mutex<Arc<Mutex<MemorySet>>>,
     /// 管理进程的所有文件描述符
     pub fd_manager: Arc<Mutex<FdManager>>,
+    /// 用户和用户组凭证。同一线程组的所有线程共享
+    pub credentials: Arc<Mutex<Credentials>>,
+    /// 资源限制。同一线程组的所有线程共享
+    pub rlimits: Arc<Mutex<ResourceLimits>>,
     /// 任务的运行时间信息
     pub time: Mutex<TimeStat>,
+    /// 允许运行任务的 cpu 组成的掩码，第 i 位表示编号为 i 的 cpu。clone 时继承
+    pub cpu_mask: Mutex<usize>,
+    /// 系统调用跟踪记录。只有被 `/proc/sys/kernel/syscall_trace` 选中的进程才有，参见 syscall/trace.rs
+    pub syscall_trace: Mutex<Option<SyscallTrace>>,
     /// 任务的状态信息
     pub inner: Arc<Mutex<TaskControlBlockInner>>,
 }
 
 /// 任务控制块的可变部分
 pub struct TaskControlBlockInner {
+    /// 线程名，exec 时设为程序文件名，也可以通过 prctl 修改。最长 TASK_COMM_LEN - 1 字节
+    pub comm: String,
     /// 用户程序当前的工作目录
     /// - 注意 dir\[0\] == '.' ，如以 ./ 开头时代表根目录，以 "./abc/" 开头代表根目录下的abc目录。
     /// 这样处理是因为 open_file 时先打开文件所在目录，它的实现是先打开根目录，再从根目录找相对路径
@@ -63,6 +90,10 @@ pub struct TaskControlBlockInner {
     /// - 因为拿到 Pid 代表“拥有”这个 id 且 Drop 时会自动释放，所以此处用 usize 而不是 Pid。
     /// - 又因为它可能会在父进程结束时被修改为初始进程，所以是可变的。
     pub ppid: usize,
+    /// 进程组 id。新进程默认继承父进程的进程组
+    pub pgid: usize,
+    /// 会话 id。新进程默认继承父进程的会话
+    pub sid: usize,
     /// 用户堆的堆顶。
     /// 用户堆和用户栈共用空间，反向增长，即从 USER_STACK_OFFSET 开始往上增加。
     /// 本来不应该由内存记录的，但 brk() 系统调用要用
@@ -78,18 +109,58 @@ pub struct TaskControlBlockInner {
     pub children: Vec<Arc<TaskControlBlock>>,
     /// sys_exit 时输出的值
     pub exit_code: i32,
+    /// 如果任务是被信号终止的，记录这个信号的编号，否则为 0
+    pub term_signal: usize,
+    /// 被信号暂停后，还未被 wait 报告的暂停信号编号。没有则为 0
+    pub stop_signal_to_report: usize,
+    /// 被 SIGCONT 恢复执行后，是否还未被 wait 报告
+    pub continued_to_report: bool,
+    /// 父进程退出时向自己发送的信号，由 prctl 设置。为 0 时不发送
+    pub pdeath_signal: usize,
+    /// 是否已经成功执行过 execve。父进程不能再通过 setpgid 修改已 exec 的子进程的进程组
+    pub did_exec: bool,
     /// 子线程初始化时，存放 tid 的地址。当且仅当创建时包含 CLONE_CHILD_SETTID 才非0
     pub set_child_tid: usize,
     /// 子线程初始化时，将这个地址清空；子线程退出时，触发这里的 futex。
     /// 在创建时包含 CLONE_CHILD_SETTID 时才非0，但可以被 sys_set_tid_address 修改
     pub clear_child_tid: usize,
-    /// 处理信号时，保存的之前的用户线程的上下文信息
-    trap_cx_before_signal: Option<TrapContext>,
-    /// 保存信息时，处理函数是否设置了 SIGINFO 选项
+    /// 被跟踪时的状态，没有被跟踪时为 None
+    pub ptrace: Option<PtraceState>,
+    /// 被信号打断的系统调用通过 restart_syscall 继续执行时需要的状态
+    pub restart_block: Option<RestartBlock>,
+    /// sigaltstack 设置的备用信号栈
+    pub signal_stack: SignalStack,
+    /// 处理信号时保存的状态。最后一项对应最内层的信号处理函数
+    signal_frames: Vec<SignalFrame>,
+    /// 用户程序的浮点寄存器。只在切换走任务或者需要读写时才从寄存器中保存，所以不一定是最新的
+    pub fp_context: FpContext,
+    /// 最近一次把 fp_context 恢复到了哪个核的浮点寄存器上。为 None 时下次切换到任务时一定要恢复
+    fp_cpu: Option<usize>,
+}
+
+/// 进入信号处理函数前保存的状态，sigreturn 时恢复。
+///
+/// 通常处理函数返回前不会再进入其他处理函数，但异常信号可以在其他信号的处理函数中嵌套进入自己的处理函数，
+/// 所以每个任务保存的是一个栈
+struct SignalFrame {
+    /// 正在处理的信号
+    signum: usize,
+    /// 之前的用户线程的上下文信息
+    trap_cx: TrapContext,
+    /// 信号处理函数可以使用的用户栈范围，依次为最低地址和进入处理函数时的 sp。
+    /// 用户 sp 离开这个范围时，说明处理函数通过 siglongjmp 跳出了，不会再调用 sigreturn
+    handler_sp: Option<(usize, usize)>,
+    /// 处理函数是否设置了 SIGINFO 选项
     /// 如果设置了，说明信号触发前的上下文信息通过 ucontext 传递给了用户，
     /// 此时用户可能修改其中的 pc 信息(如musl-libc 的 pthread_cancel 函数)。
     /// 在这种情况下，需要手动在 sigreturn 时更新已保存的上下文信息
-    signal_set_siginfo: bool,
+    set_siginfo: bool,
+    /// 之前的信号掩码
+    mask: Bitset,
+    /// 之前的备用信号栈。设置了 SS_AUTODISARM 时，sigreturn 需要用它恢复
+    signal_stack: SignalStack,
+    /// 之前的浮点寄存器
+    fp_context: FpContext,
 }
 
 unsafe impl Send for TaskControlBlockInner {}
@@ -107,7 +178,7 @@ impl TaskControlBlock {
     ///
     /// 目前只有初始进程(/task/mod.rs: ORIGIN_USER_PROC) 直接通过这个函数初始化，
     /// 其他进程应通过 clone / exec 生成
-    pub fn from_app_name(app_dir: &str, ppid: usize, args: Vec<String>) -> Option<Self> {
+    pub fn from_app_name(app_dir: &str, ppid: usize, args: Vec<String>) -> Option<Arc<Self>> {
         if args.len() < 1 {
             // 需要至少有一项指定文件名
             return None;
@@ -119,8 +190,16 @@ impl TaskControlBlock {
         }
         // 新建页表，包含内核段
         let mut vm = new_memory_set_for_task().unwrap();
+        let env = UserEnv {
+            envs: ORIGIN_USER_PROC_ENVS
+                .iter()
+                .map(|&env| String::from(env))
+                .collect(),
+            execfn: app_name_string.clone(),
+            credentials: Credentials::default(),
+        };
         // 找到用户名对应的文件，将用户地址段信息插入页表和 VmArea
-        parse_user_app(app_dir, app_name, &mut vm, args)
+        parse_user_app(app_dir, app_name, &mut vm, args, env)
             .map(|(user_entry, user_stack)| {
                 //println!("user MemorySet {:#x?}", vm);
                 // 初始化内核栈，它包含关于进入用户程序的所有信息
@@ -132,64 +211,95 @@ impl TaskControlBlock {
                 let stack_top = kernel_stack
                     .push_first_context(TrapContext::app_init_context(user_entry, user_stack));
                 let signal_handlers = Arc::new(Mutex::new(SignalHandlers::new()));
-                let signal_receivers = Arc::new(Mutex::new(SignalReceivers::new()));
+                let signal_receivers = Arc::new(Mutex::new(SignalReceivers::new(None)));
                 global_register_signals(tid.0, signal_receivers.clone());
                 //println!("tid = {}", tid.0);
-                TaskControlBlock {
+                let new_tcb = Arc::new(TaskControlBlock {
                     kernel_stack: kernel_stack,
                     pid: pid,
-                    tid: tid,
-                    send_sigchld_when_exit: true,
+                    tid: Mutex::new(tid),
+                    thread_group: Arc::new(Mutex::new(ThreadGroup::new(
+                        pid,
+                        SignalNo::SIGCHLD as usize,
+                    ))),
                     signal_handlers: signal_handlers,
                     signal_receivers: signal_receivers,
-                    vm: Arc::new(Mutex::new(vm)),
+                    vm: Mutex::new(Arc::new(Mutex::new(vm))),
                     fd_manager: Arc::new(Mutex::new(FdManager::new(0))), // 初始 umask 设为 0
+                    credentials: Arc::new(Mutex::new(Credentials::default())),
+                    rlimits: Arc::new(Mutex::new(ResourceLimits::default())),
                     time: Mutex::new(TimeStat::new(tid_raw)),
+                    cpu_mask: Mutex::new(ALL_CPU_MASK),
+                    syscall_trace: Mutex::new(SyscallTrace::new_if_selected(
+                        pid,
+                        &comm_from_path(app_name),
+                    )),
                     inner: Arc::new(Mutex::new(TaskControlBlockInner {
+                        comm: comm_from_path(app_name),
                         dir: String::from(app_dir),
                         ppid: ppid,
+                        pgid: pid,
+                        sid: pid,
                         user_heap_top: USER_STACK_OFFSET,
                         task_cx: TaskContext::goto_restore(stack_top),
                         task_status: TaskStatus::Ready,
                         parent: None,
                         children: Vec::new(),
                         exit_code: 0,
+                        term_signal: 0,
+                        stop_signal_to_report: 0,
+                        continued_to_report: false,
+                        pdeath_signal: 0,
+                        did_exec: false,
                         set_child_tid: 0,
                         clear_child_tid: 0,
-                        trap_cx_before_signal: None,
-                        signal_set_siginfo: false,
+                        ptrace: None,
+                        restart_block: None,
+                        signal_stack: SignalStack::default(),
+                        signal_frames: Vec::new(),
+                        fp_context: FpContext::default(),
+                        fp_cpu: None,
                     })),
-                }
+                });
+                global_register_task(&new_tcb);
+                add_task_count(new_tcb.credentials.lock().uid.real, 1);
+                new_tcb
             })
             .ok()
     }
     /// 从 clone 系统调用初始化一个TCB，并设置子进程对用户程序的返回值为0。
     ///
     /// - 参数 user_stack 为是否指定用户栈地址。如为 None，则沿用同进程的栈，否则使用该地址。由用户保证这个地址是有效的。
-    /// - send_sigchld_when_exit 参见 TaskControlBlock 定义说明
+    /// - exit_signal 为新进程退出时向父进程发送的信号，为 0 时不发送。创建线程时不使用，
+    ///   因为线程退出时不发送信号，整个线程组退出时使用的是组长线程创建时的值
     /// - flags 参见 clone_flags.rs
     /// - tls 为新任务的 tp 值，当包含 CLONE_SETTLS 时设置
     /// - ptid 为当前任务地址空间中的地址，当包含 CLONE_PARENT_SETTID 时，新任务 tid 被存入此处
     /// - ctid 为新任务地址空间中的地址，当包含 CLONE_CHILD_SETTID 时，新任务 tid 被存入此处
+    /// - tid 为调用者指定的新任务 tid(如 clone3 的 set_tid)，如为 None，则自动分配
     ///
     /// 这里只把父进程内核栈栈底的第一个 TrapContext 复制到子进程，
     /// 所以**必须保证对这个函数的调用是来自用户异常中断，而不是内核异常中断**。因为只有这时内核栈才只有一层 TrapContext。
     pub fn from_clone(
         self: &Arc<TaskControlBlock>,
         user_stack: Option<usize>,
-        send_sigchld_when_exit: bool,
+        exit_signal: usize,
         flags: CloneFlags,
         tls: usize,
         ptid: usize,
         ctid: usize,
+        tid: Option<Tid>,
     ) -> Arc<Self> {
         // println!("start clone");
         let mut inner = self.inner.lock();
+        // 浮点寄存器可能还没保存，先保存下来再复制给子任务
+        self.save_dirty_fp(&mut inner);
         // 是否共享 MemorySet
+        let self_vm = self.get_vm();
         let vm = if flags.contains(CloneFlags::CLONE_VM) {
-            self.vm.clone()
+            self_vm.clone()
         } else {
-            Arc::new(Mutex::new(self.vm.lock().copy_as_fork().unwrap()))
+            Arc::new(Mutex::new(self_vm.lock().copy_as_fork().unwrap()))
         };
         // 是否共享文件描述符
         let fd_manager = if flags.contains(CloneFlags::CLONE_FILES) {
@@ -197,6 +307,18 @@ impl TaskControlBlock {
         } else {
             Arc::new(Mutex::new(self.fd_manager.lock().copy_all()))
         };
+        // 同一线程组共享凭证，否则复制一份
+        let credentials = if flags.contains(CloneFlags::CLONE_THREAD) {
+            self.credentials.clone()
+        } else {
+            Arc::new(Mutex::new(self.credentials.lock().clone()))
+        };
+        // 资源限制同理
+        let rlimits = if flags.contains(CloneFlags::CLONE_THREAD) {
+            self.rlimits.clone()
+        } else {
+            Arc::new(Mutex::new(self.rlimits.lock().clone()))
+        };
         // 是否共享信号处理函数
         let new_signal_handlers = if flags.contains(CloneFlags::CLONE_SIGHAND) {
             self.signal_handlers.clone()
@@ -204,19 +326,43 @@ impl TaskControlBlock {
             // 如果不共享，也要复制整个模块的值，只是不一起更新
             Arc::new(Mutex::new(self.signal_handlers.lock().clone()))
         };
-        let tid = Tid::new().unwrap();
+        let tid = tid.unwrap_or_else(|| Tid::new().unwrap());
         let tid_raw = tid.0;
         let pid = if flags.contains(CloneFlags::CLONE_THREAD) {
             self.pid
         } else {
             tid.0
         };
-        let ppid = if flags.contains(CloneFlags::CLONE_PARENT) {
+        // 同一线程组的线程的父进程是相同的，所以 CLONE_THREAD 和 CLONE_PARENT 一样沿用当前任务的父进程
+        let share_parent =
+            flags.contains(CloneFlags::CLONE_PARENT) || flags.contains(CloneFlags::CLONE_THREAD);
+        let ppid = if share_parent {
             inner.ppid
         } else {
-            self.tid.0
+            self.get_tid_num()
+        };
+        let parent = if share_parent {
+            inner.parent.clone()
+        } else {
+            Some(Arc::downgrade(self))
         };
-        let signal_receivers = Arc::new(Mutex::new(SignalReceivers::new()));
+        // 是否加入当前线程组
+        // 同时把新任务计入它的实际用户 id 下。
+        // 加入线程组和计数需在凭证锁内完成，以免与修改实际用户 id 的 setuid 交错
+        let thread_group = if flags.contains(CloneFlags::CLONE_THREAD) {
+            let credentials = credentials.lock();
+            self.thread_group.lock().add_member(tid_raw);
+            add_task_count(credentials.uid.real, 1);
+            self.thread_group.clone()
+        } else {
+            add_task_count(credentials.lock().uid.real, 1);
+            Arc::new(Mutex::new(ThreadGroup::new(tid_raw, exit_signal)))
+        };
+        // 同一进程的线程共享发给进程的信号队列
+        let shared_pending = flags
+            .contains(CloneFlags::CLONE_THREAD)
+            .then(|| self.signal_receivers.lock().shared_pending.clone());
+        let signal_receivers = Arc::new(Mutex::new(SignalReceivers::new(shared_pending)));
         // 存入全局表中的 signals 是只复制指针
         global_register_signals(tid.0, signal_receivers.clone());
 
@@ -232,7 +378,7 @@ impl TaskControlBlock {
         // 检查是否在父任务地址中写入 tid
         if flags.contains(CloneFlags::CLONE_PARENT_SETTID) {
             // 有可能这个地址是 lazy alloc 的，需要先检查
-            if self.vm.lock().manually_alloc_page(ptid).is_ok() {
+            if self_vm.lock().manually_alloc_page(ptid).is_ok() {
                 unsafe {
                     *(ptid as *mut i32) = tid.0 as i32;
                 }
@@ -243,7 +389,7 @@ impl TaskControlBlock {
         {
             // 复制地址空间时就可以直接在当前地址空间下操作
             if flags.contains(CloneFlags::CLONE_VM) {
-                if self.vm.lock().manually_alloc_page(ctid).is_ok() {
+                if self_vm.lock().manually_alloc_page(ctid).is_ok() {
                     unsafe {
                         *(ctid as *mut i32) = if flags.contains(CloneFlags::CLONE_CHILD_SETTID) {
                             tid.0 as i32
@@ -284,24 +430,36 @@ impl TaskControlBlock {
         let dir = String::from(&inner.dir[..]);
         let new_tcb = Arc::new(TaskControlBlock {
             pid: pid,
-            tid: tid,
-            send_sigchld_when_exit: send_sigchld_when_exit,
+            tid: Mutex::new(tid),
+            thread_group: thread_group,
             kernel_stack: kernel_stack,
             signal_handlers: new_signal_handlers,
             signal_receivers: signal_receivers,
-            vm: vm,
+            vm: Mutex::new(vm),
             fd_manager: fd_manager,
+            credentials: credentials,
+            rlimits: rlimits,
             time: Mutex::new(TimeStat::new(tid_raw)), // fork 出的任务不继承时间
+            cpu_mask: Mutex::new(*self.cpu_mask.lock()),
+            syscall_trace: Mutex::new(SyscallTrace::new_if_selected(pid, &inner.comm)),
             inner: {
                 Arc::new(Mutex::new(TaskControlBlockInner {
+                    comm: inner.comm.clone(),
                     dir: dir,
                     ppid: ppid,
+                    pgid: inner.pgid,
+                    sid: inner.sid,
                     user_heap_top: USER_STACK_OFFSET,
                     task_cx: TaskContext::goto_restore(stack_top),
                     task_status: TaskStatus::Ready,
-                    parent: Some(Arc::downgrade(self)),
+                    parent: parent,
                     children: Vec::new(),
                     exit_code: 0,
+                    term_signal: 0,
+                    stop_signal_to_report: 0,
+                    continued_to_report: false,
+                    pdeath_signal: 0,
+                    did_exec: false,
                     set_child_tid: if flags.contains(CloneFlags::CLONE_CHILD_SETTID) {
                         ctid
                     } else {
@@ -312,8 +470,20 @@ impl TaskControlBlock {
                     } else {
                         0
                     },
-                    trap_cx_before_signal: None,
-                    signal_set_siginfo: false,
+                    ptrace: None,
+                    restart_block: None,
+                    // 共享地址空间的新线程不能沿用父线程的备用信号栈
+                    signal_stack: if flags.contains(CloneFlags::CLONE_VM)
+                        && !flags.contains(CloneFlags::CLONE_VFORK)
+                    {
+                        SignalStack::default()
+                    } else {
+                        inner.signal_stack
+                    },
+                    signal_frames: Vec::new(),
+                    // 子任务继承浮点寄存器，切换到它时再恢复
+                    fp_context: inner.fp_context,
+                    fp_cpu: None,
                 }))
             },
         });
@@ -322,9 +492,11 @@ impl TaskControlBlock {
         let ec = new_tcb.inner.lock().exit_code;
         // drop(inner);
         info!("new tcb pid {} exit_code {}", p_id, ec);
-        if !flags.contains(CloneFlags::CLONE_PARENT) {
+        // 同一线程组中的其他线程不是子进程，不能被 wait 回收
+        if !share_parent {
             inner.children.push(new_tcb.clone());
         }
+        global_register_task(&new_tcb);
         //info!("end clone");
         new_tcb
     }
@@ -334,21 +506,45 @@ impl TaskControlBlock {
     /// 2. 修改内核栈栈底的第一个 TrapContext 为新的用户程序的入口
     /// 3. 将传入的 args 作为用户程序执行时的参数
     ///
-    /// 如找不到对应的用户程序，则不修改当前进程且返回 False。
+    /// 如找不到对应的用户程序，则不修改当前进程且返回 ENOENT。
+    /// 如果加载失败，此时原来的地址空间已经被清空，无法再回到原来的程序，
+    /// 所以与 Linux 相同，向当前线程发送 SIGSEGV 并返回 ENOEXEC，进程会在回到用户态之前被结束
     ///
     /// 注意 exec 不会清空用户程序执行的时间
-    pub fn exec(&self, app_name: &str, args: Vec<String>) -> bool {
+    pub fn exec(
+        &self,
+        app_name: &str,
+        args: Vec<String>,
+        envs: Vec<String>,
+    ) -> Result<(), ErrorNo> {
         let mut inner = self.inner.lock();
         if !check_file_exists(inner.dir.as_str(), app_name) {
-            return false;
+            return Err(ErrorNo::ENOENT);
+        }
+        // 根据文件的 set-user-ID / set-group-ID 位计算新程序的凭证
+        let mut credentials = self.credentials.lock().clone();
+        let mut perm = get_file_perm(inner.dir.as_str(), app_name);
+        // 被跟踪时忽略这两个位，否则跟踪者可以借此控制有特权的程序
+        if inner.ptrace.is_some() {
+            perm.mode &= !(StMode::S_ISUID | StMode::S_ISGID).bits();
         }
+        credentials.apply_exec(&perm);
         // 清空用户堆
         inner.user_heap_top = USER_STACK_OFFSET;
-        // 清空 MemorySet 中用户段的地址
-        self.vm.lock().clear_user_pages_and_save_kernel();
+        // 换成一个新的地址空间，而不是清空原来的，因为它可能是和其他任务共享的(如 vfork 出的子进程和父进程)。
+        // 原来的地址空间的页表还在使用中，切换到新的页表之后才能释放
+        let old_vm = core::mem::replace(
+            &mut *self.vm.lock(),
+            Arc::new(Mutex::new(new_memory_set_for_task().unwrap())),
+        );
+        unsafe {
+            self.get_vm().lock().activate();
+        }
+        drop(old_vm);
         // 清空信号模块
         self.signal_handlers.lock().clear();
         self.signal_receivers.lock().clear();
+        inner.signal_stack = SignalStack::default();
         // 清空时间统计
         self.time.lock().clear();
         // 处理 fd 中需要在 exec 时关闭的文件
@@ -369,9 +565,30 @@ impl TaskControlBlock {
 
         // 然后把新的信息插入页表和 VmArea
         let dir = String::from(&inner.dir[..]);
-        let mut self_vm = self.vm.lock();
-        parse_user_app(dir.as_str(), app_name, &mut self_vm, args)
+        let vm = self.get_vm();
+        let mut self_vm = vm.lock();
+        let env = UserEnv {
+            envs,
+            execfn: String::from(app_name),
+            credentials: credentials.clone(),
+        };
+        let loaded = parse_user_app(dir.as_str(), app_name, &mut self_vm, args, env)
             .map(|(user_entry, user_stack)| {
+                // 通过 set-user-ID / set-group-ID 位获得了新的权限时，不再接收父进程退出的信号
+                let old_credentials = self.credentials.lock().clone();
+                if old_credentials.uid.effective != credentials.uid.effective
+                    || old_credentials.gid.effective != credentials.gid.effective
+                {
+                    inner.pdeath_signal = 0;
+                }
+                *self.credentials.lock() = credentials;
+                inner.comm = comm_from_path(app_name);
+                inner.did_exec = true;
+                // 换了程序名，可能从此开始需要跟踪。已经在跟踪的则继续跟踪
+                if self.syscall_trace.lock().is_none() {
+                    *self.syscall_trace.lock() =
+                        SyscallTrace::new_if_selected(self.pid, &inner.comm);
+                }
                 // 修改完 MemorySet 映射后要 flush 一次
                 self_vm.flush_tlb();
                 //println!("user vm {:#x?}", inner.vm);
@@ -386,6 +603,9 @@ impl TaskControlBlock {
                             user_entry, user_stack, argc, argv,
                         ));
                 inner.task_cx = TaskContext::goto_restore(stack_top);
+                // 新程序的浮点寄存器从全 0 开始
+                inner.fp_context = FpContext::default();
+                inner.fp_context.restore();
 
                 let trap_context = unsafe { *self.kernel_stack.get_first_context() };
                 debug!(
@@ -395,7 +615,16 @@ impl TaskControlBlock {
                     trap_context.sstatus.bits()
                 );
             })
-            .is_ok()
+            .is_ok();
+        if loaded {
+            Ok(())
+        } else {
+            // 发送信号前需要先释放锁
+            drop(self_vm);
+            drop(inner);
+            send_signal(self.get_tid_num(), SignalNo::SIGSEGV as usize);
+            Err(ErrorNo::ENOEXEC)
+        }
     }
 
     /// 映射一段内存地址到文件或设备。
@@ -410,22 +639,48 @@ impl TaskControlBlock {
         backend: Option<BackEndFile>,
         anywhere: bool,
     ) -> Option<usize> {
-        self.vm
-            .lock()
-            .push_with_backend(start, end, flags, backend, anywhere)
-            .ok()
+        let vm = self.get_vm();
+        let mut vm = vm.lock();
+        // 映射到新的地址时，原来的页表项都是无效的，其他核的 TLB 中不会有它们，不需要清空。
+        // 只有 MAP_FIXED 覆盖了已有的映射时，才需要像 munmap 一样清空其他核的 TLB
+        let replace_existing = !anywhere && vm.is_overlap_with(start, end);
+        let ret = vm.push_with_backend(start, end, flags, backend, anywhere);
+        let root_paddr = vm.pt.get_root_paddr();
+        let released_frames = vm.pt.take_released_frames();
+        drop(vm);
+        if replace_existing {
+            tlb_shootdown(root_paddr);
+        }
+        // 被覆盖的映射中的页帧要等其他核都清空 TLB 后才能释放
+        drop(released_frames);
+        ret.ok()
     }
     /// 取消一段内存地址映射
     pub fn munmap(&self, start: VirtAddr, end: VirtAddr) -> bool {
-        self.vm.lock().munmap(start, end)
+        self.modify_vm(|vm| vm.munmap(start, end))
     }
     /// 修改一段内存映射的权限
     pub fn mprotect(&self, start: VirtAddr, end: VirtAddr, new_flags: PTEFlags) -> bool {
-        self.vm.lock().mprotect(start, end, new_flags)
+        self.modify_vm(|vm| vm.mprotect(start, end, new_flags))
+    }
+    /// 修改地址空间中已有的映射，然后清空所有正在使用这个地址空间的核的 TLB。
+    ///
+    /// 等待其他核清空 TLB 时不能持有 vm 的锁，否则可能与正在等这把锁的核互相等待。
+    /// 被删除的映射中的页帧在其他核都清空 TLB 后才释放，否则它们还可能通过旧的映射访问已经被重新分配的页帧
+    fn modify_vm<T>(&self, op: impl FnOnce(&mut MemorySet) -> T) -> T {
+        let vm = self.get_vm();
+        let mut vm = vm.lock();
+        let ret = op(&mut vm);
+        let root_paddr = vm.pt.get_root_paddr();
+        let released_frames = vm.pt.take_released_frames();
+        drop(vm);
+        tlb_shootdown(root_paddr);
+        drop(released_frames);
+        ret
     }
     /// 将一段区域中的数据同步到和其对应的文件中，返回给定区间是否至少和一个mmap的区间相交
     pub fn msync(&self, start: VirtAddr, end: VirtAddr) -> bool {
-        self.vm.lock().msync_areas(start, end).is_ok()
+        self.get_vm().lock().msync_areas(start, end).is_ok()
     }
     /// 修改任务状态
     pub fn set_status(&self, new_status: TaskStatus) {
@@ -453,7 +708,198 @@ impl TaskControlBlock {
     }
     /// 获取 tid 的值，不会转移或释放 Tid 的所有权
     pub fn get_tid_num(&self) -> usize {
-        self.tid.0
+        self.tid.lock().0
+    }
+    /// 任务是否可以在编号为 cpu_id 的 cpu 上运行
+    pub fn can_run_on(&self, cpu_id: usize) -> bool {
+        *self.cpu_mask.lock() & (1 << cpu_id) != 0
+    }
+    /// 获取任务的地址空间
+    pub fn get_vm(&self) -> Arc<Mutex<MemorySet>> {
+        self.vm.lock().clone()
+    }
+    /// 获取进程退出时向父进程发送的信号，为 0 时不发送
+    pub fn get_exit_signal(&self) -> usize {
+        self.thread_group.lock().exit_signal
+    }
+    /// 获取进程组 id
+    pub fn get_pgid(&self) -> usize {
+        self.inner.lock().pgid
+    }
+    /// 所在的进程是否被信号暂停
+    pub fn is_stopped(&self) -> bool {
+        self.thread_group.lock().is_stopped()
+    }
+    /// 获取会话 id
+    pub fn get_sid(&self) -> usize {
+        self.inner.lock().sid
+    }
+    /// 设置进程的进程组和会话。同一线程组的所有线程都会被修改
+    pub fn set_pgid_and_sid(&self, pgid: usize, sid: usize) {
+        let mut members = self.thread_group.lock().get_members();
+        // 组长线程可能已退出而不在 members 中，但它仍被父进程持有，需要修改以便 wait 等查询
+        members.push(self.pid);
+        for tid in members {
+            if let Some(task) = get_task_from_tid(tid) {
+                let mut inner = task.inner.lock();
+                inner.pgid = pgid;
+                inner.sid = sid;
+            }
+        }
+    }
+    /// 向当前任务所在的进程发送一个由内核产生的信号
+    pub fn send_signal_to_process(&self, signum: usize) {
+        self.send_siginfo_to_process(SigInfo::kernel(signum));
+    }
+    /// 向当前任务所在的进程发送带附加信息的信号。
+    /// 信号放入进程共享的队列，由组内任意一个没有屏蔽它的线程处理。如果实时信号的队列已满，则返回 false
+    pub fn send_siginfo_to_process(&self, info: SigInfo) -> bool {
+        let members = self.thread_group.lock().get_members();
+        match members
+            .iter()
+            .find(|&&tid| tid == self.pid)
+            .or(members.first())
+        {
+            Some(&tid) => send_process_siginfo(tid, info),
+            None => true,
+        }
+    }
+    /// 生成当前任务状态变化时发给父进程(或跟踪者)的 SIGCHLD 信息。
+    /// code 为 CLD_* 中的一种，status 为退出码或导致状态变化的信号
+    pub fn child_siginfo(&self, code: i32, status: i32) -> SigInfo {
+        let uid = self.credentials.lock().uid.real;
+        let (utime_us, stime_us) = self.time.lock().output_raw();
+        SigInfo::child(code, self.pid, uid, status, utime_us, stime_us)
+    }
+    /// 在向当前任务所在的进程发出信号前，处理与暂停和继续相关的信号。由 send_siginfo 等发送信号的函数调用：
+    /// - 发出 SIGCONT 或 SIGKILL 时，让被暂停的进程继续执行，并丢弃还未处理的暂停信号
+    /// - 发出暂停信号时，丢弃还未处理的 SIGCONT
+    ///
+    /// 因为被暂停的进程不会被调度，也就没有机会自己处理 SIGCONT，所以需要在发出信号时就完成这些操作
+    pub fn prepare_signal(&self, signum: usize) {
+        let signal = SignalNo::from(signum);
+        let (discarded, resume): (&[SignalNo], bool) = match signal {
+            SignalNo::SIGCONT | SignalNo::SIGKILL => (
+                &[
+                    SignalNo::SIGSTOP,
+                    SignalNo::SIGTSTP,
+                    SignalNo::SIGTTIN,
+                    SignalNo::SIGTTOU,
+                ],
+                true,
+            ),
+            _ if matches!(SigActionDefault::of_signal(signal), SigActionDefault::Stop) => {
+                (&[SignalNo::SIGCONT], false)
+            }
+            _ => return,
+        };
+        for tid in self.thread_group.lock().get_members() {
+            if let Some(receivers) = get_signals_from_tid(tid) {
+                let mut receivers = receivers.lock();
+                for &sig in discarded {
+                    receivers.discard_signal(sig as usize);
+                }
+            }
+        }
+        if resume {
+            self.continue_process(signal == SignalNo::SIGCONT);
+        }
+    }
+    /// 暂停当前任务所在的进程，记录暂停的信号以供父进程 wait，并通知父进程。
+    /// 如果进程已经被暂停或正在退出，则不做任何操作
+    pub fn stop_process(&self, signum: usize) {
+        if !self.thread_group.lock().stop() {
+            return;
+        }
+        let leader = self.get_leader();
+        let mut leader_inner = leader.inner.lock();
+        leader_inner.stop_signal_to_report = signum;
+        leader_inner.continued_to_report = false;
+        drop(leader_inner);
+        leader.notify_parent_stopped_or_continued(CLD_STOPPED, signum as i32);
+    }
+    /// 让被暂停的进程继续执行。report 表示是否需要让父进程通过 wait(WCONTINUED) 得知这一事件，
+    /// 因 SIGKILL 而继续执行时不需要报告
+    pub fn continue_process(&self, report: bool) {
+        if !self.thread_group.lock().resume() {
+            return;
+        }
+        // 被暂停的线程不在任务队列中，需要放回去
+        wake_stopped_tasks(self.pid);
+        let leader = self.get_leader();
+        let mut leader_inner = leader.inner.lock();
+        leader_inner.stop_signal_to_report = 0;
+        leader_inner.continued_to_report = report;
+        drop(leader_inner);
+        if report {
+            leader.notify_parent_stopped_or_continued(CLD_CONTINUED, SignalNo::SIGCONT as i32);
+        }
+    }
+    /// execve 时，非组长线程在组内其他线程都退出后接替组长的 tid，之后它就是进程的组长线程，
+    /// 父进程 wait 到的也是它。leader 为已退出的组长线程，它换上当前线程原来的 tid 后就可以被释放了
+    ///
+    /// 组长线程已被回收时，尝试重新分配它的 tid
+    pub fn take_over_leader(self: &Arc<Self>, leader: Option<Arc<Self>>) {
+        let old_tid = self.get_tid_num();
+        match leader.as_ref() {
+            Some(leader) => core::mem::swap(&mut *self.tid.lock(), &mut *leader.tid.lock()),
+            None => match Tid::new_at(self.pid) {
+                Some(tid) => *self.tid.lock() = tid,
+                None => return,
+            },
+        }
+        // 更新所有以 tid 为索引的记录
+        global_logoff_task(old_tid);
+        global_register_task(self);
+        global_logoff_signals(old_tid);
+        global_register_signals(self.pid, self.signal_receivers.clone());
+        self.thread_group.lock().replace_member(old_tid, self.pid);
+        self.time.lock().set_tid(self.pid);
+        // 父进程可能在组长线程退出后变化过，以组长线程记录的为准，并在父进程的子进程列表中替换掉组长线程
+        if let Some(leader) = leader {
+            let leader_inner = leader.inner.lock();
+            let (ppid, parent) = (leader_inner.ppid, leader_inner.parent.clone());
+            drop(leader_inner);
+            let mut inner = self.inner.lock();
+            inner.ppid = ppid;
+            inner.parent = parent.clone();
+            drop(inner);
+            if let Some(parent) = parent.and_then(|p| p.upgrade()) {
+                for child in parent.inner.lock().children.iter_mut() {
+                    if Arc::ptr_eq(child, &leader) {
+                        *child = self.clone();
+                    }
+                }
+            }
+        }
+        let children = self.inner.lock().children.clone();
+        // 自己的子进程记录的 ppid 是原来的 tid
+        for child in children {
+            child.inner.lock().ppid = self.pid;
+        }
+    }
+    /// 获取进程的组长线程。如果组长线程已被回收，则返回自己
+    pub fn get_leader(&self) -> Arc<Self> {
+        get_task_from_tid(self.pid)
+            .or_else(|| get_task_from_tid(self.get_tid_num()))
+            .unwrap()
+    }
+    /// 进程暂停或继续时，向父进程发送 SIGCHLD，除非父进程的 SIGCHLD 处理函数设置了 SA_NOCLDSTOP。
+    /// code 和 status 为 SIGCHLD 附带的 si_code 和 si_status
+    fn notify_parent_stopped_or_continued(&self, code: i32, status: i32) {
+        let parent = self.inner.lock().parent.as_ref().and_then(|p| p.upgrade());
+        if let Some(parent) = parent {
+            let no_cld_stop = parent
+                .signal_handlers
+                .lock()
+                .get_action_ref(SignalNo::SIGCHLD as usize)
+                .map_or(false, |action| {
+                    action.flags.contains(SigActionFlags::SA_NOCLDSTOP)
+                });
+            if !no_cld_stop {
+                parent.send_siginfo_to_process(self.child_siginfo(code, status));
+            }
+        }
     }
     /// 获取 ppid 的值
     pub fn get_ppid(&self) -> usize {
@@ -481,10 +927,18 @@ impl TaskControlBlock {
         }
     }
     /// 如果当前进程已是运行结束，则获取其 exit_code，否则返回 None
+    ///
+    /// 进程的组长线程退出后，如果线程组中还有其他线程在运行，则进程仍未结束
     pub fn get_code_if_exit(&self) -> Option<i32> {
         let inner = self.inner.try_lock()?;
         match inner.task_status {
-            TaskStatus::Zombie => Some(inner.exit_code),
+            TaskStatus::Zombie => {
+                // 进程的 exit_code 以线程组为准，而不是组长线程自己的
+                let thread_group = self.thread_group.lock();
+                thread_group
+                    .is_empty()
+                    .then(|| thread_group.exit_status().0)
+            }
             _ => None,
         }
     }
@@ -492,29 +946,170 @@ impl TaskControlBlock {
     pub fn set_tid_address(&self, addr: usize) {
         self.inner.lock().clear_child_tid = addr;
     }
-    /// 如果当前没有在信号处理函数中，则保存当前用户上下文信息，返回true。
-    /// 否则不保存并返回false
-    pub fn save_trap_cx_if_not_handling_signals(&self) -> bool {
-        //println!("in sig");
+    /// 当前是否在信号处理函数中。
+    ///
+    /// 处理函数通过 siglongjmp 跳出时不会调用 sigreturn，此时用户 sp 已经离开了处理函数的栈，
+    /// 据此丢弃保存的上下文，视为处理函数已经结束
+    pub fn is_handling_signals(&self) -> bool {
         let mut inner = self.inner.lock();
-        if inner.trap_cx_before_signal.is_some() {
-            //println!("has");
+        let sp = unsafe { (*self.kernel_stack.get_first_context()).get_sp() };
+        // 可能一次跳出了多层嵌套的处理函数
+        while let Some(frame) = inner.signal_frames.last() {
+            match frame.handler_sp {
+                Some((low, high)) if sp < low || sp > high => {
+                    inner.signal_frames.pop();
+                }
+                _ => return true,
+            }
+        }
+        false
+    }
+    /// 是否正在 signum 的处理函数中(包括外层的处理函数)。调用前需要先用 is_handling_signals 丢弃已经跳出的处理函数
+    pub fn is_handling_signal(&self, signum: usize) -> bool {
+        self.inner
+            .lock()
+            .signal_frames
+            .iter()
+            .any(|frame| frame.signum == signum)
+    }
+    /// 记录最内层的信号处理函数可以使用的用户栈范围：low 为最低地址，sp 为进入处理函数时的 sp
+    pub fn set_signal_handler_sp(&self, low: usize, sp: usize) {
+        if let Some(frame) = self.inner.lock().signal_frames.last_mut() {
+            frame.handler_sp = Some((low, sp));
+        }
+    }
+    /// 暂时不能取出的信号。
+    ///
+    /// 在信号处理函数返回前，不能再进入其他处理函数。
+    /// 这些信号暂时留在队列里，等 sigreturn 之后再处理。
+    /// 但由异常同步产生的信号仍要取出，否则返回用户态后会反复触发同一个异常
+    pub fn deferred_signals(
+        &self,
+        receivers: &SignalReceivers,
+        handlers: &SignalHandlers,
+    ) -> Bitset {
+        let mut deferred = Bitset::new(0);
+        if self.is_handling_signals() {
+            deferred = handlers.handled_set();
+            deferred.get_difference(receivers.pending.fault_set());
+        }
+        deferred
+    }
+    /// 是否有需要处理的信号，即处理时会调用处理函数、结束或暂停进程，或者需要交给跟踪者的信号。
+    /// 阻塞在系统调用中的线程需要因此返回
+    pub fn has_pending_signal(&self) -> bool {
+        let receivers = self.signal_receivers.lock();
+        let handlers = self.signal_handlers.lock();
+        let mut pending = receivers.unmasked_pending();
+        pending.get_difference(self.deferred_signals(&receivers, &handlers));
+        if pending.0 == 0 {
             return false;
         }
-        inner.trap_cx_before_signal = Some(unsafe { *self.kernel_stack.get_first_context() });
-        // 默认没有 SIGINFO，如果有则需要用 save_if_set_siginfo 设置
-        inner.signal_set_siginfo = false;
-        true
+        if self.inner.lock().ptrace.is_some() {
+            return true;
+        }
+        (1..=SIGSET_SIZE_IN_BIT)
+            .filter(|&signum| pending.contain_bit(signum - 1))
+            .any(|signum| match handlers.get_action_ref(signum) {
+                Some(action) => action.handler != SIG_IGN,
+                None => !matches!(
+                    SigActionDefault::of_signal(SignalNo::from(signum)),
+                    SigActionDefault::Ignore | SigActionDefault::Continue
+                ),
+            })
+    }
+    /// 进入 signum 的处理函数前，保存当前用户上下文信息和处理函数返回后要恢复的信号掩码 mask
+    pub fn push_signal_frame(&self, signum: usize, mask: Bitset) {
+        let mut inner = self.inner.lock();
+        // 处理函数可能修改浮点寄存器，所以要先保存信号触发前的状态
+        self.save_dirty_fp(&mut inner);
+        let frame = SignalFrame {
+            signum,
+            trap_cx: unsafe { *self.kernel_stack.get_first_context() },
+            // 栈的范围在放好信号栈帧后再记录
+            handler_sp: None,
+            // 默认没有 SIGINFO，如果有则需要用 save_if_set_siginfo 设置
+            set_siginfo: false,
+            mask,
+            signal_stack: inner.signal_stack,
+            fp_context: inner.fp_context,
+        };
+        inner.signal_frames.push(frame);
+    }
+    /// 如果用户程序修改过浮点寄存器，则把它们保存到 fp_context 中，并返回保存后的值。
+    ///
+    /// 只能对当前核上正在运行的任务调用，因为只有这时浮点寄存器里才是它的状态
+    pub fn save_fp_context(&self) -> FpContext {
+        let mut inner = self.inner.lock();
+        self.save_dirty_fp(&mut inner);
+        inner.fp_context
+    }
+    /// 获取不在运行的任务(如暂停中的被跟踪的任务)的浮点寄存器。它们在任务切换出去时已经保存了
+    pub fn get_saved_fp_context(&self) -> FpContext {
+        self.inner.lock().fp_context
+    }
+    /// 修改不在运行的任务的浮点寄存器，下次切换到它时会恢复到寄存器中
+    pub fn set_saved_fp_context(&self, fp_context: &FpContext) {
+        let mut inner = self.inner.lock();
+        inner.fp_context = FpContext::from_user(fp_context);
+        inner.fp_cpu = None;
+    }
+    /// 切换到任务时调用。如果这个核的浮点寄存器里不是任务最新的状态，则从 fp_context 恢复。
+    ///
+    /// fp_owner 为这个核的浮点寄存器最近一次恢复的是哪个线程的状态
+    pub fn load_fp_context(&self, cpu_id: usize, fp_owner: &mut Option<usize>) {
+        let tid = self.get_tid_num();
+        let mut inner = self.inner.lock();
+        // 即使这个核上次恢复的就是它，如果它之后在其他核上运行过，这个核上的状态也已经过时了
+        if *fp_owner != Some(tid) || inner.fp_cpu != Some(cpu_id) {
+            inner.fp_context.restore();
+            inner.fp_cpu = Some(cpu_id);
+            *fp_owner = Some(tid);
+        }
+        // 此时寄存器与 fp_context 一致，用户程序再次修改后硬件会重新置为 Dirty
+        let trap_cx = unsafe { &mut *self.kernel_stack.get_first_context() };
+        trap_cx.set_fs(FS::Clean);
+    }
+    /// 如果 trap 上下文中的 FS 为 Dirty，说明用户程序修改过浮点寄存器，需要保存到 fp_context 中
+    fn save_dirty_fp(&self, inner: &mut TaskControlBlockInner) {
+        let trap_cx = unsafe { &mut *self.kernel_stack.get_first_context() };
+        if trap_cx.sstatus.fs() == FS::Dirty {
+            inner.fp_context.save();
+            trap_cx.set_fs(FS::Clean);
+        }
     }
-    /// 记录信号处理函数是否设置了 SIGINFO
+    /// 选择信号处理函数使用的栈。on_stack 表示处理函数设置了 SA_ONSTACK
+    ///
+    /// 返回信号栈帧的栈顶、处理函数可以使用的栈的最低地址，以及需要写入 ucontext 的 uc_stack
+    pub fn signal_frame_stack(&self, sp: usize, on_stack: bool) -> (usize, usize, SignalStack) {
+        let mut inner = self.inner.lock();
+        let stack = inner.signal_stack;
+        let state = stack.state(sp);
+        if on_stack && stack.is_enabled() && !stack.contains(sp) {
+            if stack.flags & SS_AUTODISARM != 0 {
+                inner.signal_stack = SignalStack::default();
+            }
+            (stack.sp + stack.size, stack.sp, state)
+        } else if stack.is_enabled() && stack.contains(sp) {
+            // 已经在备用信号栈上，接着当前的栈向下放
+            (sp - USER_STACK_RED_ZONE, stack.sp, state)
+        } else {
+            // 不使用备用信号栈时，接着当前的栈向下放
+            (sp - USER_STACK_RED_ZONE, 0, state)
+        }
+    }
+    /// 记录最内层的信号处理函数是否设置了 SIGINFO
     pub fn save_if_set_siginfo(&self, signal_set_siginfo: bool) {
-        self.inner.lock().signal_set_siginfo = signal_set_siginfo;
+        if let Some(frame) = self.inner.lock().signal_frames.last_mut() {
+            frame.set_siginfo = signal_set_siginfo;
+        }
     }
-    /// 恢复用户上下文信息，返回true。如没有已保存的上下文信息，则返回 false
-    pub fn load_trap_cx_if_handling_signals(&self) -> bool {
+    /// 按最内层的信号处理函数保存的状态恢复用户上下文信息，并返回需要恢复的信号掩码。
+    /// 如没有已保存的上下文信息，则返回 None
+    pub fn load_trap_cx_if_handling_signals(&self) -> Option<Bitset> {
         //println!("out sig");
         let mut inner = self.inner.lock();
-        if let Some(trap_cx_old) = inner.trap_cx_before_signal.take() {
+        if let Some(frame) = inner.signal_frames.pop() {
             //info!("sig returned");
             unsafe {
                 let trap_cx_now = self.kernel_stack.get_first_context();
@@ -523,20 +1118,49 @@ impl TaskControlBlock {
                 let sp = (*trap_cx_now).get_sp();
                 // 获取可能被修改的 pc
                 let pc = (*(sp as *const SignalUserContext)).get_pc();
-                *trap_cx_now = trap_cx_old;
-                if inner.signal_set_siginfo {
-                    // 更新用户修改的 pc
+                *trap_cx_now = frame.trap_cx;
+                if frame.set_siginfo {
+                    // 更新用户修改的 pc 和信号掩码
                     (*trap_cx_now).set_sepc(pc);
                     info!("sig return sp = {:x} pc = {:x}", sp, pc);
+                    // 备用信号栈也按 ucontext 中可能被用户修改的 uc_stack 恢复，不合法时保持原样
+                    let ucontext = &*(sp as *const SignalUserContext);
+                    if let Ok(stack) = SignalStack::from_user(&ucontext.stack) {
+                        inner.signal_stack = stack;
+                    }
+                    // 浮点寄存器同理
+                    inner.fp_context = FpContext::from_user(&ucontext.context.fpstate);
+                    inner.fp_context.restore();
+                    return Some(Bitset::new(ucontext.sig_mask as usize));
                 }
             }
-            true
+            inner.signal_stack = frame.signal_stack;
+            inner.fp_context = frame.fp_context;
+            inner.fp_context.restore();
+            Some(frame.mask)
         } else {
-            false
+            None
         }
     }
 }
 
+impl Drop for TaskControlBlock {
+    /// TCB 被回收时，从全局表中删除。此时 tid 还没有被释放，所以不会误删其他线程
+    fn drop(&mut self) {
+        global_logoff_task(self.tid.get_mut().0);
+    }
+}
+
+/// 从程序路径获取线程名，即路径最后一项的前 TASK_COMM_LEN - 1 个字节
+fn comm_from_path(path: &str) -> String {
+    let name = path.rsplit('/').next().unwrap_or(path);
+    let mut len = name.len().min(TASK_COMM_LEN - 1);
+    while !name.is_char_boundary(len) {
+        len -= 1;
+    }
+    String::from(&name[..len])
+}
+
 /// 任务执行状态
 #[derive(Copy, Clone, PartialEq)]
 pub enum TaskStatus {
@@ -548,6 +1172,8 @@ pub enum TaskStatus {
     Running,
     /// 进程在用户端已退出，但内核端还有些工作要处理，例如把它的所有子进程交给初始进程
     Dying,
+    /// 被信号暂停，在收到 SIGCONT 前不会被调度
+    Stopped,
     /// 僵尸进程，已退出，但其资源还在等待回收
     Zombie,
     /// 已执行完成
@@ -0,0 +1,157 @@
+//! 线程组，即拥有相同 pid(tgid) 的所有线程
+//!
+//! 同一线程组内的 TCB 共享一个 `Arc<Mutex<ThreadGroup>>`，
+//! 用于 exit_group 和致命信号时找到组内的所有线程，以及判断组内最后一个线程何时退出
+
+use alloc::{collections::BTreeSet, vec::Vec};
+
+/// 线程组信息
+pub struct ThreadGroup {
+    /// 线程组 id，即组长线程的 tid，也就是进程的 pid
+    pub tgid: usize,
+    /// 组内所有还未退出的线程的 tid
+    members: BTreeSet<usize>,
+    /// 整个线程组退出时的 exit_code。
+    /// 不为 None 时说明线程组正在退出(调用了 exit_group 或收到了致命信号)，组内其他线程退出时也使用这个值
+    group_exit_code: Option<i32>,
+    /// 如果整个线程组是被信号终止的，记录这个信号的编号，否则为 0
+    group_term_signal: usize,
+    /// 组长线程自己退出时的 exit_code。线程组不是整体退出时，进程的 exit_code 以它为准
+    leader_exit_code: i32,
+    /// 整个线程组被信号终止时是否生成了 core dump
+    pub core_dumped: bool,
+    /// 正在执行 execve 的线程。不为 None 时组内其他线程正在被终止，它们退出时不会导致整个进程退出
+    exec_tid: Option<usize>,
+    /// 组内最后一个线程退出时向父进程发送的信号，为 0 时不发送。
+    /// 由组长线程创建时 clone 的参数决定，被交给其他进程回收时改为 SIGCHLD
+    pub exit_signal: usize,
+    /// 整个线程组是否被暂停(收到 SIGSTOP 等信号)。为 true 时组内线程都不会被调度，直到收到 SIGCONT
+    stopped: bool,
+    /// 是否是子进程回收者(由 prctl 设置)。
+    /// 如果是，则它的后代进程成为孤儿时，会被交给它而不是初始进程
+    pub child_subreaper: bool,
+}
+
+impl ThreadGroup {
+    /// 新建一个只包含组长线程的线程组
+    pub fn new(tgid: usize, exit_signal: usize) -> Self {
+        let mut members = BTreeSet::new();
+        members.insert(tgid);
+        Self {
+            tgid,
+            members,
+            group_exit_code: None,
+            group_term_signal: 0,
+            leader_exit_code: 0,
+            core_dumped: false,
+            exec_tid: None,
+            exit_signal,
+            stopped: false,
+            child_subreaper: false,
+        }
+    }
+    /// 加入一个新线程
+    pub fn add_member(&mut self, tid: usize) {
+        self.members.insert(tid);
+    }
+    /// 删除一个已退出的线程，exit_code 为它自己退出时的值。如果它是组内最后一个线程，则返回 true
+    pub fn remove_member(&mut self, tid: usize, exit_code: i32) -> bool {
+        if tid == self.tgid {
+            self.leader_exit_code = exit_code;
+        }
+        self.members.remove(&tid);
+        self.members.is_empty()
+    }
+    /// 组内是否所有线程都已退出
+    pub fn is_empty(&self) -> bool {
+        self.members.is_empty()
+    }
+    /// 获取组内所有还未退出的线程的 tid
+    pub fn get_members(&self) -> Vec<usize> {
+        self.members.iter().copied().collect()
+    }
+    /// 获取整个线程组退出时的 exit_code，如线程组没有在退出则返回 None
+    pub fn get_group_exit_code(&self) -> Option<i32> {
+        self.group_exit_code
+    }
+    /// 获取终止整个线程组的信号编号，如果不是被信号终止的则返回 0
+    pub fn get_group_term_signal(&self) -> usize {
+        self.group_term_signal
+    }
+    /// 获取整个进程退出时的 exit_code 和终止信号，用于 wait 和通知父进程。
+    ///
+    /// 与 Linux 相同，线程组整体退出时使用线程组的状态，否则使用组长线程自己的 exit_code，
+    /// 而不是最后一个退出的线程的。如组长先 pthread_exit(0)，之后其他线程 exit_group(3)，则进程的 exit_code 为 3
+    pub fn exit_status(&self) -> (i32, usize) {
+        (
+            self.group_exit_code.unwrap_or(self.leader_exit_code),
+            self.group_term_signal,
+        )
+    }
+    /// 标记整个线程组开始退出，并返回组内除 tid 外的其他线程，需要由调用者通知它们退出。
+    /// term_signal 为终止线程组的信号编号，正常退出时为 0
+    ///
+    /// 如果线程组已经在退出了，则不修改 exit_code，并返回空数组
+    pub fn start_group_exit(
+        &mut self,
+        exit_code: i32,
+        term_signal: usize,
+        tid: usize,
+    ) -> Vec<usize> {
+        if self.group_exit_code.is_some() {
+            return Vec::new();
+        }
+        self.group_exit_code = Some(exit_code);
+        self.group_term_signal = term_signal;
+        // 正在退出的线程组不能再保持暂停，否则其他线程无法被调度去处理 SIGKILL
+        self.stopped = false;
+        self.members.iter().copied().filter(|&t| t != tid).collect()
+    }
+    /// 开始 execve：终止组内除 tid 外的其他线程，并返回它们，需要由调用者通知它们退出。
+    /// 其他线程按 exit_group 的方式退出，但 tid 会留下来继续执行新的程序
+    ///
+    /// 如果线程组已经在退出(包括有其他线程在 execve)，则返回 None
+    pub fn start_exec(&mut self, tid: usize) -> Option<Vec<usize>> {
+        if self.group_exit_code.is_some() {
+            return None;
+        }
+        self.exec_tid = Some(tid);
+        Some(self.start_group_exit(0, 0, tid))
+    }
+    /// 组内其他线程都已退出，execve 的线程成为组内唯一的线程，线程组不再处于退出状态
+    pub fn finish_exec(&mut self) {
+        self.exec_tid = None;
+        self.group_exit_code = None;
+        self.group_term_signal = 0;
+    }
+    /// 获取正在执行 execve 的线程，没有则返回 None
+    pub fn get_exec_tid(&self) -> Option<usize> {
+        self.exec_tid
+    }
+    /// execve 的线程接替组长线程的 tid
+    pub fn replace_member(&mut self, old_tid: usize, new_tid: usize) {
+        self.members.remove(&old_tid);
+        self.members.insert(new_tid);
+        if self.exec_tid == Some(old_tid) {
+            self.exec_tid = Some(new_tid);
+        }
+    }
+    /// 线程组是否被暂停
+    pub fn is_stopped(&self) -> bool {
+        self.stopped
+    }
+    /// 暂停整个线程组。如果线程组已经被暂停或正在退出，则返回 false
+    pub fn stop(&mut self) -> bool {
+        if self.stopped || self.group_exit_code.is_some() {
+            return false;
+        }
+        self.stopped = true;
+        true
+    }
+    /// 让被暂停的线程组继续执行。如果线程组之前确实被暂停了，则返回 true
+    pub fn resume(&mut self) -> bool {
+        let was_stopped = self.stopped;
+        self.stopped = false;
+        was_stopped
+    }
+}
@@ -0,0 +1,42 @@
+//! 一张全局的表，从 tid 映射到对应的 TCB
+//!
+//! 表中只存放 Weak 指针，不影响 TCB 的回收。用于 kill / setpgid 等需要通过 pid 查找其他任务的系统调用
+
+use super::TaskControlBlock;
+use alloc::{
+    collections::BTreeMap,
+    sync::{Arc, Weak},
+    vec::Vec,
+};
+use lock::Mutex;
+
+/// 从 tid 获取 TCB
+static TID2TASK: Mutex<BTreeMap<usize, Weak<TaskControlBlock>>> = Mutex::new(BTreeMap::new());
+
+/// 所有线程初始化时均需要加入表
+pub fn global_register_task(task: &Arc<TaskControlBlock>) {
+    TID2TASK
+        .lock()
+        .insert(task.get_tid_num(), Arc::downgrade(task))
+        .take();
+}
+
+/// 所有线程的 TCB 被回收时均需要从表中删除
+pub fn global_logoff_task(tid: usize) {
+    TID2TASK.lock().remove(&tid).take();
+}
+
+/// 获取 tid 对应的 TCB。如果线程不存在或者已被回收，则返回 None
+pub fn get_task_from_tid(tid: usize) -> Option<Arc<TaskControlBlock>> {
+    TID2TASK.lock().get(&tid).and_then(|task| task.upgrade())
+}
+
+/// 获取所有还未被回收的线程的 TCB。
+/// 这里会复制所有的 Arc，调用者处理它们时不会持有表的锁
+pub fn get_all_tasks() -> Vec<Arc<TaskControlBlock>> {
+    TID2TASK
+        .lock()
+        .values()
+        .filter_map(|task| task.upgrade())
+        .collect()
+}
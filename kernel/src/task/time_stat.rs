@@ -9,7 +9,7 @@
 //! > 如果在 trap 的过程中，通过其他方式退出了进程，那么内核时间统计会在 `run_tasks()` 切出时中断。
 //! > 这样统计的时间仍然是对的
 
-use crate::signal::{send_signal, SignalNo};
+use crate::signal::{send_siginfo, SigInfo, SignalNo};
 use timer::{get_time_us, TimeVal};
 
 /// 进程的时间统计，基于 lmbench 需要，主要用于 sys_getrusage
@@ -80,6 +80,10 @@ impl TimeStat {
             timer_remained_us: 0,
         }
     }
+    /// 修改到时间时接收信号的线程 id。用于 execve 时非组长线程接替组长的 tid
+    pub fn set_tid(&mut self, tid: usize) {
+        self.tid = tid;
+    }
     /// 清空使用的时间，用于
     pub fn clear(&mut self) {
         self.utime_us = 0;
@@ -164,12 +168,14 @@ impl TimeStat {
         // 到此说明计时器已经到时间了，更新计时器
         // 如果是 one-shot 计时器，则 timer_interval_us == 0，这样赋值也恰好是符合语义的
         self.timer_remained_us = self.timer_interval_us;
-        match &self.timer_type {
-            TimerType::REAL => send_signal(self.tid, SignalNo::SIGALRM as usize),
-            TimerType::VIRTUAL => send_signal(self.tid, SignalNo::SIGVTALRM as usize),
-            TimerType::PROF => send_signal(self.tid, SignalNo::SIGPROF as usize),
-            _ => {}
+        let signum = match &self.timer_type {
+            TimerType::REAL => SignalNo::SIGALRM,
+            TimerType::VIRTUAL => SignalNo::SIGVTALRM,
+            TimerType::PROF => SignalNo::SIGPROF,
+            _ => return true,
         };
+        // 计时器产生的信号，si_code 为 SI_TIMER
+        send_siginfo(self.tid, SigInfo::timer(signum as usize));
         true
     }
 }
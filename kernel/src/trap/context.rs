@@ -6,7 +6,7 @@
 
 //#![deny(missing_docs)]
 
-use riscv::register::sstatus::{self, Sstatus, SPP};
+use riscv::register::sstatus::{self, Sstatus, FS, SPP};
 /// 异常/中断上下文
 #[repr(C)]
 #[derive(Clone, Copy, Debug)]
@@ -21,10 +21,11 @@ pub struct TrapContext {
     pub sepc: usize,
     /// CPU 的编号。在内核时，这个信息存在 tp 寄存器上
     pub cpu_id: usize,
-    /// 浮点寄存器
-    pub fs: [usize; 2],
 }
 
+/// sstatus 中 FS 字段的起始位置
+const SSTATUS_FS_SHIFT: usize = 13;
+
 impl TrapContext {
     /// 设置 ra 寄存器
     pub fn set_ra(&mut self, ra: usize) {
@@ -74,6 +75,12 @@ impl TrapContext {
     pub fn get_sepc(&mut self) -> usize {
         self.sepc
     }
+    /// 设置 sstatus 中的浮点状态 FS。Sstatus 没有提供修改它的方法，所以直接改对应的位
+    pub fn set_fs(&mut self, fs: FS) {
+        let bits = (self.sstatus.bits() & !(0b11 << SSTATUS_FS_SHIFT))
+            | ((fs as usize) << SSTATUS_FS_SHIFT);
+        self.sstatus = unsafe { core::mem::transmute::<usize, Sstatus>(bits) };
+    }
     /// 初始化用户程序的中断信息，用于第一次进入用户程序前
     pub fn app_init_context(entry: usize, sp: usize) -> Self {
         info!("init app entry {:x} sp {:x}", entry, sp);
@@ -84,9 +91,9 @@ impl TrapContext {
             sstatus,
             sepc: entry,        // sepc 设为用户程序入口
             cpu_id: usize::MAX, // 这个信息会在 restore 进入用户时被保存，所以此处无需处理
-            fs: [0; 2],
         };
         cx.set_sp(sp); // 设置用户栈地址
+        cx.set_fs(FS::Initial); // 浮点寄存器在切换到任务时才初始化
 
         cx.set_gp(0x17908); // 这个 magic number 参见 set_gp() 描述
         cx.set_a0(sp);
@@ -107,7 +114,6 @@ impl TrapContext {
             sstatus: sstatus::read(),
             sepc: 0,
             cpu_id: usize::MAX,
-            fs: [0; 2],
         }
     }
 }
@@ -0,0 +1,91 @@
+//! 浮点寄存器上下文
+//!
+//! 内核本身不使用浮点寄存器，所以 trap.S 中不保存它们，而是在切换任务时按需保存/恢复：
+//! - 用户程序修改浮点寄存器后，硬件会把 sstatus 的 FS 位置为 Dirty。切换走任务时，只有 Dirty 的才需要保存
+//! - 切换到任务时，如果这个核的浮点寄存器里已经是它最新的状态，则不需要恢复
+//!
+//! 内核的编译目标不含 F/D 扩展，所以下面的 fsd/fld 指令直接按编码写出
+
+use core::arch::global_asm;
+
+global_asm!(
+    "
+    .section .text
+    .globl __save_fp
+    .globl __restore_fp
+    .align 2
+# __save_fp(cx: *mut FpContext)
+__save_fp:
+    # 先打开 FS，否则访问浮点寄存器会触发非法指令异常
+    li t0, 0x2000
+    csrs sstatus, t0
+    # fsd fn, n*8(a0)
+    .irp n, 0,1,2,3,4,5,6,7,8,9,10,11,12,13,14,15,16,17,18,19,20,21,22,23,24,25,26,27,28,29,30,31
+        .word ((\\n / 4) * 0x2000000) + (\\n * 0x100000) + (10 * 0x8000) + (3 * 0x1000) + ((\\n - (\\n / 4) * 4) * 8 * 0x80) + 0x27
+    .endr
+    # fcsr 的编号为 0x003
+    csrr t0, 0x003
+    sw t0, 32*8(a0)
+    ret
+
+# __restore_fp(cx: *const FpContext)
+__restore_fp:
+    li t0, 0x2000
+    csrs sstatus, t0
+    # fld fn, n*8(a0)
+    .irp n, 0,1,2,3,4,5,6,7,8,9,10,11,12,13,14,15,16,17,18,19,20,21,22,23,24,25,26,27,28,29,30,31
+        .word (\\n * 8 * 0x100000) + (10 * 0x8000) + (3 * 0x1000) + (\\n * 0x80) + 0x07
+    .endr
+    lw t0, 32*8(a0)
+    csrw 0x003, t0
+    ret
+"
+);
+
+extern "C" {
+    fn __save_fp(cx: *mut FpContext);
+    fn __restore_fp(cx: *const FpContext);
+}
+
+/// fcsr 中有效的位，即舍入模式 frm 和异常标志 fflags
+const FCSR_MASK: u32 = 0xff;
+
+/// 浮点寄存器 f0~f31 和 fcsr。内存布局与 Linux riscv64 下的 `__riscv_d_ext_state` 相同，
+/// 所以也可以直接放在信号处理函数的 ucontext 中
+#[repr(C)]
+#[derive(Clone, Copy, Debug)]
+pub struct FpContext {
+    /// f0~f31
+    pub f: [u64; 32],
+    /// 浮点控制状态寄存器
+    pub fcsr: u32,
+    _pad: u32,
+}
+
+impl Default for FpContext {
+    fn default() -> Self {
+        Self {
+            f: [0; 32],
+            fcsr: 0,
+            _pad: 0,
+        }
+    }
+}
+
+impl FpContext {
+    /// 检查用户在 ucontext 中给出的浮点状态，去掉 fcsr 中的保留位
+    pub fn from_user(cx: &Self) -> Self {
+        Self {
+            fcsr: cx.fcsr & FCSR_MASK,
+            ..*cx
+        }
+    }
+    /// 把当前核的浮点寄存器保存到这里
+    pub fn save(&mut self) {
+        unsafe { __save_fp(self) }
+    }
+    /// 把这里的值写入当前核的浮点寄存器
+    pub fn restore(&self) {
+        unsafe { __restore_fp(self) }
+    }
+}
@@ -20,16 +20,22 @@
 //#![deny(missing_docs)]
 
 mod context;
+mod fp_context;
 
 use crate::{
-    arch::get_cpu_id,
+    arch::{get_cpu_id, ipi},
     constants::SIGNAL_RETURN_TRAP,
+    error::OSError,
     memory::PTEFlags,
-    signal::{send_signal, SignalNo},
+    signal::{
+        SigInfo, SignalNo, BUS_ADRALN, BUS_ADRERR, ILL_ILLOPC, ILL_ILLTRP, SEGV_ACCERR, SEGV_MAPERR,
+    },
     syscall::syscall,
     task::{
-        get_current_task, handle_signals, handle_user_page_fault, signal_return,
-        suspend_current_task, timer_kernel_to_user, timer_user_to_kernel,
+        check_cpu_time_limit, get_current_task, handle_breakpoint, handle_signals,
+        handle_user_page_fault, prepare_single_step, ptrace_syscall_enter, ptrace_syscall_exit,
+        send_fault_signal, signal_return, suspend_current_task, timer_kernel_to_user,
+        timer_user_to_kernel,
     },
 };
 use core::arch::global_asm;
@@ -42,9 +48,13 @@ use timer::get_next_trigger;
 
 use crate::arch::set_timer;
 pub use context::TrapContext;
+pub use fp_context::FpContext;
 
 global_asm!(include_str!("trap.S"));
 
+/// load 地址未对齐时的 scause。riscv 库中的 scause::Exception 没有列出它
+const SCAUSE_LOAD_MISALIGNED: usize = 4;
+
 /// 设置寄存器 stvec 指向 __alltraps，它定义在 trap.S 中
 pub fn init() {
     extern "C" {
@@ -53,6 +63,7 @@ pub fn init() {
     unsafe {
         stvec::write(__alltraps as usize, TrapMode::Direct);
     }
+    ipi::init();
 }
 
 /// 打开时间中断
@@ -84,21 +95,17 @@ pub fn trap_handler(cx: &mut TrapContext) -> &mut TrapContext {
 #[no_mangle]
 /// 处理来自用户程序的异常/中断
 pub fn user_trap_handler(cx: &mut TrapContext) -> &mut TrapContext {
-    //if get_current_task().unwrap().get_tid_num() == 2 {
-    //let mut fs1: f64;
-    //unsafe { core::arch::asm!("fmv.d.x {0}, fs1", out(reg) fs1) };
-    //println!("in fs1 {}", fs1);
-    //unsafe { core::arch::asm!("fsd fs1, 0(sp)") };
     trace!(
         "user sp = {:x}, entry = {:x}, sstatus = {:x}",
         cx.x[2],
         cx.sepc,
         cx.sstatus.bits()
     );
-    //}
     let scause = scause::read(); // get trap cause
     let stval = stval::read(); // get extra value
     timer_user_to_kernel();
+    // 系统调用的第一个参数。系统调用被信号打断后，需要用它重启
+    let mut orig_a0 = None;
     match scause.cause() {
         Trap::Exception(Exception::UserEnvCall) => {
             //let mut pc: usize;
@@ -108,24 +115,60 @@ pub fn user_trap_handler(cx: &mut TrapContext) -> &mut TrapContext {
 
             // Todo, enable timer interrupt when syscall
             cx.sepc += 4;
-            cx.x[10] = syscall(
-                cx.x[17],
-                [cx.x[10], cx.x[11], cx.x[12], cx.x[13], cx.x[14], cx.x[15]],
-            ) as usize;
+            orig_a0 = Some(cx.x[10]);
+            // 被跟踪时，跟踪者可能要求在进入和退出系统调用时暂停
+            if ptrace_syscall_enter(cx) {
+                cx.x[10] = syscall(
+                    cx.x[17],
+                    [cx.x[10], cx.x[11], cx.x[12], cx.x[13], cx.x[14], cx.x[15]],
+                ) as usize;
+            }
+            ptrace_syscall_exit();
         }
-        Trap::Exception(Exception::StoreFault) => {
-            info!("[kernel] StoreFault in application, bad addr = {:#x}, bad instruction = {:#x}, kernel killed it.", stval, cx.sepc);
-            send_signal(
-                get_current_task().unwrap().get_tid_num(),
-                SignalNo::SIGSEGV as usize,
+        Trap::Exception(Exception::Breakpoint) => {
+            info!(
+                "[cpu {}] Breakpoint in application, sepc = {:x}",
+                get_cpu_id(),
+                cx.sepc
             );
+            handle_breakpoint(cx.sepc);
+        }
+        Trap::Exception(Exception::InstructionMisaligned)
+        | Trap::Exception(Exception::StoreMisaligned) => {
+            info!(
+                "[cpu {}] {:?} in application, bad addr = {:#x}, bad instruction = {:#x}.",
+                get_cpu_id(),
+                scause.cause(),
+                stval,
+                cx.sepc
+            );
+            send_fault_signal(SigInfo::fault(SignalNo::SIGBUS as usize, BUS_ADRALN, stval));
+        }
+        Trap::Exception(Exception::InstructionFault)
+        | Trap::Exception(Exception::LoadFault)
+        | Trap::Exception(Exception::StoreFault) => {
+            info!(
+                "[cpu {}] {:?} in application, bad addr = {:#x}, bad instruction = {:#x}.",
+                get_cpu_id(),
+                scause.cause(),
+                stval,
+                cx.sepc
+            );
+            // 页表允许访问，但物理地址上没有设备或内存，和 Linux 一样视为总线错误
+            send_fault_signal(SigInfo::fault(SignalNo::SIGBUS as usize, BUS_ADRERR, stval));
         }
         Trap::Exception(Exception::IllegalInstruction) => {
-            info!("[cpu {}] IllegalInstruction in application, sepc = {:x}, stval = {:#x}, kernel killed it.", get_cpu_id(), cx.sepc, stval);
-            send_signal(
-                get_current_task().unwrap().get_tid_num(),
-                SignalNo::SIGSEGV as usize,
+            info!(
+                "[cpu {}] IllegalInstruction in application, sepc = {:x}, stval = {:#x}.",
+                get_cpu_id(),
+                cx.sepc,
+                stval
             );
+            send_fault_signal(SigInfo::fault(
+                SignalNo::SIGILL as usize,
+                ILL_ILLOPC,
+                cx.sepc,
+            ));
         }
         Trap::Exception(Exception::InstructionPageFault) => {
             info!("[cpu {}] InstructionPageFault in application, bad addr = {:#x}, bad instruction = {:#x}.", get_cpu_id(), stval, cx.sepc);
@@ -136,10 +179,7 @@ pub fn user_trap_handler(cx: &mut TrapContext) -> &mut TrapContext {
             }
             if let Err(e) = handle_user_page_fault(stval, PTEFlags::USER | PTEFlags::EXECUTE) {
                 info!("{:#?}", e);
-                send_signal(
-                    get_current_task().unwrap().get_tid_num(),
-                    SignalNo::SIGSEGV as usize,
-                );
+                send_page_fault_signal(e, stval);
             }
             //PageFault(stval, PTEFlags::USER | PTEFlags::EXECUTE)
         }
@@ -156,10 +196,7 @@ pub fn user_trap_handler(cx: &mut TrapContext) -> &mut TrapContext {
             if let Err(e) = handle_user_page_fault(stval, PTEFlags::USER | PTEFlags::READ) {
                 info!("[cpu {}] LoadPageFault in application, bad addr = {:#x}, bad instruction = {:#x}.", get_cpu_id(), stval, cx.sepc);
                 info!("{:#?}", e);
-                send_signal(
-                    get_current_task().unwrap().get_tid_num(),
-                    SignalNo::SIGSEGV as usize,
-                );
+                send_page_fault_signal(e, stval);
             }
             //PageFault(stval, PTEFlags::USER | PTEFlags::READ)
         }
@@ -168,14 +205,23 @@ pub fn user_trap_handler(cx: &mut TrapContext) -> &mut TrapContext {
             if let Err(e) = handle_user_page_fault(stval, PTEFlags::USER | PTEFlags::WRITE) {
                 info!("[cpu {}] StorePageFault in application, bad addr = {:#x}, bad instruction = {:#x}.", get_cpu_id(), stval, cx.sepc);
                 info!("{:#?}", e);
-                send_signal(
-                    get_current_task().unwrap().get_tid_num(),
-                    SignalNo::SIGSEGV as usize,
-                );
+                send_page_fault_signal(e, stval);
             }
             //PageFault(stval, PTEFlags::USER | PTEFlags::WRITE)
         }
-
+        Trap::Exception(_) => {
+            // riscv 库中没有单独列出的异常，如 load 地址未对齐
+            info!("[cpu {}] Unknown exception {:#x} in application, bad addr = {:#x}, bad instruction = {:#x}.", get_cpu_id(), scause.bits(), stval, cx.sepc);
+            if scause.bits() == SCAUSE_LOAD_MISALIGNED {
+                send_fault_signal(SigInfo::fault(SignalNo::SIGBUS as usize, BUS_ADRALN, stval));
+            } else {
+                send_fault_signal(SigInfo::fault(
+                    SignalNo::SIGILL as usize,
+                    ILL_ILLTRP,
+                    cx.sepc,
+                ));
+            }
+        }
         Trap::Interrupt(Interrupt::SupervisorTimer) => {
             // println!("[cpu {}] timer interrupt", get_cpu_id());
             info!(
@@ -186,18 +232,26 @@ pub fn user_trap_handler(cx: &mut TrapContext) -> &mut TrapContext {
 
             // 之后需要判断如果是在内核态，则不切换任务
             set_timer(get_next_trigger());
+            check_cpu_time_limit();
             suspend_current_task();
         }
-        _ => {
-            panic!(
-                "[cpu {}] Unsupported trap {:?}, stval = {:#x}!",
+        Trap::Interrupt(Interrupt::SupervisorSoft) => {
+            // 其他核发来的消息。如果是信号，会在下面返回用户态前处理
+            ipi::handle_ipi();
+        }
+        Trap::Interrupt(interrupt) => {
+            // 其他中断与用户程序无关，忽略即可
+            warn!(
+                "[cpu {}] Unexpected interrupt {:?} in application, sepc = {:#x}",
                 get_cpu_id(),
-                scause.cause(),
-                stval
+                interrupt,
+                cx.sepc
             );
         }
     }
-    handle_signals();
+    handle_signals(orig_a0);
+    // 被跟踪的任务单步执行时，在回到用户态前插入断点
+    prepare_single_step();
     /*
     let mut sp: usize;
     unsafe { core::arch::asm!("mv {0}, sp", out(reg) sp) };
@@ -298,6 +352,10 @@ pub fn kernel_trap_handler(cx: &mut TrapContext) -> &mut TrapContext {
             set_timer(get_next_trigger());
             //suspend_current_and_run_next();
         }
+        Trap::Interrupt(Interrupt::SupervisorSoft) => {
+            ipi::handle_ipi();
+            return cx;
+        }
         _ => {
             panic!(
                 "[cpu {}] Unsupported trap {:?}, stval = {:#x}!",
@@ -314,3 +372,27 @@ pub fn kernel_trap_handler(cx: &mut TrapContext) -> &mut TrapContext {
     );
     //cx
 }
+
+/// 用户程序缺页且无法处理时，向它发送 SIGSEGV。
+/// 如果地址不在任何 VmArea 中，则 si_code 为 SEGV_MAPERR，否则说明是没有访问权限，为 SEGV_ACCERR
+///
+/// 如果是因为没有物理页可以分配，则访问本身是合法的，发送 SIGSEGV 会让程序误以为自己有错。
+/// 这时和 Linux 的 OOM killer 一样，用 SIGKILL 杀死整个进程
+fn send_page_fault_signal(error: OSError, addr: usize) {
+    let code = match error {
+        OSError::Memory_RunOutOfMemory => {
+            warn!(
+                "[cpu {}] out of memory when handling page fault at {:#x}, kill the process",
+                get_cpu_id(),
+                addr
+            );
+            get_current_task()
+                .unwrap()
+                .send_signal_to_process(SignalNo::SIGKILL as usize);
+            return;
+        }
+        OSError::PageFaultHandler_Unhandled => SEGV_MAPERR,
+        _ => SEGV_ACCERR,
+    };
+    send_fault_signal(SigInfo::fault(SignalNo::SIGSEGV as usize, code, addr));
+}
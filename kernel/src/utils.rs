@@ -4,6 +4,8 @@
 
 use alloc::string::String;
 use alloc::vec::Vec;
+use lock::Mutex;
+use timer::get_time;
 
 /// 获取一个裸指针指向的字符串长度
 ///
@@ -59,3 +61,24 @@ pub unsafe fn str_ptr_array_to_vec_string(str_ptr: *const usize) -> Vec<String>
     }
     strs
 }
+
+/// 随机数生成器的状态
+static RANDOM_STATE: Mutex<u64> = Mutex::new(0x2545_f491_4f6c_dd1d);
+
+/// 生成随机字节并填满 buf。
+///
+/// 目前没有硬件随机源，所以每次调用时用时钟计数打乱 splitmix64 的状态。不能用于密码学用途。
+///
+/// splitmix64 的状态只是一个每次加上固定奇数的计数器，任何值(包括 0)都不会让输出卡住
+pub fn fill_random_bytes(buf: &mut [u8]) {
+    let mut state = RANDOM_STATE.lock();
+    *state ^= (get_time() as u64).rotate_left(32);
+    for chunk in buf.chunks_mut(8) {
+        *state = state.wrapping_add(0x9e37_79b9_7f4a_7c15);
+        let mut z = *state;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        z ^= z >> 31;
+        chunk.copy_from_slice(&z.to_ne_bytes()[..chunk.len()]);
+    }
+}
@@ -54,22 +54,26 @@ bitflags! {
         const S_IFDIR = 1 << 14;
         /// 是字符设备
         const S_IFCHR = 1 << 13;
-        /// 是否设置 uid/gid/sticky
-        //const S_ISUID = 1 << 14;
-        //const S_ISGID = 1 << 13;
-        //const S_ISVTX = 1 << 12;
+        /// 是命名管道(FIFO)
+        const S_IFIFO = 1 << 12;
+        /// 执行时设置有效用户 id 为文件所有者
+        const S_ISUID = 0o4000;
+        /// 执行时设置有效用户组 id 为文件所在用户组
+        const S_ISGID = 0o2000;
+        /// 目录中的文件只能由所有者删除
+        const S_ISVTX = 0o1000;
         /// 所有者权限
-        const S_IXUSR = 1 << 10;
-        const S_IWUSR = 1 << 9;
-        const S_IRUSR = 1 << 8;
+        const S_IRUSR = 0o400;
+        const S_IWUSR = 0o200;
+        const S_IXUSR = 0o100;
         /// 用户组权限
-        const S_IXGRP = 1 << 6;
-        const S_IWGRP = 1 << 5;
-        const S_IRGRP = 1 << 4;
+        const S_IRGRP = 0o40;
+        const S_IWGRP = 0o20;
+        const S_IXGRP = 0o10;
         /// 其他用户权限
-        const S_IXOTH = 1 << 2;
-        const S_IWOTH = 1 << 1;
-        const S_IROTH = 1 << 0;
+        const S_IROTH = 0o4;
+        const S_IWOTH = 0o2;
+        const S_IXOTH = 0o1;
         /// 报告已执行结束的用户进程的状态
         const WIMTRACED = 1 << 1;
         /// 报告还未结束的用户进程的状态
@@ -80,5 +84,19 @@ bitflags! {
 /// 文件类型，输入 IFCHR / IFDIR / IFREG 等具体类型，
 /// 输出这些类型加上普遍的文件属性后得到的 mode 参数
 pub fn normal_file_mode(file_type: StMode) -> StMode {
-    file_type | StMode::S_IWUSR | StMode::S_IWUSR | StMode::S_IWGRP | StMode::S_IRGRP
+    file_type | StMode::S_IRUSR | StMode::S_IWUSR | StMode::S_IWGRP | StMode::S_IRGRP
+}
+
+/// 文件系统中没有被 chmod 修改过的文件默认的权限位。
+/// 文件系统本身不保存权限信息，所以默认所有用户都可以读写和执行
+pub fn normal_file_perm() -> StMode {
+    StMode::S_IRUSR
+        | StMode::S_IWUSR
+        | StMode::S_IXUSR
+        | StMode::S_IRGRP
+        | StMode::S_IWGRP
+        | StMode::S_IXGRP
+        | StMode::S_IROTH
+        | StMode::S_IWOTH
+        | StMode::S_IXOTH
 }
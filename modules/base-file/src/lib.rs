@@ -9,7 +9,8 @@ mod open_flags;
 use alloc::vec::Vec;
 use core::any::Any;
 use fatfs::SeekFrom;
-pub use kstat::{Kstat, StMode, normal_file_mode};
+use syscall::ErrorNo;
+pub use kstat::{Kstat, StMode, normal_file_mode, normal_file_perm};
 pub use open_flags::OpenFlags;
 
 /// 最大允许的文件描述符数量
@@ -25,6 +26,20 @@ pub trait File: Send + Sync + AsAny {
     /// 写 buf 中的内容到文件中，返回写入的字节数。
     /// 如文件不可写，返回 None。(相对应地，如果可写但无法继续写入内容，返回 Some(0))
     fn write(&self, buf: &[u8]) -> Option<usize>;
+    /// 读文件内容到 buf，返回读到的字节数，失败时返回具体的错误码。sys_read 通过它读文件。
+    ///
+    /// 默认调用 read，把 None 视为 EINVAL。会阻塞的文件类型应重写它，
+    /// 以便返回 EAGAIN，或者在被信号打断时返回 ERESTARTSYS 等错误
+    fn try_read(&self, buf: &mut [u8]) -> Result<usize, ErrorNo> {
+        self.read(buf).ok_or(ErrorNo::EINVAL)
+    }
+    /// 写 buf 中的内容到文件中，返回写入的字节数，失败时返回具体的错误码。sys_write 通过它写文件。
+    ///
+    /// 默认调用 write，把 None 视为 EINVAL。会阻塞的文件类型应重写它，
+    /// 以便返回 EAGAIN、EPIPE，或者在被信号打断时返回 ERESTARTSYS 等错误
+    fn try_write(&self, buf: &[u8]) -> Result<usize, ErrorNo> {
+        self.write(buf).ok_or(ErrorNo::EINVAL)
+    }
     /// 从某个位置读文件内容到 buf 中，返回读到的字节数。如果文件不可读，返回 None。
     ///
     /// **需要支持 seek，但不改变指针位置；需要保证文件满足对同一个位置反复读/写是有效的，也即对于pipe、流等不适用**
@@ -21,11 +21,13 @@ bitflags! {
         const NOCTTY = 1 << 8;
         /// 同上，在不同的库中可能会用到这个或者上一个
         const EXCL = 1 << 9;
+        /// 每次写入前都把文件指针移到文件末尾
+        const APPEND = 1 << 10;
         /// 非阻塞读写?(虽然不知道为什么但 date.lua 也要)
         const NON_BLOCK = 1 << 11;
-        /// 要求把 CR-LF 都换成 LF
-        const TEXT = 1 << 14;
-        /// 和上面不同，要求输入输出都不进行这个翻译
+        /// 绕过缓存直接读写。对管道来说，表示写端以“包”为单位写入
+        const DIRECT = 1 << 14;
+        /// 要求输入输出都不进行 CR-LF 的翻译
         const BINARY = 1 << 15;
         /// 对这个文件的输出需符合 IO 同步一致性。可以理解为随时 fsync
         const DSYNC = 1 << 16;
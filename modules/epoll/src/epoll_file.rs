@@ -6,7 +6,7 @@ use alloc::sync::Arc;
 use alloc::collections::{BTreeMap, BTreeSet};
 use alloc::vec::Vec;
 use syscall::ErrorNo;
-use task_trampoline::{get_file, suspend_current_task};
+use task_trampoline::{get_file, signal_pending, suspend_current_task};
 use crate::{EpollEvent, EpollCtl, EpollEventType};
 
 /// 用作 epoll 的文件
@@ -100,8 +100,9 @@ impl EpollFile {
         return events;
     }
 
-    /// 实现 epoll_wait 系统调用，返回的第一个参数 0 表示超时，正数表示响应的事件个数，第二个参数表示响应后的 `epoll_events`
-    pub fn epoll_wait(&self, expire_time: usize) -> Vec<EpollEvent> {
+    /// 实现 epoll_wait 系统调用，返回响应后的 `epoll_events`，为空表示超时。
+    /// 等待时被信号打断则返回 EINTR，且不会被重启
+    pub fn epoll_wait(&self, expire_time: usize) -> Result<Vec<EpollEvent>, ErrorNo> {
         let epoll_events = self.get_epoll_events();
         let mut ret_events: Vec<EpollEvent> = Vec::new();
         loop {
@@ -124,12 +125,15 @@ impl EpollFile {
             }
             if !ret_events.is_empty() {
                 // 正常返回响应了事件的fd个数
-                return ret_events;
+                return Ok(ret_events);
             }
             // 否则暂时 block 住
             if timer::get_time_ms() > expire_time {
                 // 超时返回0
-                return ret_events;
+                return Ok(ret_events);
+            }
+            if signal_pending() {
+                return Err(ErrorNo::EINTR);
             }
             suspend_current_task();
         }
@@ -66,7 +66,7 @@ pub fn sys_epoll_wait(epfd: i32, event: *mut EpollEvent, _maxevents: i32, timeou
     } else {
         usize::MAX // 没有过期时间
     };
-    let ret_events = epoll_file.epoll_wait(expire_time);
+    let ret_events = epoll_file.epoll_wait(expire_time)?;
     for i in 0..ret_events.len() {
         // 回写epollevent,
         unsafe {
@@ -94,6 +94,10 @@ mod tests {
     impl task_trampoline::TaskTrampoline for MyTaskTrampoline {
         fn suspend_current_task(&self) {}
 
+        fn signal_pending(&self) -> bool {
+            false
+        }
+
         fn get_file(&self, fd: usize) -> Option<Arc<dyn File>> {
             unsafe {
                 if fd >= FILES.len() || FILES[fd].is_none() {
@@ -11,7 +11,9 @@ use alloc::vec::Vec;
 use core::mem::size_of;
 use base_file::File;
 use bitflags::bitflags;
-use task_trampoline::{get_file, manually_alloc_type, manually_alloc_user_str, suspend_current_task};
+use task_trampoline::{
+    get_file, manually_alloc_type, manually_alloc_user_str, signal_pending, suspend_current_task,
+};
 
 bitflags! {
     /// sys_ppoll 使用，表示对应在文件上等待或者发生过的事件
@@ -66,8 +68,9 @@ fn poll(file: Arc<dyn File>, events: PollEvents) -> PollEvents {
 /// * `fds`: 一个 `PollFd` 的列表。
 /// * `expire_time`: 超时的时间戳，会与 `get_time()` 接口返回的时间戳比较。
 ///
-/// returns: (usize, Vec<PollFd>) 第一个参数遵守 ppoll 系统调用的返回值约定，第二个参数为返回的 `PollFd` 列表
-fn ppoll(mut fds: Vec<PollFd>, expire_time: usize) -> (usize, Vec<PollFd>) {
+/// returns: (usize, Vec<PollFd>) 第一个参数遵守 ppoll 系统调用的返回值约定，第二个参数为返回的 `PollFd` 列表。
+/// 等待时被信号打断则返回 ERESTARTNOHAND
+fn ppoll(mut fds: Vec<PollFd>, expire_time: usize) -> Result<(usize, Vec<PollFd>), syscall::ErrorNo> {
     loop {
         // 已触发的 fd
         let mut set: usize = 0;
@@ -84,11 +87,14 @@ fn ppoll(mut fds: Vec<PollFd>, expire_time: usize) -> (usize, Vec<PollFd>) {
         }
         if set > 0 {
             // 如果找到满足条件的 fd，则返回找到的 fd 数量
-            return (set, fds);
+            return Ok((set, fds));
         }
         // 否则暂时 block 住
         if timer::get_time() > expire_time {
-            return (0, fds);
+            return Ok((0, fds));
+        }
+        if signal_pending() {
+            return Err(syscall::ErrorNo::ERESTARTNOHAND);
         }
         suspend_current_task();
     }
@@ -98,7 +104,7 @@ fn ppoll(mut fds: Vec<PollFd>, expire_time: usize) -> (usize, Vec<PollFd>) {
 pub fn sys_ppoll(
     ufds: *mut PollFd,
     nfds: usize,
-    timeout: *const timer::TimeSpec, // ppoll 不会更新 timeout 的值，而 poll 会
+    timeout: *const timer::TimeSpec, // 只在被信号打断时更新 timeout 的值，以便重启时继续等待剩余的时间
     _sigmask: *const usize
 ) -> Result<usize, syscall::ErrorNo> {
     // TODO: MemorySet 模块化之后，是否可以不再依赖 task-trampoline 的 manually_alloc_user_str 接口
@@ -128,7 +134,16 @@ pub fn sys_ppoll(
         usize::MAX // 没有过期时间
     };
     // drop(task_vm); // select 的时间可能很长，之后不用 vm 了就及时释放
-    let (result, ret_fds) = ppoll(fds, expire_time);
+    let (result, ret_fds) = ppoll(fds, expire_time).map_err(|err| {
+        // 重启时会重新读取 timeout，所以需要把它改成剩余的时间
+        if timeout as usize != 0 {
+            unsafe {
+                *(timeout as *mut timer::TimeSpec) =
+                    timer::TimeSpec::from_ticks(expire_time.saturating_sub(timer::get_time()));
+            }
+        }
+        err
+    })?;
     for i in 0..ret_fds.len() {
         unsafe { *ufds.add(i) = ret_fds[i]; }
     }
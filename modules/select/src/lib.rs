@@ -11,7 +11,9 @@ use alloc::vec::Vec;
 use base_file::File;
 use bitset::ShadowBitset;
 use syscall::ErrorNo;
-use task_trampoline::{get_file, manually_alloc_range, manually_alloc_type, suspend_current_task};
+use task_trampoline::{
+    get_file, manually_alloc_range, manually_alloc_type, signal_pending, suspend_current_task,
+};
 
 /// 获取 fd 指向文件的集合，
 /// 每个文件存在 arc 里，每个 fd 值存在一个 usize 里，然后在用户地址原地清空建立一个 ShadowBitset。
@@ -59,7 +61,7 @@ pub fn sys_pselect6(
     readfds: *mut usize,
     writefds: *mut usize,
     exceptfds: *mut usize,
-    timeout: *const timer::TimeSpec, // pselect 不会更新 timeout 的值，而 select 会
+    timeout: *const timer::TimeSpec, // 只在被信号打断时更新 timeout 的值，以便重启时继续等待剩余的时间
     _sigmask: *const usize,
 ) -> Result<usize, ErrorNo> {
     if nfds >= base_file::FD_LIMIT_HARD {
@@ -69,7 +71,7 @@ pub fn sys_pselect6(
     let (wfile, wfd, wset) = init_fd_sets(writefds, nfds)?;
     let (efile, efd, eset) = init_fd_sets(exceptfds, nfds)?;
     // 过期时间
-    // 注意 pselect 不会修改用户空间中的 timeout，所以需要内核自己记录
+    // 注意除了被信号打断时，pselect 不会修改用户空间中的 timeout，所以需要内核自己记录
     // 这里用**时钟周期数**来记录，足够精确的同时 usize 也能存下。实际用微秒或者纳秒应该也没问题。
     let expire_time = if timeout as usize != 0 {
         if manually_alloc_type(timeout).is_err() {
@@ -136,5 +138,15 @@ pub fn sys_pselect6(
             // 检查超时
             return Ok(0);
         }
+        if signal_pending() {
+            // 重启时会重新读取 timeout，所以需要把它改成剩余的时间
+            if timeout as usize != 0 {
+                unsafe {
+                    *(timeout as *mut timer::TimeSpec) =
+                        timer::TimeSpec::from_ticks(expire_time.saturating_sub(timer::get_time()));
+                }
+            }
+            return Err(ErrorNo::ERESTARTNOHAND);
+        }
     }
 }
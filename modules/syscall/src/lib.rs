@@ -12,12 +12,26 @@ pub enum ErrorNo {
     ENOENT = -2,
     /// 找不到对应进程
     ESRCH = -3,
+    /// 阻塞时被信号打断
+    EINTR = -4,
+    /// 输入输出错误
+    EIO = -5,
+    /// 设备或地址不存在。例如以 O_NONBLOCK 只写打开没有读端的 FIFO
+    ENXIO = -6,
+    /// 参数过长
+    E2BIG = -7,
+    /// 文件不是可以执行的格式
+    ENOEXEC = -8,
     /// 错误的文件描述符
     EBADF = -9,
+    /// 没有可等待的子进程
+    ECHILD = -10,
     /// 资源暂时不可用。也可因为 futex_wait 时对应用户地址处的值与给定值不符
     EAGAIN = -11,
     /// 内存耗尽，或者没有对应的内存映射
     ENOMEM = -12,
+    /// 没有访问权限
+    EACCES = -13,
     /// 无效地址
     EFAULT = -14,
     /// 设备或者资源被占用
@@ -32,14 +46,30 @@ pub enum ErrorNo {
     EINVAL = -22,
     /// fd（文件描述符）已满
     EMFILE = -24,
+    /// 文件过大，超过了 RLIMIT_FSIZE
+    EFBIG = -27,
     /// 对文件进行了无效的 seek
     ESPIPE = -29,
+    /// 管道的读端已全部关闭
+    EPIPE = -32,
     /// 超过范围。例如用户提供的buffer不够长
     ERANGE = -34,
+    /// 符号链接或者脚本解释器的层数过多
+    ELOOP = -40,
     /// 不支持的协议
     EPFNOSUPPORT = -96,
     /// 不支持的地址
     EAFNOSUPPORT = -97,
     /// 拒绝连接
     ECONNREFUSED = -111,
+    // 以下错误码只在内核中使用，用于重启被信号打断的系统调用，不会返回给用户
+    /// 如果信号处理函数设置了 SA_RESTART 则重启，否则返回 EINTR
+    ERESTARTSYS = -512,
+    /// 总是重启
+    ERESTARTNOINTR = -513,
+    /// 如果调用了信号处理函数则返回 EINTR，否则重启
+    ERESTARTNOHAND = -514,
+    /// 如果调用了信号处理函数则返回 EINTR，否则通过 restart_syscall 从保存的状态继续执行
+    #[allow(non_camel_case_types)]
+    ERESTART_RESTARTBLOCK = -516,
 }
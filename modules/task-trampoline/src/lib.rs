@@ -42,6 +42,7 @@ use spin::Once;
 /// 这个接口定义了一些可供外部模块调用的 Task 接口。
 pub trait TaskTrampoline: Sync {
     fn suspend_current_task(&self);
+    fn signal_pending(&self) -> bool;
     fn get_file(&self, fd: usize) -> Option<Arc<dyn File>>;
     fn push_file(&self, file: Arc<dyn File>) -> Result<usize, u64>;
     fn manually_alloc_user_str(&self, buf: *const u8, len: usize) -> Result<(), u64>;
@@ -63,6 +64,11 @@ pub fn suspend_current_task() {
     TASK.get().unwrap().suspend_current_task();
 }
 
+/// 当前任务是否有需要处理的信号。阻塞中的系统调用检查到它时，应当返回 EINTR 或重启
+pub fn signal_pending() -> bool {
+    TASK.get().unwrap().signal_pending()
+}
+
 /// 从当前任务的文件描述符中找到指定文件。
 pub fn get_file(fd: usize) -> Option<Arc<dyn File>> {
     TASK.get().unwrap().get_file(fd)
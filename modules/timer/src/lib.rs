@@ -7,7 +7,9 @@
 use core::ops::Add;
 use riscv::register::time;
 use syscall::ErrorNo;
-use task_trampoline::{manually_alloc_type, raw_time, raw_timer, set_timer, suspend_current_task};
+use task_trampoline::{
+    manually_alloc_type, raw_time, raw_timer, set_timer, signal_pending, suspend_current_task,
+};
 
 /* Constants */
 
@@ -94,6 +96,13 @@ impl TimeSpec {
     pub fn now() -> Self {
         Self::new(get_time_f64())
     }
+    /// 通过时钟周期数创建数组，是 get_ticks 的逆过程
+    pub fn from_ticks(ticks: usize) -> Self {
+        Self {
+            tv_sec: ticks / CLOCK_FREQ,
+            tv_nsec: ticks % CLOCK_FREQ * NSEC_PER_MACHINE_TICKS,
+        }
+    }
     /// 返回以秒为单位的时间
     pub fn time_in_sec(&self) -> f64 {
         self.tv_sec as f64 + self.tv_nsec as f64 / NSEC_PER_SEC as f64
@@ -239,14 +248,22 @@ pub fn sys_clock_gettime(_clockid: usize, time_spec: *mut TimeSpec) -> Result<us
     Ok(0)
 }
 
-/// sys_nanosleep 系统调用实现
+/// 休眠到 end_time(以秒计)为止。nanosleep 系统调用的实现见内核的 `syscall/restart.rs`
 ///
-/// 该进程休眠一段时间
-pub fn sys_nanosleep(req: *const TimeSpec, rem: *mut TimeSpec) -> Result<usize, ErrorNo> {
-    let end_time = unsafe { get_time_f64() + (*req).time_in_sec() };
+/// 如果休眠时被信号打断，则把剩余时间写入 rem，并返回 ERESTART_RESTARTBLOCK。
+/// 此时调用者需要记录 end_time，以便之后通过 restart_syscall 继续休眠
+pub fn nanosleep_until(end_time: f64, rem: *mut TimeSpec) -> Result<usize, ErrorNo> {
     //let now = get_time_f64();
     //info!("now {} end time {}", now, end_time);
     while get_time_f64() < end_time {
+        if signal_pending() {
+            if rem as usize != 0 {
+                unsafe {
+                    (*rem) = TimeSpec::new((end_time - get_time_f64()).max(0.0));
+                }
+            }
+            return Err(ErrorNo::ERESTART_RESTARTBLOCK);
+        }
         suspend_current_task();
     }
     // 如果用户提供了 rem 数组，则需要修改它